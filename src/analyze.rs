@@ -1,28 +1,367 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
 use clap::ValueEnum;
+use std::io::Write;
 
-use crate::docker::DockerClient;
-use crate::format::print_snapshot_table;
-use crate::models::ImageSnapshot;
+use crate::diff::compute_diff;
+use crate::docker::{ImageInspector, ImageSpec};
+use crate::extract::{export_layer_files, measure_extract};
+use crate::format::{
+    format_size, print_path_usage_table, print_previous_changes, print_snapshot_table,
+    render_pseudo_dockerfile, LayerOrder, LayerSizeUnit, OutputStyle,
+};
+use crate::layer_labels::LayerLabels;
+use crate::models::{ImageSnapshot, SizeDiff};
+use crate::path_budget::{final_files, path_usage, PathBudget, PathUsage};
+use crate::platforms::platform_mismatch;
+use crate::reference::ImageRef;
+use crate::store::Store;
+use crate::track::{attach_git_context, warn_revision_mismatch};
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum OutputFormat {
     Table,
     Json,
+    /// Spreadsheet rows; only `dit summary` has a CSV layout
+    Csv,
 }
 
-pub async fn analyze_image(image: &str, format: OutputFormat) -> Result<ImageSnapshot> {
-    let docker = DockerClient::new()?;
-    let snapshot = docker.inspect_image(image).await?;
+impl OutputFormat {
+    /// Bail for `--format csv` in commands that only print tables or JSON
+    pub fn ensure_not_csv(&self, command: &str) -> Result<()> {
+        if matches!(self, OutputFormat::Csv) {
+            bail!(
+                "dit {} has no CSV output; use --format table or json (csv is for dit summary)",
+                command
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct AnalyzeOptions {
+    pub format: OutputFormat,
+    pub warn_large_layer: Option<u64>,
+    pub show_as_dockerfile: bool,
+    pub measure_extract: bool,
+    /// Print only the total size, for scripts
+    pub total_only: bool,
+    /// Append a diff against the latest tracked snapshot of the same image:tag
+    pub compare_to_previous: bool,
+    /// Expected `os/arch`; defaults to the Docker host's platform
+    pub platform: Option<String>,
+    /// Save the analyzed snapshot to history, like `dit track`
+    pub auto_track: bool,
+    /// Track without commit, branch or author (outside a git repository)
+    pub no_git: bool,
+    /// Export the image and report the final filesystem against these path budgets
+    pub deep: Option<Vec<PathBudget>>,
+    pub layer_size_unit: LayerSizeUnit,
+    pub layer_order: LayerOrder,
+    /// Names for layers in the table's Label column
+    pub layer_labels: Option<LayerLabels>,
+    pub output: OutputStyle,
+}
+
+pub async fn analyze_image(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    store: &Store,
+    image: &ImageSpec,
+    options: AnalyzeOptions,
+) -> Result<ImageSnapshot> {
+    let analysis = run_analysis(docker, store, image, &options).await?;
+    print_analysis(out, &analysis, &options)?;
+    Ok(analysis.snapshot)
+}
+
+/// What `dit analyze` found, before anything is printed
+#[derive(Debug)]
+pub struct Analysis {
+    pub snapshot: ImageSnapshot,
+    /// Changes from the latest tracked snapshot, with `compare_to_previous`
+    pub previous_diff: Option<SizeDiff>,
+    pub platform_mismatch: Option<String>,
+    /// Final filesystem usage against the `deep` path budgets
+    pub path_usages: Option<Vec<PathUsage>>,
+    /// Whether `auto_track` saved the snapshot
+    pub tracked: bool,
+}
+
+/// Inspect `image` and, with `auto_track`, save it to `store`; prints nothing
+pub async fn run_analysis(
+    docker: &impl ImageInspector,
+    store: &Store,
+    image: &ImageSpec,
+    options: &AnalyzeOptions,
+) -> Result<Analysis> {
+    let mut snapshot = docker.inspect_spec(image).await?;
+
+    if options.measure_extract {
+        measure_extract(docker, &image.reference, &mut snapshot).await?;
+    }
+
+    let previous_diff = if options.compare_to_previous {
+        previous_snapshot(store, &snapshot)?.map(|previous| compute_diff(previous, snapshot.clone()))
+    } else {
+        None
+    };
+
+    let platform_mismatch = platform_mismatch(&snapshot, options.platform.as_deref());
+
+    let path_usages = match &options.deep {
+        Some(budgets) => {
+            let layers = export_layer_files(docker, &image.reference).await?;
+            Some(path_usage(&final_files(&layers), budgets))
+        }
+        None => None,
+    };
 
-    match format {
-        OutputFormat::Table => {
-            print_snapshot_table(&snapshot);
+    // The read-only notice was printed up front
+    let tracked = options.auto_track && !store.is_read_only();
+    if tracked {
+        if !options.no_git {
+            attach_git_context(&mut snapshot)
+                .context("Cannot track without git context (pass --no-git to track anyway)")?;
+            warn_revision_mismatch(&snapshot);
         }
-        OutputFormat::Json => {
-            println!("{}", serde_json::to_string_pretty(&snapshot)?);
+        snapshot.timestamp = Utc::now();
+        store.save_snapshot(&snapshot)?;
+    }
+
+    Ok(Analysis {
+        snapshot,
+        previous_diff,
+        platform_mismatch,
+        path_usages,
+        tracked,
+    })
+}
+
+/// Print `analysis` the way `options` asks, with large-layer warnings on stderr
+pub fn print_analysis(
+    out: &mut impl Write,
+    analysis: &Analysis,
+    options: &AnalyzeOptions,
+) -> Result<()> {
+    options.format.ensure_not_csv("analyze")?;
+    let snapshot = &analysis.snapshot;
+    let mismatch = &analysis.platform_mismatch;
+    let previous_diff = &analysis.previous_diff;
+    let path_usages = &analysis.path_usages;
+
+    if options.total_only {
+        writeln!(out, "{}", format_size(snapshot.total_size))?;
+    } else if options.show_as_dockerfile {
+        write!(out, "{}", render_pseudo_dockerfile(snapshot))?;
+    } else {
+        match options.format {
+            OutputFormat::Table => {
+                print_snapshot_table(
+                    out,
+                    snapshot,
+                    options.layer_size_unit,
+                    options.layer_order,
+                    options.layer_labels.as_ref(),
+                    options.output,
+                )?;
+                if let Some(notice) = mismatch {
+                    writeln!(out, "\n⚠️  Platform mismatch: {}", notice)?;
+                }
+                match previous_diff {
+                    Some(diff) => print_previous_changes(out, diff, options.output)?,
+                    None if options.compare_to_previous => writeln!(
+                        out,
+                        "\nNo previous snapshot of {}:{} to compare with",
+                        snapshot.image,
+                        snapshot.tag.as_deref().unwrap_or("latest")
+                    )?,
+                    None => {}
+                }
+                if let Some(usages) = path_usages {
+                    print_path_usage_table(out, usages, options.output)?;
+                }
+            }
+            OutputFormat::Json if options.compare_to_previous => {
+                let mut output = serde_json::json!({
+                    "snapshot": snapshot,
+                    "changes_from_previous": previous_diff,
+                    "platform_mismatch": mismatch.is_some(),
+                });
+                if let Some(usages) = path_usages {
+                    output["path_budgets"] = serde_json::to_value(usages)?;
+                }
+                writeln!(out, "{}", serde_json::to_string_pretty(&output)?)?;
+            }
+            OutputFormat::Json => {
+                let mut output = serde_json::to_value(snapshot)?;
+                output["platform_mismatch"] = mismatch.is_some().into();
+                if let Some(usages) = path_usages {
+                    output["path_budgets"] = serde_json::to_value(usages)?;
+                }
+                writeln!(out, "{}", serde_json::to_string_pretty(&output)?)?;
+            }
+            OutputFormat::Csv => unreachable!("rejected by ensure_not_csv"),
         }
     }
 
-    Ok(snapshot)
+    if let Some(threshold) = options.warn_large_layer {
+        for layer in snapshot.layers_larger_than(threshold) {
+            eprintln!(
+                "⚠️  Large layer: {} exceeds {} — {}",
+                format_size(layer.size),
+                format_size(threshold),
+                layer.command
+            );
+        }
+    }
+
+    if analysis.tracked {
+        // stderr, so JSON and --total-only output stay machine-readable
+        eprintln!(
+            "✅ Tracked snapshot for {}:{}{}",
+            snapshot.image,
+            snapshot.tag.as_deref().unwrap_or("latest"),
+            match snapshot.commit_sha.get(..7) {
+                Some(sha) => format!(" at commit {}", sha),
+                None => String::new(),
+            }
+        );
+    }
+
+    Ok(())
+}
+
+/// Latest tracked snapshot with the same image and tag as `current`
+fn previous_snapshot(store: &Store, current: &ImageSnapshot) -> Result<Option<ImageSnapshot>> {
+    let reference = ImageRef::parse_lenient(&format!(
+        "{}:{}",
+        current.image,
+        current.tag.as_deref().unwrap_or("latest")
+    ));
+
+    Ok(store
+        .load_history()?
+        .into_iter()
+        .filter(|s| reference.matches(s))
+        .max_by_key(|s| s.timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::MockDockerClient;
+    use crate::fixtures::{layer, snapshot, tracked};
+
+    fn options() -> AnalyzeOptions {
+        AnalyzeOptions {
+            format: OutputFormat::Json,
+            warn_large_layer: None,
+            show_as_dockerfile: false,
+            measure_extract: false,
+            total_only: false,
+            compare_to_previous: false,
+            platform: Some("linux/amd64".to_string()),
+            auto_track: false,
+            no_git: true,
+            deep: None,
+            layer_size_unit: LayerSizeUnit::default(),
+            layer_order: LayerOrder::default(),
+            layer_labels: None,
+            output: Default::default(),
+        }
+    }
+
+    fn docker() -> MockDockerClient {
+        MockDockerClient::new().with_image(
+            "myapp:1.0",
+            Ok(snapshot("myapp", "1.0", vec![layer("sha256:a", 100, "COPY . .")])),
+        )
+    }
+
+    #[tokio::test]
+    async fn returns_the_inspected_snapshot_without_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+
+        let spec = ImageSpec::new("myapp:1.0");
+        let analyzed = analyze_image(&mut Vec::new(), &docker(), &store, &spec, options())
+            .await
+            .unwrap();
+
+        assert_eq!(analyzed.image, "myapp");
+        assert_eq!(analyzed.total_size, 100);
+        assert!(store.load_history().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn inspect_errors_are_returned() {
+        let dir = tempfile::tempdir().unwrap();
+        let docker = MockDockerClient::new().with_image("broken:1", Err(anyhow::anyhow!("no such image")));
+
+        let spec = ImageSpec::new("broken:1");
+        let store = Store::new(dir.path());
+        let error = analyze_image(&mut Vec::new(), &docker, &store, &spec, options())
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("no such image"));
+    }
+
+    #[tokio::test]
+    async fn auto_track_saves_the_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+        let options = AnalyzeOptions {
+            auto_track: true,
+            ..options()
+        };
+
+        analyze_image(&mut Vec::new(), &docker(), &store, &ImageSpec::new("myapp:1.0"), options)
+            .await
+            .unwrap();
+
+        let history = store.load_history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].tag.as_deref(), Some("1.0"));
+    }
+
+    #[tokio::test]
+    async fn auto_track_skips_read_only_stores() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).read_only();
+        let options = AnalyzeOptions {
+            auto_track: true,
+            ..options()
+        };
+
+        analyze_image(&mut Vec::new(), &docker(), &store, &ImageSpec::new("myapp:1.0"), options)
+            .await
+            .unwrap();
+
+        assert!(!dir.path().join("history.json").exists());
+    }
+
+    #[test]
+    fn csv_is_refused_outside_summary() {
+        assert!(OutputFormat::Table.ensure_not_csv("analyze").is_ok());
+        assert!(OutputFormat::Json.ensure_not_csv("analyze").is_ok());
+
+        let error = OutputFormat::Csv.ensure_not_csv("top").unwrap_err().to_string();
+        assert_eq!(error, "dit top has no CSV output; use --format table or json (csv is for dit summary)");
+    }
+
+    #[test]
+    fn previous_snapshot_is_the_latest_of_the_same_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+        for (commit, tag, minutes) in [("aaa", "1.0", 1), ("bbb", "1.0", 2), ("ccc", "2.0", 3)] {
+            store.save_snapshot(&tracked("myapp", tag, commit, "main", minutes, vec![])).unwrap();
+        }
+
+        let current = snapshot("myapp", "1.0", vec![]);
+        let previous = previous_snapshot(&store, &current).unwrap().unwrap();
+        assert_eq!(previous.commit_sha, "bbb");
+    }
 }
@@ -1,83 +1,197 @@
 use anyhow::Result;
 use colored::Colorize;
-use tabled::{
-    builder::Builder,
-    settings::{object::Rows, Alignment, Modify, Style},
-};
+use std::io::Write;
+use tracing::field::Empty;
+use tracing::Span;
+use tabled::builder::Builder;
 
 use crate::analyze::OutputFormat;
-use crate::docker::DockerClient;
-use crate::format::format_size;
+use crate::docker::{ImageFilter, ImageInspector};
+use crate::format::{
+    format_size, middle_ellipsis, render_table, short_image_id, shorten_reference, OutputStyle,
+    NAME_WIDTH,
+};
+use crate::labels::{group_by_label, label_cells, label_headers, LabelKey};
+use crate::models::ImageSnapshot;
+use crate::progress::ProgressReporter;
+use crate::summary::subtotal_row;
+use crate::telemetry::ci_provider;
+
+#[derive(Debug)]
+pub struct AnalyzeAllOptions {
+    pub format: OutputFormat,
+    pub group_by: Option<LabelKey>,
+    pub columns: Vec<LabelKey>,
+    pub wide: bool,
+    /// Add a User column flagging images that run as root
+    pub warn_root_user: bool,
+    /// Add an Image ID column
+    pub include_image_id: bool,
+    pub output: OutputStyle,
+}
 
-pub async fn analyze_all_images(filter: Option<&str>, format: OutputFormat) -> Result<()> {
-    let docker = DockerClient::new()?;
-    let images = docker.list_all_images(filter).await?;
+#[tracing::instrument(
+    name = "dit.analyze_all",
+    skip_all,
+    fields(images = Empty, bytes_analyzed = Empty, provider = ci_provider())
+)]
+pub async fn analyze_all_images(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    filter: &ImageFilter,
+    mut progress: Box<dyn ProgressReporter>,
+    options: &AnalyzeAllOptions,
+) -> Result<()> {
+    options.format.ensure_not_csv("analyze-all")?;
+    let images = docker.select_images(filter).await?;
+    // Signed: the OpenTelemetry bridge would export unsigned span fields as strings
+    Span::current().record("images", images.len() as i64);
 
     if images.is_empty() {
-        println!("No images found");
+        writeln!(out, "No images found")?;
         return Ok(());
     }
 
-    println!("Analyzing {} images...\n", images.len());
+    writeln!(out, "Analyzing {} images...\n", images.len())?;
 
     let mut snapshots = Vec::new();
+    progress.start(images.len());
     for image in &images {
+        progress.item_started(image);
         match docker.inspect_image(image).await {
-            Ok(snapshot) => snapshots.push(snapshot),
-            Err(e) => eprintln!("⚠️  Failed to analyze {}: {}", image, e),
+            Ok(snapshot) => {
+                progress.item_succeeded(image, &format_size(snapshot.total_size));
+                snapshots.push(snapshot);
+            }
+            Err(e) => progress.item_failed(image, &format!("Failed to analyze: {}", e)),
         }
     }
+    progress.finish();
+    Span::current().record("bytes_analyzed", snapshots.iter().map(|s| s.total_size).sum::<u64>() as i64);
 
     // Sort by size (biggest first)
-    snapshots.sort_by(|a, b| b.total_size.cmp(&a.total_size));
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.total_size));
 
-    match format {
+    match options.format {
         OutputFormat::Json => {
-            println!("{}", serde_json::to_string_pretty(&snapshots)?);
+            writeln!(out, "{}", serde_json::to_string_pretty(&snapshots)?)?;
         }
         OutputFormat::Table => {
-            print_analyze_all_table(&snapshots);
+            print_analyze_all_table(out, snapshots, options)?;
         }
+        OutputFormat::Csv => unreachable!("rejected by ensure_not_csv"),
     }
 
     Ok(())
 }
 
-fn print_analyze_all_table(snapshots: &[crate::models::ImageSnapshot]) {
+fn print_analyze_all_table(
+    out: &mut impl Write,
+    snapshots: Vec<ImageSnapshot>,
+    options: &AnalyzeAllOptions,
+) -> Result<()> {
     let total_size: u64 = snapshots.iter().map(|s| s.total_size).sum();
-
-    println!("{}", "All Docker Images".bold().underline());
+    let image_count = snapshots.len();
+    let root_count = snapshots
+        .iter()
+        .filter(|s| s.runs_as_root() == Some(true))
+        .count();
+    let columns = &options.columns;
+
+    writeln!(out, "{}", "All Docker Images".bold().underline())?;
+
+    let mut header: Vec<String> = ["Image", "Tag", "Size", "Layers", "OS/Arch"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+    if options.include_image_id {
+        header.insert(2, "Image ID".to_string());
+    }
+    if options.warn_root_user {
+        header.push("User".to_string());
+    }
+    header.extend(label_headers(columns));
+    let width = header.len();
 
     let mut builder = Builder::default();
-    builder.push_record(["Image", "Tag", "Size", "Layers", "OS/Arch"]);
-
-    for snapshot in snapshots {
-        let tag = snapshot.tag.as_deref().unwrap_or("latest");
-        let os_arch = format!("{}/{}", snapshot.os, snapshot.arch);
-
-        builder.push_record([
-            &snapshot.image,
-            tag,
-            &format_size(snapshot.total_size),
-            &snapshot.layer_count.to_string(),
-            &os_arch,
-        ]);
-    }
+    builder.push_record(header);
+
+    let groups = match &options.group_by {
+        Some(key) => group_by_label(snapshots, key, |s| s),
+        None => vec![(String::new(), snapshots)],
+    };
+
+    for (group, snapshots) in groups {
+        for snapshot in &snapshots {
+            let tag = snapshot.tag.as_deref().unwrap_or("latest");
+            let os_arch = format!("{}/{}", snapshot.os, snapshot.arch);
+
+            let (image, tag) = if options.wide {
+                (snapshot.image.clone(), tag.to_string())
+            } else {
+                (
+                    shorten_reference(&snapshot.image, options.output.name_width()),
+                    middle_ellipsis(tag, NAME_WIDTH / 2),
+                )
+            };
+
+            let mut row = vec![
+                image,
+                tag,
+                format_size(snapshot.total_size),
+                snapshot.layer_count.to_string(),
+                os_arch,
+            ];
+            if options.include_image_id {
+                row.insert(2, snapshot.image_id.as_deref().map_or("-".to_string(), short_image_id));
+            }
+            if options.warn_root_user {
+                row.push(user_cell(snapshot));
+            }
+            row.extend(label_cells(snapshot, columns));
+            builder.push_record(row);
+        }
 
-    let mut table = builder.build();
-    table
-        .with(Style::rounded())
-        .with(Modify::new(Rows::first()).with(Alignment::center()));
+        if let Some(key) = &options.group_by {
+            let group_size = snapshots.iter().map(|s| s.total_size).sum();
+            builder.push_record(subtotal_row(&key.0, &group, snapshots.len(), group_size, width));
+        }
+    }
 
-    println!("{}\n", table);
+    writeln!(out, "{}\n", render_table(builder, options.output.plain))?;
 
-    println!(
+    writeln!(
+        out,
         "{}",
         format!(
             "Total: {} images, {} combined",
-            snapshots.len(),
+            image_count,
             format_size(total_size)
         )
         .bold()
-    );
+    )?;
+
+    if options.warn_root_user && root_count > 0 {
+        writeln!(
+            out,
+            "{}",
+            format!("⚠️  {} of {} images run as root", root_count, image_count).yellow()
+        )?;
+    }
+
+    Ok(())
+}
+
+fn user_cell(snapshot: &ImageSnapshot) -> String {
+    let user = match snapshot.user.as_deref() {
+        Some("") => "root",
+        Some(user) => user,
+        None => return "—".to_string(),
+    };
+
+    if snapshot.runs_as_root() == Some(true) {
+        format!("⚠️ {}", user)
+    } else {
+        user.to_string()
+    }
 }
@@ -0,0 +1,60 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Parse a `--annotation KEY=VALUE` argument
+pub fn parse_annotation(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("invalid annotation '{}': expected KEY=VALUE", s)),
+    }
+}
+
+/// Read a JSON object of annotations; nested objects and arrays flatten to `key.subkey` / `key.0`
+pub fn read_annotation_file(path: &str) -> Result<BTreeMap<String, String>> {
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read annotation file: {}", path))?;
+    let value: Value = serde_json::from_str(&content)
+        .context(format!("Failed to parse annotation file: {}", path))?;
+
+    if !value.is_object() {
+        bail!("Annotation file {} must contain a JSON object", path);
+    }
+
+    let mut annotations = BTreeMap::new();
+    flatten("", &value, &mut annotations);
+    Ok(annotations)
+}
+
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, String>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(&join(key), value, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(&join(&i.to_string()), value, out);
+            }
+        }
+        Value::Null => {}
+        Value::String(s) => {
+            out.insert(prefix.to_string(), s.clone());
+        }
+        other => {
+            out.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
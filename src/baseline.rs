@@ -0,0 +1,215 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::{ImageOrigin, ImageSnapshot};
+use crate::reference::ImageRef;
+
+/// Why a candidate snapshot was or wasn't chosen as the baseline
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BaselineDecision {
+    Selected,
+    DifferentBranch,
+    /// `--baseline-origin same`: built locally vs pulled, or the other way round
+    DifferentOrigin,
+    Superseded,
+}
+
+impl BaselineDecision {
+    pub fn describe(&self) -> &str {
+        match self {
+            BaselineDecision::Selected => "selected",
+            BaselineDecision::DifferentBranch => "excluded: different branch",
+            BaselineDecision::DifferentOrigin => "excluded: different origin",
+            BaselineDecision::Superseded => "excluded: newer match exists",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BaselineCandidate {
+    pub commit_sha: String,
+    pub branch: String,
+    pub timestamp: DateTime<Utc>,
+    pub total_size: u64,
+    pub decision: BaselineDecision,
+}
+
+/// Structured record of how a baseline was picked for one image
+#[derive(Debug, Clone, Serialize)]
+pub struct BaselineSelection {
+    pub image: String,
+    pub base_branch: Option<String>,
+    pub history_snapshots: usize,
+    pub image_snapshots: usize,
+    pub candidates: Vec<BaselineCandidate>,
+}
+
+impl BaselineSelection {
+    pub fn selected(&self) -> Option<&BaselineCandidate> {
+        self.candidates
+            .iter()
+            .find(|c| c.decision == BaselineDecision::Selected)
+    }
+}
+
+/// Find the baseline for `image`: the latest snapshot, or the latest on `base_branch` if given.
+/// With `origin`, snapshots known to come from elsewhere (built vs pulled) are skipped.
+pub fn find_baseline_snapshot<'a>(
+    history: &'a [ImageSnapshot],
+    image: &str,
+    base_branch: Option<&str>,
+    origin: Option<ImageOrigin>,
+) -> (Option<&'a ImageSnapshot>, BaselineSelection) {
+    let reference = ImageRef::parse_lenient(image);
+    let image_history: Vec<_> = history.iter().filter(|s| reference.matches(s)).collect();
+
+    let mut selected: Option<&ImageSnapshot> = None;
+    let mut candidates = Vec::with_capacity(image_history.len());
+
+    // Walk newest first so the first eligible snapshot wins
+    for snapshot in image_history.iter().rev() {
+        let decision = if base_branch.is_some_and(|b| snapshot.branch != b) {
+            BaselineDecision::DifferentBranch
+        } else if origin.is_some_and(|o| {
+            o != ImageOrigin::Unknown
+                && snapshot.origin != ImageOrigin::Unknown
+                && snapshot.origin != o
+        }) {
+            BaselineDecision::DifferentOrigin
+        } else if selected.is_some() {
+            BaselineDecision::Superseded
+        } else {
+            selected = Some(*snapshot);
+            BaselineDecision::Selected
+        };
+
+        candidates.push(BaselineCandidate {
+            commit_sha: snapshot.commit_sha.clone(),
+            branch: snapshot.branch.clone(),
+            timestamp: snapshot.timestamp,
+            total_size: snapshot.size(),
+            decision,
+        });
+    }
+
+    let selection = BaselineSelection {
+        image: image.to_string(),
+        base_branch: base_branch.map(String::from),
+        history_snapshots: history.len(),
+        image_snapshots: image_history.len(),
+        candidates,
+    };
+
+    (selected, selection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+
+    fn with_origin(mut snapshot: ImageSnapshot, origin: ImageOrigin) -> ImageSnapshot {
+        snapshot.origin = origin;
+        snapshot
+    }
+
+    fn history() -> Vec<ImageSnapshot> {
+        vec![
+            tracked("myapp", "1", "a1", "main", 1, vec![layer("sha256:a", 100, "")]),
+            tracked("other", "1", "o1", "main", 2, vec![]),
+            with_origin(tracked("myapp", "1", "a2", "main", 3, vec![]), ImageOrigin::Pulled),
+            tracked("myapp", "1", "f1", "feature", 4, vec![]),
+            tracked("myapp", "2", "a3", "main", 5, vec![]),
+        ]
+    }
+
+    fn decisions(selection: &BaselineSelection) -> Vec<(&str, BaselineDecision)> {
+        selection
+            .candidates
+            .iter()
+            .map(|c| (c.commit_sha.as_str(), c.decision.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn latest_snapshot_of_the_tag_wins_without_a_branch() {
+        let history = history();
+        let (selected, selection) = find_baseline_snapshot(&history, "myapp:1", None, None);
+
+        assert_eq!(selected.unwrap().commit_sha, "f1");
+        assert_eq!(selection.history_snapshots, 5);
+        assert_eq!(selection.image_snapshots, 3);
+        assert_eq!(
+            decisions(&selection),
+            [
+                ("f1", BaselineDecision::Selected),
+                ("a2", BaselineDecision::Superseded),
+                ("a1", BaselineDecision::Superseded),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_branches_are_excluded() {
+        let history = history();
+        let (selected, selection) = find_baseline_snapshot(&history, "myapp:1", Some("main"), None);
+
+        assert_eq!(selected.unwrap().commit_sha, "a2");
+        assert_eq!(selection.selected().unwrap().commit_sha, "a2");
+        assert_eq!(
+            decisions(&selection),
+            [
+                ("f1", BaselineDecision::DifferentBranch),
+                ("a2", BaselineDecision::Selected),
+                ("a1", BaselineDecision::Superseded),
+            ]
+        );
+    }
+
+    #[test]
+    fn known_origins_must_match_but_unknown_ones_pass() {
+        let history = history();
+        let (selected, selection) =
+            find_baseline_snapshot(&history, "myapp:1", Some("main"), Some(ImageOrigin::Built));
+
+        assert_eq!(selected.unwrap().commit_sha, "a1");
+        assert_eq!(
+            decisions(&selection),
+            [
+                ("f1", BaselineDecision::DifferentBranch),
+                ("a2", BaselineDecision::DifferentOrigin),
+                ("a1", BaselineDecision::Selected),
+            ]
+        );
+
+        // An image of unknown origin accepts any baseline
+        let (selected, _) =
+            find_baseline_snapshot(&history, "myapp:1", Some("main"), Some(ImageOrigin::Unknown));
+        assert_eq!(selected.unwrap().commit_sha, "a2");
+    }
+
+    #[test]
+    fn nothing_eligible_selects_nothing() {
+        let history = history();
+        let (selected, selection) = find_baseline_snapshot(&history, "myapp:1", Some("release"), None);
+
+        assert!(selected.is_none());
+        assert!(selection.selected().is_none());
+        assert!(selection.candidates.iter().all(|c| c.decision == BaselineDecision::DifferentBranch));
+
+        let (_, selection) = find_baseline_snapshot(&history, "missing", None, None);
+        assert!(selection.candidates.is_empty());
+    }
+
+    #[test]
+    fn decisions_serialize_for_json_output() {
+        let history = history();
+        let (_, selection) = find_baseline_snapshot(&history, "myapp:1", Some("main"), None);
+        let json = serde_json::to_value(&selection).unwrap();
+
+        assert_eq!(json["candidates"][0]["decision"], "different_branch");
+        assert_eq!(json["candidates"][1]["decision"], "selected");
+        assert_eq!(json["base_branch"], "main");
+    }
+}
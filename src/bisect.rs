@@ -0,0 +1,337 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::io::Write;
+use tokio::process::Command;
+
+use crate::docker::DockerClient;
+use crate::format::{format_size, format_size_delta, print_history_table, OutputStyle};
+use crate::metric::Metric;
+use crate::models::ImageSnapshot;
+use crate::reference::ImageRef;
+use crate::store::Store;
+use crate::track::run_git;
+
+#[derive(Debug)]
+pub struct BisectOptions {
+    pub good: String,
+    pub bad: String,
+    /// Build command to run at each bisect step; history-only when unset
+    pub rebuild: Option<String>,
+    /// Growth over the good size that counts as bad (default: half the largest jump)
+    pub threshold: Option<u64>,
+    pub output: OutputStyle,
+}
+
+/// The pair of consecutive tracked snapshots with the biggest size increase
+#[derive(Debug)]
+pub struct Jump<'a> {
+    pub before: &'a ImageSnapshot,
+    pub after: &'a ImageSnapshot,
+    pub delta: i64,
+}
+
+/// Tracked snapshots of `image` from the `good` commit through the `bad` commit, in tracking order
+pub fn snapshots_between<'a>(
+    history: &'a [ImageSnapshot],
+    image: &str,
+    good: &str,
+    bad: &str,
+) -> Result<Vec<&'a ImageSnapshot>> {
+    let reference = ImageRef::parse_lenient(image);
+    let mut image_history: Vec<&ImageSnapshot> =
+        history.iter().filter(|s| reference.matches(s)).collect();
+    image_history.sort_by_key(|s| s.timestamp);
+
+    if image_history.is_empty() {
+        bail!("No history found for image '{}'", image);
+    }
+
+    let position = |commit: &str| {
+        image_history
+            .iter()
+            .rposition(|s| s.commit_sha.starts_with(commit))
+            .context(format!("No snapshot of '{}' for commit '{}'", image, commit))
+    };
+    let start = position(good)?;
+    let end = position(bad)?;
+
+    if start >= end {
+        bail!("--good {} was tracked after --bad {}; swap them?", good, bad);
+    }
+
+    Ok(image_history[start..=end].to_vec())
+}
+
+pub fn largest_jump<'a>(snapshots: &[&'a ImageSnapshot]) -> Option<Jump<'a>> {
+    snapshots
+        .windows(2)
+        .map(|pair| Jump {
+            before: pair[0],
+            after: pair[1],
+            delta: pair[1].total_size as i64 - pair[0].total_size as i64,
+        })
+        .filter(|jump| jump.delta > 0)
+        .max_by_key(|jump| jump.delta)
+}
+
+pub async fn bisect(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    options: &BisectOptions,
+) -> Result<()> {
+    let history = store.load_history()?;
+    let snapshots = snapshots_between(&history, image, &options.good, &options.bad)?;
+
+    let owned: Vec<ImageSnapshot> = snapshots.iter().map(|s| (*s).clone()).collect();
+    print_history_table(out, &owned, false, Metric::Size, options.output)?;
+
+    let Some(jump) = largest_jump(&snapshots) else {
+        writeln!(out, "\n✅ No size increase between {} and {}", options.good, options.bad)?;
+        return Ok(());
+    };
+
+    let from = short_sha(&jump.before.commit_sha);
+    let to = short_sha(&jump.after.commit_sha);
+    writeln!(
+        out,
+        "\n{} {} between {} and {}",
+        "Largest jump:".bold(),
+        format_size_delta(jump.delta, options.output.plain).red(),
+        from.bright_cyan(),
+        to.bright_cyan()
+    )?;
+
+    match run_git(&[
+        "log",
+        "--oneline",
+        &format!("{}..{}", jump.before.commit_sha, jump.after.commit_sha),
+    ]) {
+        Ok(log) if log.is_empty() => writeln!(out, "No commits in between (same commit rebuilt?)")?,
+        Ok(log) => {
+            writeln!(out, "\nCommits in that interval:")?;
+            for line in log.lines() {
+                writeln!(out, "  {}", line)?;
+            }
+        }
+        Err(e) => writeln!(
+            out,
+            "{}",
+            format!("Could not list commits {}..{}: {}", from, to, e).yellow()
+        )?,
+    }
+
+    if let Some(command) = &options.rebuild {
+        let threshold = options
+            .threshold
+            .unwrap_or((jump.delta as u64 / 2).max(1));
+        let culprit = rebuild_bisect(out, image, &jump, command, threshold).await?;
+        writeln!(
+            out,
+            "\n🎯 First commit over the threshold: {}",
+            culprit.bright_cyan().bold()
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Drive `git bisect` across the jump, rebuilding and measuring at each step.
+/// Returns the first bad commit line from git.
+async fn rebuild_bisect(
+    out: &mut impl Write,
+    image: &str,
+    jump: &Jump<'_>,
+    command: &str,
+    threshold: u64,
+) -> Result<String> {
+    if run_git(&["bisect", "log"]).is_ok() {
+        bail!("A git bisect session is already in progress; finish it or run 'git bisect reset'");
+    }
+    if !run_git(&["status", "--porcelain", "--untracked-files=no"])?.is_empty() {
+        bail!("Working tree has uncommitted changes; commit or stash them before bisecting");
+    }
+
+    let docker = DockerClient::new()?;
+    let good_size = jump.before.total_size;
+    writeln!(
+        out,
+        "\nBisecting with '{}': bad means bigger than {} ({} + {})",
+        command,
+        format_size(good_size + threshold),
+        format_size(good_size),
+        format_size(threshold)
+    )?;
+
+    let started = run_git(&[
+        "bisect",
+        "start",
+        &jump.after.commit_sha,
+        &jump.before.commit_sha,
+    ])
+    .context("Failed to start git bisect")?;
+
+    // Always leave the repository where we found it, even if a step fails
+    let result = match first_bad_commit(&started) {
+        Some(commit) => Ok(commit),
+        None => bisect_steps(out, &docker, image, command, good_size + threshold).await,
+    };
+    if let Err(e) = run_git(&["bisect", "reset"]) {
+        eprintln!("⚠️  'git bisect reset' failed, run it manually: {}", e);
+    }
+
+    result
+}
+
+async fn bisect_steps(
+    out: &mut impl Write,
+    docker: &DockerClient,
+    image: &str,
+    command: &str,
+    limit: u64,
+) -> Result<String> {
+    loop {
+        let head = run_git(&["rev-parse", "--short", "HEAD"])?;
+
+        let built = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .status()
+            .await
+            .context(format!("Failed to run rebuild command '{}'", command))?
+            .success();
+
+        let verdict = if !built {
+            writeln!(out, "  {} build failed → skip", head)?;
+            "skip"
+        } else {
+            let size = docker.inspect_image(image).await?.total_size;
+            let verdict = if size > limit { "bad" } else { "good" };
+            writeln!(out, "  {} {} → {}", head, format_size(size), verdict)?;
+            verdict
+        };
+
+        let output = run_git(&["bisect", verdict])?;
+        if let Some(commit) = first_bad_commit(&output) {
+            return Ok(commit);
+        }
+        if output.contains("only 'skip'ped commits left") {
+            bail!("Bisect could not narrow further: the remaining commits failed to build");
+        }
+    }
+}
+
+fn first_bad_commit(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find(|l| l.contains("is the first bad commit"))
+        .map(|l| l.replace(" is the first bad commit", ""))
+}
+
+fn short_sha(sha: &str) -> String {
+    sha.chars().take(7).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+    use crate::output::TextOutput;
+    use tempfile::TempDir;
+
+    /// myapp at commits aaa (100), bbb (150), ccc (140) and ddd (400), with another image
+    /// tracked in between
+    fn history() -> Vec<ImageSnapshot> {
+        let at = |commit: &str, minutes: i64, size: u64| {
+            let layers = vec![layer("sha256:a", size, "RUN build")];
+            tracked("myapp", "v1", commit, "main", minutes, layers)
+        };
+        vec![
+            at("aaa1111", 0, 100),
+            tracked("other", "v1", "bbb2222", "main", 1, vec![layer("sha256:o", 999, "RUN other")]),
+            at("bbb2222", 2, 150),
+            at("ccc3333", 3, 140),
+            at("ddd4444", 4, 400),
+        ]
+    }
+
+    fn commits(snapshots: &[&ImageSnapshot]) -> Vec<String> {
+        snapshots.iter().map(|s| s.commit_sha.clone()).collect()
+    }
+
+    /// What `bisect` prints, without color
+    async fn run(store: &Store, good: &str, bad: &str) -> String {
+        let mut out = TextOutput::new(Vec::new());
+        out.strip_color();
+        bisect(&mut out, store, "myapp", &options(good, bad)).await.unwrap();
+        String::from_utf8(out.into_inner()).unwrap()
+    }
+
+    fn options(good: &str, bad: &str) -> BisectOptions {
+        BisectOptions {
+            good: good.to_string(),
+            bad: bad.to_string(),
+            rebuild: None,
+            threshold: None,
+            output: OutputStyle::default(),
+        }
+    }
+
+    #[test]
+    fn snapshots_between_span_good_through_bad_for_the_image_only() {
+        let history = history();
+
+        let between = snapshots_between(&history, "myapp", "aaa", "ccc3333").unwrap();
+        assert_eq!(commits(&between), vec!["aaa1111", "bbb2222", "ccc3333"]);
+
+        let between = snapshots_between(&history, "myapp:v1", "bbb", "ddd").unwrap();
+        assert_eq!(commits(&between), vec!["bbb2222", "ccc3333", "ddd4444"]);
+    }
+
+    #[test]
+    fn snapshots_between_reject_unknown_or_swapped_commits() {
+        let history = history();
+
+        let error = snapshots_between(&history, "myapp", "ddd", "aaa").unwrap_err();
+        assert!(error.to_string().contains("swap them?"), "{}", error);
+
+        let error = snapshots_between(&history, "myapp", "aaa", "fff").unwrap_err();
+        assert_eq!(error.to_string(), "No snapshot of 'myapp' for commit 'fff'");
+
+        let error = snapshots_between(&history, "missing", "aaa", "ddd").unwrap_err();
+        assert_eq!(error.to_string(), "No history found for image 'missing'");
+    }
+
+    #[test]
+    fn the_largest_jump_is_the_biggest_increase() {
+        let history = history();
+        let between = snapshots_between(&history, "myapp", "aaa", "ddd").unwrap();
+
+        let jump = largest_jump(&between).unwrap();
+        assert_eq!(jump.before.commit_sha, "ccc3333");
+        assert_eq!(jump.after.commit_sha, "ddd4444");
+        assert_eq!(jump.delta, 260);
+
+        // Only shrinking: no jump
+        let shrinking = snapshots_between(&history, "myapp", "bbb", "ccc").unwrap();
+        assert!(largest_jump(&shrinking).is_none());
+        assert!(largest_jump(&[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn bisect_reports_the_largest_jump_from_history() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        for snapshot in history() {
+            store.save_snapshot(&snapshot).unwrap();
+        }
+
+        let printed = run(&store, "aaa", "ddd").await;
+        assert!(printed.contains("Largest jump:"), "{}", printed);
+        assert!(printed.contains("+260 B"), "{}", printed);
+        assert!(printed.contains("ccc3333 and ddd4444"), "{}", printed);
+
+        let printed = run(&store, "bbb", "ccc").await;
+        assert!(printed.contains("✅ No size increase between bbb and ccc"), "{}", printed);
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::Result;
+use colored::Colorize;
+use globset::GlobMatcher;
+use std::io::Write;
+
+use crate::ci::ImageBudget;
+use crate::format::format_size;
+use crate::models::ImageSnapshot;
+use crate::paths::{is_glob, parse_glob};
+use crate::reference::ImageRef;
+
+/// How a `[budgets]` key selects images. One budget applies per image: an exact
+/// `name:tag`, then an exact name, then the most specific glob, then `[default_budget]`.
+#[derive(Debug, Clone)]
+pub enum BudgetKey {
+    /// `myapp:prod`
+    Tag(ImageRef),
+    /// `myapp`, any tag
+    Name(ImageRef),
+    /// `ghcr.io/org/*`, matched against `name` and `name:tag`
+    Glob(GlobMatcher),
+    /// `[default_budget]`; a table of its own so an image named `default` keeps its key
+    Default,
+}
+
+impl BudgetKey {
+    pub fn parse(key: &str) -> Result<Self> {
+        if is_glob(key) {
+            return Ok(BudgetKey::Glob(parse_glob(key)?.compile_matcher()));
+        }
+
+        let reference = ImageRef::parse_lenient(key);
+        Ok(if reference.tag.is_some() {
+            BudgetKey::Tag(reference)
+        } else {
+            BudgetKey::Name(reference)
+        })
+    }
+
+    pub fn matches(&self, image: &ImageRef) -> bool {
+        match self {
+            BudgetKey::Tag(key) => {
+                key.name() == image.name() && key.tag_or_latest() == image.tag_or_latest()
+            }
+            BudgetKey::Name(key) => key.name() == image.name(),
+            BudgetKey::Glob(glob) => {
+                let name = image.name();
+                glob.is_match(&name)
+                    || glob.is_match(format!("{}:{}", name, image.tag_or_latest()))
+            }
+            BudgetKey::Default => true,
+        }
+    }
+
+    /// Sort key, higher wins: the kind, then for globs how many literal characters they
+    /// pin down (`ghcr.io/org/api-*` beats `ghcr.io/org/*`)
+    fn rank(&self) -> (u8, usize) {
+        match self {
+            BudgetKey::Tag(_) => (3, 0),
+            BudgetKey::Name(_) => (2, 0),
+            BudgetKey::Glob(glob) => (1, literal_chars(glob.glob().glob())),
+            BudgetKey::Default => (0, 0),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            BudgetKey::Tag(_) => "exact name and tag".to_string(),
+            BudgetKey::Name(_) => "exact name, any tag".to_string(),
+            BudgetKey::Glob(glob) => {
+                format!("glob, {} literal characters", literal_chars(glob.glob().glob()))
+            }
+            BudgetKey::Default => "catch-all".to_string(),
+        }
+    }
+}
+
+fn literal_chars(pattern: &str) -> usize {
+    pattern
+        .chars()
+        .filter(|c| !matches!(c, '*' | '?' | '[' | ']' | '{' | '}' | ','))
+        .count()
+}
+
+/// Every budget that matches `image`, the one that applies first. Equally specific globs
+/// are tried in key order.
+pub fn matching_budgets<'a>(budgets: &'a [ImageBudget], image: &ImageRef) -> Vec<&'a ImageBudget> {
+    let mut matching: Vec<&ImageBudget> = budgets.iter().filter(|b| b.key.matches(image)).collect();
+    matching.sort_by(|a, b| b.key.rank().cmp(&a.key.rank()).then_with(|| a.image.cmp(&b.image)));
+    matching
+}
+
+/// The budget that applies to `snapshot`, if any
+pub fn resolve_budget<'a>(
+    budgets: &'a [ImageBudget],
+    snapshot: &ImageSnapshot,
+) -> Option<&'a ImageBudget> {
+    let reference = ImageRef::parse_lenient(&format!(
+        "{}:{}",
+        snapshot.image,
+        snapshot.tag.as_deref().unwrap_or("latest")
+    ));
+    matching_budgets(budgets, &reference).into_iter().next()
+}
+
+/// `dit budgets resolve`: which `[budgets]` key applies to `image`, and which it outranked
+pub fn print_resolution(out: &mut impl Write, budgets: &[ImageBudget], image: &str) -> Result<()> {
+    let reference = ImageRef::parse_lenient(image);
+    let shown = format!("{}:{}", reference.name(), reference.tag_or_latest());
+    let matching = matching_budgets(budgets, &reference);
+
+    let Some((winner, others)) = matching.split_first() else {
+        writeln!(
+            out,
+            "No budget applies to {}; add [budgets.\"{}\"], a glob key or [default_budget]",
+            shown,
+            reference.name()
+        )?;
+        return Ok(());
+    };
+
+    writeln!(
+        out,
+        "{} → {} from {} ({}), warn at {}%",
+        shown.bold(),
+        format_size(winner.bytes).bold(),
+        winner.table(),
+        winner.key.describe(),
+        winner.warn_at_percent
+    )?;
+
+    if !others.is_empty() {
+        writeln!(out, "Also matched, but less specific:")?;
+        for budget in others {
+            writeln!(
+                out,
+                "  {} {} ({})",
+                budget.table(),
+                format_size(budget.bytes),
+                budget.key.describe()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::image_budgets;
+    use crate::config::DitConfig;
+    use crate::fixtures::snapshot;
+
+    fn config(toml: &str) -> DitConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    /// Every key from the precedence examples, each with its own size
+    const KEYS: &str = r#"
+        [budgets."ghcr.io/org/api:prod"]
+        budget = "100"
+        [budgets."ghcr.io/org/api"]
+        budget = "200"
+        [budgets."ghcr.io/org/api-*"]
+        budget = "300"
+        [budgets."ghcr.io/org/*"]
+        budget = "400"
+        [budgets."ghcr.io/*"]
+        budget = "500"
+        [default_budget]
+        budget = "600"
+    "#;
+
+    fn budgets(toml: &str) -> Vec<ImageBudget> {
+        image_budgets(&config(toml), None, None).unwrap()
+    }
+
+    /// Bytes of the budget that applies to `image`, then of those it outranked
+    fn resolution(budgets: &[ImageBudget], image: &str) -> Vec<u64> {
+        matching_budgets(budgets, &ImageRef::parse_lenient(image))
+            .iter()
+            .map(|b| b.bytes)
+            .collect()
+    }
+
+    #[test]
+    fn exact_tag_beats_everything() {
+        assert_eq!(resolution(&budgets(KEYS), "ghcr.io/org/api:prod"), vec![100, 200, 400, 500, 600]);
+    }
+
+    #[test]
+    fn exact_name_beats_globs() {
+        assert_eq!(resolution(&budgets(KEYS), "ghcr.io/org/api:dev"), vec![200, 400, 500, 600]);
+        // An untagged image is :latest, which the :prod key doesn't cover
+        assert_eq!(resolution(&budgets(KEYS), "ghcr.io/org/api"), vec![200, 400, 500, 600]);
+    }
+
+    #[test]
+    fn the_most_literal_glob_wins() {
+        assert_eq!(resolution(&budgets(KEYS), "ghcr.io/org/api-gateway"), vec![300, 400, 500, 600]);
+        assert_eq!(resolution(&budgets(KEYS), "ghcr.io/org/web"), vec![400, 500, 600]);
+        // `*` crosses `/`, so nested repositories fall under the org glob too
+        assert_eq!(resolution(&budgets(KEYS), "ghcr.io/org/team/web"), vec![400, 500, 600]);
+        assert_eq!(resolution(&budgets(KEYS), "ghcr.io/other/web"), vec![500, 600]);
+    }
+
+    #[test]
+    fn default_budget_catches_the_rest() {
+        assert_eq!(resolution(&budgets(KEYS), "docker.io/library/nginx"), vec![600]);
+    }
+
+    #[test]
+    fn images_matching_nothing_have_no_budget() {
+        let budgets = budgets(r#"
+            [budgets."ghcr.io/org/*"]
+            budget = "400"
+        "#);
+        assert!(resolution(&budgets, "nginx").is_empty());
+        assert!(resolve_budget(&budgets, &snapshot("nginx", "1.27", Vec::new())).is_none());
+        assert!(resolve_budget(&[], &snapshot("nginx", "1.27", Vec::new())).is_none());
+    }
+
+    #[test]
+    fn equally_specific_globs_go_in_key_order() {
+        let budgets = budgets(r#"
+            [budgets."*-api"]
+            budget = "1"
+            [budgets."app-*"]
+            budget = "2"
+        "#);
+        // `*` sorts before letters
+        assert_eq!(resolution(&budgets, "app-api"), vec![1, 2]);
+    }
+
+    #[test]
+    fn glob_keys_also_match_the_tag() {
+        let budgets = budgets(r#"
+            [budgets."myapp:release-*"]
+            budget = "1"
+        "#);
+        assert_eq!(resolution(&budgets, "myapp:release-2"), vec![1]);
+        assert!(resolution(&budgets, "myapp:dev").is_empty());
+    }
+
+    #[test]
+    fn an_image_named_default_keeps_its_own_key() {
+        let budgets = budgets(r#"
+            [budgets.default]
+            budget = "1"
+            [default_budget]
+            budget = "2"
+        "#);
+        assert_eq!(resolution(&budgets, "default:latest"), vec![1, 2]);
+        assert_eq!(resolution(&budgets, "other"), vec![2]);
+        assert_eq!(budgets.iter().map(ImageBudget::table).collect::<Vec<_>>(), vec![
+            "[budgets.\"default\"]",
+            "[default_budget]",
+        ]);
+    }
+
+    #[test]
+    fn hub_spellings_resolve_alike() {
+        let budgets = budgets(r#"
+            [budgets."docker.io/library/nginx"]
+            budget = "1"
+        "#);
+        assert_eq!(resolution(&budgets, "nginx"), vec![1]);
+        assert_eq!(resolution(&budgets, "library/nginx:1.27"), vec![1]);
+    }
+
+    #[test]
+    fn warn_at_comes_from_the_flag_then_the_key_then_the_default() {
+        let toml = r#"
+            [budgets."api"]
+            budget = "1"
+            warn_at = 80
+            [default_budget]
+            budget = "2"
+        "#;
+        let warn_at = |flag: Option<f64>| -> Vec<f64> {
+            image_budgets(&config(toml), None, flag)
+                .unwrap()
+                .iter()
+                .map(|b| b.warn_at_percent)
+                .collect()
+        };
+        assert_eq!(warn_at(None), vec![80.0, crate::ci::DEFAULT_WARN_AT]);
+        assert_eq!(warn_at(Some(95.0)), vec![95.0, 95.0]);
+    }
+
+    #[test]
+    fn profiles_overlay_keys_and_the_default_budget() {
+        let toml = r#"
+            [budgets."api"]
+            budget = "100"
+            warn_at = 80
+            [default_budget]
+            budget = "600"
+            [profiles.prod.budgets."api"]
+            budget = "50"
+            [profiles.prod.budgets."web"]
+            budget = "70"
+            [profiles.prod.default_budget]
+            warn_at = 70
+        "#;
+        let budgets = image_budgets(&config(toml), Some("prod"), None).unwrap();
+        let entries: Vec<(String, u64, f64)> = budgets
+            .iter()
+            .map(|b| (b.table(), b.bytes, b.warn_at_percent))
+            .collect();
+        assert_eq!(entries, vec![
+            ("[budgets.\"api\"]".to_string(), 50, 80.0),
+            ("[budgets.\"web\"]".to_string(), 70, crate::ci::DEFAULT_WARN_AT),
+            ("[default_budget]".to_string(), 600, 70.0),
+        ]);
+
+        assert!(image_budgets(&config(toml), Some("staging"), None).is_err());
+    }
+
+    #[test]
+    fn a_profile_can_add_the_default_budget() {
+        let toml = r#"
+            [profiles.prod.default_budget]
+            budget = "1"
+        "#;
+        assert!(image_budgets(&config(toml), None, None).unwrap().is_empty());
+        assert_eq!(resolution(&image_budgets(&config(toml), Some("prod"), None).unwrap(), "x"), vec![1]);
+    }
+
+    #[test]
+    fn keys_without_a_size_are_skipped_and_bad_sizes_name_their_table() {
+        let budgets = budgets(r#"
+            [budgets."api"]
+            warn_at = 80
+        "#);
+        assert!(budgets.is_empty());
+
+        let error = image_budgets(&config("[default_budget]\nbudget = \"lots\""), None, None).unwrap_err();
+        assert!(format!("{:#}", error).contains("for [default_budget]"), "{:#}", error);
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::fs;
+
+use crate::models::ImageSnapshot;
+
+/// What dit uses from a `docker buildx build --metadata-file` file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildMetadata {
+    /// `containerimage.config.digest`: the built image's ID
+    pub config_digest: Option<String>,
+    /// `containerimage.digest`: manifest or index digest
+    pub digest: Option<String>,
+    /// From the provenance start and finish times; missing with `--provenance=false`
+    pub duration_ms: Option<u64>,
+}
+
+impl BuildMetadata {
+    /// Whether this build produced `snapshot`; without a config digest it can't tell and assumes so
+    pub fn describes(&self, snapshot: &ImageSnapshot) -> bool {
+        match (&self.config_digest, &snapshot.image_id) {
+            (Some(digest), Some(id)) => digest == id,
+            _ => true,
+        }
+    }
+
+    /// Record the build time on `snapshot`, warning when the metadata is from another build
+    pub fn apply(&self, snapshot: &mut ImageSnapshot) {
+        if !self.describes(snapshot) {
+            eprintln!(
+                "⚠️  Build metadata is for {}, not {} ({}); build time not recorded",
+                self.config_digest.as_deref().unwrap_or_default(),
+                snapshot.image,
+                snapshot.image_id.as_deref().unwrap_or_default()
+            );
+            return;
+        }
+        if self.duration_ms.is_none() {
+            eprintln!("⚠️  Build metadata has no provenance timestamps (built with --provenance=false?); build time not recorded");
+        }
+        snapshot.build_duration_ms = self.duration_ms;
+    }
+}
+
+/// Read a buildx metadata file. `docker buildx bake` files hold one object per target;
+/// those work when there is a single target.
+pub fn read_build_metadata(path: &str) -> Result<BuildMetadata> {
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read build metadata '{}'", path))?;
+    let value: Value = serde_json::from_str(&content)
+        .context(format!("Failed to parse build metadata '{}'", path))?;
+    parse_build_metadata(&value).context(format!("Unexpected build metadata in '{}'", path))
+}
+
+pub fn parse_build_metadata(value: &Value) -> Result<BuildMetadata> {
+    let Some(object) = value.as_object() else {
+        bail!("expected a JSON object");
+    };
+
+    let is_build = |v: &Value| {
+        v.get("containerimage.config.digest").is_some() || v.get("buildx.build.ref").is_some()
+    };
+    let build = if is_build(value) {
+        value
+    } else {
+        let targets: Vec<(&String, &Value)> = object.iter().filter(|(_, v)| is_build(v)).collect();
+        match targets.as_slice() {
+            [(_, build)] => *build,
+            [] => bail!("no containerimage.config.digest or buildx.build.ref found"),
+            _ => bail!(
+                "bake metadata for {} targets ({}); pass a file from a single build",
+                targets.len(),
+                targets.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    };
+
+    let text = |key: &str| build.get(key).and_then(Value::as_str).map(str::to_string);
+
+    Ok(BuildMetadata {
+        config_digest: text("containerimage.config.digest"),
+        digest: text("containerimage.digest"),
+        duration_ms: build.get("buildx.build.provenance").and_then(provenance_duration_ms),
+    })
+}
+
+/// Build time from SLSA v0.2 (`metadata.buildStartedOn`) or v1
+/// (`runDetails.metadata.startedOn`) provenance
+fn provenance_duration_ms(provenance: &Value) -> Option<u64> {
+    let time = |v: &Value| {
+        v.as_str()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|t| t.with_timezone(&Utc))
+    };
+
+    let (started, finished) = match provenance.pointer("/runDetails/metadata") {
+        Some(metadata) => (time(&metadata["startedOn"])?, time(&metadata["finishedOn"])?),
+        None => {
+            let metadata = provenance.get("metadata")?;
+            (time(&metadata["buildStartedOn"])?, time(&metadata["buildFinishedOn"])?)
+        }
+    };
+
+    u64::try_from((finished - started).num_milliseconds()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::snapshot;
+    use serde_json::json;
+
+    const CONFIG: &str = "sha256:2937f66a9722f7f4a2df583de2f8cb97fc9196059a410e7f00072fc918930e66";
+
+    fn fixture(name: &str) -> Result<BuildMetadata> {
+        read_build_metadata(&format!("{}/tests/fixtures/buildx/{}", env!("CARGO_MANIFEST_DIR"), name))
+    }
+
+    #[test]
+    fn buildx_0_10_has_digests_but_no_provenance() {
+        let metadata = fixture("v0.10.json").unwrap();
+
+        assert_eq!(metadata.config_digest.as_deref(), Some(CONFIG));
+        assert_eq!(
+            metadata.digest.as_deref(),
+            Some("sha256:19ffeab6f8bc9293ac2c3fdf94ebe28396254c993aea0b5a542cfb02e0883fa3")
+        );
+        assert_eq!(metadata.duration_ms, None);
+    }
+
+    #[test]
+    fn buildx_0_11_times_the_build_from_slsa_0_2_provenance() {
+        let metadata = fixture("v0.11.json").unwrap();
+
+        assert_eq!(metadata.config_digest.as_deref(), Some(CONFIG));
+        // 09:14:03.518 to 09:15:51.093
+        assert_eq!(metadata.duration_ms, Some(107_574));
+    }
+
+    #[test]
+    fn slsa_v1_provenance_is_timed_too() {
+        let metadata = fixture("v0.17-slsa-v1.json").unwrap();
+
+        assert_eq!(metadata.duration_ms, Some(48_567));
+        assert_eq!(
+            metadata.config_digest.as_deref(),
+            Some("sha256:5c0e4f6a1d8b7e9f2a3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192")
+        );
+    }
+
+    #[test]
+    fn builds_without_provenance_have_no_duration() {
+        let metadata = fixture("provenance-disabled.json").unwrap();
+
+        assert_eq!(metadata.config_digest.as_deref(), Some(CONFIG));
+        assert_eq!(metadata.duration_ms, None);
+    }
+
+    #[test]
+    fn bake_files_work_with_a_single_target() {
+        let metadata = fixture("bake-single.json").unwrap();
+
+        assert_eq!(metadata.config_digest.as_deref(), Some(CONFIG));
+        assert_eq!(metadata.duration_ms, Some(125_500));
+
+        let error = format!("{:#}", fixture("bake-multi.json").unwrap_err());
+        assert!(error.contains("bake metadata for 2 targets (api, worker)"), "{}", error);
+    }
+
+    #[test]
+    fn unexpected_files_are_errors() {
+        for value in [json!([]), json!({}), json!({ "image.name": "app" })] {
+            assert!(parse_build_metadata(&value).is_err(), "{}", value);
+        }
+        assert!(fixture("missing.json").is_err());
+    }
+
+    #[test]
+    fn unreadable_provenance_times_are_ignored() {
+        let value = |started: &str, finished: &str| {
+            json!({
+                "buildx.build.ref": "builder/builder0/x",
+                "buildx.build.provenance": {
+                    "metadata": { "buildStartedOn": started, "buildFinishedOn": finished }
+                }
+            })
+        };
+
+        let duration = |v| parse_build_metadata(&v).unwrap().duration_ms;
+        assert_eq!(duration(value("2024-01-01T00:00:00Z", "2024-01-01T00:00:01Z")), Some(1000));
+        assert_eq!(duration(value("yesterday", "2024-01-01T00:00:01Z")), None);
+        // A clock that went backwards
+        assert_eq!(duration(value("2024-01-01T00:00:01Z", "2024-01-01T00:00:00Z")), None);
+    }
+
+    #[test]
+    fn build_time_is_only_recorded_for_the_image_that_was_built() {
+        let metadata = fixture("v0.11.json").unwrap();
+
+        let mut built = snapshot("ghcr.io/acme/api", "1.4.0", Vec::new());
+        built.image_id = Some(CONFIG.to_string());
+        metadata.apply(&mut built);
+        assert_eq!(built.build_duration_ms, Some(107_574));
+
+        let mut other = snapshot("ghcr.io/acme/api", "1.4.0", Vec::new());
+        other.image_id = Some("sha256:other".to_string());
+        assert!(!metadata.describes(&other));
+        metadata.apply(&mut other);
+        assert_eq!(other.build_duration_ms, None);
+
+        // No image ID to check against
+        let mut unknown = snapshot("ghcr.io/acme/api", "1.4.0", Vec::new());
+        unknown.image_id = None;
+        metadata.apply(&mut unknown);
+        assert_eq!(unknown.build_duration_ms, Some(107_574));
+    }
+}
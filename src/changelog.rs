@@ -0,0 +1,313 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+
+use crate::diff::compute_diff;
+use crate::format::{format_size, format_size_delta, OutputStyle};
+use crate::github::{GitHubClient, GitHubContext};
+use crate::models::{ImageSnapshot, LayerChange, LayerInfo};
+use crate::store::Store;
+use crate::track::resolve_commit;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ChangelogFormat {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug)]
+pub struct ChangelogOptions {
+    /// Tag or commit the entry starts from
+    pub since: String,
+    pub format: ChangelogFormat,
+    /// Layer changes listed, largest first
+    pub top: usize,
+    /// Append the markdown to the latest draft GitHub release
+    pub github_release: bool,
+    pub output: OutputStyle,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangelogEntry {
+    pub image: String,
+    pub tag: Option<String>,
+    /// `--since` as given
+    pub since: String,
+    pub since_commit: String,
+    pub commit: String,
+    pub previous_size: u64,
+    pub current_size: u64,
+    pub delta: i64,
+    /// Largest layer changes between the two snapshots
+    pub changes: Vec<ChangelogChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangelogChange {
+    pub kind: String,
+    pub command: String,
+    pub delta: i64,
+    /// Tracked commit where the change first appeared, when it could be found
+    pub commit: Option<String>,
+    pub commit_message: Option<String>,
+}
+
+/// Build the entry from the snapshot at `since` to the latest one. `walk` is every tracked
+/// snapshot in between, oldest first, used to find the commit behind each layer change.
+pub fn build_entry(since: &str, walk: &[&ImageSnapshot], top: usize) -> Result<ChangelogEntry> {
+    let (Some(&before), Some(&after)) = (walk.first(), walk.last()) else {
+        bail!("No snapshots to compare");
+    };
+
+    let diff = compute_diff(before.clone(), after.clone());
+    let mut changes: Vec<&LayerChange> = diff
+        .layer_changes
+        .iter()
+        .filter(|c| !matches!(c, LayerChange::Unchanged(_) | LayerChange::Reordered { .. }))
+        .collect();
+    changes.sort_by_key(|c| std::cmp::Reverse(c.size_delta().abs()));
+
+    let changes = changes
+        .into_iter()
+        .take(top)
+        .map(|change| {
+            let introduced = introduced_by(walk, change);
+            ChangelogChange {
+                kind: change.kind().to_string(),
+                command: change.layer().command.clone(),
+                delta: change.size_delta(),
+                commit: introduced.map(|s| s.commit_sha.clone()),
+                commit_message: introduced.map(|s| s.commit_message.clone()),
+            }
+        })
+        .collect();
+
+    Ok(ChangelogEntry {
+        image: after.image.clone(),
+        tag: after.tag.clone(),
+        since: since.to_string(),
+        since_commit: before.commit_sha.clone(),
+        commit: after.commit_sha.clone(),
+        previous_size: diff.before_size(),
+        current_size: diff.after_size(),
+        delta: diff.total_delta,
+        changes,
+    })
+}
+
+/// First snapshot after the start of `walk` where `change` shows up: the added layer
+/// appears, or the removed layer is gone
+fn introduced_by<'a>(walk: &[&'a ImageSnapshot], change: &LayerChange) -> Option<&'a ImageSnapshot> {
+    let contains = |snapshot: &ImageSnapshot, layer: &LayerInfo| {
+        snapshot
+            .layers
+            .iter()
+            .any(|l| l.digest == layer.digest && l.size == layer.size)
+    };
+
+    walk.iter().skip(1).copied().find(|snapshot| match change {
+        LayerChange::Added(layer) | LayerChange::Modified { after: layer, .. } => {
+            contains(snapshot, layer)
+        }
+        LayerChange::Removed(layer) => !contains(snapshot, layer),
+        LayerChange::Unchanged(_) | LayerChange::Reordered { .. } => false,
+    })
+}
+
+pub fn render_markdown(entry: &ChangelogEntry, plain: bool) -> String {
+    let mut out = format!(
+        "### 🐋 `{}:{}`: {} → {} ({})\n\n",
+        entry.image,
+        entry.tag.as_deref().unwrap_or("latest"),
+        format_size(entry.previous_size),
+        format_size(entry.current_size),
+        format_size_delta(entry.delta, plain)
+    );
+
+    out.push_str(&format!(
+        "Since {} ({})",
+        entry.since,
+        short_sha(&entry.since_commit)
+    ));
+
+    if entry.changes.is_empty() {
+        out.push_str(": no layer changes\n");
+        return out;
+    }
+    out.push_str(":\n\n");
+
+    for change in &entry.changes {
+        out.push_str(&format!(
+            "- {} `{}`",
+            format_size_delta(change.delta, plain),
+            change.command.replace('`', "'")
+        ));
+        if let Some(commit) = &change.commit {
+            out.push_str(&format!(" ({}", short_sha(commit)));
+            if let Some(message) = change.commit_message.as_deref().filter(|m| !m.is_empty()) {
+                out.push_str(&format!(" {}", message));
+            }
+            out.push(')');
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+fn short_sha(sha: &str) -> String {
+    sha.chars().take(7).collect()
+}
+
+/// `dit changelog-entry`
+pub async fn changelog_entry(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    options: &ChangelogOptions,
+) -> Result<()> {
+    let history = store.load_history_for_image(image)?;
+    let mut image_history: Vec<&ImageSnapshot> = history.iter().collect();
+    image_history.sort_by_key(|s| s.timestamp);
+
+    let Some(&current) = image_history.last() else {
+        bail!("No history found for image '{}'", image);
+    };
+
+    // Tags and short SHAs resolve through git; outside a repository the SHA prefix is used as is
+    let since_sha = resolve_commit(&options.since).unwrap_or_else(|_| options.since.clone());
+    let start = image_history
+        .iter()
+        .rposition(|s| s.commit_sha.starts_with(&since_sha))
+        .context(format!(
+            "No snapshot of '{}' for '{}'. Track that commit first (git checkout {} && dit track {})",
+            image, options.since, options.since, image
+        ))?;
+
+    // The reference point, then the current snapshot's branch up to now
+    let walk: Vec<&ImageSnapshot> = image_history[start..]
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| *i == 0 || s.branch == current.branch)
+        .map(|(_, s)| *s)
+        .collect();
+
+    let entry = build_entry(&options.since, &walk, options.top)?;
+    let markdown = render_markdown(&entry, options.output.plain);
+
+    match options.format {
+        ChangelogFormat::Markdown => write!(out, "{}", markdown)?,
+        ChangelogFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&entry)?)?,
+    }
+
+    if options.github_release {
+        let ctx = GitHubContext::from_env()?;
+        let client = GitHubClient::new(ctx.token, ctx.repo);
+        client.append_to_draft_release(out, &markdown).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+    use std::path::Path;
+
+    const MB: u64 = 1024 * 1024;
+
+    /// base, then curl installed at c1, then the app layer rebuilt bigger (a new digest)
+    /// and the cache layer dropped at c2
+    fn walk() -> Vec<ImageSnapshot> {
+        let base = layer("sha256:base", 80 * MB, "FROM debian:12");
+        let cache = layer("sha256:cache", 3 * MB, "RUN apt-get update");
+        let app = layer("sha256:app-1", 20 * MB, "COPY `dist` /app");
+        let curl = layer("sha256:curl", 5 * MB, "RUN apt-get install -y curl");
+        let app_2 = layer("sha256:app-2", 24 * MB, "COPY `dist` /app");
+
+        let mut c0 = tracked("api", "v1", "0000000aaaa", "main", 0, vec![base.clone(), cache.clone(), app.clone()]);
+        c0.commit_message = "Release 1.0".to_string();
+        let mut c1 = tracked("api", "v1", "1111111bbbb", "main", 1, vec![base.clone(), cache, app, curl.clone()]);
+        c1.commit_message = "Add curl for health checks".to_string();
+        let mut c2 = tracked("api", "v1", "2222222cccc", "main", 2, vec![base, app_2, curl]);
+        c2.commit_message = "Bundle the admin UI".to_string();
+        vec![c0, c1, c2]
+    }
+
+    /// Drop colour codes: `format_size_delta` colours deltas when stdout is a terminal
+    fn strip_ansi(text: &str) -> String {
+        regex::Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(text, "").into_owned()
+    }
+
+    fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/changelog").join(name);
+        let expected = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(actual, expected, "{} is out of date", path.display());
+    }
+
+    #[test]
+    fn entry_lists_the_largest_changes_with_their_commits() {
+        let walk = walk();
+        let walk: Vec<&ImageSnapshot> = walk.iter().collect();
+        let entry = build_entry("v1.0.0", &walk, 10).unwrap();
+
+        assert_eq!(entry.previous_size, 103 * MB);
+        assert_eq!(entry.current_size, 109 * MB);
+        assert_eq!(entry.delta, 6 * MB as i64);
+        let changes: Vec<(&str, Option<&str>)> = entry
+            .changes
+            .iter()
+            .map(|c| (c.kind.as_str(), c.commit.as_deref()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("added", Some("2222222cccc")),
+                ("removed", Some("2222222cccc")),
+                ("added", Some("1111111bbbb")),
+                ("removed", Some("2222222cccc")),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_limits_the_changes() {
+        let walk = walk();
+        let walk: Vec<&ImageSnapshot> = walk.iter().collect();
+        let entry = build_entry("v1.0.0", &walk, 1).unwrap();
+        assert_eq!(entry.changes.len(), 1);
+        assert_eq!(entry.changes[0].delta, 24 * MB as i64);
+    }
+
+    #[test]
+    fn markdown_matches_golden() {
+        let walk = walk();
+        let walk: Vec<&ImageSnapshot> = walk.iter().collect();
+        let entry = build_entry("v1.0.0", &walk, 10).unwrap();
+        assert_golden("entry.md", &strip_ansi(&render_markdown(&entry, false)));
+    }
+
+    #[test]
+    fn unchanged_markdown_matches_golden() {
+        let walk = walk();
+        let walk = [&walk[0], &walk[0]];
+        let entry = build_entry("v1.0.0", &walk, 10).unwrap();
+        assert_golden("unchanged.md", &strip_ansi(&render_markdown(&entry, false)));
+    }
+
+    #[test]
+    fn json_matches_golden() {
+        let walk = walk();
+        let walk: Vec<&ImageSnapshot> = walk.iter().collect();
+        let entry = build_entry("v1.0.0", &walk, 10).unwrap();
+        let json = serde_json::to_string_pretty(&entry).unwrap() + "\n";
+        assert_golden("entry.json", &json);
+    }
+
+    #[test]
+    fn empty_walk_is_an_error() {
+        assert!(build_entry("v1.0.0", &[], 10).is_err());
+    }
+}
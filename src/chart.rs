@@ -1,32 +1,103 @@
 use anyhow::{bail, Result};
-use bytesize::ByteSize;
 use colored::Colorize;
 use std::collections::HashMap;
+use std::io::Write;
+use tabled::builder::Builder;
+
+use crate::diff::{select_basis, BasisChoice};
+use crate::format::{format_change, format_size, format_size_delta, render_table};
+use crate::history::branch_series;
+use crate::metric::Metric;
+use crate::models::{ImageSnapshot, SizeBasis};
+use crate::store::Store;
+
+const UNICODE_SPARKLINE: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const ASCII_SPARKLINE: [char; 7] = ['.', '_', '.', '-', '+', '|', '^'];
+
+/// Character set for sparklines, bars and chart decorations
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SparklineStyle {
+    #[default]
+    Unicode,
+    /// Pure ASCII, for CI log viewers that can't render UTF-8 block characters
+    Ascii,
+    /// `--plain`: sentences instead of sparklines and bars
+    Plain,
+}
 
-use crate::format::format_size;
-use crate::models::ImageSnapshot;
-use crate::track::load_history;
+impl SparklineStyle {
+    /// `Plain` with `--plain`, `Ascii` when `--ascii` is passed or `DIT_ASCII=1` is set
+    pub fn select(ascii: bool, plain: bool) -> Self {
+        if plain {
+            SparklineStyle::Plain
+        } else if ascii || std::env::var("DIT_ASCII").is_ok_and(|v| v == "1") {
+            SparklineStyle::Ascii
+        } else {
+            SparklineStyle::Unicode
+        }
+    }
 
-const SPARKLINE_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    pub fn sparkline_chars(self) -> &'static [char] {
+        match self {
+            SparklineStyle::Unicode => &UNICODE_SPARKLINE,
+            SparklineStyle::Ascii | SparklineStyle::Plain => &ASCII_SPARKLINE,
+        }
+    }
 
-/// Show bar chart for a single image
-pub async fn show_chart(image: &str, last: Option<usize>) -> Result<()> {
-    let history = load_history()?;
+    pub fn bar(self) -> &'static str {
+        match self {
+            SparklineStyle::Unicode => "█",
+            SparklineStyle::Ascii => "#",
+            SparklineStyle::Plain => "",
+        }
+    }
 
-    if history.is_empty() {
-        bail!("No history found. Run 'dit track' first.");
+    /// Column separator between commit and bar
+    pub fn separator(self) -> &'static str {
+        match self {
+            SparklineStyle::Unicode => "│",
+            SparklineStyle::Ascii | SparklineStyle::Plain => "|",
+        }
     }
 
-    // Filter history for the specified image
-    let mut image_history: Vec<_> = history
-        .into_iter()
-        .filter(|s| s.image == image)
-        .collect();
+    /// Dash used in titles and empty cells
+    pub fn dash(self) -> &'static str {
+        match self {
+            SparklineStyle::Unicode => "—",
+            SparklineStyle::Ascii | SparklineStyle::Plain => "-",
+        }
+    }
+}
+
+/// Show bar chart for a single image
+pub async fn show_chart(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    last: Option<usize>,
+    style: SparklineStyle,
+    metric: Metric,
+) -> Result<()> {
+    let image_history = store.load_history_for_image(image)?;
+
+    if image_history.is_empty() && store.load_history()?.is_empty() {
+        bail!("No history found. Run 'dit track' first.");
+    }
 
     if image_history.is_empty() {
         bail!("No history found for image '{}'", image);
     }
 
+    let mut image_history = metric.recorded(&image_history);
+    if image_history.is_empty() {
+        bail!(
+            "No build times recorded for '{}'; track it with --build-metadata <buildx metadata file>",
+            image
+        );
+    }
+    let value = |s: &ImageSnapshot| metric.value(s).unwrap_or_default();
+    let title = format!("{} {} {} History", image, style.dash(), metric.label());
+
     // Sort by timestamp (oldest first)
     image_history.sort_by_key(|s| s.timestamp);
 
@@ -37,28 +108,36 @@ pub async fn show_chart(image: &str, last: Option<usize>) -> Result<()> {
         image_history = image_history[start..].to_vec();
     }
 
+    if style == SparklineStyle::Plain {
+        print_chart_records(out, image, &image_history, metric)?;
+        return Ok(());
+    }
+
     // Handle single snapshot
     if image_history.len() == 1 {
         let snapshot = &image_history[0];
         let commit_short = snapshot.commit_sha.chars().take(7).collect::<String>();
-        println!("\n{}", format!("{} — Size History", image).bold().underline());
-        println!(
-            "\n  {} │ {} {}",
+        writeln!(out, "\n{}", title.bold().underline())?;
+        writeln!(
+            out,
+            "\n  {} {} {} {}",
             commit_short.bright_cyan(),
-            "█".repeat(40),
-            format_size(snapshot.total_size).bold()
-        );
-        println!("\n{}", "Only one snapshot available. Track more commits to see trends!".dimmed());
+            style.separator(),
+            style.bar().repeat(40),
+            metric.format(value(snapshot)).bold()
+        )?;
+        let hint = "Only one snapshot available. Track more commits to see trends!";
+        writeln!(out, "\n{}", hint.dimmed())?;
         return Ok(());
     }
 
     // Find min and max for scaling
-    let sizes: Vec<u64> = image_history.iter().map(|s| s.total_size).collect();
+    let sizes: Vec<u64> = image_history.iter().map(value).collect();
     let min_size = *sizes.iter().min().unwrap();
     let max_size = *sizes.iter().max().unwrap();
 
-    println!("\n{}", format!("{} — Size History", image).bold().underline());
-    println!();
+    writeln!(out, "\n{}", title.bold().underline())?;
+    writeln!(out)?;
 
     // Draw bar chart
     for (i, snapshot) in image_history.iter().enumerate() {
@@ -68,47 +147,89 @@ pub async fn show_chart(image: &str, last: Option<usize>) -> Result<()> {
         let bar_width = if max_size == min_size {
             40
         } else {
-            let normalized = (snapshot.total_size - min_size) as f64 / (max_size - min_size) as f64;
+            let normalized = (value(snapshot) - min_size) as f64 / (max_size - min_size) as f64;
             ((normalized * 40.0).round() as usize).max(1)
         };
 
         // Calculate delta from previous
         let (delta_str, bar_color) = if i > 0 {
-            let prev_size = image_history[i - 1].total_size;
-            let delta = snapshot.total_size as i64 - prev_size as i64;
+            let prev_size = value(&image_history[i - 1]);
+            let delta = value(snapshot) as i64 - prev_size as i64;
             
             if delta > 0 {
-                let delta_display = format!(" (+{})", ByteSize(delta as u64).to_string_as(true));
-                (delta_display.red().to_string(), "█".red())
+                (format!(" ({})", metric.format_delta(delta, false)), style.bar().red())
             } else if delta < 0 {
-                let delta_display = format!(" (-{})", ByteSize((-delta) as u64).to_string_as(true));
-                (delta_display.green().to_string(), "█".green())
+                (format!(" ({})", metric.format_delta(delta, false)), style.bar().green())
             } else {
-                ("".to_string(), "█".normal())
+                ("".to_string(), style.bar().normal())
             }
         } else {
-            ("".to_string(), "█".normal())
+            ("".to_string(), style.bar().normal())
         };
 
         let bar = bar_color.to_string().repeat(bar_width);
-        let size_str = format_size(snapshot.total_size).bold();
+        let size_str = metric.format(value(snapshot)).bold();
 
-        println!(
-            "  {} │ {} {}{}",
+        writeln!(
+            out,
+            "  {} {} {} {}{}",
             commit_short.bright_cyan(),
+            style.separator(),
             bar,
             size_str,
             delta_str
-        );
+        )?;
     }
 
-    println!();
+    writeln!(out)?;
+    Ok(())
+}
+
+/// `dit chart --plain`: one record per snapshot instead of bars
+fn print_chart_records(
+    out: &mut impl Write,
+    image: &str,
+    snapshots: &[ImageSnapshot],
+    metric: Metric,
+) -> Result<()> {
+    writeln!(
+        out,
+        "\n{} {} history, {}",
+        image,
+        metric.label().to_lowercase(),
+        trend_statement(snapshots, metric)
+    )?;
+
+    let value = |s: &ImageSnapshot| metric.value(s).unwrap_or_default();
+    let mut builder = Builder::default();
+    builder.push_record(["Commit", metric.label(), "Change"]);
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        let change = match i.checked_sub(1).map(|p| &snapshots[p]) {
+            Some(previous) => {
+                metric.format_delta(value(snapshot) as i64 - value(previous) as i64, true)
+            }
+            None => String::new(),
+        };
+        builder.push_record([
+            snapshot.commit_sha.chars().take(7).collect(),
+            metric.format(value(snapshot)),
+            change,
+        ]);
+    }
+    writeln!(out, "\n{}\n", render_table(builder, true))?;
+
     Ok(())
 }
 
 /// Show sparklines for all tracked images
-pub async fn show_chart_all(last: Option<usize>) -> Result<()> {
-    let history = load_history()?;
+pub async fn show_chart_all(
+    out: &mut impl Write,
+    store: &Store,
+    last: Option<usize>,
+    style: SparklineStyle,
+    metric: Metric,
+) -> Result<()> {
+    let history = store.load_history()?;
 
     if history.is_empty() {
         bail!("No history found. Run 'dit track' first.");
@@ -119,18 +240,22 @@ pub async fn show_chart_all(last: Option<usize>) -> Result<()> {
 
     for snapshot in history {
         let key = snapshot.image.clone();
-        by_image.entry(key).or_insert_with(Vec::new).push(snapshot);
+        by_image.entry(key).or_default().push(snapshot);
     }
 
     // Sort each image's snapshots by timestamp
     for snapshots in by_image.values_mut() {
-        snapshots.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        snapshots.sort_by_key(|s| s.timestamp);
     }
 
     let limit = last.unwrap_or(10);
     
-    println!("\n{}", format!("Image Trends (last {} snapshots)", limit).bold().underline());
-    println!();
+    let heading = match metric {
+        Metric::Size => "Image Trends",
+        Metric::BuildTime => "Build Time Trends",
+    };
+    writeln!(out, "\n{}", format!("{} (last {} snapshots)", heading, limit).bold().underline())?;
+    writeln!(out)?;
 
     // Convert to sorted vector for consistent output
     let mut images: Vec<_> = by_image.iter().collect();
@@ -140,6 +265,7 @@ pub async fn show_chart_all(last: Option<usize>) -> Result<()> {
     let max_name_len = images.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
 
     for (image_name, snapshots) in images {
+        let snapshots = metric.recorded(snapshots);
         if snapshots.is_empty() {
             continue;
         }
@@ -148,27 +274,50 @@ pub async fn show_chart_all(last: Option<usize>) -> Result<()> {
         let recent_count = snapshots.len().min(limit);
         let recent = &snapshots[snapshots.len() - recent_count..];
 
-        let sparkline = generate_sparkline(recent);
+        let sparkline = generate_sparkline(recent, metric, style);
         
         let latest = recent.last().unwrap();
         let first = recent.first().unwrap();
+        let latest_value = metric.format(metric.value(latest).unwrap_or_default());
         
         // Calculate overall change
         let (change_str, change_color) = if recent.len() > 1 {
-            let total_delta = latest.total_size as i64 - first.total_size as i64;
-            let percent = if first.total_size > 0 {
-                (total_delta as f64 / first.total_size as f64) * 100.0
+            let (before, after, note) = match metric {
+                Metric::Size => {
+                    // A registry-only snapshot next to a local one has no common basis
+                    let basis = select_basis(first, latest, BasisChoice::Auto).ok();
+                    let size = |s: &ImageSnapshot| basis.and_then(|b| b.size_of(s)).unwrap_or_else(|| s.size());
+                    let note = match basis {
+                        Some(SizeBasis::Compressed) => ", compressed",
+                        Some(SizeBasis::Uncompressed) => "",
+                        None => ", mixed registry and local sizes",
+                    };
+                    (size(first), size(latest), note)
+                }
+                Metric::BuildTime => (
+                    metric.value(first).unwrap_or_default(),
+                    metric.value(latest).unwrap_or_default(),
+                    "",
+                ),
+            };
+            let total_delta = after as i64 - before as i64;
+            let percent = if before > 0 {
+                (total_delta as f64 / before as f64) * 100.0
             } else {
                 0.0
             };
 
-            if total_delta.abs() < 1024 * 10 {
-                // Less than 10KB change
+            if metric.is_stable(total_delta) {
                 ("(stable)".dimmed().to_string(), "stable")
+            } else if style == SparklineStyle::Plain && metric == Metric::Size {
+                (format!("({}{})", format_change(total_delta, percent, true), note), "")
+            } else if style == SparklineStyle::Plain {
+                let delta = metric.format_delta(total_delta, true);
+                (format!("({}, {:.1} percent)", delta, percent.abs()), "")
             } else if total_delta > 0 {
-                (format!("(+{:.1}%)", percent).red().to_string(), "increase")
+                (format!("(+{:.1}%{})", percent, note).red().to_string(), "increase")
             } else {
-                (format!("({:.1}%)", percent).green().to_string(), "decrease")
+                (format!("({:.1}%{})", percent, note).green().to_string(), "decrease")
             }
         } else {
             ("".to_string(), "stable")
@@ -181,31 +330,152 @@ pub async fn show_chart_all(last: Option<usize>) -> Result<()> {
             _ => sparkline.dimmed().to_string(),
         };
 
-        println!(
+        if style == SparklineStyle::Plain {
+            let line = format!("{}: {}, {} {}", image_name, latest_value, sparkline, change_str);
+            writeln!(out, "{}", line.trim_end())?;
+            continue;
+        }
+
+        writeln!(
+            out,
             "  {:<width$}  {}  {} {}",
             image_name.bright_cyan(),
             colored_sparkline,
-            format_size(latest.total_size).bold(),
+            latest_value.bold(),
             change_str,
             width = max_name_len
-        );
+        )?;
     }
 
-    println!();
+    writeln!(out)?;
     Ok(())
 }
 
-/// Generate sparkline from snapshots
-pub fn generate_sparkline(snapshots: &[ImageSnapshot]) -> String {
-    if snapshots.is_empty() {
+/// Show one sparkline per branch for a single image, plus the gap between branch heads
+pub async fn show_chart_by_branch(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    last: Option<usize>,
+    style: SparklineStyle,
+) -> Result<()> {
+    let history = store.load_history()?;
+
+    if history.is_empty() {
+        bail!("No history found. Run 'dit track' first.");
+    }
+
+    let limit = last.unwrap_or(20);
+    let series = branch_series(&history, image, &[], limit);
+
+    if series.is_empty() {
+        bail!("No history found for image '{}'", image);
+    }
+
+    writeln!(
+        out,
+        "\n{}",
+        format!("{} {} Size by Branch (last {} snapshots each)", image, style.dash(), limit)
+            .bold()
+            .underline()
+    )?;
+    writeln!(out)?;
+
+    let max_name_len = series.iter().map(|s| s.branch.len()).max().unwrap_or(0);
+
+    for branch in &series {
+        // Pad short series so sizes line up across branches
+        let sparkline = calculate_trend_with_sparkline(&branch.snapshots, limit, Metric::Size, style);
+        let head = branch.head().unwrap();
+        let first = branch.snapshots.first().unwrap();
+
+        let colored_sparkline = if head.size() > first.size() {
+            sparkline.red().to_string()
+        } else if head.size() < first.size() {
+            sparkline.green().to_string()
+        } else {
+            sparkline.dimmed().to_string()
+        };
+
+        if style == SparklineStyle::Plain {
+            writeln!(
+                out,
+                "{}: {}, {}",
+                branch.branch,
+                format_size(head.size()),
+                generate_sparkline(&branch.snapshots, Metric::Size, style)
+            )?;
+            continue;
+        }
+
+        writeln!(
+            out,
+            "  {:<width$}  {}  {} {}",
+            branch.branch.bright_cyan(),
+            colored_sparkline,
+            format_size(head.size()).bold(),
+            format!("({} snapshots)", branch.snapshots.len()).dimmed(),
+            width = max_name_len
+        )?;
+    }
+
+    if style != SparklineStyle::Plain {
+        writeln!(
+            out,
+            "\n  {}",
+            format!(
+                "Legend: one row per branch, oldest {} newest, scaled per branch",
+                match style {
+                    SparklineStyle::Unicode => "→",
+                    SparklineStyle::Ascii | SparklineStyle::Plain => "->",
+                }
+            )
+            .dimmed()
+        )?;
+    }
+
+    if series.len() > 1 {
+        writeln!(out, "\n{}", "Head gaps".bold())?;
+        for (i, a) in series.iter().enumerate() {
+            for b in &series[i + 1..] {
+                let delta = b.head().unwrap().size() as i64 - a.head().unwrap().size() as i64;
+                writeln!(
+                    out,
+                    "  {} vs {}: {}",
+                    b.branch.bright_cyan(),
+                    a.branch.bright_cyan(),
+                    format_size_delta(delta, style == SparklineStyle::Plain)
+                )?;
+            }
+        }
+    } else {
+        writeln!(out, "\n{}", "Only one branch tracked for this image.".dimmed())?;
+    }
+
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Generate sparkline of `metric` from snapshots (those without it are skipped);
+/// [`trend_statement`] with `Plain`
+pub fn generate_sparkline(snapshots: &[ImageSnapshot], metric: Metric, style: SparklineStyle) -> String {
+    if style == SparklineStyle::Plain {
+        return trend_statement(snapshots, metric);
+    }
+
+    let chars = style.sparkline_chars();
+    let middle = chars[chars.len() / 2];
+
+    let sizes: Vec<u64> = snapshots.iter().filter_map(|s| metric.value(s)).collect();
+
+    if sizes.is_empty() {
         return "".to_string();
     }
 
-    if snapshots.len() == 1 {
-        return SPARKLINE_CHARS[4].to_string();
+    if sizes.len() == 1 {
+        return middle.to_string();
     }
 
-    let sizes: Vec<u64> = snapshots.iter().map(|s| s.total_size).collect();
     let min_size = *sizes.iter().min().unwrap();
     let max_size = *sizes.iter().max().unwrap();
 
@@ -213,29 +483,78 @@ pub fn generate_sparkline(snapshots: &[ImageSnapshot]) -> String {
         .iter()
         .map(|&size| {
             if max_size == min_size {
-                SPARKLINE_CHARS[4] // Middle character if all same
+                middle // Middle character if all same
             } else {
                 let normalized = (size - min_size) as f64 / (max_size - min_size) as f64;
-                let index = (normalized * (SPARKLINE_CHARS.len() - 1) as f64).round() as usize;
-                SPARKLINE_CHARS[index.min(SPARKLINE_CHARS.len() - 1)]
+                let index = (normalized * (chars.len() - 1) as f64).round() as usize;
+                chars[index.min(chars.len() - 1)]
             }
         })
         .collect()
 }
 
+/// How `metric` moved across `snapshots`, in words: "grew in 4 of the last 5 snapshots"
+pub fn trend_statement(snapshots: &[ImageSnapshot], metric: Metric) -> String {
+    let values: Vec<u64> = snapshots.iter().filter_map(|s| metric.value(s)).collect();
+    if values.len() < 2 {
+        return format!("{} snapshot", values.len());
+    }
+
+    let count = values.len();
+    let grew = values.windows(2).filter(|w| w[1] > w[0]).count();
+    let shrank = values.windows(2).filter(|w| w[1] < w[0]).count();
+
+    match (grew, shrank) {
+        (0, 0) => format!("unchanged over the last {} snapshots", count),
+        (grew, 0) => format!("grew in {} of the last {} snapshots", grew, count),
+        (0, shrank) => format!("shrank in {} of the last {} snapshots", shrank, count),
+        (grew, shrank) => format!(
+            "grew in {} and shrank in {} of the last {} snapshots",
+            grew, shrank, count
+        ),
+    }
+}
+
 /// Calculate trend description for summary
-pub fn calculate_trend_with_sparkline(snapshots: &[ImageSnapshot], sparkline_count: usize) -> String {
+pub fn calculate_trend_with_sparkline(
+    snapshots: &[ImageSnapshot],
+    sparkline_count: usize,
+    metric: Metric,
+    style: SparklineStyle,
+) -> String {
+    let snapshots = metric.recorded(snapshots);
     if snapshots.is_empty() {
-        return format!("{:width$}", "—", width = sparkline_count);
+        return format!("{:width$}", style.dash(), width = sparkline_count);
     }
 
     // Take last N snapshots for sparkline
     let recent_count = snapshots.len().min(sparkline_count);
     let recent = &snapshots[snapshots.len() - recent_count..];
 
-    let sparkline = generate_sparkline(recent);
+    let sparkline = generate_sparkline(recent, metric, style);
 
     // Pad sparkline to fixed width so table columns align properly
     // Do NOT add ANSI colors here — they break tabled's width calculation
     format!("{:<width$}", sparkline, width = sparkline_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+
+    #[test]
+    fn short_branch_series_are_padded_to_the_chart_width() {
+        let snapshots: Vec<ImageSnapshot> = (0..3)
+            .map(|i| tracked("myapp", "1", "c", "main", i, vec![layer("sha256:a", 100 * (i as u64 + 1), "")]))
+            .collect();
+
+        let sparkline = calculate_trend_with_sparkline(&snapshots, 10, Metric::Size, SparklineStyle::Unicode);
+        assert_eq!(sparkline.chars().count(), 10);
+        assert!(sparkline.starts_with("▁"), "{:?}", sparkline);
+        assert!(sparkline.trim_end().ends_with('█'), "{:?}", sparkline);
+
+        let empty = calculate_trend_with_sparkline(&[], 10, Metric::Size, SparklineStyle::Ascii);
+        assert_eq!(empty.chars().count(), 10);
+    }
+}
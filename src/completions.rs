@@ -0,0 +1,153 @@
+use anyhow::Result;
+use clap::{Command, ValueEnum};
+use clap_complete::{generate, Shell};
+use std::io::Write;
+
+/// What `dit __complete` suggests
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompletionKind {
+    /// Cached local images and tracked image names
+    Images,
+}
+
+/// Completes the image right after analyze, history, diff, chart or track from
+/// `dit __complete images`, leaving everything else to clap's `_dit`. Words are re-split
+/// from the line because bash breaks `myapp:latest` at the colon.
+const BASH_IMAGES: &str = r#"
+_dit_images() {
+    local line="${COMP_LINE:0:COMP_POINT}" words i
+    read -ra words <<< "$line"
+    [[ "$line" == *[[:space:]] ]] && words+=("")
+    local cword=$((${#words[@]} - 1))
+    local cur="${words[cword]}"
+    for ((i = 1; i < cword; i++)); do
+        case "${words[i]}" in
+            analyze|history|diff|chart|track)
+                if ((cword == i + 1)) && [[ "$cur" != -* ]]; then
+                    local IFS=$'\n'
+                    COMPREPLY=($(compgen -W "$(dit __complete images "$cur" 2>/dev/null)" -- "$cur"))
+                    if [[ "$cur" == *:* ]]; then
+                        COMPREPLY=("${COMPREPLY[@]#"${cur%:*}:"}")
+                    fi
+                    return 0
+                fi
+                break
+                ;;
+        esac
+    done
+    _dit "$@"
+}
+
+complete -F _dit_images -o bashdefault -o default dit
+"#;
+
+/// The zsh counterpart of [`BASH_IMAGES`]. It takes over the script's own dispatch, so
+/// an autoloaded `_dit` also hands later completions to `_dit_images`.
+const ZSH_IMAGES: &str = r#"
+_dit_images() {
+    local i
+    for ((i = 2; i < CURRENT; i++)); do
+        case ${words[i]} in
+            analyze|history|diff|chart|track)
+                if (( CURRENT == i + 1 )) && [[ ${words[CURRENT]} != -* ]]; then
+                    local -a images
+                    images=(${(f)"$(dit __complete images "${words[CURRENT]}" 2>/dev/null)"})
+                    compadd -a images
+                    return
+                fi
+                break
+                ;;
+        esac
+    done
+    _dit "$@"
+}
+
+if [ "$funcstack[1]" = "_dit" ]; then
+    compdef _dit_images dit
+    _dit_images "$@"
+else
+    compdef _dit_images dit
+fi
+"#;
+
+/// Where clap's zsh script registers `_dit`
+const ZSH_DISPATCH: &str = "\nif [ \"$funcstack[1]\" = \"_dit\" ]; then";
+
+/// `cmd` without its hidden subcommands, which clap's scripts would otherwise offer
+fn visible_commands(cmd: &Command) -> Command {
+    Command::new("dit")
+        // Only so the scripts complete --version
+        .version(env!("CARGO_PKG_VERSION"))
+        .args(cmd.get_arguments().cloned())
+        .subcommands(cmd.get_subcommands().filter(|sub| !sub.is_hide_set()).cloned())
+}
+
+/// Print clap's script for `shell`; the bash and zsh ones also complete image names
+pub fn print_completions(out: &mut impl Write, shell: Shell, cmd: &Command) -> Result<()> {
+    write!(out, "{}", completion_script(shell, cmd)?)?;
+    Ok(())
+}
+
+/// What [`print_completions`] prints
+fn completion_script(shell: Shell, cmd: &Command) -> Result<String> {
+    let mut script = Vec::new();
+    generate(shell, &mut visible_commands(cmd), "dit", &mut script);
+    let mut script = String::from_utf8(script)?;
+
+    match shell {
+        Shell::Bash => script.push_str(BASH_IMAGES),
+        Shell::Zsh => {
+            if let Some(dispatch) = script.rfind(ZSH_DISPATCH) {
+                script.truncate(dispatch);
+            }
+            script.push_str(ZSH_IMAGES);
+        }
+        _ => {}
+    }
+
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stand-in for `dit`'s CLI: a couple of image commands and the hidden helper
+    fn cli() -> Command {
+        Command::new("dit")
+            .subcommand(Command::new("analyze").arg(clap::Arg::new("image")))
+            .subcommand(Command::new("history").arg(clap::Arg::new("image")))
+            .subcommand(Command::new("__complete").hide(true))
+    }
+
+    #[test]
+    fn bash_completes_images_through_the_hidden_helper() {
+        let script = completion_script(Shell::Bash, &cli()).unwrap();
+
+        assert!(script.contains("_dit()"));
+        assert!(script.contains("analyze|history|diff|chart|track)"));
+        assert!(script.contains("dit __complete images \"$cur\""));
+        assert!(script.ends_with("complete -F _dit_images -o bashdefault -o default dit\n"));
+        assert!(!script.contains("dit,__complete"));
+    }
+
+    #[test]
+    fn zsh_dispatches_to_the_image_completer() {
+        let script = completion_script(Shell::Zsh, &cli()).unwrap();
+
+        assert!(script.contains("analyze|history|diff|chart|track)"));
+        assert!(script.contains("dit __complete images \"${words[CURRENT]}\""));
+        // clap's own registration is replaced, not duplicated
+        assert_eq!(script.matches(ZSH_DISPATCH).count(), 1);
+        assert!(script.contains("compdef _dit_images dit"));
+        assert!(!script.contains("compdef _dit dit"));
+    }
+
+    #[test]
+    fn other_shells_get_clap_scripts_without_hidden_commands() {
+        let script = completion_script(Shell::Fish, &cli()).unwrap();
+
+        assert!(script.contains("analyze"));
+        assert!(!script.contains("__complete"));
+    }
+}
@@ -1,18 +1,40 @@
 use anyhow::{Context, Result};
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use tabled::builder::Builder;
 
-use crate::docker::DockerClient;
-use crate::format::format_size;
-use crate::history::show_history;
-use crate::track_all::track_all_images;
+use crate::docker::{ImageFilter, ImageInspector};
+use crate::format::{format_size, render_table, OutputStyle};
+use crate::history::{show_history, HistoryOptions};
+use crate::progress::reporter_for;
+use crate::store::Store;
+use crate::track_all::{track_all_images, TrackAllOptions};
+
+/// Environment variable docker compose takes the project name from
+pub const PROJECT_NAME_ENV: &str = "COMPOSE_PROJECT_NAME";
 
 #[derive(Debug, Deserialize, Serialize)]
 struct ComposeFile {
+    /// Top-level `name:` (compose spec project name)
+    #[serde(default)]
+    name: Option<String>,
     #[serde(default)]
     services: HashMap<String, Service>,
+    /// Project name override, for when the directory name doesn't match the image prefix
+    #[serde(rename = "x-dit-project", default)]
+    dit_project: Option<String>,
+}
+
+/// A service with a `build:` directive
+#[derive(Debug)]
+struct BuildService {
+    name: String,
+    /// Dockerfile location relative to the current directory, e.g. `./services/frontend/Dockerfile`
+    dockerfile_path: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -35,47 +57,67 @@ enum BuildConfig {
     },
 }
 
-pub async fn compose_analyze(file: Option<&str>) -> Result<()> {
+/// Where the compose project name came from, strongest first
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProjectNameSource {
+    Flag,
+    Environment,
+    DitProject,
+    ComposeName,
+    Directory,
+}
+
+impl ProjectNameSource {
+    fn describe(self) -> &'static str {
+        match self {
+            ProjectNameSource::Flag => "from --project-name",
+            ProjectNameSource::Environment => "from COMPOSE_PROJECT_NAME",
+            ProjectNameSource::DitProject => "from x-dit-project",
+            ProjectNameSource::ComposeName => "from the compose file's name:",
+            ProjectNameSource::Directory => "from the compose file's directory",
+        }
+    }
+}
+
+pub async fn compose_analyze(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    file: Option<&str>,
+    project_name: Option<&str>,
+    show_dockerfile_path: bool,
+    output: OutputStyle,
+) -> Result<()> {
     let compose_path = find_compose_file(file)?;
-    let project_name = get_project_name(&compose_path)?;
-    let services = parse_compose_file_internal(&compose_path)?;
+    let compose = load_compose_file(&compose_path)?;
+    let project_name = project_name_for(project_name, &compose_path, &compose);
+    let services = build_services(&compose_path, compose);
 
     if services.is_empty() {
-        println!("No services with build directives found in {}", compose_path.display());
+        writeln!(out, "No services with build directives found in {}", compose_path.display())?;
         return Ok(());
     }
 
-    println!("Found {} services with build directives in {}:\n", 
+    writeln!(out, "Found {} services with build directives in {}:\n", 
         services.len(), 
         compose_path.display()
-    );
-
-    // Build filter pattern for compose images
-    // Docker Compose typically names images as: <project>_<service> or <project>-<service>
-    let mut images = Vec::new();
-    for service in &services {
-        let image_name = format!("{}_{}", project_name, service);
-        let alt_name = format!("{}-{}", project_name, service);
-        images.push(image_name);
-        images.push(alt_name);
-    }
+    )?;
 
     // Try to find matching images
-    let docker = DockerClient::new()?;
-    let all_images = docker.list_all_images(None).await?;
+    // Docker Compose typically names images as: <project>_<service> or <project>-<service>
+    let all_images = docker.list_all_images(None, None).await?;
 
     let mut found_images = Vec::new();
     for image in &all_images {
         for service in &services {
             let patterns = vec![
-                format!("{}_{}", project_name, service),
-                format!("{}-{}", project_name, service),
-                format!("{}/{}", project_name, service),
+                format!("{}_{}", project_name, service.name),
+                format!("{}-{}", project_name, service.name),
+                format!("{}/{}", project_name, service.name),
             ];
 
             for pattern in patterns {
                 if image.to_lowercase().contains(&pattern.to_lowercase()) {
-                    found_images.push(image.clone());
+                    found_images.push((image.clone(), service));
                     break;
                 }
             }
@@ -83,21 +125,38 @@ pub async fn compose_analyze(file: Option<&str>) -> Result<()> {
     }
 
     if found_images.is_empty() {
-        println!("⚠️  No built images found for services: {}", services.join(", "));
-        println!("Run 'docker-compose build' first or check that images are tagged correctly.");
+        let names: Vec<&str> = services.iter().map(|s| s.name.as_str()).collect();
+        writeln!(out, "⚠️  No built images found for services: {}", names.join(", "))?;
+        writeln!(
+            out,
+            "Run 'docker-compose build' first or check that images are tagged correctly."
+        )?;
         return Ok(());
     }
 
-    println!("Analyzing {} compose images...\n", found_images.len());
+    writeln!(out, "Analyzing {} compose images...\n", found_images.len())?;
+
+    let mut header = vec!["Service", "Image", "Size", "Layers"];
+    if show_dockerfile_path {
+        header.push("Dockerfile Path");
+    }
 
-    for image in &found_images {
+    let mut builder = Builder::default();
+    builder.push_record(header);
+
+    for (image, service) in &found_images {
         match docker.inspect_image(image).await {
             Ok(snapshot) => {
-                println!("  {} — {} ({} layers)", 
-                    image, 
+                let mut row = vec![
+                    service.name.clone(),
+                    image.clone(),
                     format_size(snapshot.total_size),
-                    snapshot.layer_count
-                );
+                    snapshot.layer_count.to_string(),
+                ];
+                if show_dockerfile_path {
+                    row.push(service.dockerfile_path.clone());
+                }
+                builder.push_record(row);
             }
             Err(e) => {
                 eprintln!("  ⚠️  {} — Failed: {}", image, e);
@@ -105,22 +164,30 @@ pub async fn compose_analyze(file: Option<&str>) -> Result<()> {
         }
     }
 
+    writeln!(out, "{}", render_table(builder, output.plain))?;
+
     Ok(())
 }
 
-pub async fn compose_track(file: Option<&str>) -> Result<()> {
+pub async fn compose_track(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    store: &Store,
+    file: Option<&str>,
+    project_name: Option<&str>,
+) -> Result<()> {
     let compose_path = find_compose_file(file)?;
-    let project_name = get_project_name(&compose_path)?;
-    let services = parse_compose_file_internal(&compose_path)?;
+    let compose = load_compose_file(&compose_path)?;
+    let project_name = project_name_for(project_name, &compose_path, &compose);
+    let services = build_service_names(&compose_path, compose);
 
     if services.is_empty() {
-        println!("No services with build directives found in {}", compose_path.display());
+        writeln!(out, "No services with build directives found in {}", compose_path.display())?;
         return Ok(());
     }
 
     // Find compose images
-    let docker = DockerClient::new()?;
-    let all_images = docker.list_all_images(None).await?;
+    let all_images = docker.list_all_images(None, None).await?;
 
     let mut found_images = Vec::new();
     for image in &all_images {
@@ -141,38 +208,58 @@ pub async fn compose_track(file: Option<&str>) -> Result<()> {
     }
 
     if found_images.is_empty() {
-        println!("⚠️  No built images found for compose services");
+        writeln!(out, "⚠️  No built images found for compose services")?;
         return Ok(());
     }
 
-    println!("Tracking {} compose images...\n", found_images.len());
+    writeln!(out, "Tracking {} compose images...\n", found_images.len())?;
     
     // Track all found images
-    track_all_images(None).await?;
+    let filter = ImageFilter::default();
+    let options = TrackAllOptions::default();
+    track_all_images(out, docker, store, &filter, reporter_for(None), &options).await?;
 
     Ok(())
 }
 
-pub async fn compose_history(file: Option<&str>) -> Result<()> {
+pub async fn compose_history(
+    out: &mut impl Write,
+    store: &Store,
+    file: Option<&str>,
+    project_name: Option<&str>,
+    show_dockerfile_path: bool,
+    output: OutputStyle,
+) -> Result<()> {
     let compose_path = find_compose_file(file)?;
-    let project_name = get_project_name(&compose_path)?;
-    let services = parse_compose_file_internal(&compose_path)?;
+    let compose = load_compose_file(&compose_path)?;
+    let project_name = project_name_for(project_name, &compose_path, &compose);
+    let services = build_services(&compose_path, compose);
 
     if services.is_empty() {
-        println!("No services with build directives found");
+        writeln!(out, "No services with build directives found")?;
         return Ok(());
     }
 
     // Show history for each service
     for service in &services {
+        if show_dockerfile_path {
+            writeln!(
+                out,
+                "{} {}",
+                format!("{}:", service.name).bold(),
+                service.dockerfile_path.dimmed()
+            )?;
+        }
+
         let patterns = vec![
-            format!("{}_{}", project_name, service),
-            format!("{}-{}", project_name, service),
+            format!("{}_{}", project_name, service.name),
+            format!("{}-{}", project_name, service.name),
         ];
 
         for pattern in patterns {
             // Try to show history for this pattern
-            if let Ok(_) = show_history(&pattern, None).await {
+            let options = HistoryOptions { output, ..Default::default() };
+            if show_history(out, store, &pattern, &options).await.is_ok() {
                 break;
             }
         }
@@ -208,40 +295,204 @@ fn find_compose_file(file: Option<&str>) -> Result<PathBuf> {
     anyhow::bail!("No docker-compose file found in current directory. Use --file to specify a path.")
 }
 
-pub fn parse_compose_file(path: Option<&str>) -> Result<Vec<String>> {
+/// Images `docker compose build` produces for `path`: each build service's `image:`,
+/// or `<project>-<service>` when it has none
+pub fn compose_images(path: Option<&str>, project_name: Option<&str>) -> Result<Vec<String>> {
     let compose_path = find_compose_file(path)?;
-    parse_compose_file_internal(&compose_path)
+    let compose = load_compose_file(&compose_path)?;
+    let project_name = project_name_for(project_name, &compose_path, &compose);
+    Ok(built_images(compose, &project_name))
 }
 
-fn parse_compose_file_internal(path: &Path) -> Result<Vec<String>> {
+/// Each build service's `image:`, or `<project>-<service>` with the project lowercased
+/// the way compose does; services without `build:` are pulled, not built, and skipped
+fn built_images(compose: ComposeFile, project_name: &str) -> Vec<String> {
+    let project_name = project_name.to_lowercase();
+    let mut images: Vec<String> = compose
+        .services
+        .into_iter()
+        .filter(|(_, service)| service.build.is_some())
+        .map(|(name, service)| {
+            service
+                .image
+                .unwrap_or_else(|| format!("{}-{}", project_name, name))
+        })
+        .collect();
+    images.sort();
+    images
+}
+
+fn load_compose_file(path: &Path) -> Result<ComposeFile> {
     let content = fs::read_to_string(path)
         .context(format!("Failed to read compose file: {}", path.display()))?;
 
-    let compose: ComposeFile = serde_yaml::from_str(&content)
-        .context("Failed to parse docker-compose file")?;
+    serde_yaml::from_str(&content).context("Failed to parse docker-compose file")
+}
+
+/// Services with a `build:` directive, sorted by name
+fn build_services(compose_path: &Path, compose: ComposeFile) -> Vec<BuildService> {
+    let base = compose_path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut services_with_build: Vec<BuildService> = compose
+        .services
+        .into_iter()
+        .filter_map(|(name, service)| {
+            let build = service.build?;
+            Some(BuildService {
+                name,
+                dockerfile_path: dockerfile_path(base, &build),
+            })
+        })
+        .collect();
+
+    services_with_build.sort_by(|a, b| a.name.cmp(&b.name));
+    services_with_build
+}
+
+fn build_service_names(compose_path: &Path, compose: ComposeFile) -> Vec<String> {
+    build_services(compose_path, compose)
+        .into_iter()
+        .map(|s| s.name)
+        .collect()
+}
+
+/// Combine the compose file's directory, build context and dockerfile into one path
+fn dockerfile_path(base: &Path, build: &BuildConfig) -> String {
+    let (context, dockerfile) = match build {
+        BuildConfig::Simple(context) => (context.as_str(), None),
+        BuildConfig::Complex {
+            context,
+            dockerfile,
+        } => (context.as_deref().unwrap_or("."), dockerfile.as_deref()),
+    };
+    let dockerfile = dockerfile.unwrap_or("Dockerfile");
+
+    // Remote contexts (git URLs, tarballs) have no local path to show
+    if context.contains("://") || context.starts_with("git@") {
+        return format!("{} ({})", dockerfile, context);
+    }
+
+    // Lexical cleanup of `.` and `dir/..` so the path reads the way you'd type it
+    let mut normalized = PathBuf::new();
+    for component in base.join(context).join(dockerfile).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normalized.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+
+    if normalized.is_absolute() || normalized.starts_with("..") {
+        normalized.display().to_string()
+    } else {
+        format!("./{}", normalized.display())
+    }
+}
 
-    let mut services_with_build = Vec::new();
+/// Resolve the project name and say which source it came from
+fn project_name_for(flag: Option<&str>, compose_path: &Path, compose: &ComposeFile) -> String {
+    let env = std::env::var(PROJECT_NAME_ENV).ok();
+    let (name, source) = resolve_project_name(flag, env.as_deref(), compose_path, compose);
+    eprintln!("{}", format!("Compose project: {} ({})", name, source.describe()).dimmed());
+    name
+}
 
-    for (name, service) in compose.services {
-        if service.build.is_some() {
-            services_with_build.push(name);
+/// `--project-name`, then `COMPOSE_PROJECT_NAME`, then `x-dit-project` and `name:` from
+/// the compose file, then the compose file's directory. Blank values are skipped.
+fn resolve_project_name(
+    flag: Option<&str>,
+    env: Option<&str>,
+    compose_path: &Path,
+    compose: &ComposeFile,
+) -> (String, ProjectNameSource) {
+    let candidates = [
+        (flag, ProjectNameSource::Flag),
+        (env, ProjectNameSource::Environment),
+        (compose.dit_project.as_deref(), ProjectNameSource::DitProject),
+        (compose.name.as_deref(), ProjectNameSource::ComposeName),
+    ];
+    for (name, source) in candidates {
+        if let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) {
+            return (name.to_string(), source);
         }
     }
 
-    Ok(services_with_build)
+    (directory_project_name(compose_path), ProjectNameSource::Directory)
 }
 
-fn get_project_name(compose_path: &Path) -> Result<String> {
+fn directory_project_name(compose_path: &Path) -> String {
     // Get project name from parent directory
     let parent = compose_path
         .parent()
+        .filter(|p| !p.as_os_str().is_empty())
         .unwrap_or_else(|| Path::new("."));
 
-    let project_name = parent
+    parent
+        .canonicalize()
+        .ok()
+        .as_deref()
+        .unwrap_or(parent)
         .file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("project")
-        .to_string();
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(project_name)
+    fn parse(yaml: &str) -> ComposeFile {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn project_name_precedence() {
+        let path = Path::new("/srv/shop/deploy/docker-compose.yml");
+        let both = parse("name: spec\nx-dit-project: dit\nservices: {}\n");
+        let named = parse("name: spec\nservices: {}\n");
+        let bare = parse("services: {}\n");
+
+        let cases = [
+            (Some("flag"), Some("env"), &both, "flag", ProjectNameSource::Flag),
+            (None, Some("env"), &both, "env", ProjectNameSource::Environment),
+            (Some("  "), Some(""), &both, "dit", ProjectNameSource::DitProject),
+            (None, None, &named, "spec", ProjectNameSource::ComposeName),
+            (None, None, &bare, "deploy", ProjectNameSource::Directory),
+        ];
+        for (flag, env, compose, name, source) in cases {
+            assert_eq!(
+                resolve_project_name(flag, env, path, compose),
+                (name.to_string(), source),
+                "flag {:?}, env {:?}",
+                flag,
+                env
+            );
+        }
+    }
+
+    #[test]
+    fn built_images_prefer_image_over_project_default() {
+        let compose = parse(
+            "services:
+  web:
+    build: .
+    image: registry.example.com/shop/web:1.0
+  worker:
+    build:
+      context: ./worker
+  cache:
+    image: redis:7
+",
+        );
+
+        assert_eq!(
+            built_images(compose, "Shop"),
+            vec!["registry.example.com/shop/web:1.0", "shop-worker"]
+        );
+    }
 }
@@ -0,0 +1,383 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use toml::Value;
+
+use crate::remote::RemoteConfig;
+use crate::storage::StorageKind;
+
+const CONFIG_FILE: &str = ".dit.toml";
+
+/// Top-level `.dit.toml` keys this version understands; keep in sync with [`DitConfig`]
+const KNOWN_KEYS: [&str; 13] = [
+    "label_filters",
+    "cost",
+    "cache_history",
+    "budgets",
+    "default_budget",
+    "profiles",
+    "path_budgets",
+    "trigger_paths",
+    "exclude_authors",
+    "exclude_messages",
+    "plain",
+    "storage",
+    "remote",
+];
+
+/// Project settings from `.dit.toml` in the current directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DitConfig {
+    /// `KEY=VALUE` (or bare `KEY`) image labels that must all match for track-all / analyze-all
+    pub label_filters: Vec<String>,
+
+    /// Registry storage pricing; enables the cost line in `dit ci` reports
+    pub cost: Option<CostConfig>,
+
+    /// Same as `--cache-history`
+    pub cache_history: bool,
+
+    /// `[budgets."<key>"]` tables checked by `dit ci`; keys are image names or globs
+    pub budgets: BTreeMap<String, BudgetConfig>,
+
+    /// `[default_budget]`: applies to images no `[budgets]` key matches
+    pub default_budget: Option<BudgetConfig>,
+
+    /// `[profiles.<name>]` overlays selected with `--profile` or `DIT_PROFILE`
+    pub profiles: BTreeMap<String, ProfileConfig>,
+
+    /// `[path_budgets]`: `"/usr/share/doc" = "50MB"`; most each image may ship under a path
+    /// or glob, checked by `dit ci` and shown by `dit analyze --deep`
+    pub path_budgets: BTreeMap<String, String>,
+
+    /// `[trigger_paths]`: `"myapp" = ["services/api/**"]`; `dit ci` skips an image when no
+    /// file matching its globs changed since the baseline. Keys match like `[budgets]` keys.
+    pub trigger_paths: BTreeMap<String, Vec<String>>,
+
+    /// Author globs hidden from history, chart and summary (same as `--exclude-author`)
+    pub exclude_authors: Vec<String>,
+
+    /// Commit message regexes hidden like `exclude_authors` (same as `--exclude-message`)
+    pub exclude_messages: Vec<String>,
+
+    /// Same as `--plain`
+    pub plain: bool,
+
+    /// Same as `--storage`
+    pub storage: StorageKind,
+
+    /// S3-compatible bucket used by `--remote`
+    pub remote: Option<RemoteConfig>,
+}
+
+/// Size budget for one image (`myapp` or `myapp:tag`)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    /// Maximum size, e.g. `500MB`
+    pub budget: Option<String>,
+    /// Same as `--warn-at`, for this image only
+    pub warn_at: Option<f64>,
+}
+
+impl BudgetConfig {
+    /// Take the values `overlay` sets
+    fn overlay(&mut self, overlay: &BudgetConfig) {
+        if overlay.budget.is_some() {
+            self.budget = overlay.budget.clone();
+        }
+        if overlay.warn_at.is_some() {
+            self.warn_at = overlay.warn_at;
+        }
+    }
+}
+
+/// `[profiles.<name>]` table; set values replace the defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub budgets: BTreeMap<String, BudgetConfig>,
+    pub default_budget: Option<BudgetConfig>,
+}
+
+/// `[cost]` table
+#[derive(Debug, Clone, Deserialize)]
+pub struct CostConfig {
+    /// Price per GiB stored per month
+    pub per_gb_month: f64,
+    /// How many tags of an image the registry keeps
+    pub retained_tags: u32,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    /// Report line; `{delta}`, `{total}` and `{tags}` are substituted
+    #[serde(default = "default_cost_format")]
+    pub format: String,
+}
+
+fn default_currency() -> String {
+    "$".to_string()
+}
+
+fn default_cost_format() -> String {
+    "storage cost impact: {delta}/month across {tags} retained tags".to_string()
+}
+
+impl DitConfig {
+    /// Load `.dit.toml`, or defaults when the file doesn't exist
+    pub fn load() -> Result<Self> {
+        let path = PathBuf::from(CONFIG_FILE);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path).context("Failed to read .dit.toml")?;
+        Self::parse(&content, &|name| std::env::var(name).ok())
+    }
+
+    /// Parse `.dit.toml` content, expanding `${VAR}` with `env`
+    fn parse(content: &str, env: &impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut value: Value = toml::from_str(content).context("Failed to parse .dit.toml")?;
+        expand_env(&mut value, "", env)?;
+
+        value.try_into().context("Failed to parse .dit.toml")
+    }
+
+    /// Top-level `.dit.toml` keys this version ignores, e.g. settings added by a newer dit
+    pub fn unknown_keys() -> Result<Vec<String>> {
+        let path = PathBuf::from(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = fs::read_to_string(&path).context("Failed to read .dit.toml")?;
+        let value: Value = toml::from_str(&content).context("Failed to parse .dit.toml")?;
+
+        Ok(value
+            .as_table()
+            .map(|table| {
+                table
+                    .keys()
+                    .filter(|key| !KNOWN_KEYS.contains(&key.as_str()))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Per-image budgets with `[profiles.<profile>]` overlaid on the `[budgets]` defaults
+    pub fn budgets(&self, profile: Option<&str>) -> Result<BTreeMap<String, BudgetConfig>> {
+        let mut budgets = self.budgets.clone();
+
+        let Some(name) = profile else {
+            return Ok(budgets);
+        };
+        for (image, budget) in &self.profile(name)?.budgets {
+            budgets.entry(image.clone()).or_default().overlay(budget);
+        }
+
+        Ok(budgets)
+    }
+
+    /// `[default_budget]` with the profile's `[profiles.<profile>.default_budget]` overlaid
+    pub fn default_budget(&self, profile: Option<&str>) -> Result<Option<BudgetConfig>> {
+        let overlay = match profile {
+            Some(name) => self.profile(name)?.default_budget.as_ref(),
+            None => None,
+        };
+
+        Ok(match (self.default_budget.clone(), overlay) {
+            (Some(mut budget), Some(overlay)) => {
+                budget.overlay(overlay);
+                Some(budget)
+            }
+            (budget, overlay) => budget.or_else(|| overlay.cloned()),
+        })
+    }
+
+    fn profile(&self, name: &str) -> Result<&ProfileConfig> {
+        self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            anyhow!(
+                "Profile '{}' not found in {} (known: {})",
+                name,
+                CONFIG_FILE,
+                if known.is_empty() { "none".to_string() } else { known.join(", ") }
+            )
+        })
+    }
+}
+
+/// Expand `${VAR}` and `${VAR:-default}` in every string value; `path` names the value in errors
+fn expand_env(value: &mut Value, path: &str, env: &impl Fn(&str) -> Option<String>) -> Result<()> {
+    match value {
+        Value::String(s) => *s = expand_str(s, path, env)?,
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                expand_env(item, &format!("{}[{}]", path, i), env)?;
+            }
+        }
+        Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                expand_env(item, &path, env)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn variable() -> &'static Regex {
+    static VARIABLE: OnceLock<Regex> = OnceLock::new();
+    VARIABLE.get_or_init(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").unwrap())
+}
+
+/// Like the shell: `${VAR}` is empty when VAR is set but empty, while `${VAR:-default}`
+/// uses the default for both unset and empty
+fn expand_str(s: &str, path: &str, env: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::new();
+    let mut last = 0;
+
+    let push_literal = |out: &mut String, literal: &str| -> Result<()> {
+        if literal.contains("${") {
+            bail!("Invalid ${{...}} in {} in {}: '{}'", path, CONFIG_FILE, s);
+        }
+        out.push_str(literal);
+        Ok(())
+    };
+
+    for caps in variable().captures_iter(s) {
+        let whole = caps.get(0).expect("match");
+        let name = &caps[1];
+
+        let value = match (env(name), caps.get(2)) {
+            (Some(value), Some(default)) if value.is_empty() => default.as_str().to_string(),
+            (Some(value), _) => value,
+            (None, Some(default)) => default.as_str().to_string(),
+            (None, None) => bail!(
+                "Environment variable {} is not set ({} in {}); set it or use ${{{}:-default}}",
+                name,
+                path,
+                CONFIG_FILE,
+                name
+            ),
+        };
+
+        push_literal(&mut out, &s[last..whole.start()])?;
+        out.push_str(&value);
+        last = whole.end();
+    }
+    push_literal(&mut out, &s[last..])?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `REGISTRY=ghcr.io`, `EMPTY=` and nothing else set
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "REGISTRY" => Some("ghcr.io".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn variables_expand_like_the_shell() {
+        for (input, expected) in [
+            ("no variables", "no variables"),
+            ("${REGISTRY}/org/*", "ghcr.io/org/*"),
+            ("${REGISTRY}-${REGISTRY}", "ghcr.io-ghcr.io"),
+            ("${REGISTRY:-docker.io}", "ghcr.io"),
+            ("${UNSET:-docker.io}/app", "docker.io/app"),
+            ("${UNSET:-}", ""),
+            ("[${EMPTY}]", "[]"),
+            ("${EMPTY:-fallback}", "fallback"),
+            ("$REGISTRY", "$REGISTRY"),
+        ] {
+            assert_eq!(expand_str(input, "key", &env).unwrap(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn unset_variables_without_a_default_are_errors() {
+        let error = expand_str("${UNSET}/app", "budgets.app.budget", &env).unwrap_err().to_string();
+        assert!(error.contains("UNSET is not set"), "{}", error);
+        assert!(error.contains("budgets.app.budget in .dit.toml"), "{}", error);
+
+        let error = expand_str("${REGISTRY", "key", &env).unwrap_err().to_string();
+        assert!(error.contains("Invalid ${...}"), "{}", error);
+    }
+
+    #[test]
+    fn errors_name_the_value_in_nested_tables_and_arrays() {
+        let error = DitConfig::parse("exclude_authors = [\"bot\", \"${UNSET}\"]", &env)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("exclude_authors[1]"), "{}", error);
+
+        let error = DitConfig::parse("[budgets.app]\nbudget = \"${UNSET}\"", &env).unwrap_err();
+        assert!(error.to_string().contains("budgets.app.budget"), "{}", error);
+    }
+
+    #[test]
+    fn profiles_overlay_the_values_they_set() {
+        let config = DitConfig::parse(
+            r#"
+            [budgets.api]
+            budget = "${API_BUDGET:-500MB}"
+            warn_at = 80.0
+
+            [budgets.web]
+            budget = "1GB"
+
+            [default_budget]
+            budget = "2GB"
+
+            [profiles.prod.budgets.api]
+            budget = "400MB"
+
+            [profiles.prod.budgets.worker]
+            warn_at = 95.0
+
+            [profiles.prod.default_budget]
+            warn_at = 70.0
+            "#,
+            &env,
+        )
+        .unwrap();
+
+        let defaults = config.budgets(None).unwrap();
+        assert_eq!(defaults["api"].budget.as_deref(), Some("500MB"));
+        assert!(!defaults.contains_key("worker"));
+
+        let prod = config.budgets(Some("prod")).unwrap();
+        assert_eq!(prod["api"].budget.as_deref(), Some("400MB"));
+        assert_eq!(prod["api"].warn_at, Some(80.0));
+        assert_eq!(prod["web"].budget.as_deref(), Some("1GB"));
+        assert_eq!(prod["worker"].budget, None);
+        assert_eq!(prod["worker"].warn_at, Some(95.0));
+
+        let default = config.default_budget(Some("prod")).unwrap().unwrap();
+        assert_eq!(default.budget.as_deref(), Some("2GB"));
+        assert_eq!(default.warn_at, Some(70.0));
+
+        let error = config.budgets(Some("staging")).unwrap_err().to_string();
+        assert!(error.contains("Profile 'staging' not found"), "{}", error);
+        assert!(error.contains("known: prod"), "{}", error);
+    }
+}
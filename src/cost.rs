@@ -0,0 +1,170 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::config::CostConfig;
+use crate::models::{ImageSnapshot, SizeDiff};
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Monthly cost of keeping `retained_tags` copies of a `bytes`-sized image at `per_gb_month`
+pub fn monthly_storage_cost(bytes: u64, retained_tags: u32, per_gb_month: f64) -> f64 {
+    bytes as f64 / GIB * retained_tags as f64 * per_gb_month
+}
+
+/// Registry storage cost before and after a change, per month
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct StorageCostImpact {
+    pub currency: String,
+    pub retained_tags: u32,
+    pub monthly_before: f64,
+    pub monthly_after: f64,
+    pub monthly_delta: f64,
+    /// `config.format` with placeholders filled in
+    pub summary: String,
+}
+
+impl StorageCostImpact {
+    pub fn new(config: &CostConfig, before_bytes: u64, after_bytes: u64) -> Self {
+        let monthly_before =
+            monthly_storage_cost(before_bytes, config.retained_tags, config.per_gb_month);
+        let monthly_after =
+            monthly_storage_cost(after_bytes, config.retained_tags, config.per_gb_month);
+        let monthly_delta = monthly_after - monthly_before;
+
+        let sign = if monthly_delta < 0.0 { "-" } else { "+" };
+        let summary = config
+            .format
+            .replace(
+                "{delta}",
+                &format!("{}{}{:.2}", sign, config.currency, monthly_delta.abs()),
+            )
+            .replace("{total}", &format!("{}{:.2}", config.currency, monthly_after))
+            .replace("{tags}", &config.retained_tags.to_string());
+
+        Self {
+            currency: config.currency.clone(),
+            retained_tags: config.retained_tags,
+            monthly_before,
+            monthly_after,
+            monthly_delta,
+            summary,
+        }
+    }
+
+    /// Impact across a CI run. Compared images cost both sides on their diff's size basis,
+    /// so one delta never mixes compressed and uncompressed bytes. New and skipped images
+    /// prefer compressed sizes, since that is what registries store; new images count from
+    /// zero, and skipped images cost the same on both sides.
+    pub fn for_comparisons(
+        config: &CostConfig,
+        comparisons: &[(ImageSnapshot, Option<SizeDiff>)],
+        skipped: &[ImageSnapshot],
+    ) -> Self {
+        let stored = |s: &ImageSnapshot| s.compressed_size.unwrap_or_else(|| s.size());
+
+        let unchanged: u64 = skipped.iter().map(stored).sum();
+        let (mut before, mut after) = (0, 0);
+        for (current, diff) in comparisons {
+            match diff {
+                Some(diff) => {
+                    before += diff.before_size();
+                    after += diff.after_size();
+                }
+                None => after += stored(current),
+            }
+        }
+
+        Self::new(config, before + unchanged, after + unchanged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::compute_diff;
+    use crate::fixtures::{layer, snapshot};
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn config() -> CostConfig {
+        CostConfig {
+            per_gb_month: 0.10,
+            retained_tags: 10,
+            currency: "$".to_string(),
+            format: "storage cost impact: {delta}/month across {tags} retained tags".to_string(),
+        }
+    }
+
+    fn sized(size: u64, compressed: Option<u64>) -> ImageSnapshot {
+        let mut snapshot = snapshot("myapp", "1.0", vec![layer("sha256:a", size, "COPY")]);
+        snapshot.compressed_size = compressed;
+        snapshot
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn cost_scales_with_size_tags_and_price() {
+        let gib = 1024 * MIB;
+
+        assert!(close(monthly_storage_cost(gib, 1, 0.10), 0.10));
+        assert!(close(monthly_storage_cost(gib, 10, 0.10), 1.0));
+        assert!(close(monthly_storage_cost(gib / 2, 10, 0.023), 0.115));
+        assert!(close(monthly_storage_cost(0, 10, 0.10), 0.0));
+    }
+
+    #[test]
+    fn summary_fills_in_the_placeholders() {
+        let impact = StorageCostImpact::new(&config(), 1024 * MIB, 512 * MIB);
+
+        assert!(close(impact.monthly_delta, -0.5));
+        assert_eq!(impact.summary, "storage cost impact: -$0.50/month across 10 retained tags");
+
+        let mut config = config();
+        config.currency = "€".to_string();
+        config.format = "{delta} ({total} total, {tags} tags)".to_string();
+        let impact = StorageCostImpact::new(&config, 512 * MIB, 1024 * MIB);
+        assert_eq!(impact.summary, "+€0.50 (€1.00 total, 10 tags)");
+    }
+
+    #[test]
+    fn a_mixed_basis_pair_costs_both_sides_on_one_basis() {
+        // Only the newer snapshot was measured compressed, so the diff stays uncompressed;
+        // costing 400 MiB compressed against 1000 MiB uncompressed would invent a saving
+        let before = sized(1000 * MIB, None);
+        let after = sized(1024 * MIB, Some(400 * MIB));
+        let diff = compute_diff(before, after.clone());
+
+        let impact = StorageCostImpact::for_comparisons(&config(), &[(after, Some(diff))], &[]);
+
+        assert!(impact.monthly_delta > 0.0);
+        assert!(close(impact.monthly_before, monthly_storage_cost(1000 * MIB, 10, 0.10)));
+        assert!(close(impact.monthly_after, monthly_storage_cost(1024 * MIB, 10, 0.10)));
+    }
+
+    #[test]
+    fn compressed_pairs_cost_compressed_bytes() {
+        let before = sized(1000 * MIB, Some(300 * MIB));
+        let after = sized(1024 * MIB, Some(400 * MIB));
+        let diff = compute_diff(before, after.clone());
+
+        let impact = StorageCostImpact::for_comparisons(&config(), &[(after, Some(diff))], &[]);
+
+        assert!(close(impact.monthly_before, monthly_storage_cost(300 * MIB, 10, 0.10)));
+        assert!(close(impact.monthly_after, monthly_storage_cost(400 * MIB, 10, 0.10)));
+    }
+
+    #[test]
+    fn new_images_count_from_zero_and_skipped_ones_on_both_sides() {
+        let new = sized(1024 * MIB, Some(512 * MIB));
+        let skipped = sized(2048 * MIB, None);
+
+        let impact = StorageCostImpact::for_comparisons(&config(), &[(new, None)], &[skipped]);
+
+        assert!(close(impact.monthly_before, 2.0));
+        assert!(close(impact.monthly_after, 2.5));
+        assert_eq!(impact.summary, "storage cost impact: +$0.50/month across 10 retained tags");
+    }
+}
@@ -0,0 +1,536 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::docker::{CachingInspector, DockerClient, ImageFilter, ImageInspector};
+use crate::models::ImageSnapshot;
+use crate::store::Store;
+use crate::track::get_git_context;
+
+const PID_FILE: &str = "daemon.pid";
+const HEALTH_FILE: &str = "daemon.health";
+
+#[derive(Debug)]
+pub struct DaemonOptions {
+    pub interval: Duration,
+    /// Shell command run after each recorded change; `{image}`, `{tag}`, `{delta}`,
+    /// `{size}` and `{previous_size}` are substituted
+    pub on_change: Option<String>,
+    /// Alert when a scanned image is larger than this many bytes
+    pub watch_budget: Option<u64>,
+    /// Shell command run on each alert, with the image as `$1` and its size in bytes as `$2`
+    pub alert_cmd: Option<String>,
+    /// Minimum time between two alerts for an image that stays over budget
+    pub alert_cooldown: Duration,
+}
+
+/// Debounces budget alerts: an image that stays over budget alerts once per cooldown,
+/// and again right away if it drops under and breaches the budget later
+#[derive(Debug, Default)]
+struct BudgetAlerts {
+    last_alert: HashMap<String, Instant>,
+}
+
+impl BudgetAlerts {
+    async fn check(
+        &mut self,
+        out: &mut impl Write,
+        image: &str,
+        snapshot: &ImageSnapshot,
+        options: &DaemonOptions,
+    ) -> Result<()> {
+        let Some(budget) = options.watch_budget else {
+            return Ok(());
+        };
+
+        if snapshot.total_size <= budget {
+            if self.last_alert.remove(image).is_some() {
+                log(
+                    out,
+                    "budget_recovered",
+                    json!({ "image": image, "size": snapshot.total_size, "budget": budget }),
+                )?;
+            }
+            return Ok(());
+        }
+
+        let due = self
+            .last_alert
+            .get(image)
+            .is_none_or(|at| at.elapsed() >= options.alert_cooldown);
+        if !due {
+            return Ok(());
+        }
+        self.last_alert.insert(image.to_string(), Instant::now());
+
+        tracing::info!(monotonic_counter.dit.budget_failures = 1_u64);
+        log(
+            out,
+            "budget_exceeded",
+            json!({ "image": image, "size": snapshot.total_size, "budget": budget }),
+        )?;
+
+        if let Some(command) = &options.alert_cmd {
+            run_alert(out, command, image, snapshot.total_size).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What a freshly inspected image looks like relative to its recorded history
+#[derive(Debug, PartialEq)]
+pub enum ImageChange {
+    New,
+    Changed { previous_size: u64, delta: i64 },
+    Unchanged,
+}
+
+/// Compare `current` against the latest snapshot of the same image and tag.
+/// Uses the image ID when both sides have one, the layer fingerprint otherwise.
+pub fn detect_change(history: &[ImageSnapshot], current: &ImageSnapshot) -> ImageChange {
+    let previous = history
+        .iter()
+        .filter(|s| s.image == current.image && s.tag == current.tag)
+        .max_by_key(|s| s.timestamp);
+
+    let Some(previous) = previous else {
+        return ImageChange::New;
+    };
+
+    let same = match (&previous.image_id, &current.image_id) {
+        (Some(a), Some(b)) => a == b,
+        _ => previous.same_layers(current),
+    };
+
+    if same {
+        ImageChange::Unchanged
+    } else {
+        ImageChange::Changed {
+            previous_size: previous.total_size,
+            delta: current.total_size as i64 - previous.total_size as i64,
+        }
+    }
+}
+
+/// Parse `30s`, `10m`, `1h` or a bare number of seconds
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{}' (expected e.g. 30s, 10m, 1h)", s))?;
+
+    let seconds = match unit {
+        "s" => value,
+        "m" => value * 60,
+        "h" => value * 3600,
+        _ => return Err(format!("invalid interval '{}' (expected e.g. 30s, 10m, 1h)", s)),
+    };
+
+    if seconds == 0 {
+        return Err("interval must be greater than zero".to_string());
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Poll Docker every `interval`, recording a snapshot whenever a matching image changes.
+/// Runs until SIGINT/SIGTERM; a scan in progress always finishes before exit.
+pub async fn run_daemon(
+    out: &mut impl Write,
+    docker: &CachingInspector<DockerClient>,
+    store: &Store,
+    filter: &ImageFilter,
+    options: &DaemonOptions,
+) -> Result<()> {
+    let pid_path = store.dir().join(PID_FILE);
+    let health_path = store.dir().join(HEALTH_FILE);
+
+    fs::create_dir_all(store.dir()).context("Failed to create .dit directory")?;
+    claim_pid_file(&pid_path)?;
+
+    log(
+        out,
+        "started",
+        json!({ "pid": std::process::id(), "interval_secs": options.interval.as_secs() }),
+    )?;
+
+    let result = daemon_loop(out, store, docker, filter, options, &health_path).await;
+
+    let _ = fs::remove_file(&pid_path);
+    let _ = fs::remove_file(&health_path);
+
+    match &result {
+        Ok(()) => log(out, "stopped", json!({}))?,
+        Err(e) => log(out, "stopped", json!({ "error": format!("{:#}", e) }))?,
+    }
+
+    result
+}
+
+async fn daemon_loop(
+    out: &mut impl Write,
+    store: &Store,
+    docker: &CachingInspector<DockerClient>,
+    filter: &ImageFilter,
+    options: &DaemonOptions,
+    health_path: &Path,
+) -> Result<()> {
+    // Listen from the start so a signal during the first scan is not lost
+    let mut shutdown = tokio::spawn(shutdown_signal());
+    let mut alerts = BudgetAlerts::default();
+
+    loop {
+        match scan(out, store, docker, filter, options, &mut alerts).await {
+            Ok((checked, recorded)) => {
+                write_health(health_path, "ok", checked, recorded, None)?;
+            }
+            Err(e) => {
+                let message = format!("{:#}", e);
+                log(out, "scan_failed", json!({ "error": message }))?;
+                write_health(health_path, "error", 0, 0, Some(&message))?;
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(options.interval) => {}
+            signal = &mut shutdown => {
+                log(out, "shutdown", json!({ "signal": signal.unwrap_or("unknown") }))?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// One pass over the matching images; returns (checked, recorded)
+async fn scan(
+    out: &mut impl Write,
+    store: &Store,
+    docker: &CachingInspector<DockerClient>,
+    filter: &ImageFilter,
+    options: &DaemonOptions,
+    alerts: &mut BudgetAlerts,
+) -> Result<(usize, usize)> {
+    docker.invalidate_cache();
+    let images = docker.select_images(filter).await?;
+    let mut history = store.load_history()?;
+    let git = get_git_context().ok();
+    let mut recorded = 0;
+
+    for image in &images {
+        let mut snapshot = match docker.inspect_image(image).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                let error = format!("{:#}", e);
+                log(out, "inspect_failed", json!({ "image": image, "error": error }))?;
+                continue;
+            }
+        };
+
+        // Every scan, not just changes, so a persistent breach alerts again after the cooldown
+        alerts.check(out, image, &snapshot, options).await?;
+
+        let change = detect_change(&history, &snapshot);
+        if change == ImageChange::Unchanged {
+            continue;
+        }
+
+        if let Some(git) = &git {
+            snapshot.commit_sha = git.commit_sha.clone();
+            snapshot.branch = git.branch.clone();
+            snapshot.commit_message = git.commit_message.clone();
+            snapshot.author = git.author.clone();
+        }
+        snapshot.timestamp = Utc::now();
+
+        store.save_snapshot(&snapshot)?;
+        recorded += 1;
+
+        let (previous_size, delta) = match change {
+            ImageChange::Changed { previous_size, delta } => (Some(previous_size), delta),
+            _ => (None, 0),
+        };
+
+        log(
+            out,
+            "snapshot_recorded",
+            json!({
+                "image": image,
+                "image_id": snapshot.image_id,
+                "size": snapshot.total_size,
+                "previous_size": previous_size,
+                "delta": delta,
+            }),
+        )?;
+
+        if let Some(command) = &options.on_change {
+            run_hook(out, command, &snapshot, previous_size, delta).await?;
+        }
+
+        history.push(snapshot);
+    }
+
+    log(out, "scan_complete", json!({ "checked": images.len(), "recorded": recorded }))?;
+
+    Ok((images.len(), recorded))
+}
+
+/// `--on-change`, awaited on tokio's process driver so a slow hook doesn't block a runtime worker
+async fn run_hook(
+    out: &mut impl Write,
+    template: &str,
+    snapshot: &ImageSnapshot,
+    previous_size: Option<u64>,
+    delta: i64,
+) -> Result<()> {
+    let command = template
+        .replace("{image}", &snapshot.image)
+        .replace("{tag}", snapshot.tag.as_deref().unwrap_or("latest"))
+        .replace("{delta}", &delta.to_string())
+        .replace("{size}", &snapshot.total_size.to_string())
+        .replace(
+            "{previous_size}",
+            &previous_size.map(|s| s.to_string()).unwrap_or_default(),
+        );
+
+    match tokio::process::Command::new("sh").arg("-c").arg(&command).status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => log(
+            out,
+            "hook_failed",
+            json!({ "command": command, "exit_code": status.code() }),
+        )?,
+        Err(e) => log(
+            out,
+            "hook_failed",
+            json!({ "command": command, "error": e.to_string() }),
+        )?,
+    }
+
+    Ok(())
+}
+
+/// `--alert-cmd`, awaited like [`run_hook`] rather than blocking a runtime worker
+async fn run_alert(out: &mut impl Write, command: &str, image: &str, size: u64) -> Result<()> {
+    let status = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .arg("sh")
+        .arg(image)
+        .arg(size.to_string())
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => log(
+            out,
+            "alert_failed",
+            json!({ "command": command, "exit_code": status.code() }),
+        )?,
+        Err(e) => log(
+            out,
+            "alert_failed",
+            json!({ "command": command, "error": e.to_string() }),
+        )?,
+    }
+
+    Ok(())
+}
+
+/// Refuse to start if another daemon owns the PID file and is still alive
+fn claim_pid_file(path: &Path) -> Result<()> {
+    if let Ok(existing) = fs::read_to_string(path) {
+        let pid = existing.trim();
+        if !pid.is_empty() && process_alive(pid) {
+            bail!(
+                "dit daemon is already running (pid {}); remove {} if that is stale",
+                pid,
+                path.display()
+            );
+        }
+    }
+
+    fs::write(path, format!("{}\n", std::process::id()))
+        .context(format!("Failed to write PID file: {}", path.display()))
+}
+
+fn process_alive(pid: &str) -> bool {
+    if cfg!(target_os = "linux") {
+        return PathBuf::from("/proc").join(pid).exists();
+    }
+
+    Command::new("kill")
+        .args(["-0", pid])
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+fn write_health(
+    path: &Path,
+    status: &str,
+    checked: usize,
+    recorded: usize,
+    error: Option<&str>,
+) -> Result<()> {
+    let health = json!({
+        "status": status,
+        "pid": std::process::id(),
+        "last_scan": Utc::now(),
+        "images_checked": checked,
+        "snapshots_recorded": recorded,
+        "error": error,
+    });
+
+    fs::write(path, serde_json::to_string_pretty(&health)?)
+        .context(format!("Failed to write health file: {}", path.display()))
+}
+
+/// One JSON object per line on stdout
+fn log(out: &mut impl Write, event: &str, mut fields: Value) -> Result<()> {
+    if let Value::Object(map) = &mut fields {
+        map.insert("ts".to_string(), json!(Utc::now()));
+        map.insert("event".to_string(), json!(event));
+    }
+    writeln!(out, "{}", fields)?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = term.recv() => "SIGTERM",
+        },
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+            "SIGINT"
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, snapshot};
+    use tempfile::TempDir;
+
+    #[test]
+    fn a_rebuild_with_new_layer_digests_is_a_change() {
+        let previous = snapshot("myapp", "1.0", vec![layer("sha256:a", 10, "COPY")]);
+        let rebuilt = snapshot("myapp", "1.0", vec![layer("sha256:b", 10, "COPY")]);
+
+        let history = vec![previous];
+
+        assert!(matches!(
+            detect_change(&history, &rebuilt),
+            ImageChange::Changed { delta: 0, .. }
+        ));
+        assert_eq!(detect_change(&history, &history[0]), ImageChange::Unchanged);
+    }
+
+    #[tokio::test]
+    async fn hooks_run_with_the_change_filled_in() {
+        let dir = TempDir::new().unwrap();
+        let out = dir.path().join("hook.txt");
+        let template = format!("echo {{image}} {{tag}} {{size}} {{previous_size}} {{delta}} > {}", out.display());
+
+        let current = snapshot("myapp", "1.0", vec![layer("sha256:a", 150, "COPY")]);
+        run_hook(&mut Vec::new(), &template, &current, Some(100), 50).await.unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "myapp 1.0 150 100 50\n");
+
+        // New images have no previous size
+        run_hook(&mut Vec::new(), &template, &current, None, 0).await.unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "myapp 1.0 150 0\n");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn hooks_do_not_block_the_runtime() {
+        let current = snapshot("myapp", "1.0", Vec::new());
+        let started = Instant::now();
+
+        // On a single-threaded runtime a blocking wait would hold the timer until the hook exits
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            started.elapsed()
+        });
+        run_hook(&mut Vec::new(), "sleep 1", &current, None, 0).await.unwrap();
+
+        assert!(timer.await.unwrap() < Duration::from_millis(900));
+    }
+
+    fn alert_options(out: &Path) -> DaemonOptions {
+        DaemonOptions {
+            interval: Duration::from_secs(60),
+            on_change: None,
+            watch_budget: Some(100),
+            alert_cmd: Some(format!("echo \"$1 $2\" >> {}", out.display())),
+            alert_cooldown: Duration::from_secs(3600),
+        }
+    }
+
+    fn alerts_run(out: &Path) -> Vec<String> {
+        fs::read_to_string(out)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn alerts_run_once_per_breach_within_the_cooldown() {
+        let dir = TempDir::new().unwrap();
+        let out = dir.path().join("alerts.txt");
+        let options = alert_options(&out);
+        let mut alerts = BudgetAlerts::default();
+
+        let small = snapshot("myapp", "1.0", vec![layer("sha256:a", 80, "COPY")]);
+        let big = snapshot("myapp", "1.0", vec![layer("sha256:b", 150, "COPY")]);
+
+        alerts.check(&mut Vec::new(), "myapp:1.0", &small, &options).await.unwrap();
+        assert!(alerts_run(&out).is_empty());
+
+        alerts.check(&mut Vec::new(), "myapp:1.0", &big, &options).await.unwrap();
+        alerts.check(&mut Vec::new(), "myapp:1.0", &big, &options).await.unwrap();
+        assert_eq!(alerts_run(&out), ["myapp:1.0 150"]);
+
+        // Recovering resets the cooldown, so the next breach alerts right away
+        alerts.check(&mut Vec::new(), "myapp:1.0", &small, &options).await.unwrap();
+        alerts.check(&mut Vec::new(), "myapp:1.0", &big, &options).await.unwrap();
+        assert_eq!(alerts_run(&out), ["myapp:1.0 150", "myapp:1.0 150"]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn alerts_do_not_block_the_runtime() {
+        let started = Instant::now();
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            started.elapsed()
+        });
+        run_alert(&mut Vec::new(), "sleep 1", "myapp:1.0", 150).await.unwrap();
+
+        assert!(timer.await.unwrap() < Duration::from_millis(900));
+    }
+}
@@ -1,29 +1,184 @@
 use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
 use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
 
-use crate::format::print_diff_table;
-use crate::models::{ImageSnapshot, LayerChange, SizeDiff};
-use crate::track::load_history;
+use crate::baseline::find_baseline_snapshot;
+use crate::docker::{parse_image_reference, DockerClient, ImageInspector};
+use crate::format::{
+    format_size, format_size_delta, print_baseline_trace, print_diff_table, LayerSizeUnit,
+    OutputStyle, UnchangedLayers,
+};
+use crate::k8s::{resolve_deployed_image, K8sTarget};
+use crate::models::{ImageSnapshot, LayerChange, LayerInfo, SizeBasis, SizeDiff};
+use crate::schema::read_snapshot_file;
+use crate::store::Store;
+use crate::track::resolve_commit;
 
+/// `--basis`: which size comparisons use
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum BasisChoice {
+    /// Compressed when both sides have a compressed size, uncompressed otherwise
+    #[default]
+    Auto,
+    Compressed,
+    Uncompressed,
+}
+
+/// Basis for comparing `before` with `after`: each side needs a size on it. Only
+/// registry-only snapshots lack an uncompressed size, and only local ones a compressed size.
+pub fn select_basis(
+    before: &ImageSnapshot,
+    after: &ImageSnapshot,
+    choice: BasisChoice,
+) -> Result<SizeBasis> {
+    let missing = |basis: SizeBasis| {
+        if basis.size_of(before).is_none() {
+            Some("older")
+        } else if basis.size_of(after).is_none() {
+            Some("newer")
+        } else {
+            None
+        }
+    };
+
+    match (choice, missing(SizeBasis::Compressed), missing(SizeBasis::Uncompressed)) {
+        (BasisChoice::Uncompressed, _, Some(side)) => bail!(
+            "--basis uncompressed needs an uncompressed size on both sides, but the {} snapshot \
+             only has its registry size (tracked with --multi-platform)",
+            side
+        ),
+        (BasisChoice::Uncompressed, _, None) => Ok(SizeBasis::Uncompressed),
+        (_, None, _) => Ok(SizeBasis::Compressed),
+        (BasisChoice::Compressed, Some(side), _) => bail!(
+            "--basis compressed needs a compressed size on both sides, but the {} snapshot has none \
+             (registry sizes come from --multi-platform or --measure-extract)",
+            side
+        ),
+        (BasisChoice::Auto, _, None) => Ok(SizeBasis::Uncompressed),
+        (BasisChoice::Auto, Some(_), Some(side)) => bail!(
+            "Can't compare a registry-only snapshot with a local one: the {} snapshot only has \
+             its registry size, and the other side has no compressed size (--measure-extract records one)",
+            side
+        ),
+    }
+}
+
+/// Re-express `diff` on the basis `choice` selects for it
+pub fn apply_basis(diff: &mut SizeDiff, choice: BasisChoice) -> Result<()> {
+    let basis = select_basis(&diff.before, &diff.after, choice)?;
+    diff.set_basis(basis);
+    Ok(())
+}
+
+/// `--diff-algorithm`: how layers of the two snapshots are paired
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum DiffAlgorithm {
+    /// Pair layers with the same digest
+    #[default]
+    Digest,
+    /// Pair layer N with layer N, for builds whose digests change every time
+    Positional,
+}
+
+#[derive(Debug)]
+pub struct DiffOptions {
+    pub base_branch: Option<String>,
+    pub explain_baseline: bool,
+    pub show_env_values: bool,
+    pub layer_order_matters: bool,
+    pub algorithm: DiffAlgorithm,
+    /// Pair removed and added layers built by the same command as `Modified`
+    pub match_by_command: bool,
+    /// Layer changes to show, most significant first; 0 shows all
+    pub max_changes: usize,
+    /// Print only the total size delta, for scripts
+    pub total_only: bool,
+    /// Compare HEAD~1's snapshot with HEAD's (or the latest)
+    pub compare_with_parent_commit: bool,
+    /// Compare against the image a Kubernetes workload is running
+    pub against_k8s: Option<K8sTarget>,
+    /// Print nothing when the total delta is below this many bytes
+    pub ignore_below: Option<u64>,
+    /// Print nothing when the total delta is below this percentage of the older size
+    pub ignore_below_pct: Option<f64>,
+    /// Print a one-line note instead of nothing when the delta is ignored
+    pub verbose_if_below_threshold: bool,
+    /// Show word-level command changes in modified layers
+    pub word_diff: bool,
+    /// Also write the computed diff under `.dit/diffs/`
+    pub save_to_history: bool,
+    pub layer_size_unit: LayerSizeUnit,
+    pub basis: BasisChoice,
+    pub unchanged_layers: UnchangedLayers,
+    /// `--left`: an exported snapshot file as the older side
+    pub left_file: Option<String>,
+    /// `--right`: an exported snapshot file as the newer side
+    pub right_file: Option<String>,
+    pub output: OutputStyle,
+}
+
+impl DiffOptions {
+    /// Whether `diff` is small enough to suppress: below every threshold that was given
+    fn below_threshold(&self, diff: &SizeDiff) -> bool {
+        if self.ignore_below.is_none() && self.ignore_below_pct.is_none() {
+            return false;
+        }
+
+        let delta = diff.total_delta.unsigned_abs();
+        let below_bytes = match self.ignore_below {
+            Some(bytes) => delta < bytes,
+            None => true,
+        };
+        let below_pct = match self.ignore_below_pct {
+            Some(pct) => diff.percent().is_some_and(|p| p.abs() < pct),
+            None => true,
+        };
+
+        below_bytes && below_pct
+    }
+}
+
+/// Returns the total size delta shown (0 when it was below the ignore threshold)
 pub async fn diff_images(
-    image: &str,
+    out: &mut impl Write,
+    store: &Store,
+    image: Option<&str>,
     commit_a: Option<String>,
     commit_b: Option<String>,
-    base_branch: Option<String>,
-) -> Result<()> {
-    let history = load_history()?;
-
-    if history.is_empty() {
-        bail!("No history found. Run 'dit track' first.");
+    options: DiffOptions,
+) -> Result<i64> {
+    if options.left_file.is_some() || options.right_file.is_some() {
+        return diff_with_files(out, store, image, commit_a, &options);
+    }
+    let image = image.context("An image name is required without --left / --right")?;
+    if let Some(target) = &options.against_k8s {
+        return diff_against_deployed(out, store, image, target, &options).await;
     }
 
-    // Filter history for the specified image
-    let image_history: Vec<_> = history
-        .iter()
-        .filter(|s| s.image == image)
-        .collect();
+    let (before, after) = select_snapshots(out, store, image, commit_a, commit_b, &options)?;
+    print_diff(out, store, before, after, &options, &[])
+}
+
+/// The tracked snapshots of `image` to compare: two commits, the parent commit's, the
+/// `--base` baseline's or the latest two, each against the latest
+pub fn select_snapshots(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    commit_a: Option<String>,
+    commit_b: Option<String>,
+    options: &DiffOptions,
+) -> Result<(ImageSnapshot, ImageSnapshot)> {
+    // Baselines are this image's snapshots too, so nothing else needs loading
+    let history = store.load_history_for_image(image)?;
+    let image_history: Vec<_> = history.iter().collect();
 
     if image_history.is_empty() {
+        if store.load_history()?.is_empty() {
+            bail!("No history found. Run 'dit track' first.");
+        }
         bail!("No history found for image '{}'", image);
     }
 
@@ -33,9 +188,31 @@ pub async fn diff_images(
         let snap_a = find_snapshot_by_commit(&image_history, &a)?;
         let snap_b = find_snapshot_by_commit(&image_history, &b)?;
         (snap_a, snap_b)
-    } else if let Some(base) = base_branch {
+    } else if options.compare_with_parent_commit {
+        let parent_sha = resolve_commit("HEAD~1")?;
+        let parent = find_snapshot_by_commit(&image_history, &parent_sha).map_err(|_| {
+            anyhow::anyhow!(
+                "No snapshot of '{}' for parent commit {}. Track the parent commit first \
+                 (git checkout HEAD~1 && dit track {}) or compare with --base main instead.",
+                image,
+                &parent_sha[..7.min(parent_sha.len())],
+                image
+            )
+        })?;
+
+        // Prefer the snapshot for HEAD itself; fall back to the latest one
+        let head_sha = resolve_commit("HEAD")?;
+        let current = find_snapshot_by_commit(&image_history, &head_sha)
+            .unwrap_or_else(|_| *image_history.last().unwrap());
+        (parent, current)
+    } else if let Some(base) = &options.base_branch {
         // Compare against base branch
-        let base_snap = find_latest_snapshot_by_branch(&image_history, &base)?;
+        let (base_snap, selection) = find_baseline_snapshot(&history, image, Some(base), None);
+        if options.explain_baseline {
+            print_baseline_trace(out, &selection)?;
+        }
+        let base_snap =
+            base_snap.context(format!("No snapshot found for branch '{}'", base))?;
         let current_snap = *image_history.last().unwrap();
         (base_snap, current_snap)
     } else {
@@ -48,13 +225,359 @@ pub async fn diff_images(
         (before, after)
     };
 
-    // Compute diff
-    let diff = compute_diff((*before).clone(), (*after).clone());
+    Ok((before.clone(), after.clone()))
+}
+
+/// How [`normalize_layer_lists`] reconciled layer lists recorded by different providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerNormalization {
+    /// Both sides cut down to their non-empty layers, which line up one to one
+    ContentLayers,
+    /// Even the content layers don't line up (`before` vs `after` of them)
+    TotalOnly { before: usize, after: usize },
+}
+
+/// Whether one snapshot lists `docker history`'s zero-size metadata entries (ENV, LABEL,
+/// ...) and the other doesn't, e.g. a daemon-inspected image against a registry manifest.
+/// Matching such lists layer by layer only produces a wall of additions and removals.
+pub fn layer_lists_differ(before: &ImageSnapshot, after: &ImageSnapshot) -> bool {
+    let has_metadata = |s: &ImageSnapshot| s.layers.iter().any(|l| l.size == 0);
+    !before.layers.is_empty()
+        && !after.layers.is_empty()
+        && has_metadata(before) != has_metadata(after)
+}
+
+/// When [`layer_lists_differ`], keep only both sides' content layers; `None` leaves
+/// the snapshots alone
+pub fn normalize_layer_lists(
+    before: &mut ImageSnapshot,
+    after: &mut ImageSnapshot,
+) -> Option<LayerNormalization> {
+    if !layer_lists_differ(before, after) {
+        return None;
+    }
+
+    for snapshot in [&mut *before, &mut *after] {
+        snapshot.layers.retain(|l| l.size > 0);
+        snapshot.layer_count = snapshot.layers.len();
+    }
+
+    Some(if before.layers.len() == after.layers.len() {
+        LayerNormalization::ContentLayers
+    } else {
+        LayerNormalization::TotalOnly {
+            before: before.layers.len(),
+            after: after.layers.len(),
+        }
+    })
+}
+
+/// `--emit-exit-code-for-increase`: 1 for an increase above `threshold` bytes,
+/// 2 for a decrease, 0 otherwise
+pub fn exit_code_for_delta(delta: i64, threshold: Option<u64>) -> u8 {
+    if delta > 0 && delta as u64 > threshold.unwrap_or(0) {
+        1
+    } else if delta < 0 {
+        2
+    } else {
+        0
+    }
+}
+
+/// A diff as `dit diff` computed it, before anything is printed
+#[derive(Debug)]
+pub struct DiffOutcome {
+    pub diff: SizeDiff,
+    /// Set when the layer lists had to be reconciled first
+    pub normalization: Option<LayerNormalization>,
+    /// Where `save_to_history` wrote the diff
+    pub saved_to: Option<PathBuf>,
+}
+
+/// Diff `before` against `after` with the algorithm and basis `options` ask for, saving
+/// the diff to `store` with `save_to_history`; prints nothing
+pub fn diff_snapshots(
+    store: &Store,
+    mut before: ImageSnapshot,
+    mut after: ImageSnapshot,
+    options: &DiffOptions,
+) -> Result<DiffOutcome> {
+    let normalization = normalize_layer_lists(&mut before, &mut after);
+
+    let mut diff = match normalization {
+        // The providers list the same content layers in the same order, under different IDs
+        Some(LayerNormalization::ContentLayers) => compute_diff_positional(before, after),
+        Some(LayerNormalization::TotalOnly { .. }) => {
+            let mut diff = compute_diff_positional(before, after);
+            diff.layer_changes.clear();
+            diff
+        }
+        None => match options.algorithm {
+            DiffAlgorithm::Positional => compute_diff_positional(before, after),
+            DiffAlgorithm::Digest if options.layer_order_matters => compute_diff_ordered(before, after),
+            DiffAlgorithm::Digest => compute_diff(before, after),
+        },
+    };
+    if options.match_by_command && normalization.is_none() {
+        match_by_command(&mut diff);
+    }
+    apply_basis(&mut diff, options.basis)?;
+
+    // Saved before any threshold check: the audit trail wants small changes too
+    let saved_to = if options.save_to_history {
+        store.save_diff(&diff)?
+    } else {
+        None
+    };
+
+    Ok(DiffOutcome {
+        diff,
+        normalization,
+        saved_to,
+    })
+}
+
+/// `sources` (where each side came from) head the table, not `--total-only` output
+fn print_diff(
+    out: &mut impl Write,
+    store: &Store,
+    before: ImageSnapshot,
+    after: ImageSnapshot,
+    options: &DiffOptions,
+    sources: &[String],
+) -> Result<i64> {
+    let DiffOutcome {
+        diff,
+        normalization,
+        saved_to,
+    } = diff_snapshots(store, before, after, options)?;
+
+    match normalization {
+        Some(LayerNormalization::ContentLayers) => eprintln!(
+            "ℹ️  Only one snapshot lists metadata layers (ENV, LABEL, ...), as when a \
+             daemon-inspected image meets a registry manifest; comparing content layers only"
+        ),
+        Some(LayerNormalization::TotalOnly { before, after }) => eprintln!(
+            "⚠️  The snapshots list their layers differently and have {} vs {} content \
+             layers, so layers can't be paired; comparing total size only",
+            before, after
+        ),
+        None => {}
+    }
+    if let Some(path) = saved_to {
+        eprintln!("Saved diff to {}", path.display());
+    }
+
+    if options.below_threshold(&diff) {
+        if options.verbose_if_below_threshold {
+            writeln!(
+                out,
+                "Size unchanged within threshold (delta: {}{})",
+                format_size_delta(diff.total_delta, options.output.plain),
+                diff.size_basis.suffix()
+            )?;
+        }
+        return Ok(0);
+    }
 
     // Display diff
-    print_diff_table(&diff);
+    if options.total_only {
+        // Plain text, uncolored by the binary: this output is meant to be captured by scripts
+        let delta = format_size_delta(diff.total_delta, options.output.plain);
+        writeln!(out, "{}{}", delta, diff.size_basis.suffix())?;
+    } else {
+        for source in sources {
+            writeln!(out, "{}", source)?;
+        }
+        print_diff_table(out, &diff, options)?;
+    }
 
-    Ok(())
+    Ok(diff.total_delta)
+}
+
+/// `--left` / `--right`: diff exported snapshot files without importing them. With one
+/// file, the other side comes from history: `commit`, the `--base` baseline, or the
+/// latest snapshot of `image` (default: the file's image).
+fn diff_with_files(
+    out: &mut impl Write,
+    store: &Store,
+    image: Option<&str>,
+    commit: Option<String>,
+    options: &DiffOptions,
+) -> Result<i64> {
+    let read = |path: &Option<String>| -> Result<Option<(ImageSnapshot, String)>> {
+        path.as_deref()
+            .map(|path| Ok((read_snapshot_file(path)?, format!("file {}", path))))
+            .transpose()
+    };
+    let left = read(&options.left_file)?;
+    let right = read(&options.right_file)?;
+
+    let (before, after) = match (left, right) {
+        (Some(left), Some(right)) => {
+            if commit.is_some() || options.base_branch.is_some() {
+                bail!(
+                    "A commit or --base picks a history snapshot; with both --left and --right \
+                     there is none"
+                );
+            }
+            (left, right)
+        }
+        (Some(file), None) => {
+            let tracked = tracked_side(out, store, image, &file.0, commit, options)?;
+            (file, tracked)
+        }
+        (None, Some(file)) => {
+            let tracked = tracked_side(out, store, image, &file.0, commit, options)?;
+            (tracked, file)
+        }
+        (None, None) => unreachable!("diff_with_files needs --left or --right"),
+    };
+
+    let sources: Vec<String> = [("Before", &before), ("After", &after)]
+        .iter()
+        .map(|(side, (snapshot, source))| {
+            format!(
+                "{}: {} ({}, commit {})",
+                side,
+                source,
+                display_name(snapshot),
+                snapshot.commit_sha.chars().take(7).collect::<String>()
+            )
+        })
+        .collect();
+
+    print_diff(out, store, before.0, after.0, options, &sources)
+}
+
+/// The history side of a one-file diff, labelled for the header
+fn tracked_side(
+    out: &mut impl Write,
+    store: &Store,
+    image: Option<&str>,
+    file: &ImageSnapshot,
+    commit: Option<String>,
+    options: &DiffOptions,
+) -> Result<(ImageSnapshot, String)> {
+    let image = image.map(str::to_string).unwrap_or_else(|| display_name(file));
+    let history = store.load_history_for_image(&image)?;
+    let image_history: Vec<_> = history.iter().collect();
+    if image_history.is_empty() {
+        bail!(
+            "No history found for image '{}' to compare the snapshot file with \
+             (pass the image name, or both --left and --right)",
+            image
+        );
+    }
+
+    let snapshot = if let Some(commit) = commit {
+        find_snapshot_by_commit(&image_history, &commit)?
+    } else if let Some(base) = &options.base_branch {
+        let (base_snap, selection) = find_baseline_snapshot(&history, &image, Some(base), None);
+        if options.explain_baseline {
+            print_baseline_trace(out, &selection)?;
+        }
+        base_snap.context(format!("No snapshot found for branch '{}'", base))?
+    } else {
+        *image_history.last().unwrap()
+    };
+
+    let source = match &snapshot.branch {
+        branch if branch.is_empty() => "dit history".to_string(),
+        branch => format!("dit history on {}", branch),
+    };
+    Ok((snapshot.clone(), source))
+}
+
+/// `myapp:1.2`, or just the name when no tag was recorded
+fn display_name(snapshot: &ImageSnapshot) -> String {
+    match &snapshot.tag {
+        Some(tag) => format!("{}:{}", snapshot.image, tag),
+        None => snapshot.image.clone(),
+    }
+}
+
+/// Diff a candidate image against what a Kubernetes workload is currently running
+async fn diff_against_deployed(
+    out: &mut impl Write,
+    store: &Store,
+    candidate: &str,
+    target: &K8sTarget,
+    options: &DiffOptions,
+) -> Result<i64> {
+    let deployed = resolve_deployed_image(target)?;
+    let history = store.load_history()?;
+    let docker = DockerClient::new().ok();
+
+    let before = find_image(docker.as_ref(), &history, &deployed)
+        .await
+        .context(format!(
+            "{} is running {}, but it isn't available locally or in dit history. \
+             Pull it first (docker pull {}) or track it from the deploy pipeline.",
+            target.resource, deployed, deployed
+        ))?;
+    let after = find_image(docker.as_ref(), &history, candidate)
+        .await
+        .context(format!("Candidate image '{}' not found locally or in dit history", candidate))?;
+
+    if !options.total_only {
+        writeln!(out, "Deployed: {} ({})", deployed, target.resource)?;
+        writeln!(out, "Candidate: {}", candidate)?;
+        match (before.compressed_size, after.compressed_size) {
+            (Some(deployed_size), Some(candidate_size)) => writeln!(
+                out,
+                "Compressed: {} → {} (what nodes will pull)",
+                format_size(deployed_size),
+                format_size(candidate_size)
+            )?,
+            _ => writeln!(
+                out,
+                "Note: sizes are uncompressed; the registry transfer will be smaller \
+                 (track with --measure-extract to record compressed sizes)"
+            )?,
+        }
+        writeln!(out)?;
+    }
+
+    print_diff(out, store, before, after, options, &[])
+}
+
+/// Inspect `reference` locally, falling back to the latest tracked snapshot of it
+async fn find_image(
+    docker: Option<&impl ImageInspector>,
+    history: &[ImageSnapshot],
+    reference: &str,
+) -> Result<ImageSnapshot> {
+    let local_error = match docker {
+        Some(docker) => match docker.inspect_image(reference).await {
+            Ok(snapshot) => return Ok(snapshot),
+            Err(e) => e,
+        },
+        None => anyhow::anyhow!("Docker is not available"),
+    };
+
+    // Pinned references (repo@sha256:...) match on digest; otherwise image and tag
+    let (name, digest) = match reference.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (reference, None),
+    };
+    let (image, tag) = parse_image_reference(name);
+
+    let tracked = history
+        .iter()
+        .filter(|s| match digest {
+            Some(digest) => s
+                .digest
+                .as_deref()
+                .is_some_and(|d| d.ends_with(digest)),
+            None => s.image == image && s.tag == tag,
+        })
+        .max_by_key(|s| s.timestamp);
+
+    match tracked {
+        Some(snapshot) => Ok(snapshot.clone()),
+        None => Err(local_error),
+    }
 }
 
 fn find_snapshot_by_commit<'a>(
@@ -68,21 +591,11 @@ fn find_snapshot_by_commit<'a>(
         .context(format!("No snapshot found for commit '{}'", commit))
 }
 
-fn find_latest_snapshot_by_branch<'a>(
-    history: &[&'a ImageSnapshot],
-    branch: &str,
-) -> Result<&'a ImageSnapshot> {
-    history
-        .iter()
-        .rev()
-        .find(|s| s.branch == branch)
-        .copied()
-        .context(format!("No snapshot found for branch '{}'", branch))
+fn auto_basis(diff: &SizeDiff) -> SizeBasis {
+    select_basis(&diff.before, &diff.after, BasisChoice::Auto).unwrap_or_default()
 }
 
-fn compute_diff(before: ImageSnapshot, after: ImageSnapshot) -> SizeDiff {
-    let total_delta = after.total_size as i64 - before.total_size as i64;
-
+pub fn compute_diff(before: ImageSnapshot, after: ImageSnapshot) -> SizeDiff {
     // Build maps of layers by digest for quick lookup
     let before_layers: HashMap<_, _> = before
         .layers
@@ -121,10 +634,492 @@ fn compute_diff(before: ImageSnapshot, after: ImageSnapshot) -> SizeDiff {
         }
     }
 
-    SizeDiff {
+    let mut diff = SizeDiff {
+        compressed_before: before.compressed_size,
+        compressed_after: after.compressed_size,
+        before,
+        after,
+        total_delta: 0,
+        layer_changes,
+        size_basis: SizeBasis::Uncompressed,
+        truncated: false,
+    };
+    let basis = auto_basis(&diff);
+    diff.set_basis(basis);
+    diff
+}
+
+/// Turn a removed layer and an added layer with the same command into one `Modified`
+/// change, in order, e.g. a `RUN` whose digest changed only because a layer below it did
+pub fn match_by_command(diff: &mut SizeDiff) {
+    let mut added: Vec<Option<LayerInfo>> = Vec::new();
+    let mut rest = Vec::new();
+    for change in diff.layer_changes.drain(..) {
+        match change {
+            LayerChange::Added(layer) => added.push(Some(layer)),
+            other => rest.push(other),
+        }
+    }
+
+    let mut layer_changes: Vec<LayerChange> = rest
+        .into_iter()
+        .map(|change| match change {
+            LayerChange::Removed(before) if !before.command.is_empty() => {
+                let paired = added
+                    .iter_mut()
+                    .find(|a| a.as_ref().is_some_and(|a| a.command == before.command))
+                    .and_then(Option::take);
+                match paired {
+                    Some(after) => LayerChange::Modified { before, after },
+                    None => LayerChange::Removed(before),
+                }
+            }
+            other => other,
+        })
+        .collect();
+
+    layer_changes.extend(added.into_iter().flatten().map(LayerChange::Added));
+    diff.layer_changes = layer_changes;
+}
+
+/// Pair layers by index (first with first, ...). Insertions shift every later pair, but
+/// when digests differ on every build this still shows which step changed and by how much.
+pub fn compute_diff_positional(before: ImageSnapshot, after: ImageSnapshot) -> SizeDiff {
+    let mut layer_changes = Vec::new();
+
+    for i in 0..before.layers.len().max(after.layers.len()) {
+        let change = match (before.layers.get(i), after.layers.get(i)) {
+            // Digests are expected to change from build to build here
+            (Some(b), Some(a)) if b.same_content(a) || b.same_build_step(a) => {
+                LayerChange::Unchanged(a.clone())
+            }
+            (Some(b), Some(a)) => LayerChange::Modified {
+                before: b.clone(),
+                after: a.clone(),
+            },
+            (Some(b), None) => LayerChange::Removed(b.clone()),
+            (None, Some(a)) => LayerChange::Added(a.clone()),
+            (None, None) => unreachable!(),
+        };
+        layer_changes.push(change);
+    }
+
+    let mut diff = SizeDiff {
+        compressed_before: before.compressed_size,
+        compressed_after: after.compressed_size,
+        before,
+        after,
+        total_delta: 0,
+        layer_changes,
+        size_basis: SizeBasis::Uncompressed,
+        truncated: false,
+    };
+    let basis = auto_basis(&diff);
+    diff.set_basis(basis);
+    diff
+}
+
+/// Position-aware variant of `compute_diff`: layers on the longest common subsequence are
+/// unchanged, and layers present on both sides but off that sequence are `Reordered`, since
+/// moving a layer breaks the build cache for everything after it. Layers are compared with
+/// [`LayerInfo::same_content`], so `<missing>` digests never pair on their own.
+pub fn compute_diff_ordered(before: ImageSnapshot, after: ImageSnapshot) -> SizeDiff {
+    let (n, m) = (before.layers.len(), after.layers.len());
+
+    // lcs[i][j] = LCS length of before[i..] and after[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if before.layers[i].same_content(&after.layers[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut matched_before = vec![None; n];
+    let mut matched_after = vec![false; m];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if before.layers[i].same_content(&after.layers[j]) {
+            matched_before[i] = Some(j);
+            matched_after[j] = true;
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    let mut layer_changes = Vec::new();
+
+    for (i, layer) in before.layers.iter().enumerate() {
+        if let Some(j) = matched_before[i] {
+            let after_layer = &after.layers[j];
+            if layer.size == after_layer.size {
+                layer_changes.push(LayerChange::Unchanged(layer.clone()));
+            } else {
+                layer_changes.push(LayerChange::Modified {
+                    before: layer.clone(),
+                    after: after_layer.clone(),
+                });
+            }
+            continue;
+        }
+
+        // Same layer elsewhere in the new image: it moved
+        let moved_to = (0..m).find(|&j| !matched_after[j] && after.layers[j].same_content(layer));
+        match moved_to {
+            Some(j) => {
+                matched_after[j] = true;
+                layer_changes.push(LayerChange::Reordered {
+                    layer: after.layers[j].clone(),
+                    from: i,
+                    to: j,
+                });
+            }
+            None => layer_changes.push(LayerChange::Removed(layer.clone())),
+        }
+    }
+
+    for (j, layer) in after.layers.iter().enumerate() {
+        if !matched_after[j] {
+            layer_changes.push(LayerChange::Added(layer.clone()));
+        }
+    }
+
+    let mut diff = SizeDiff {
+        compressed_before: before.compressed_size,
+        compressed_after: after.compressed_size,
         before,
         after,
-        total_delta,
+        total_delta: 0,
         layer_changes,
+        size_basis: SizeBasis::Uncompressed,
+        truncated: false,
+    };
+    let basis = auto_basis(&diff);
+    diff.set_basis(basis);
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::MockDockerClient;
+    use crate::fixtures::{layer, snapshot, tracked};
+    use crate::models::MISSING_DIGEST;
+
+    #[tokio::test]
+    async fn find_image_prefers_the_local_image() {
+        let docker = MockDockerClient::new().with_image("myapp:2.0", Ok(snapshot("myapp", "2.0", vec![layer("sha256:new", 50, "")])));
+        let history = vec![tracked("myapp", "2.0", "aaa", "main", 1, vec![layer("sha256:old", 10, "")])];
+
+        let found = find_image(Some(&docker), &history, "myapp:2.0").await.unwrap();
+        assert_eq!(found.total_size, 50);
+    }
+
+    #[tokio::test]
+    async fn find_image_falls_back_to_the_latest_tracked_snapshot() {
+        let docker = MockDockerClient::new();
+        let history = vec![
+            tracked("myapp", "1.0", "aaa", "main", 1, vec![]),
+            tracked("myapp", "1.0", "bbb", "main", 2, vec![]),
+            tracked("myapp", "2.0", "ccc", "main", 3, vec![]),
+        ];
+
+        let found = find_image(Some(&docker), &history, "myapp:1.0").await.unwrap();
+        assert_eq!(found.commit_sha, "bbb");
+    }
+
+    #[tokio::test]
+    async fn find_image_matches_pinned_references_by_digest() {
+        let mut pinned = tracked("myapp", "1.0", "aaa", "main", 1, vec![]);
+        pinned.digest = Some("myapp@sha256:feed".to_string());
+        let history = vec![pinned, tracked("myapp", "1.0", "bbb", "main", 2, vec![])];
+
+        let found = find_image(Some(&MockDockerClient::new()), &history, "myapp@sha256:feed")
+            .await
+            .unwrap();
+        assert_eq!(found.commit_sha, "aaa");
+    }
+
+    #[tokio::test]
+    async fn find_image_reports_the_local_error_when_nothing_is_tracked() {
+        let docker = MockDockerClient::new().with_image("gone:1", Err(anyhow::anyhow!("image not known")));
+
+        let error = find_image(Some(&docker), &[], "gone:1").await.unwrap_err();
+        assert!(error.to_string().contains("image not known"));
+    }
+
+    #[test]
+    fn digest_diff_pairs_layers_by_digest() {
+        let before = snapshot("myapp", "1.0", vec![layer("sha256:a", 100, "FROM"), layer("sha256:b", 10, "COPY")]);
+        let after = snapshot("myapp", "1.0", vec![layer("sha256:a", 100, "FROM"), layer("sha256:c", 30, "COPY")]);
+
+        let diff = compute_diff(before, after);
+
+        assert_eq!(diff.total_delta, 20);
+        assert!(matches!(diff.layer_changes[0], LayerChange::Unchanged(_)));
+        assert!(matches!(diff.layer_changes[1], LayerChange::Removed(_)));
+        assert!(matches!(diff.layer_changes[2], LayerChange::Added(_)));
+    }
+
+    #[test]
+    fn positional_diff_ignores_rebuilt_digests() {
+        let before = snapshot("myapp", "1.0", vec![layer("sha256:a", 100, "RUN build")]);
+        let after = snapshot("myapp", "1.0", vec![layer("sha256:b", 100, "RUN build")]);
+
+        let diff = compute_diff_positional(before, after);
+
+        assert!(matches!(diff.layer_changes[0], LayerChange::Unchanged(_)));
+    }
+
+    #[test]
+    fn positional_diff_reports_a_grown_layer_without_digests() {
+        let before = snapshot("myapp", "1.0", vec![layer(MISSING_DIGEST, 100, "RUN build")]);
+        let after = snapshot("myapp", "1.0", vec![layer(MISSING_DIGEST, 150, "RUN build")]);
+
+        let diff = compute_diff_positional(before, after);
+
+        assert_eq!(diff.total_delta, 50);
+        assert!(matches!(diff.layer_changes[0], LayerChange::Modified { .. }));
+    }
+
+    /// A local snapshot (uncompressed only), one with `--measure-extract` (both sizes) or
+    /// a registry-only one (compressed only)
+    fn sized(kind: &str) -> ImageSnapshot {
+        let mut snapshot = snapshot("myapp", "1.0", vec![layer("sha256:a", 300, "COPY")]);
+        match kind {
+            "local" => {}
+            "measured" => snapshot.compressed_size = Some(100),
+            "registry" => {
+                snapshot.total_size = 0;
+                snapshot.compressed_size = Some(100);
+                snapshot.registry_only = true;
+            }
+            _ => unreachable!(),
+        }
+        snapshot
+    }
+
+    #[test]
+    fn basis_selection_needs_a_size_on_both_sides() {
+        use BasisChoice::{Auto, Compressed, Uncompressed};
+        let ok = |basis: SizeBasis| Ok::<_, &str>(basis);
+        let cases = [
+            // both compressed
+            ("measured", "measured", Auto, ok(SizeBasis::Compressed)),
+            ("measured", "measured", Compressed, ok(SizeBasis::Compressed)),
+            ("measured", "measured", Uncompressed, ok(SizeBasis::Uncompressed)),
+            ("registry", "registry", Auto, ok(SizeBasis::Compressed)),
+            ("registry", "registry", Uncompressed, Err("the older snapshot only has its registry size")),
+            // both uncompressed
+            ("local", "local", Auto, ok(SizeBasis::Uncompressed)),
+            ("local", "local", Uncompressed, ok(SizeBasis::Uncompressed)),
+            ("local", "local", Compressed, Err("the older snapshot has none")),
+            // compressed before, uncompressed after
+            ("measured", "local", Auto, ok(SizeBasis::Uncompressed)),
+            ("measured", "local", Compressed, Err("the newer snapshot has none")),
+            ("registry", "local", Auto, Err("the older snapshot only has its registry size")),
+            ("registry", "local", Uncompressed, Err("the older snapshot only has its registry size")),
+            ("registry", "measured", Auto, ok(SizeBasis::Compressed)),
+            // uncompressed before, compressed after
+            ("local", "measured", Auto, ok(SizeBasis::Uncompressed)),
+            ("local", "measured", Compressed, Err("the older snapshot has none")),
+            ("local", "registry", Auto, Err("the newer snapshot only has its registry size")),
+            ("local", "registry", Compressed, Err("the older snapshot has none")),
+            ("measured", "registry", Uncompressed, Err("the newer snapshot only has its registry size")),
+        ];
+
+        for (before, after, choice, expected) in cases {
+            let selected = select_basis(&sized(before), &sized(after), choice).map_err(|e| e.to_string());
+            match (selected, expected) {
+                (Ok(basis), Ok(expected)) => assert_eq!(basis, expected, "{} -> {} with {:?}", before, after, choice),
+                (Err(error), Err(expected)) => {
+                    assert!(error.contains(expected), "{} -> {} with {:?}: {}", before, after, choice, error)
+                }
+                (selected, expected) => panic!("{} -> {} with {:?}: {:?}, expected {:?}", before, after, choice, selected, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn applying_a_basis_recomputes_the_delta() {
+        let mut before = sized("measured");
+        let mut after = sized("measured");
+        before.compressed_size = Some(100);
+        after.total_size = 500;
+        after.compressed_size = Some(160);
+        let mut diff = compute_diff(before, after);
+        assert_eq!((diff.size_basis, diff.total_delta), (SizeBasis::Compressed, 60));
+
+        apply_basis(&mut diff, BasisChoice::Uncompressed).unwrap();
+        assert_eq!((diff.size_basis, diff.total_delta), (SizeBasis::Uncompressed, 200));
+        assert_eq!((diff.before_size(), diff.after_size()), (300, 500));
+    }
+
+    #[test]
+    fn registry_and_local_snapshots_do_not_compare_uncompressed() {
+        // An index snapshot's registry size must not pass for an uncompressed size
+        let mut diff = compute_diff(sized("registry"), sized("local"));
+
+        assert!(apply_basis(&mut diff, BasisChoice::Auto).is_err());
+        assert!(apply_basis(&mut diff, BasisChoice::Uncompressed).is_err());
+    }
+
+    fn change_kinds(diff: &SizeDiff) -> Vec<&'static str> {
+        diff.layer_changes
+            .iter()
+            .map(|change| match change {
+                LayerChange::Added(_) => "added",
+                LayerChange::Removed(_) => "removed",
+                LayerChange::Modified { .. } => "modified",
+                LayerChange::Unchanged(_) => "unchanged",
+                LayerChange::Reordered { .. } => "reordered",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ordered_diff_does_not_pair_different_missing_layers() {
+        let before = snapshot("myapp", "1.0", vec![layer("sha256:base", 100, "FROM"), layer(MISSING_DIGEST, 10, "RUN apt-get install curl")]);
+        let after = snapshot("myapp", "1.0", vec![layer("sha256:base", 100, "FROM"), layer(MISSING_DIGEST, 30, "RUN pip install requests")]);
+
+        let diff = compute_diff_ordered(before, after);
+
+        assert_eq!(change_kinds(&diff), ["unchanged", "removed", "added"]);
+        assert_eq!(diff.total_delta, 20);
+    }
+
+    #[test]
+    fn ordered_diff_pairs_missing_layers_by_build_step() {
+        let copy = layer(MISSING_DIGEST, 5, "COPY . /app");
+        let install = layer(MISSING_DIGEST, 40, "RUN npm ci");
+        let before = snapshot("myapp", "1.0", vec![copy.clone(), install.clone()]);
+        let after = snapshot("myapp", "1.0", vec![install, copy]);
+
+        let diff = compute_diff_ordered(before, after);
+
+        // One of the two stays on the common sequence; the other moved around it
+        assert_eq!(change_kinds(&diff), ["reordered", "unchanged"]);
+        assert_eq!(diff.total_delta, 0);
+    }
+
+    /// The same image as the daemon's `docker history` lists it (zero-size CMD and
+    /// WORKDIR entries included) and as a registry manifest does (content layers only)
+    fn provider_fixture(name: &str) -> ImageSnapshot {
+        let path = format!("{}/tests/fixtures/providers/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn options() -> DiffOptions {
+        DiffOptions {
+            base_branch: None,
+            explain_baseline: false,
+            show_env_values: false,
+            layer_order_matters: false,
+            algorithm: DiffAlgorithm::Digest,
+            match_by_command: false,
+            max_changes: 0,
+            total_only: false,
+            compare_with_parent_commit: false,
+            against_k8s: None,
+            ignore_below: None,
+            ignore_below_pct: None,
+            verbose_if_below_threshold: false,
+            word_diff: false,
+            save_to_history: false,
+            layer_size_unit: LayerSizeUnit::Auto,
+            basis: BasisChoice::Auto,
+            unchanged_layers: UnchangedLayers::Summary,
+            left_file: None,
+            right_file: None,
+            output: OutputStyle::default(),
+        }
+    }
+
+    fn diff_of(before: ImageSnapshot, after: ImageSnapshot) -> DiffOutcome {
+        let dir = tempfile::TempDir::new().unwrap();
+        diff_snapshots(&Store::new(dir.path().join(".dit")), before, after, &options()).unwrap()
+    }
+
+    #[test]
+    fn daemon_and_registry_layer_lists_are_told_apart() {
+        let daemon = provider_fixture("daemon");
+        let registry = provider_fixture("registry");
+
+        assert!(layer_lists_differ(&daemon, &registry));
+        assert!(layer_lists_differ(&registry, &daemon));
+        assert!(!layer_lists_differ(&daemon, &daemon));
+        assert!(!layer_lists_differ(&registry, &registry));
+
+        // Nothing to reconcile when a side has no layers at all
+        let mut empty = registry.clone();
+        empty.layers.clear();
+        assert!(!layer_lists_differ(&daemon, &empty));
+    }
+
+    #[test]
+    fn normalization_keeps_only_content_layers() {
+        let (mut daemon, mut registry) = (provider_fixture("daemon"), provider_fixture("registry"));
+
+        assert_eq!(normalize_layer_lists(&mut daemon, &mut registry), Some(LayerNormalization::ContentLayers));
+        assert_eq!(daemon.layer_count, 3);
+        assert!(daemon.layers.iter().all(|l| l.size > 0));
+        let commands = |s: &ImageSnapshot| s.layers.iter().map(|l| l.command.clone()).collect::<Vec<_>>();
+        assert_eq!(commands(&daemon), commands(&registry));
+        assert_eq!(registry.layers.len(), 3);
+    }
+
+    #[test]
+    fn normalization_leaves_matching_lists_alone() {
+        let (mut before, mut after) = (provider_fixture("daemon"), provider_fixture("daemon"));
+
+        assert_eq!(normalize_layer_lists(&mut before, &mut after), None);
+        assert_eq!(before.layers.len(), 5);
+    }
+
+    #[test]
+    fn the_same_image_from_both_providers_is_unchanged() {
+        let outcome = diff_of(provider_fixture("daemon"), provider_fixture("registry"));
+
+        assert_eq!(outcome.normalization, Some(LayerNormalization::ContentLayers));
+        assert_eq!(outcome.diff.total_delta, 0);
+        assert_eq!(outcome.diff.layer_changes.len(), 3);
+        assert!(outcome.diff.layer_changes.iter().all(|c| matches!(c, LayerChange::Unchanged(_))));
+
+        // Without normalization every layer would be added or removed
+        let raw = compute_diff(provider_fixture("daemon"), provider_fixture("registry"));
+        assert!(raw.layer_changes.iter().filter(|c| !matches!(c, LayerChange::Unchanged(_))).count() >= 4);
+    }
+
+    #[test]
+    fn a_grown_content_layer_shows_through_normalization() {
+        let mut registry = provider_fixture("registry");
+        // The pip layer, which `docker history` lists without a digest
+        registry.layers[1].size += 2048;
+        registry.total_size += 2048;
+        let outcome = diff_of(registry, provider_fixture("daemon"));
+
+        assert_eq!(outcome.normalization, Some(LayerNormalization::ContentLayers));
+        assert_eq!(outcome.diff.total_delta, -2048);
+        let kinds: Vec<&str> = outcome.diff.layer_changes.iter().map(LayerChange::kind).collect();
+        assert_eq!(kinds, vec!["unchanged", "modified", "unchanged"]);
+    }
+
+    #[test]
+    fn mismatched_content_layers_fall_back_to_the_total() {
+        let mut registry = provider_fixture("registry");
+        registry.layers.push(layer("sha256:extra", 4096, "COPY assets /srv/assets"));
+        registry.total_size += 4096;
+        let outcome = diff_of(provider_fixture("daemon"), registry);
+
+        assert_eq!(outcome.normalization, Some(LayerNormalization::TotalOnly { before: 3, after: 4 }));
+        assert!(outcome.diff.layer_changes.is_empty());
+        assert_eq!(outcome.diff.total_delta, 4096);
     }
 }
@@ -1,9 +1,367 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use bollard::image::ListImagesOptions;
 use bollard::Docker;
 use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
+use std::sync::Mutex;
+#[cfg(any(test, feature = "mock"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::models::{ImageSnapshot, LayerInfo};
+use crate::image_cache::record_images;
+use crate::models::{oci_annotations, ImageOrigin, ImageSnapshot, LayerInfo, MISSING_DIGEST};
+use crate::platforms::manifest_download_size;
+use crate::reference::ImageRef;
+
+/// What to inspect, and the name to record it under
+#[derive(Debug, Clone)]
+pub struct ImageSpec {
+    /// Reference passed to Docker: `name:tag` or an image ID
+    pub reference: String,
+    /// Logical `name:tag` to record in history instead of the reference (`--as`)
+    pub logical_name: Option<String>,
+}
+
+impl ImageSpec {
+    pub fn new(reference: &str) -> Self {
+        Self {
+            reference: reference.to_string(),
+            logical_name: None,
+        }
+    }
+
+    /// Build from CLI args: either an image reference, or an `--iidfile` plus `--as` name
+    pub fn from_args(
+        image: Option<String>,
+        iidfile: Option<&str>,
+        as_name: Option<String>,
+    ) -> Result<Self> {
+        match (image, iidfile) {
+            (Some(image), None) => Ok(Self {
+                reference: image,
+                logical_name: as_name,
+            }),
+            (None, Some(path)) => {
+                let Some(name) = as_name else {
+                    bail!("--iidfile needs --as <name:tag> so history has a stable image name");
+                };
+                Ok(Self {
+                    reference: read_iidfile(path)?,
+                    logical_name: Some(name),
+                })
+            }
+            (Some(_), Some(_)) => bail!("Pass either an image or --iidfile, not both"),
+            (None, None) => bail!("Must provide an image or --iidfile"),
+        }
+    }
+
+    /// Name the snapshot will be recorded under
+    pub fn name(&self) -> &str {
+        self.logical_name.as_deref().unwrap_or(&self.reference)
+    }
+}
+
+/// Read an image ID written by `docker build --iidfile`, normalizing to `sha256:<hex>`
+pub fn read_iidfile(path: &str) -> Result<String> {
+    let content =
+        std::fs::read_to_string(path).context(format!("Failed to read iidfile: {}", path))?;
+    parse_iid(&content).context(format!("Invalid image ID in iidfile: {}", path))
+}
+
+fn parse_iid(content: &str) -> Result<String> {
+    let id = content.trim();
+    let hex = id.strip_prefix("sha256:").unwrap_or(id);
+
+    if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("expected 'sha256:<hex>', got '{}'", id);
+    }
+
+    Ok(format!("sha256:{}", hex.to_lowercase()))
+}
+
+/// A `KEY=VALUE` (or bare `KEY`) image label requirement
+#[derive(Debug, Clone)]
+pub struct LabelFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl std::str::FromStr for LabelFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((k, v)) => (k.trim(), Some(v.trim().to_string())),
+            None => (s.trim(), None),
+        };
+
+        if key.is_empty() {
+            return Err(format!("invalid label filter '{}': expected KEY=VALUE", s));
+        }
+
+        Ok(Self {
+            key: key.to_string(),
+            value,
+        })
+    }
+}
+
+/// Criteria for picking local images in multi-image commands; all parts must match
+#[derive(Debug, Default)]
+pub struct ImageFilter {
+    pub name: Option<String>,
+    pub tag_regex: Option<Regex>,
+    pub labels: Vec<LabelFilter>,
+}
+
+/// What commands need from Docker, so tests can swap in [`MockDockerClient`]
+pub trait ImageInspector: Sync {
+    fn inspect_image(&self, image: &str) -> impl Future<Output = Result<ImageSnapshot>> + Send;
+
+    /// Image ID of every tagged local image, keyed by `repo:tag`; empty when unknown
+    fn image_ids(&self) -> impl Future<Output = Result<HashMap<String, String>>> + Send {
+        async { Ok(HashMap::new()) }
+    }
+
+    /// Tagged local images, filtered by name substring and tag regex, sorted
+    fn list_all_images(
+        &self,
+        filter: Option<&str>,
+        tag_regex: Option<&Regex>,
+    ) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// Tagged images carrying label `key` (with `value`, if given)
+    fn list_images_by_label(
+        &self,
+        key: &str,
+        value: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// Stream `docker save` output for `reference` into `path`
+    fn export_image_to_file(
+        &self,
+        reference: &str,
+        path: &Path,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Local images matching every part of `filter`
+    fn select_images(&self, filter: &ImageFilter) -> impl Future<Output = Result<Vec<String>>> + Send {
+        async move {
+            let mut images = self
+                .list_all_images(filter.name.as_deref(), filter.tag_regex.as_ref())
+                .await?;
+
+            for label in &filter.labels {
+                let labelled = self
+                    .list_images_by_label(&label.key, label.value.as_deref())
+                    .await?;
+                images.retain(|i| labelled.contains(i));
+            }
+
+            Ok(images)
+        }
+    }
+
+    /// Inspect `spec.reference`, recording it under the logical name if one is given.
+    /// When inspecting by ID the image ID stands in for a missing repo digest.
+    fn inspect_spec(&self, spec: &ImageSpec) -> impl Future<Output = Result<ImageSnapshot>> + Send {
+        async move {
+            let mut snapshot = self.inspect_image(&spec.reference).await?;
+
+            if let Some(name) = &spec.logical_name {
+                let (image_name, tag) = parse_image_reference(name);
+                snapshot.image = image_name;
+                snapshot.tag = tag;
+                if snapshot.digest.is_none() {
+                    snapshot.digest = Some(spec.reference.clone());
+                }
+            }
+
+            Ok(snapshot)
+        }
+    }
+}
+
+/// Inspections made during one command run, keyed by image ID
+#[derive(Default)]
+struct InspectCache {
+    /// Local `repo:tag` → image ID, listed on the first lookup
+    ids: Option<HashMap<String, String>>,
+    snapshots: HashMap<String, ImageSnapshot>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Reuses the first inspection of each image ID for the rest of a command run, so other
+/// tags of the image and later passes don't inspect it again. The snapshot still carries
+/// the requested name.
+pub struct CachingInspector<I> {
+    inner: I,
+    /// `None` with `--no-cache`
+    cache: Option<Mutex<InspectCache>>,
+    /// Where listings refresh the completion image cache; `None` under `--read-only`
+    image_cache: Option<PathBuf>,
+}
+
+impl<I: ImageInspector> CachingInspector<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            cache: Some(Mutex::default()),
+            image_cache: None,
+        }
+    }
+
+    /// Refresh `<dir>/images.cache` with every local tag whenever images are listed
+    pub fn recording_images_in(mut self, dir: &Path) -> Self {
+        self.image_cache = Some(dir.to_path_buf());
+        self
+    }
+
+    /// `--no-cache`: inspect images every time they're asked for
+    pub fn without_cache(mut self) -> Self {
+        self.cache = None;
+        self
+    }
+
+    /// Forget every inspection; call it once images may have been rebuilt
+    pub fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock().unwrap();
+            cache.ids = None;
+            cache.snapshots.clear();
+        }
+    }
+
+    async fn inspect_cached(&self, image: &str) -> Result<ImageSnapshot> {
+        let Some(cache) = &self.cache else {
+            return self.inner.inspect_image(image).await;
+        };
+
+        let key = self.cache_key(cache, image).await;
+        let cached = {
+            let mut cache = cache.lock().unwrap();
+            let snapshot = cache.snapshots.get(&key).cloned();
+            if snapshot.is_some() {
+                cache.hits += 1;
+            } else {
+                cache.misses += 1;
+            }
+            tracing::debug!(
+                image,
+                hit = snapshot.is_some(),
+                hits = cache.hits,
+                misses = cache.misses,
+                "inspect cache"
+            );
+            snapshot
+        };
+
+        if let Some(mut snapshot) = cached {
+            let (name, tag) = parse_image_reference(image);
+            snapshot.image = name;
+            snapshot.tag = tag;
+            snapshot.timestamp = Utc::now();
+            return Ok(snapshot);
+        }
+
+        let snapshot = self.inner.inspect_image(image).await?;
+        let key = snapshot.image_id.clone().unwrap_or(key);
+        cache.lock().unwrap().snapshots.insert(key, snapshot.clone());
+        Ok(snapshot)
+    }
+
+    /// Image ID for `image` when it is a local tag, otherwise the reference itself
+    async fn cache_key(&self, cache: &Mutex<InspectCache>, image: &str) -> String {
+        let listed = cache.lock().unwrap().ids.is_some();
+        if !listed {
+            // A failed listing only costs cache hits between tags of one image
+            let ids = self.inner.image_ids().await.unwrap_or_default();
+            cache.lock().unwrap().ids.get_or_insert(ids);
+        }
+
+        let cache = cache.lock().unwrap();
+        let ids = cache.ids.as_ref().expect("listed above");
+        ids.get(image)
+            .or_else(|| ids.get(&format!("{}:latest", image)))
+            .cloned()
+            .unwrap_or_else(|| image.to_string())
+    }
+
+    /// Completion suggests every local image, not just the ones a filtered listing keeps
+    async fn record_all_images(&self) {
+        let Some(dir) = &self.image_cache else {
+            return;
+        };
+        let listed = self.cache.as_ref().and_then(|cache| cache.lock().unwrap().ids.clone());
+        let ids = match listed {
+            Some(ids) => ids,
+            None => match self.inner.image_ids().await {
+                Ok(ids) => {
+                    if let Some(cache) = &self.cache {
+                        cache.lock().unwrap().ids.get_or_insert(ids.clone());
+                    }
+                    ids
+                }
+                Err(_) => return,
+            },
+        };
+        record_images(dir, &ids.keys().cloned().collect::<Vec<_>>());
+    }
+}
+
+impl<I> Deref for CachingInspector<I> {
+    type Target = I;
+
+    fn deref(&self) -> &I {
+        &self.inner
+    }
+}
+
+impl<I: ImageInspector> ImageInspector for CachingInspector<I> {
+    fn inspect_image(&self, image: &str) -> impl Future<Output = Result<ImageSnapshot>> + Send {
+        self.inspect_cached(image)
+    }
+
+    async fn image_ids(&self) -> Result<HashMap<String, String>> {
+        let ids = self.inner.image_ids().await?;
+        if let Some(dir) = &self.image_cache {
+            record_images(dir, &ids.keys().cloned().collect::<Vec<_>>());
+        }
+        Ok(ids)
+    }
+
+    async fn list_all_images(
+        &self,
+        filter: Option<&str>,
+        tag_regex: Option<&Regex>,
+    ) -> Result<Vec<String>> {
+        let images = self.inner.list_all_images(filter, tag_regex).await?;
+        self.record_all_images().await;
+        Ok(images)
+    }
+
+    fn list_images_by_label(
+        &self,
+        key: &str,
+        value: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<String>>> + Send {
+        self.inner.list_images_by_label(key, value)
+    }
+
+    fn export_image_to_file(
+        &self,
+        reference: &str,
+        path: &Path,
+    ) -> impl Future<Output = Result<()>> + Send {
+        self.inner.export_image_to_file(reference, path)
+    }
+}
 
 pub struct DockerClient {
     client: Docker,
@@ -16,6 +374,7 @@ impl DockerClient {
         Ok(Self { client })
     }
 
+    #[tracing::instrument(name = "dit.inspect", skip(self))]
     pub async fn inspect_image(&self, image: &str) -> Result<ImageSnapshot> {
         let inspect = self
             .client
@@ -24,18 +383,27 @@ impl DockerClient {
             .context(format!("Failed to inspect image '{}'", image))?;
 
         // Extract basic metadata
+        let image_id = inspect.id.clone();
         let total_size = inspect.size.unwrap_or(0) as u64;
         let os = inspect.os.unwrap_or_else(|| "linux".to_string());
         let arch = inspect.architecture.unwrap_or_else(|| "amd64".to_string());
+        let variant = inspect.variant.filter(|v| !v.is_empty());
+        let host_platform = self.host_platform().await.ok();
+        let origin = detect_origin(
+            inspect.repo_digests.as_deref().unwrap_or_default(),
+            inspect.parent.as_deref(),
+        );
         let digest = inspect.repo_digests.and_then(|d| d.first().cloned());
+        let (env, labels, user) = match inspect.config {
+            Some(config) => (config.env, config.labels, config.user),
+            None => (None, None, None),
+        };
+        let labels: BTreeMap<String, String> = labels.unwrap_or_default().into_iter().collect();
+        // The local image store doesn't keep manifest annotations; labels carry provenance
+        let oci_annotations = oci_annotations(Some(&labels), None);
 
         // Parse tag from image name
-        let (image_name, tag) = if image.contains(':') {
-            let parts: Vec<&str> = image.split(':').collect();
-            (parts[0].to_string(), Some(parts[1].to_string()))
-        } else {
-            (image.to_string(), Some("latest".to_string()))
-        };
+        let (image_name, tag) = parse_image_reference(image);
 
         // Use image history API to get actual per-layer sizes and commands
         let history = self
@@ -62,14 +430,14 @@ impl DockerClient {
             // Parse the created timestamp
             let created = if entry.created > 0 {
                 DateTime::from_timestamp(entry.created, 0)
-                    .unwrap_or_else(|| Utc::now())
+                    .unwrap_or_else(Utc::now)
             } else {
                 Utc::now()
             };
 
             // Use the ID as digest, or generate a placeholder
             let layer_digest = if entry.id.is_empty() {
-                "<missing>".to_string()
+                MISSING_DIGEST.to_string()
             } else {
                 entry.id.clone()
             };
@@ -79,6 +447,8 @@ impl DockerClient {
                 size,
                 command,
                 created,
+                extract_ms: None,
+                file_count: None,
             });
         }
 
@@ -88,20 +458,125 @@ impl DockerClient {
             image: image_name,
             tag,
             digest,
+            image_id,
             commit_sha: String::new(),
             branch: String::new(),
             commit_message: String::new(),
             author: String::new(),
             timestamp: Utc::now(),
             total_size,
+            compressed_size: None,
+            registry_only: false,
             layer_count,
             layers,
             os,
             arch,
+            variant,
+            group_id: None,
+            host_platform,
+            env: Some(env.unwrap_or_default()),
+            labels: Some(labels),
+            user: Some(user.unwrap_or_default()),
+            annotations: Default::default(),
+            oci_annotations,
+            extract_ms: None,
+            file_count: None,
+            watched: false,
+            origin,
+            build_duration_ms: None,
         })
     }
 
-    pub async fn list_all_images(&self, filter: Option<&str>) -> Result<Vec<String>> {
+    /// `os/arch` the daemon runs on (images of another platform run under emulation)
+    pub async fn host_platform(&self) -> Result<String> {
+        let version = self
+            .client
+            .version()
+            .await
+            .context("Failed to query Docker version")?;
+
+        match (version.os, version.arch) {
+            (Some(os), Some(arch)) => Ok(format!("{}/{}", os, arch)),
+            _ => bail!("Docker did not report its platform"),
+        }
+    }
+
+    /// Compressed size of `image` for the daemon's platform, read from the registry
+    /// manifest before any pull
+    pub async fn image_size_estimate_from_manifest(&self, image: &str) -> Result<u64> {
+        let platform = self.host_platform().await?;
+        manifest_download_size(image, &platform)
+    }
+
+    /// Stream `docker save` output for `reference` into `path`
+    pub async fn export_image_to_file(&self, reference: &str, path: &Path) -> Result<()> {
+        let mut file = std::fs::File::create(path).context("Failed to create export file")?;
+        let mut stream = self.client.export_image(reference);
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.context(format!("Failed to export image '{}'", reference))?;
+            file.write_all(&chunk).context("Failed to write export file")?;
+        }
+
+        Ok(())
+    }
+
+    /// Image ID of every tagged local image, keyed by `repo:tag`
+    pub async fn image_ids(&self) -> Result<HashMap<String, String>> {
+        let images = self
+            .client
+            .list_images(Some(ListImagesOptions::<String>::default()))
+            .await
+            .context("Failed to list Docker images")?;
+
+        let ids: HashMap<String, String> = images
+            .into_iter()
+            .flat_map(|image| {
+                let id = image.id;
+                image
+                    .repo_tags
+                    .into_iter()
+                    .filter(|t| t != "<none>:<none>")
+                    .map(move |tag| (tag, id.clone()))
+            })
+            .collect();
+        Ok(ids)
+    }
+
+    /// Tagged images carrying label `key` (with `value`, if given)
+    pub async fn list_images_by_label(&self, key: &str, value: Option<&str>) -> Result<Vec<String>> {
+        let label = match value {
+            Some(v) => format!("{}={}", key, v),
+            None => key.to_string(),
+        };
+
+        let mut filters = HashMap::new();
+        filters.insert("label".to_string(), vec![label]);
+
+        let options = ListImagesOptions::<String> {
+            all: false,
+            filters,
+            ..Default::default()
+        };
+
+        let images = self
+            .client
+            .list_images(Some(options))
+            .await
+            .context("Failed to list Docker images by label")?;
+
+        Ok(images
+            .into_iter()
+            .flat_map(|i| i.repo_tags)
+            .filter(|t| t != "<none>:<none>")
+            .collect())
+    }
+
+    pub async fn list_all_images(
+        &self,
+        filter: Option<&str>,
+        tag_regex: Option<&Regex>,
+    ) -> Result<Vec<String>> {
         let options = ListImagesOptions::<String> {
             all: false,
             ..Default::default()
@@ -129,6 +604,17 @@ impl DockerClient {
                     }
                 }
 
+                // Apply tag regex to the tag portion only
+                if let Some(re) = tag_regex {
+                    let tag_part = match tag.rsplit_once(':') {
+                        Some((_, t)) if !t.contains('/') => t,
+                        _ => "latest",
+                    };
+                    if !re.is_match(tag_part) {
+                        continue;
+                    }
+                }
+
                 result.push(tag.clone());
             }
         }
@@ -139,8 +625,162 @@ impl DockerClient {
     }
 }
 
+impl ImageInspector for DockerClient {
+    fn inspect_image(&self, image: &str) -> impl Future<Output = Result<ImageSnapshot>> + Send {
+        DockerClient::inspect_image(self, image)
+    }
+
+    fn image_ids(&self) -> impl Future<Output = Result<HashMap<String, String>>> + Send {
+        DockerClient::image_ids(self)
+    }
+
+    fn list_all_images(
+        &self,
+        filter: Option<&str>,
+        tag_regex: Option<&Regex>,
+    ) -> impl Future<Output = Result<Vec<String>>> + Send {
+        DockerClient::list_all_images(self, filter, tag_regex)
+    }
+
+    fn list_images_by_label(
+        &self,
+        key: &str,
+        value: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<String>>> + Send {
+        DockerClient::list_images_by_label(self, key, value)
+    }
+
+    fn export_image_to_file(
+        &self,
+        reference: &str,
+        path: &Path,
+    ) -> impl Future<Output = Result<()>> + Send {
+        DockerClient::export_image_to_file(self, reference, path)
+    }
+}
+
+/// In-memory stand-in for `DockerClient` with pre-programmed inspect results
+#[cfg(any(test, feature = "mock"))]
+#[derive(Default)]
+pub struct MockDockerClient {
+    pub images: HashMap<String, Result<ImageSnapshot>>,
+    inspections: AtomicUsize,
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl MockDockerClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Program the result for `image`
+    pub fn with_image(mut self, image: &str, result: Result<ImageSnapshot>) -> Self {
+        self.images.insert(image.to_string(), result);
+        self
+    }
+
+    /// How many times `inspect_image` has been called
+    pub fn inspections(&self) -> usize {
+        self.inspections.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl ImageInspector for MockDockerClient {
+    async fn inspect_image(&self, image: &str) -> Result<ImageSnapshot> {
+        self.inspections.fetch_add(1, Ordering::Relaxed);
+        match self.images.get(image) {
+            Some(Ok(snapshot)) => Ok(snapshot.clone()),
+            Some(Err(e)) => Err(anyhow::anyhow!("{:#}", e)),
+            None => bail!("Failed to inspect image '{}'", image),
+        }
+    }
+
+    async fn image_ids(&self) -> Result<HashMap<String, String>> {
+        Ok(self
+            .images
+            .iter()
+            .filter_map(|(tag, inspected)| {
+                let id = inspected.as_ref().ok()?.image_id.clone()?;
+                Some((tag.clone(), id))
+            })
+            .collect())
+    }
+
+    async fn list_all_images(
+        &self,
+        filter: Option<&str>,
+        tag_regex: Option<&Regex>,
+    ) -> Result<Vec<String>> {
+        let mut result: Vec<String> = self
+            .images
+            .keys()
+            .filter(|tag| match filter {
+                Some(f) => tag.to_lowercase().contains(&f.to_lowercase()),
+                None => true,
+            })
+            .filter(|tag| match tag_regex {
+                Some(re) => re.is_match(parse_image_reference(tag).1.as_deref().unwrap_or("latest")),
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        result.sort();
+        Ok(result)
+    }
+
+    async fn list_images_by_label(&self, key: &str, value: Option<&str>) -> Result<Vec<String>> {
+        let mut result: Vec<String> = self
+            .images
+            .iter()
+            .filter(|(_, inspected)| {
+                let label = inspected.as_ref().ok().and_then(|s| s.label(key));
+                match value {
+                    Some(value) => label == Some(value),
+                    None => label.is_some(),
+                }
+            })
+            .map(|(tag, _)| tag.clone())
+            .collect();
+
+        result.sort();
+        Ok(result)
+    }
+
+    async fn export_image_to_file(&self, reference: &str, _path: &Path) -> Result<()> {
+        bail!("MockDockerClient can't export '{}'", reference)
+    }
+}
+
+/// Best-effort provenance from `docker image inspect`. The classic builder records a
+/// parent image ID for local builds; otherwise a repo digest means the image came from
+/// (or has been pushed to) a registry, and its absence means it was built here. Images
+/// from `docker load` have no repo digest either, so they count as built.
+pub fn detect_origin(repo_digests: &[String], parent: Option<&str>) -> ImageOrigin {
+    if parent.is_some_and(|p| !p.is_empty()) {
+        ImageOrigin::Built
+    } else if repo_digests.iter().any(|d| d.contains("@sha256:")) {
+        ImageOrigin::Pulled
+    } else {
+        ImageOrigin::Built
+    }
+}
+
+/// Split an image reference into its normalized name and tag. Registry ports stay in the
+/// name (`localhost:5000/myapp` + `1.2`); the tag defaults to "latest" unless the
+/// reference is pinned by digest alone (`app@sha256:…`), which has none.
+pub fn parse_image_reference(image: &str) -> (String, Option<String>) {
+    let reference = ImageRef::parse_lenient(image);
+    let tag = match (&reference.tag, &reference.digest) {
+        (None, Some(_)) => None,
+        _ => Some(reference.tag_or_latest().to_string()),
+    };
+    (reference.name(), tag)
+}
+
 /// Clean up Docker command strings for display
-fn clean_command(cmd: &str) -> String {
+pub fn clean_command(cmd: &str) -> String {
     let mut cleaned = cmd.to_string();
 
     // Remove /bin/sh -c #(nop) prefix (metadata commands like ENV, LABEL, etc.)
@@ -170,3 +810,213 @@ fn clean_command(cmd: &str) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::ImageInspect;
+    use serde_json::json;
+
+    /// What `inspect_image` records for a `docker image inspect` payload
+    fn origin_of(payload: serde_json::Value) -> ImageOrigin {
+        let inspect: ImageInspect = serde_json::from_value(payload).unwrap();
+        detect_origin(
+            inspect.repo_digests.as_deref().unwrap_or_default(),
+            inspect.parent.as_deref(),
+        )
+    }
+
+    const DIGEST: &str = "sha256:6c3c624b58dbbcd3c0dd82b4c53f04194d1247c6eebdaab7c610cf7d66709b3b";
+
+    #[test]
+    fn pulled_images_have_a_repo_digest_and_no_parent() {
+        let pulled = json!({
+            "Id": "sha256:39286ab8a5e14aeaf5fdd6e2fac76e0c8d31a0c07224f0ee5e6be502f12e93f3",
+            "RepoTags": ["nginx:1.27"],
+            "RepoDigests": [format!("nginx@{}", DIGEST)],
+            "Parent": "",
+            "Os": "linux",
+            "Architecture": "amd64",
+        });
+        assert_eq!(origin_of(pulled), ImageOrigin::Pulled);
+
+        // Newer engines omit an empty Parent altogether
+        let pulled = json!({
+            "RepoTags": ["ghcr.io/org/app:v1"],
+            "RepoDigests": [format!("ghcr.io/org/app@{}", DIGEST)],
+        });
+        assert_eq!(origin_of(pulled), ImageOrigin::Pulled);
+    }
+
+    #[test]
+    fn classic_builds_record_their_parent() {
+        let built = json!({
+            "RepoTags": ["myapp:dev"],
+            "RepoDigests": [],
+            "Parent": "sha256:b6e1b3d1f5a8c7e2d9f4a0c3b2e1d0f9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3",
+        });
+        assert_eq!(origin_of(built), ImageOrigin::Built);
+
+        // Still built here after a push gave it a repo digest
+        let pushed = json!({
+            "RepoTags": ["ghcr.io/org/app:v1"],
+            "RepoDigests": [format!("ghcr.io/org/app@{}", DIGEST)],
+            "Parent": "sha256:b6e1b3d1f5a8c7e2d9f4a0c3b2e1d0f9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3",
+        });
+        assert_eq!(origin_of(pushed), ImageOrigin::Built);
+    }
+
+    #[test]
+    fn buildkit_builds_and_loaded_images_have_no_repo_digest() {
+        let buildkit = json!({
+            "RepoTags": ["myapp:dev"],
+            "RepoDigests": [],
+            "Parent": "",
+        });
+        assert_eq!(origin_of(buildkit), ImageOrigin::Built);
+
+        // `docker load` of a saved archive
+        let loaded = json!({ "RepoTags": ["myapp:dev"] });
+        assert_eq!(origin_of(loaded), ImageOrigin::Built);
+    }
+
+    #[test]
+    fn a_buildkit_build_pushed_from_here_looks_pulled() {
+        // The documented blind spot: nothing in the payload tells the two apart
+        let pushed = json!({
+            "RepoTags": ["ghcr.io/org/app:v1"],
+            "RepoDigests": [format!("ghcr.io/org/app@{}", DIGEST)],
+        });
+        assert_eq!(origin_of(pushed), ImageOrigin::Pulled);
+    }
+
+    #[test]
+    fn repo_digests_without_a_digest_are_ignored() {
+        assert_eq!(detect_origin(&["myapp".to_string()], None), ImageOrigin::Built);
+        assert_eq!(detect_origin(&[], Some("")), ImageOrigin::Built);
+    }
+
+    /// `app:1.0` and `app:latest` tag one image ID; `db:16` is another image
+    fn tagged_images() -> MockDockerClient {
+        let tagged = |image: &str, tag: &str, id: &str| {
+            let mut snapshot = crate::fixtures::snapshot(image, tag, vec![]);
+            snapshot.image_id = Some(id.to_string());
+            Ok(snapshot)
+        };
+        MockDockerClient::new()
+            .with_image("app:1.0", tagged("app", "1.0", "sha256:app"))
+            .with_image("app:latest", tagged("app", "latest", "sha256:app"))
+            .with_image("db:16", tagged("db", "16", "sha256:db"))
+    }
+
+    #[tokio::test]
+    async fn repeated_inspections_hit_the_cache() {
+        let docker = CachingInspector::new(tagged_images());
+
+        docker.inspect_image("app:1.0").await.unwrap();
+        docker.inspect_image("app:1.0").await.unwrap();
+        assert_eq!(docker.inspections(), 1);
+
+        // Another tag of the same image ID reuses the inspection under its own name
+        let latest = docker.inspect_image("app:latest").await.unwrap();
+        assert_eq!(latest.tag.as_deref(), Some("latest"));
+        assert_eq!(docker.inspections(), 1);
+
+        docker.inspect_image("db:16").await.unwrap();
+        assert_eq!(docker.inspections(), 2);
+    }
+
+    #[tokio::test]
+    async fn invalidating_or_disabling_the_cache_inspects_again() {
+        let docker = CachingInspector::new(tagged_images());
+        docker.inspect_image("app:1.0").await.unwrap();
+        docker.invalidate_cache();
+        docker.inspect_image("app:1.0").await.unwrap();
+        assert_eq!(docker.inspections(), 2);
+
+        let docker = CachingInspector::new(tagged_images()).without_cache();
+        docker.inspect_image("app:1.0").await.unwrap();
+        docker.inspect_image("app:1.0").await.unwrap();
+        assert_eq!(docker.inspections(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_inspections_are_not_cached() {
+        let docker = CachingInspector::new(MockDockerClient::new());
+        assert!(docker.inspect_image("gone:1").await.is_err());
+        assert!(docker.inspect_image("gone:1").await.is_err());
+        assert_eq!(docker.inspections(), 2);
+    }
+
+    #[tokio::test]
+    async fn listings_record_every_local_image_only_when_asked() {
+        use crate::image_cache::read_image_cache;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        CachingInspector::new(tagged_images()).list_all_images(Some("db"), None).await.unwrap();
+        assert!(read_image_cache(dir.path()).is_none());
+
+        let docker = CachingInspector::new(tagged_images()).recording_images_in(dir.path());
+        assert_eq!(docker.list_all_images(Some("db"), None).await.unwrap(), vec!["db:16"]);
+        let cache = read_image_cache(dir.path()).unwrap();
+        assert_eq!(cache.images, vec!["app:1.0", "app:latest", "db:16"]);
+    }
+
+    const IID: &str = "sha256:39286ab8a5e14aeaf5fdd6e2fac76e0c8d31a0c07224f0ee5e6be502f12e93f3";
+
+    #[test]
+    fn image_ids_are_normalized_to_lowercase_sha256() {
+        let hex = IID.strip_prefix("sha256:").unwrap();
+        for content in [IID, hex, &format!("{}\n", IID), &format!("  {}\r\n", hex.to_uppercase())] {
+            assert_eq!(parse_iid(content).unwrap(), IID, "{:?}", content);
+        }
+
+        for invalid in ["", "\n", "sha256:", "sha256:xyz", "myapp:1.0"] {
+            assert!(parse_iid(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn iidfiles_name_the_file_in_errors() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let iidfile = dir.path().join("iid");
+        let path = iidfile.to_str().unwrap();
+
+        std::fs::write(&iidfile, format!("{}\n", IID)).unwrap();
+        assert_eq!(read_iidfile(path).unwrap(), IID);
+
+        std::fs::write(&iidfile, "").unwrap();
+        let error = read_iidfile(path).unwrap_err();
+        assert_eq!(error.to_string(), format!("Invalid image ID in iidfile: {}", path));
+        assert_eq!(error.root_cause().to_string(), "expected 'sha256:<hex>', got ''");
+
+        let missing = dir.path().join("missing");
+        let error = read_iidfile(missing.to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().starts_with("Failed to read iidfile"), "{}", error);
+    }
+
+    #[test]
+    fn specs_come_from_an_image_or_an_iidfile_with_a_name() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let iidfile = dir.path().join("iid");
+        std::fs::write(&iidfile, IID).unwrap();
+        let iidfile = iidfile.to_str();
+
+        let spec = ImageSpec::from_args(Some("myapp:1.0".to_string()), None, None).unwrap();
+        assert_eq!((spec.reference.as_str(), spec.name()), ("myapp:1.0", "myapp:1.0"));
+
+        // --as renames what's recorded, not what's inspected
+        let renamed = Some("myapp:ci".to_string());
+        let spec = ImageSpec::from_args(Some("myapp:1.0".to_string()), None, renamed).unwrap();
+        assert_eq!((spec.reference.as_str(), spec.name()), ("myapp:1.0", "myapp:ci"));
+
+        let spec = ImageSpec::from_args(None, iidfile, Some("myapp:ci".to_string())).unwrap();
+        assert_eq!((spec.reference.as_str(), spec.name()), (IID, "myapp:ci"));
+
+        let error = ImageSpec::from_args(None, iidfile, None).unwrap_err();
+        assert!(error.to_string().contains("--iidfile needs --as"), "{}", error);
+        let both = ImageSpec::from_args(Some("myapp:1.0".to_string()), iidfile, None);
+        assert!(both.is_err());
+        assert!(ImageSpec::from_args(None, None, None).is_err());
+    }
+}
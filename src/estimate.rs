@@ -0,0 +1,42 @@
+use anyhow::Result;
+use std::io::Write;
+
+use crate::docker::DockerClient;
+use crate::format::format_size;
+use crate::platforms::manifest_download_size;
+
+/// Typical gzip ratio of image layers, used to guess the unpacked size
+pub const TYPICAL_COMPRESSION_RATIO: f64 = 2.5;
+
+/// `dit size-estimate`: download and on-disk size of `image` from its registry manifest,
+/// before pulling. Without `platform` the Docker host's platform is used.
+pub async fn size_estimate(
+    out: &mut impl Write,
+    image: &str,
+    platform: Option<&str>,
+) -> Result<()> {
+    let compressed = match platform {
+        Some(platform) => manifest_download_size(image, platform)?,
+        None => {
+            DockerClient::new()?
+                .image_size_estimate_from_manifest(image)
+                .await?
+        }
+    };
+    let uncompressed = (compressed as f64 * TYPICAL_COMPRESSION_RATIO) as u64;
+
+    writeln!(out, "Image: {}", image)?;
+    writeln!(
+        out,
+        "Download size: {} (compressed, from the registry manifest)",
+        format_size(compressed)
+    )?;
+    writeln!(
+        out,
+        "Size on disk: ~{} (estimated at {}x compression)",
+        format_size(uncompressed),
+        TYPICAL_COMPRESSION_RATIO
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use regex::Regex;
+
+use crate::config::DitConfig;
+use crate::models::ImageSnapshot;
+use crate::paths::build_globset;
+
+/// Snapshots to hide from history, charts and summaries (e.g. nightly dependency-bot
+/// rebuilds): `--exclude-author` globs and `--exclude-message` regexes, plus
+/// `exclude_authors` / `exclude_messages` in .dit.toml
+#[derive(Debug, Clone)]
+pub struct SnapshotExclusions {
+    authors: GlobSet,
+    messages: Vec<Regex>,
+    empty: bool,
+}
+
+impl SnapshotExclusions {
+    pub fn new(authors: &[String], messages: &[String]) -> Result<Self> {
+        let messages = messages
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).context(format!("Invalid --exclude-message regex '{}'", pattern))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(SnapshotExclusions {
+            authors: build_globset(authors)?,
+            empty: authors.is_empty() && messages.is_empty(),
+            messages,
+        })
+    }
+
+    /// Flags on top of the .dit.toml lists
+    pub fn from_config(config: &DitConfig, authors: &[String], messages: &[String]) -> Result<Self> {
+        let authors = [config.exclude_authors.as_slice(), authors].concat();
+        let messages = [config.exclude_messages.as_slice(), messages].concat();
+        Self::new(&authors, &messages)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    /// Authors are recorded as `Name <email>`; a glob matching either the whole string
+    /// or just the name excludes the snapshot
+    pub fn excludes(&self, snapshot: &ImageSnapshot) -> bool {
+        let name = snapshot
+            .author
+            .split_once(" <")
+            .map_or(snapshot.author.as_str(), |(name, _)| name);
+
+        (!snapshot.author.is_empty()
+            && (self.authors.is_match(&snapshot.author) || self.authors.is_match(name)))
+            || self.messages.iter().any(|re| re.is_match(&snapshot.commit_message))
+    }
+
+    pub fn apply(&self, snapshots: Vec<ImageSnapshot>) -> Vec<ImageSnapshot> {
+        if self.empty {
+            return snapshots;
+        }
+        snapshots.into_iter().filter(|s| !self.excludes(s)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{at, tracked};
+    use crate::history::{group_by_branch_name, load_image_history};
+    use crate::store::Store;
+    use tempfile::TempDir;
+
+    fn by(author: &str, message: &str, commit: &str, branch: &str, minutes: i64) -> ImageSnapshot {
+        let mut snapshot = tracked("app", "v1", commit, branch, minutes, Vec::new());
+        snapshot.author = author.to_string();
+        snapshot.commit_message = message.to_string();
+        snapshot
+    }
+
+    fn exclusions(authors: &[&str], messages: &[&str]) -> SnapshotExclusions {
+        let strings = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        SnapshotExclusions::new(&strings(authors), &strings(messages)).unwrap()
+    }
+
+    #[test]
+    fn author_globs_match_the_name_or_the_whole_author() {
+        let dependabot = by("dependabot[bot] <49699333+dependabot[bot]@users.noreply.github.com>", "", "a", "main", 0);
+        let alice = by("Alice <alice@example.com>", "", "b", "main", 1);
+
+        assert!(exclusions(&["dependabot*"], &[]).excludes(&dependabot));
+        assert!(!exclusions(&["dependabot*"], &[]).excludes(&alice));
+        assert!(exclusions(&["*@example.com>"], &[]).excludes(&alice));
+        // Globs are anchored: a substring alone doesn't match
+        assert!(!exclusions(&["bot"], &[]).excludes(&dependabot));
+    }
+
+    #[test]
+    fn unknown_authors_are_never_excluded_by_author() {
+        let unknown = by("", "", "a", "main", 0);
+        assert!(!exclusions(&["*"], &[]).excludes(&unknown));
+    }
+
+    #[test]
+    fn message_regexes_match_anywhere() {
+        let bot = by("Renovate", "chore(deps): bump openssl [bot]", "a", "main", 0);
+        let human = by("Alice", "Fix the bot detection", "b", "main", 1);
+
+        let messages = exclusions(&[], &[r"\[bot\]"]);
+        assert!(messages.excludes(&bot));
+        assert!(!messages.excludes(&human));
+        // A regex, not a glob: `*` alone is invalid
+        assert!(SnapshotExclusions::new(&[], &["*".to_string()]).is_err());
+    }
+
+    #[test]
+    fn any_pattern_excludes() {
+        let both = exclusions(&["renovate*"], &["^chore"]);
+        assert!(both.excludes(&by("renovate-bot", "Update", "a", "main", 0)));
+        assert!(both.excludes(&by("Alice", "chore: tidy", "b", "main", 1)));
+        assert!(!both.excludes(&by("Alice", "feat: tidy", "c", "main", 2)));
+    }
+
+    #[test]
+    fn config_lists_add_to_flags() {
+        let config = DitConfig {
+            exclude_authors: vec!["dependabot*".to_string()],
+            ..Default::default()
+        };
+        let combined =
+            SnapshotExclusions::from_config(&config, &[], &[r"\[skip size\]".to_string()]).unwrap();
+        assert!(combined.excludes(&by("dependabot[bot]", "", "a", "main", 0)));
+        assert!(combined.excludes(&by("Alice", "docs [skip size]", "b", "main", 1)));
+        assert!(SnapshotExclusions::from_config(&DitConfig::default(), &[], &[]).unwrap().is_empty());
+    }
+
+    fn store_with(history: &[ImageSnapshot], exclusions: SnapshotExclusions) -> (TempDir, Store) {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        store.save_history(history).unwrap();
+        (dir, store.with_exclusions(exclusions))
+    }
+
+    fn history() -> Vec<ImageSnapshot> {
+        vec![
+            by("Alice", "Add the API", "h1", "main", 0),
+            by("dependabot[bot]", "Bump serde", "b1", "main", 1),
+            by("Bob", "Feature work", "f1", "feature", 2),
+            by("dependabot[bot]", "Bump tokio", "b2", "feature", 3),
+            by("Alice", "Tune the image", "h2", "main", 4),
+            by("Carol", "Nightly rebuild [bot]", "b3", "main", 5),
+        ]
+    }
+
+    fn commits(history: &[ImageSnapshot]) -> Vec<&str> {
+        history.iter().map(|s| s.commit_sha.as_str()).collect()
+    }
+
+    #[test]
+    fn excluded_snapshots_are_counted_and_hidden_from_loads() {
+        let (_dir, store) = store_with(&history(), exclusions(&["dependabot*"], &[r"\[bot\]"]));
+        assert_eq!(store.excluded_count().unwrap(), 3);
+        assert_eq!(commits(&store.load_history().unwrap()), vec!["h1", "f1", "h2"]);
+    }
+
+    #[test]
+    fn limits_apply_after_exclusion() {
+        let (_dir, store) = store_with(&history(), exclusions(&["dependabot*"], &[r"\[bot\]"]));
+        let last_two = load_image_history(&store, "app:v1", Some(2)).unwrap();
+        assert_eq!(commits(&last_two), vec!["f1", "h2"]);
+    }
+
+    #[test]
+    fn branch_grouping_sees_the_filtered_view() {
+        let (_dir, store) = store_with(&history(), exclusions(&["dependabot*"], &[]));
+        let series = group_by_branch_name(store.load_history().unwrap(), None);
+        let branches: Vec<(&str, Vec<&str>)> = series
+            .iter()
+            .map(|s| (s.branch.as_str(), commits(&s.snapshots)))
+            .collect();
+        assert_eq!(branches, vec![("feature", vec!["f1"]), ("main", vec!["h1", "h2", "b3"])]);
+    }
+
+    #[test]
+    fn date_cutoffs_combine_with_exclusions() {
+        let (_dir, store) = store_with(&history(), exclusions(&["dependabot*"], &[]));
+        let recent: Vec<ImageSnapshot> = store
+            .load_history()
+            .unwrap()
+            .into_iter()
+            .filter(|s| s.timestamp >= at(2))
+            .collect();
+        assert_eq!(commits(&recent), vec!["f1", "h2", "b3"]);
+    }
+
+    #[test]
+    fn writes_keep_excluded_snapshots() {
+        let (_dir, store) = store_with(&history(), exclusions(&["dependabot*"], &[]));
+        store.save_snapshot(&by("Alice", "More work", "h3", "main", 6)).unwrap();
+
+        let unfiltered = Store::new(store.dir().to_path_buf());
+        assert_eq!(unfiltered.load_history().unwrap().len(), 7);
+        assert_eq!(store.load_history().unwrap().len(), 5);
+    }
+}
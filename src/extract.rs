@@ -0,0 +1,691 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+use tempfile::NamedTempFile;
+
+use crate::docker::ImageInspector;
+use crate::models::ImageSnapshot;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Entries estargz appends to a gzip layer; metadata, not image content
+const ESTARGZ_ENTRIES: [&str; 3] = [
+    "stargz.index.json",
+    ".prefetch.landmark",
+    ".no.prefetch.landmark",
+];
+
+/// How a layer blob is compressed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl LayerCompression {
+    /// Map an OCI or Docker layer media type. Estargz layers use the gzip types.
+    pub fn from_media_type(media_type: &str) -> Result<Self> {
+        match media_type {
+            "application/vnd.oci.image.layer.v1.tar"
+            | "application/vnd.oci.image.layer.nondistributable.v1.tar"
+            | "application/vnd.docker.image.rootfs.diff.tar" => Ok(LayerCompression::None),
+            "application/vnd.oci.image.layer.v1.tar+gzip"
+            | "application/vnd.oci.image.layer.nondistributable.v1.tar+gzip"
+            | "application/vnd.docker.image.rootfs.diff.tar.gzip"
+            | "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip" => {
+                Ok(LayerCompression::Gzip)
+            }
+            "application/vnd.oci.image.layer.v1.tar+zstd"
+            | "application/vnd.oci.image.layer.nondistributable.v1.tar+zstd" => {
+                Ok(LayerCompression::Zstd)
+            }
+            other => bail!(
+                "Unsupported layer media type '{}' (supported: tar, tar+gzip, tar+zstd)",
+                other
+            ),
+        }
+    }
+
+    /// Guess from the first bytes of a blob
+    fn sniff(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            LayerCompression::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            LayerCompression::Zstd
+        } else {
+            LayerCompression::None
+        }
+    }
+}
+
+/// Extraction cost of one layer blob. Timings are machine-dependent.
+#[derive(Debug, Clone, Default)]
+pub struct LayerMeasurement {
+    pub extract_ms: u64,
+    pub file_count: u64,
+    /// Blob size from the OCI descriptor, when the archive has one
+    pub compressed_size: Option<u64>,
+    pub compression: Option<LayerCompression>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveManifest {
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OciDescriptor {
+    #[serde(rename = "mediaType")]
+    media_type: String,
+    digest: String,
+    size: u64,
+}
+
+/// Either an OCI index (`manifests`) or an image manifest (`layers`)
+#[derive(Debug, Deserialize)]
+struct OciDocument {
+    #[serde(default)]
+    manifests: Vec<OciDescriptor>,
+    #[serde(default)]
+    layers: Vec<OciDescriptor>,
+}
+
+/// Export `reference` via `docker save` and annotate the snapshot's layers with
+/// decompression time and file counts. Zero-size (metadata) layers are left unmeasured.
+pub async fn measure_extract(
+    docker: &impl ImageInspector,
+    reference: &str,
+    snapshot: &mut ImageSnapshot,
+) -> Result<()> {
+    let archive = export_to_temp_file(docker, reference).await?;
+    let measurements = measure_archive(archive.path())?;
+
+    let content_layers: Vec<_> = snapshot.layers.iter_mut().filter(|l| l.size > 0).collect();
+    if content_layers.len() != measurements.len() {
+        eprintln!(
+            "⚠️  History has {} non-empty layers but the export has {}; matching by position",
+            content_layers.len(),
+            measurements.len()
+        );
+    }
+
+    for (layer, measurement) in content_layers.into_iter().zip(&measurements) {
+        layer.extract_ms = Some(measurement.extract_ms);
+        layer.file_count = Some(measurement.file_count);
+    }
+
+    snapshot.extract_ms = Some(measurements.iter().map(|m| m.extract_ms).sum());
+    snapshot.file_count = Some(measurements.iter().map(|m| m.file_count).sum());
+
+    // Only OCI-layout exports carry blob sizes; an all-tar export says nothing about compression
+    let any_compressed = measurements
+        .iter()
+        .any(|m| m.compression.is_some_and(|c| c != LayerCompression::None));
+    if any_compressed {
+        snapshot.compressed_size = measurements.iter().map(|m| m.compressed_size).sum();
+    }
+
+    Ok(())
+}
+
+/// Export `reference` via `docker save` and list the files of each layer, base first
+#[tracing::instrument(name = "dit.export", skip(docker))]
+pub async fn export_layer_files(
+    docker: &impl ImageInspector,
+    reference: &str,
+) -> Result<Vec<Vec<(PathBuf, u64)>>> {
+    let archive = export_to_temp_file(docker, reference).await?;
+    archive_layer_files(archive.path())
+}
+
+/// `docker save` `reference` into a fresh temp file of its own, so concurrent exports never
+/// share a path; the file is removed when the handle drops
+async fn export_to_temp_file(
+    docker: &impl ImageInspector,
+    reference: &str,
+) -> Result<NamedTempFile> {
+    let archive = tempfile::Builder::new()
+        .prefix("dit-export-")
+        .suffix(".tar")
+        .tempfile()
+        .context("Failed to create a temp file for the exported image")?;
+    docker.export_image_to_file(reference, archive.path()).await?;
+    Ok(archive)
+}
+
+/// Measure every layer listed in a `docker save` archive, in manifest (base-first) order
+pub fn measure_archive(path: &Path) -> Result<Vec<LayerMeasurement>> {
+    read_archive_layers(path, |entry, descriptor| {
+        let compression = descriptor
+            .map(|d| LayerCompression::from_media_type(&d.media_type))
+            .transpose()?;
+        let mut measurement = measure_layer(entry, compression)?;
+        measurement.compressed_size = descriptor.map(|d| d.size);
+        Ok(measurement)
+    })
+}
+
+/// Files (path, size) of every layer in a `docker save` archive, in manifest (base-first)
+/// order, including whiteout entries
+pub fn archive_layer_files(path: &Path) -> Result<Vec<Vec<(PathBuf, u64)>>> {
+    read_archive_layers(path, |entry, descriptor| {
+        let compression = descriptor
+            .map(|d| LayerCompression::from_media_type(&d.media_type))
+            .transpose()?;
+        let mut files = Vec::new();
+        walk_layer(entry, compression, |path, size| {
+            files.push((path.to_path_buf(), size));
+            Ok(())
+        })?;
+        Ok(files)
+    })
+}
+
+/// Run `read` on each layer blob of a `docker save` archive (with its OCI descriptor, when
+/// the export has one) and return the results in manifest order
+fn read_archive_layers<T: Clone>(
+    path: &Path,
+    mut read: impl FnMut(&mut dyn Read, Option<&OciDescriptor>) -> Result<T>,
+) -> Result<Vec<T>> {
+    let (manifest, descriptors) = read_save_metadata(path)?;
+
+    let file = File::open(path).context("Failed to open exported image")?;
+    let mut archive = tar::Archive::new(file);
+
+    let mut measured: HashMap<PathBuf, T> = HashMap::new();
+    let mut links: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+    for entry in archive.entries().context("Failed to read exported image")? {
+        let mut entry = entry?;
+        let entry_path = normalize(&entry.path()?);
+
+        if !manifest.layers.iter().any(|l| normalize(Path::new(l)) == entry_path) {
+            continue;
+        }
+
+        // Legacy saves symlink duplicate layers to the first copy
+        if entry.header().entry_type().is_symlink() {
+            if let Some(target) = entry.link_name()? {
+                let parent = entry_path.parent().unwrap_or(Path::new(""));
+                links.insert(entry_path.clone(), normalize(&parent.join(target)));
+            }
+            continue;
+        }
+
+        // Mixed-compression images are fine: each layer is dispatched on its own type
+        let result = read(&mut entry, descriptors.get(&entry_path))
+            .context(format!("Failed to read layer {}", entry_path.display()))?;
+        measured.insert(entry_path, result);
+    }
+
+    manifest
+        .layers
+        .iter()
+        .map(|layer| {
+            let path = normalize(Path::new(layer));
+            let path = links.get(&path).unwrap_or(&path);
+            measured
+                .get(path)
+                .cloned()
+                .context(format!("Layer {} missing from exported image", layer))
+        })
+        .collect()
+}
+
+/// Walk a layer tar, reading every entry; returns wall time and entry count.
+/// `compression` comes from the media type when known, otherwise it is sniffed.
+pub fn measure_layer<R: Read>(
+    reader: R,
+    compression: Option<LayerCompression>,
+) -> Result<LayerMeasurement> {
+    let start = Instant::now();
+    let file_count = walk_layer(reader, compression, |_, _| Ok(()))?;
+
+    Ok(LayerMeasurement {
+        extract_ms: start.elapsed().as_millis() as u64,
+        file_count,
+        compressed_size: None,
+        compression,
+    })
+}
+
+/// Shared layer reader: decompresses (tar, gzip, estargz or zstd), then calls
+/// `visit(path, size)` for each entry after draining its contents. Returns the number of
+/// entries, excluding estargz metadata.
+pub fn walk_layer<R: Read>(
+    reader: R,
+    compression: Option<LayerCompression>,
+    mut visit: impl FnMut(&Path, u64) -> Result<()>,
+) -> Result<u64> {
+    let mut reader = BufReader::new(reader);
+    let compression = match compression {
+        Some(c) => c,
+        None => LayerCompression::sniff(reader.fill_buf()?),
+    };
+
+    // estargz is a series of gzip members, so a single-member decoder would stop early
+    let inner: Box<dyn Read + '_> = match compression {
+        LayerCompression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        LayerCompression::Zstd => {
+            Box::new(zstd::Decoder::with_buffer(reader).context("Failed to start zstd decoder")?)
+        }
+        LayerCompression::None => Box::new(reader),
+    };
+
+    let mut archive = tar::Archive::new(inner);
+    let mut count = 0u64;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let size = io::copy(&mut entry, &mut io::sink())?;
+        let path = entry.path()?;
+
+        if ESTARGZ_ENTRIES.iter().any(|e| path == Path::new(e)) {
+            continue;
+        }
+
+        visit(&path, size)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Read `manifest.json` and, for OCI-layout exports, the layer descriptors reachable from
+/// `index.json`, keyed by blob path
+fn read_save_metadata(path: &Path) -> Result<(SaveManifest, HashMap<PathBuf, OciDescriptor>)> {
+    let file = File::open(path).context("Failed to open exported image")?;
+    let mut archive = tar::Archive::new(file);
+
+    let mut manifest = None;
+    let mut documents: HashMap<PathBuf, OciDocument> = HashMap::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let entry_path = normalize(&entry.path()?);
+
+        if entry_path == Path::new("manifest.json") {
+            let mut manifests: Vec<SaveManifest> =
+                serde_json::from_reader(entry).context("Failed to parse manifest.json")?;
+            if manifests.is_empty() {
+                bail!("manifest.json in exported image is empty");
+            }
+            manifest = Some(manifests.remove(0));
+        } else if entry_path == Path::new("index.json")
+            || (entry_path.starts_with("blobs") && entry.size() < 1024 * 1024)
+        {
+            // Small blobs are manifests or configs; anything that isn't JSON is skipped
+            if let Ok(document) = serde_json::from_reader(BufReader::new(entry)) {
+                documents.insert(entry_path, document);
+            }
+        }
+    }
+
+    let manifest = manifest.context("Exported image has no manifest.json")?;
+
+    let mut descriptors = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![PathBuf::from("index.json")];
+    while let Some(doc_path) = pending.pop() {
+        if !visited.insert(doc_path.clone()) {
+            continue;
+        }
+        let Some(document) = documents.get(&doc_path) else {
+            continue;
+        };
+        for child in &document.manifests {
+            pending.push(blob_path(&child.digest));
+        }
+        for layer in &document.layers {
+            descriptors.insert(blob_path(&layer.digest), layer.clone());
+        }
+    }
+
+    Ok((manifest, descriptors))
+}
+
+/// `sha256:abc…` → `blobs/sha256/abc…`
+fn blob_path(digest: &str) -> PathBuf {
+    let (algorithm, hex) = digest.split_once(':').unwrap_or(("sha256", digest));
+    Path::new("blobs").join(algorithm).join(hex)
+}
+
+/// Resolve `.` and `..` components so tar paths and symlink targets compare equal
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::Normal(part) => out.push(part),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// An uncompressed layer tar holding `files`
+    fn layer_tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// A `docker save` archive of `entries`; an `Err` body is a symlink to that target
+    fn save_archive(dir: &TempDir, entries: &[(&str, Result<Vec<u8>, &str>)]) -> PathBuf {
+        let path = dir.path().join("image.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        for (name, body) in entries {
+            let mut header = tar::Header::new_gnu();
+            match body {
+                Ok(content) => {
+                    header.set_size(content.len() as u64);
+                    header.set_mode(0o644);
+                    builder.append_data(&mut header, name, content.as_slice()).unwrap();
+                }
+                Err(target) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_size(0);
+                    builder.append_link(&mut header, name, target).unwrap();
+                }
+            }
+        }
+        builder.finish().unwrap();
+        path
+    }
+
+    fn manifest(layers: &[&str]) -> Result<Vec<u8>, &'static str> {
+        Ok(serde_json::to_vec(&serde_json::json!([{ "Layers": layers }])).unwrap())
+    }
+
+    fn paths(files: &[(PathBuf, u64)]) -> Vec<(&str, u64)> {
+        files.iter().map(|(path, size)| (path.to_str().unwrap(), *size)).collect()
+    }
+
+    #[test]
+    fn walking_a_layer_visits_every_entry_with_its_size() {
+        let tar = layer_tar(&[("etc/os-release", b"ID=test\n"), ("app/bin", b"\x7fELF")]);
+        let mut visited = Vec::new();
+
+        let count = walk_layer(tar.as_slice(), None, |path, size| {
+            visited.push((path.to_path_buf(), size));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(paths(&visited), vec![("etc/os-release", 8), ("app/bin", 4)]);
+    }
+
+    #[test]
+    fn legacy_saves_list_layers_in_manifest_order() {
+        let dir = TempDir::new().unwrap();
+        let archive = save_archive(
+            &dir,
+            &[
+                // Stored out of order; the manifest decides
+                ("top/layer.tar", Ok(layer_tar(&[("app/main", b"main")]))),
+                ("base/layer.tar", Ok(layer_tar(&[("bin/sh", b"shell"), ("etc/passwd", b"root")]))),
+                ("manifest.json", manifest(&["base/layer.tar", "./top/layer.tar"])),
+            ],
+        );
+
+        let layers = archive_layer_files(&archive).unwrap();
+
+        assert_eq!(layers.len(), 2);
+        assert_eq!(paths(&layers[0]), vec![("bin/sh", 5), ("etc/passwd", 4)]);
+        assert_eq!(paths(&layers[1]), vec![("app/main", 4)]);
+    }
+
+    #[test]
+    fn duplicate_layers_symlinked_to_the_first_copy_are_read_once() {
+        let dir = TempDir::new().unwrap();
+        let archive = save_archive(
+            &dir,
+            &[
+                ("a/layer.tar", Ok(layer_tar(&[("data", b"12345")]))),
+                ("b/layer.tar", Err("../a/layer.tar")),
+                ("manifest.json", manifest(&["a/layer.tar", "b/layer.tar"])),
+            ],
+        );
+
+        let mut reads = 0;
+        let layers = read_archive_layers(&archive, |entry, _| {
+            reads += 1;
+            let mut files = Vec::new();
+            walk_layer(entry, None, |path, _| {
+                files.push(path.to_path_buf());
+                Ok(())
+            })?;
+            Ok(files)
+        })
+        .unwrap();
+
+        assert_eq!(reads, 1);
+        assert_eq!(layers, vec![vec![PathBuf::from("data")], vec![PathBuf::from("data")]]);
+    }
+
+    #[test]
+    fn oci_exports_carry_blob_sizes_from_their_descriptors() {
+        let dir = TempDir::new().unwrap();
+        let layer = layer_tar(&[("etc/hosts", b"127.0.0.1 localhost\n")]);
+        let descriptor = |media_type: &str, digest: &str, size: usize| {
+            serde_json::json!({ "mediaType": media_type, "digest": digest, "size": size })
+        };
+        let image_manifest = serde_json::to_vec(&serde_json::json!({
+            "layers": [descriptor(
+                "application/vnd.oci.image.layer.v1.tar",
+                "sha256:layer",
+                layer.len()
+            )]
+        }))
+        .unwrap();
+        let index = serde_json::to_vec(&serde_json::json!({
+            "manifests": [descriptor(
+                "application/vnd.oci.image.manifest.v1+json",
+                "sha256:manifest",
+                image_manifest.len()
+            )]
+        }))
+        .unwrap();
+        let archive = save_archive(
+            &dir,
+            &[
+                ("index.json", Ok(index)),
+                ("blobs/sha256/manifest", Ok(image_manifest)),
+                ("blobs/sha256/layer", Ok(layer.clone())),
+                ("manifest.json", manifest(&["blobs/sha256/layer"])),
+            ],
+        );
+
+        let measurements = measure_archive(&archive).unwrap();
+
+        assert_eq!(measurements.len(), 1);
+        assert_eq!(measurements[0].file_count, 1);
+        assert_eq!(measurements[0].compressed_size, Some(layer.len() as u64));
+        assert_eq!(measurements[0].compression, Some(LayerCompression::None));
+    }
+
+    #[test]
+    fn archives_missing_a_manifest_layer_are_errors() {
+        let dir = TempDir::new().unwrap();
+        let archive = save_archive(
+            &dir,
+            &[
+                ("a/layer.tar", Ok(layer_tar(&[("data", b"1")]))),
+                ("manifest.json", manifest(&["a/layer.tar", "gone/layer.tar"])),
+            ],
+        );
+
+        let error = archive_layer_files(&archive).unwrap_err();
+        assert!(error.to_string().contains("gone/layer.tar"), "{:#}", error);
+
+        let archive = save_archive(&dir, &[("a/layer.tar", Ok(layer_tar(&[])))]);
+        let error = archive_layer_files(&archive).unwrap_err();
+        assert!(error.to_string().contains("no manifest.json"), "{:#}", error);
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zstd(bytes: &[u8]) -> Vec<u8> {
+        zstd::encode_all(bytes, 3).unwrap()
+    }
+
+    /// Entry paths of a layer, read as `compression` (sniffed when `None`)
+    fn entries(blob: &[u8], compression: Option<LayerCompression>) -> Vec<String> {
+        let mut names = Vec::new();
+        walk_layer(blob, compression, |path, _| {
+            names.push(path.display().to_string());
+            Ok(())
+        })
+        .unwrap();
+        names
+    }
+
+    #[test]
+    fn media_types_map_to_their_compression() {
+        use LayerCompression::*;
+        for (media_type, expected) in [
+            ("application/vnd.oci.image.layer.v1.tar", None),
+            ("application/vnd.oci.image.layer.nondistributable.v1.tar", None),
+            ("application/vnd.docker.image.rootfs.diff.tar", None),
+            ("application/vnd.oci.image.layer.v1.tar+gzip", Gzip),
+            ("application/vnd.oci.image.layer.nondistributable.v1.tar+gzip", Gzip),
+            ("application/vnd.docker.image.rootfs.diff.tar.gzip", Gzip),
+            ("application/vnd.docker.image.rootfs.foreign.diff.tar.gzip", Gzip),
+            ("application/vnd.oci.image.layer.v1.tar+zstd", Zstd),
+            ("application/vnd.oci.image.layer.nondistributable.v1.tar+zstd", Zstd),
+        ] {
+            assert_eq!(LayerCompression::from_media_type(media_type).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn unsupported_media_types_are_named() {
+        let error = LayerCompression::from_media_type("application/vnd.oci.image.layer.v1.tar+lz4")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("'application/vnd.oci.image.layer.v1.tar+lz4'"), "{}", error);
+        assert!(error.contains("tar+zstd"), "{}", error);
+    }
+
+    #[test]
+    fn compression_is_sniffed_from_magic_bytes() {
+        let tar = layer_tar(&[("file", b"x")]);
+        assert_eq!(LayerCompression::sniff(&gzip(&tar)), LayerCompression::Gzip);
+        assert_eq!(LayerCompression::sniff(&zstd(&tar)), LayerCompression::Zstd);
+        assert_eq!(LayerCompression::sniff(&tar), LayerCompression::None);
+        assert_eq!(LayerCompression::sniff(&[]), LayerCompression::None);
+    }
+
+    #[test]
+    fn gzip_and_zstd_layers_decompress_whether_typed_or_sniffed() {
+        let tar = layer_tar(&[("usr/bin/app", b"binary"), ("etc/app.conf", b"key=value")]);
+        let expected = vec!["usr/bin/app", "etc/app.conf"];
+
+        for (blob, compression) in [
+            (gzip(&tar), LayerCompression::Gzip),
+            (zstd(&tar), LayerCompression::Zstd),
+        ] {
+            assert_eq!(entries(&blob, Some(compression)), expected);
+            assert_eq!(entries(&blob, None), expected);
+        }
+    }
+
+    #[test]
+    fn estargz_layers_read_every_gzip_member_and_skip_the_toc() {
+        // estargz gzips each entry as its own member, with the TOC and the end-of-archive
+        // blocks last. Every entry here is one header block and one data block.
+        let tar = layer_tar(&[
+            ("bin/app", b"app"),
+            (".prefetch.landmark", b"\0"),
+            ("lib/libc.so", b"libc"),
+            ("stargz.index.json", b"{}"),
+        ]);
+        let blob: Vec<u8> = tar.chunks(1024).flat_map(gzip).collect();
+        assert!(tar.chunks(1024).count() > 4);
+
+        assert_eq!(entries(&blob, Some(LayerCompression::Gzip)), vec!["bin/app", "lib/libc.so"]);
+        assert_eq!(measure_layer(blob.as_slice(), None).unwrap().file_count, 2);
+    }
+
+    #[test]
+    fn mixed_compression_images_read_each_layer_by_its_own_type() {
+        let dir = TempDir::new().unwrap();
+        let base = gzip(&layer_tar(&[("bin/sh", b"shell")]));
+        let top = zstd(&layer_tar(&[("app/main", b"main"), ("app/lib", b"lib")]));
+        let image_manifest = serde_json::to_vec(&serde_json::json!({
+            "layers": [
+                {
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                    "digest": "sha256:base",
+                    "size": base.len(),
+                },
+                {
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar+zstd",
+                    "digest": "sha256:top",
+                    "size": top.len(),
+                },
+            ]
+        }))
+        .unwrap();
+        let index = serde_json::to_vec(&serde_json::json!({
+            "manifests": [{
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": "sha256:manifest",
+                "size": image_manifest.len(),
+            }]
+        }))
+        .unwrap();
+        let archive = save_archive(
+            &dir,
+            &[
+                ("index.json", Ok(index)),
+                ("blobs/sha256/manifest", Ok(image_manifest)),
+                ("blobs/sha256/base", Ok(base.clone())),
+                ("blobs/sha256/top", Ok(top.clone())),
+                ("manifest.json", manifest(&["blobs/sha256/base", "blobs/sha256/top"])),
+            ],
+        );
+
+        let measurements = measure_archive(&archive).unwrap();
+
+        let summary: Vec<_> = measurements
+            .iter()
+            .map(|m| (m.compression, m.file_count, m.compressed_size))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some(LayerCompression::Gzip), 1, Some(base.len() as u64)),
+                (Some(LayerCompression::Zstd), 2, Some(top.len() as u64)),
+            ]
+        );
+    }
+}
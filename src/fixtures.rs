@@ -0,0 +1,72 @@
+//! Snapshots for unit tests
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::models::{ImageOrigin, ImageSnapshot, LayerInfo};
+
+/// 2024-01-01 plus `minutes`, so fixtures sort in the order they're built
+pub fn at(minutes: i64) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + chrono::TimeDelta::minutes(minutes)
+}
+
+pub fn layer(digest: &str, size: u64, command: &str) -> LayerInfo {
+    LayerInfo {
+        digest: digest.to_string(),
+        size,
+        command: command.to_string(),
+        created: at(0),
+        extract_ms: None,
+        file_count: None,
+    }
+}
+
+/// `image:tag` on linux/amd64 with `layers`; the total is their sum
+pub fn snapshot(image: &str, tag: &str, layers: Vec<LayerInfo>) -> ImageSnapshot {
+    ImageSnapshot {
+        image: image.to_string(),
+        tag: Some(tag.to_string()),
+        digest: None,
+        image_id: None,
+        commit_sha: String::new(),
+        branch: String::new(),
+        commit_message: String::new(),
+        author: String::new(),
+        timestamp: at(0),
+        total_size: layers.iter().map(|l| l.size).sum(),
+        compressed_size: None,
+        registry_only: false,
+        layer_count: layers.len(),
+        layers,
+        os: "linux".to_string(),
+        arch: "amd64".to_string(),
+        variant: None,
+        group_id: None,
+        host_platform: None,
+        env: None,
+        labels: None,
+        user: None,
+        annotations: Default::default(),
+        oci_annotations: Default::default(),
+        extract_ms: None,
+        file_count: None,
+        watched: false,
+        origin: ImageOrigin::Unknown,
+        build_duration_ms: None,
+    }
+}
+
+/// [`snapshot`] recorded at `commit` on `branch`, `minutes` after the fixture epoch
+pub fn tracked(
+    image: &str,
+    tag: &str,
+    commit: &str,
+    branch: &str,
+    minutes: i64,
+    layers: Vec<LayerInfo>,
+) -> ImageSnapshot {
+    let mut snapshot = snapshot(image, tag, layers);
+    snapshot.commit_sha = commit.to_string();
+    snapshot.branch = branch.to_string();
+    snapshot.timestamp = at(minutes);
+    snapshot
+}
@@ -1,17 +1,276 @@
+use anyhow::Result;
 use bytesize::ByteSize;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
 use colored::Colorize;
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+use std::io::Write;
 use tabled::{
     builder::Builder,
-    settings::{object::Rows, Alignment, Modify, Style},
+    settings::{
+        object::{Columns, Rows},
+        Alignment, Modify, Style,
+    },
+    Table,
 };
 
-use crate::models::{ImageSnapshot, LayerChange, SizeDiff};
+use crate::baseline::{BaselineDecision, BaselineSelection};
+use crate::diff::DiffOptions;
+use crate::env::diff_env;
+use crate::history::BranchSeries;
+use crate::layer_labels::LayerLabels;
+use crate::metric::Metric;
+use crate::models::{
+    ImageOrigin, ImageSnapshot, LayerChange, LayerInfo, SizeDiff, OCI_ANNOTATION_PREFIX,
+};
+use crate::path_budget::PathUsage;
+use crate::platforms::has_groups;
+
+/// How text output is rendered, from the global display flags. JSON output ignores it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutputStyle {
+    /// `--time-zone` / `DIT_TZ`: show timestamps here instead of UTC
+    pub time_zone: Option<Tz>,
+    /// `--plain` / `plain = true` in .dit.toml: screen-reader-friendly output. Tables become
+    /// "Header: value" records, deltas are spelled out, and sparklines and bars become
+    /// sentences. Color is stripped where the output is written.
+    pub plain: bool,
+}
+
+impl OutputStyle {
+    /// Widest image reference shown in tables; `--plain` always shows it in full
+    pub fn name_width(self) -> usize {
+        if self.plain {
+            usize::MAX
+        } else {
+            NAME_WIDTH
+        }
+    }
+}
+
+/// Print-ready table: rounded borders and a centered header, or with `--plain` one
+/// "Header: value" line per cell and a blank line between rows
+pub fn render_table(builder: Builder, plain: bool) -> String {
+    render_table_with(builder, plain, |_| {})
+}
+
+/// [`render_table`], with `adjust` applied after the border style and before the header
+/// is centered (ignored with `--plain`)
+pub fn render_table_with(builder: Builder, plain: bool, adjust: impl FnOnce(&mut Table)) -> String {
+    if plain {
+        return plain_records(builder.into());
+    }
+
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    adjust(&mut table);
+    table.with(Modify::new(Rows::first()).with(Alignment::center()));
+    table.to_string()
+}
+
+/// Rows after the header as "Header: value" records; empty and "—" cells are left out
+fn plain_records(rows: Vec<Vec<String>>) -> String {
+    let Some((header, rows)) = rows.split_first() else {
+        return String::new();
+    };
+
+    rows.iter()
+        .map(|row| {
+            header
+                .iter()
+                .zip(row)
+                .filter(|(_, value)| !matches!(value.trim(), "" | "—" | "-"))
+                .map(|(name, value)| format!("{}: {}", name, value.replace('\n', ", ")))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// `timestamp` in `time_zone` (UTC when unset), formatted with `fmt`
+pub fn format_timestamp(timestamp: DateTime<Utc>, fmt: &str, time_zone: Option<Tz>) -> String {
+    match time_zone {
+        Some(zone) => timestamp.with_timezone(&zone).format(fmt).to_string(),
+        None => timestamp.format(fmt).to_string(),
+    }
+}
+
+/// Widest image reference shown in tables before shortening (see `--wide`)
+pub const NAME_WIDTH: usize = 40;
+
+/// Table form of an image reference: unchanged when it fits in `max` characters, otherwise
+/// the middle path segments are elided, keeping the registry host's initial, the last path
+/// segment and the tag (`e…/service-name:feature-x`). Display only; history keys always
+/// use the full reference.
+pub fn shorten_reference(reference: &str, max: usize) -> String {
+    if reference.chars().count() <= max {
+        return reference.to_string();
+    }
+
+    // Tag is whatever follows the last ':' unless that ':' is a registry port; a digest
+    // (`@sha256:…`) is kept whole in its place
+    let (name, tag) = match (reference.split_once('@'), reference.rsplit_once(':')) {
+        (Some((name, digest)), _) => (name, format!("@{}", digest)),
+        (None, Some((name, tag))) if !tag.contains('/') => (name, format!(":{}", tag)),
+        _ => (reference, String::new()),
+    };
+
+    let segments: Vec<&str> = name.split('/').collect();
+    let rest = format!("{}{}", segments[segments.len() - 1], tag);
+
+    if segments.len() > 2 {
+        let candidate = format!("{}/…/{}", segments[0], rest);
+        if candidate.chars().count() <= max {
+            return candidate;
+        }
+    }
+
+    if segments.len() > 1 {
+        let prefix: String = segments[0].chars().take(1).chain("…/".chars()).collect();
+        let budget = max.saturating_sub(prefix.chars().count());
+        // Not worth keeping the host hint if it squeezes the name to nothing
+        if budget >= 12 {
+            return format!("{}{}", prefix, middle_ellipsis(&rest, budget));
+        }
+    }
+
+    middle_ellipsis(&rest, max)
+}
+
+/// Cut the middle out of `s` so it fits in `max` characters
+pub fn middle_ellipsis(s: &str, max: usize) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() <= max || max < 3 {
+        return s.to_string();
+    }
+
+    let keep = max - 1;
+    let head = keep / 2;
+    let tail = keep - head;
+
+    let mut out: String = chars[..head].iter().collect();
+    out.push('…');
+    out.extend(&chars[chars.len() - tail..]);
+    out
+}
+
+/// First 12 hex characters of an image ID, as `docker images` shows it
+pub fn short_image_id(id: &str) -> String {
+    id.trim_start_matches("sha256:").chars().take(12).collect()
+}
 
 pub fn format_size(bytes: u64) -> String {
     ByteSize(bytes).to_string_as(true)
 }
 
-pub fn format_size_delta(delta: i64) -> String {
+/// `--layer-size-unit`: a fixed unit for layer tables' Size column, so layers compare at a glance
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum LayerSizeUnit {
+    /// Scale each size on its own, like the total
+    #[default]
+    Auto,
+    /// Exact byte counts
+    Bytes,
+    #[value(name = "KB", alias = "KiB")]
+    Kb,
+    #[value(name = "MB", alias = "MiB")]
+    Mb,
+}
+
+impl LayerSizeUnit {
+    pub fn format(self, bytes: u64) -> String {
+        match self {
+            LayerSizeUnit::Auto => format_size(bytes),
+            LayerSizeUnit::Bytes => format!("{} B", bytes),
+            LayerSizeUnit::Kb => format!("{:.1} KiB", bytes as f64 / 1024.0),
+            LayerSizeUnit::Mb => format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0)),
+        }
+    }
+
+    /// Fixed units line up on the right; call before centering the header
+    fn align(self, table: &mut Table) {
+        if self != LayerSizeUnit::Auto {
+            table.with(Modify::new(Columns::single(1)).with(Alignment::right()));
+        }
+    }
+}
+
+/// Row order of `dit analyze`'s layer table; `#` always shows the build position
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LayerOrder {
+    /// Oldest first, as built
+    #[default]
+    Chronological,
+    /// FROM, then RUN and COPY/ADD (each largest first), then metadata instructions
+    Command,
+    /// Largest first
+    Size,
+}
+
+impl LayerOrder {
+    /// `(build position, layer)` pairs in this order
+    pub fn sort(self, layers: &[LayerInfo]) -> Vec<(usize, &LayerInfo)> {
+        let mut rows: Vec<(usize, &LayerInfo)> = layers.iter().enumerate().collect();
+        match self {
+            LayerOrder::Chronological => {}
+            LayerOrder::Size => rows.sort_by_key(|(_, layer)| std::cmp::Reverse(layer.size)),
+            LayerOrder::Command => rows.sort_by(|a, b| {
+                let (rank_a, rank_b) = (instruction_rank(&a.1.command), instruction_rank(&b.1.command));
+                rank_a.cmp(&rank_b).then_with(|| match rank_a {
+                    // RUN and COPY/ADD groups are where the size goes
+                    1 | 2 => b.1.size.cmp(&a.1.size),
+                    _ => std::cmp::Ordering::Equal,
+                })
+            }),
+        }
+        rows
+    }
+}
+
+/// 0 FROM, 1 RUN (and bare shell commands), 2 COPY/ADD, 3 other instructions
+fn instruction_rank(command: &str) -> u8 {
+    let keyword = command.split_whitespace().next().unwrap_or("");
+    match keyword {
+        "FROM" => 0,
+        "RUN" => 1,
+        "COPY" | "ADD" => 2,
+        _ if DOCKERFILE_INSTRUCTIONS.contains(&keyword) => 3,
+        _ => 1,
+    }
+}
+
+/// `12.4 MiB` as `12.4 mebibytes`, for `--plain`
+pub fn spell_size(bytes: u64) -> String {
+    let size = format_size(bytes);
+    let Some((number, unit)) = size.split_once(' ') else {
+        return size;
+    };
+    let unit = match unit {
+        "B" if number == "1" => "byte",
+        "B" => "bytes",
+        "kiB" => "kibibytes",
+        "MiB" => "mebibytes",
+        "GiB" => "gibibytes",
+        "TiB" => "tebibytes",
+        "PiB" => "pebibytes",
+        other => other,
+    };
+    format!("{} {}", number, unit)
+}
+
+/// `+12.4 MiB` in red / `-3.0 MiB` in green, or with `--plain` `increase of 12.4 mebibytes`
+pub fn format_size_delta(delta: i64, plain: bool) -> String {
+    if plain {
+        return match delta.cmp(&0) {
+            std::cmp::Ordering::Equal => "unchanged".to_string(),
+            std::cmp::Ordering::Greater => format!("increase of {}", spell_size(delta as u64)),
+            std::cmp::Ordering::Less => format!("decrease of {}", spell_size(delta.unsigned_abs())),
+        };
+    }
+
     if delta == 0 {
         "unchanged".to_string()
     } else if delta > 0 {
@@ -23,63 +282,268 @@ pub fn format_size_delta(delta: i64) -> String {
     }
 }
 
-pub fn print_snapshot_table(snapshot: &ImageSnapshot) {
-    println!("\n{}", "Image Analysis".bold().underline());
-    println!("Image: {}", snapshot.image.bright_cyan());
+/// Size change with its percentage: `+12.4 MiB (+3.1%)`, or with `--plain`
+/// `increase of 12.4 mebibytes, 3.1 percent`
+pub fn format_change(delta: i64, percent: f64, plain: bool) -> String {
+    if plain {
+        if delta == 0 {
+            return "unchanged".to_string();
+        }
+        return format!("{}, {:.1} percent", format_size_delta(delta, plain), percent.abs());
+    }
+    format!("{} ({:+.1}%)", format_size_delta(delta, plain).bold(), percent)
+}
+
+/// One plain Markdown line for PR descriptions or commit messages:
+/// `🐋 myapp:latest: 142.0 MiB (+2.4 MiB, +1.7%)`. Without a diff the image is new.
+pub fn format_snapshot_one_liner(snapshot: &ImageSnapshot, diff: Option<&SizeDiff>) -> String {
+    let platform = match snapshot.group_id {
+        Some(_) => format!(" ({})", snapshot.platform()),
+        None => String::new(),
+    };
+
+    let change = match diff {
+        None => "new".to_string(),
+        Some(diff) if diff.total_delta == 0 => "unchanged".to_string(),
+        Some(diff) => {
+            let sign = if diff.total_delta > 0 { "+" } else { "-" };
+            let size = ByteSize(diff.total_delta.unsigned_abs()).to_string_as(true);
+            match diff.percent() {
+                None => format!("{}{}", sign, size),
+                Some(percent) => format!("{}{}, {}{:.1}%", sign, size, sign, percent.abs()),
+            }
+        }
+    };
+
+    let (size, basis) = match diff {
+        Some(diff) => (diff.after_size(), diff.size_basis.suffix()),
+        None => (snapshot.size(), ""),
+    };
+
+    format!(
+        "🐋 {}:{}{}: {} ({}){}",
+        snapshot.image,
+        snapshot.tag.as_deref().unwrap_or("latest"),
+        platform,
+        format_size(size),
+        change,
+        basis
+    )
+}
+
+pub fn print_snapshot_table(
+    out: &mut impl Write,
+    snapshot: &ImageSnapshot,
+    layer_unit: LayerSizeUnit,
+    layer_order: LayerOrder,
+    layer_labels: Option<&LayerLabels>,
+    output: OutputStyle,
+) -> Result<()> {
+    write!(out, "{}", snapshot_table(snapshot, layer_unit, layer_order, layer_labels, output))?;
+
+    Ok(())
+}
+
+/// What `dit analyze` prints for `snapshot`
+pub fn snapshot_table(
+    snapshot: &ImageSnapshot,
+    layer_unit: LayerSizeUnit,
+    layer_order: LayerOrder,
+    layer_labels: Option<&LayerLabels>,
+    output: OutputStyle,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("\n{}\n", "Image Analysis".bold().underline()));
+    out.push_str(&format!("Image: {}\n", snapshot.image.bright_cyan()));
     if let Some(ref tag) = snapshot.tag {
-        println!("Tag: {}", tag);
+        out.push_str(&format!("Tag: {}\n", tag));
+    }
+    out.push_str(&format!("Total Size: {}\n", format_size(snapshot.size()).bold()));
+    out.push_str(&format!("Layers: {}\n", snapshot.layer_count));
+    out.push_str(&format!("OS/Arch: {}/{}\n", snapshot.os, snapshot.arch));
+    if let Some(user) = &snapshot.user {
+        if user.is_empty() {
+            out.push_str(&format!("User: root {}\n", "(no USER set)".dimmed()));
+        } else {
+            out.push_str(&format!("User: {}\n", user));
+        }
+    }
+    if !snapshot.oci_annotations.is_empty() {
+        out.push_str("Provenance:\n");
+        for (key, value) in &snapshot.oci_annotations {
+            let name = key.strip_prefix(OCI_ANNOTATION_PREFIX).unwrap_or(key);
+            out.push_str(&format!("  {}: {}\n", name, value));
+        }
+    }
+    if let (Some(ms), Some(files)) = (snapshot.extract_ms, snapshot.file_count) {
+        out.push_str(&format!(
+            "Extraction: {} ms, {} files {}\n",
+            ms,
+            files,
+            "(machine-dependent)".dimmed()
+        ));
     }
-    println!("Total Size: {}", format_size(snapshot.total_size).bold());
-    println!("Layers: {}", snapshot.layer_count);
-    println!("OS/Arch: {}/{}", snapshot.os, snapshot.arch);
 
     if !snapshot.layers.is_empty() {
-        println!("\n{}", "Layer Breakdown".bold().underline());
+        out.push_str(&format!("\n{}\n", "Layer Breakdown".bold().underline()));
 
+        let measured = snapshot.file_count.is_some();
+        let labels = layer_labels.filter(|labels| {
+            snapshot.layers.iter().any(|layer| labels.label(layer).is_some())
+        });
         let mut builder = Builder::default();
-        builder.push_record(["#", "Size", "Created", "Command"]);
+        let mut header = if measured {
+            vec!["#", "Size", "Files", "Extract", "Created", "Command"]
+        } else {
+            vec!["#", "Size", "Created", "Command"]
+        };
+        if labels.is_some() {
+            header.push("Label");
+        }
+        builder.push_record(header);
 
-        for (i, layer) in snapshot.layers.iter().enumerate() {
-            builder.push_record([
-                &format!("{}", i + 1),
-                &format_size(layer.size),
-                &layer.created.format("%Y-%m-%d").to_string(),
-                &layer.command,
-            ]);
+        for (i, layer) in layer_order.sort(&snapshot.layers) {
+            let mut record = vec![format!("{}", i + 1), layer_unit.format(layer.size)];
+            if measured {
+                record.push(layer.file_count.map_or("—".to_string(), |n| n.to_string()));
+                record.push(layer.extract_ms.map_or("—".to_string(), |ms| format!("{} ms", ms)));
+            }
+            record.push(format_timestamp(layer.created, "%Y-%m-%d", output.time_zone));
+            record.push(layer.command.clone());
+            if let Some(labels) = labels {
+                record.push(labels.label(layer).unwrap_or_default().to_string());
+            }
+            builder.push_record(record);
         }
 
-        let mut table = builder.build();
-        table
-            .with(Style::rounded())
-            .with(Modify::new(Rows::first()).with(Alignment::center()));
+        let table = render_table_with(builder, output.plain, |table| layer_unit.align(table));
+        out.push_str(&format!("{}\n", table));
+    }
+
+    out
+}
+
+const DOCKERFILE_INSTRUCTIONS: [&str; 18] = [
+    "FROM", "RUN", "CMD", "LABEL", "MAINTAINER", "EXPOSE", "ENV", "ADD", "COPY", "ENTRYPOINT",
+    "VOLUME", "USER", "WORKDIR", "ARG", "ONBUILD", "STOPSIGNAL", "HEALTHCHECK", "SHELL",
+];
+
+/// Rebuild an approximate Dockerfile from layer history (oldest first).
+/// Consecutive RUN layers are chained with `&&`; the result is illustrative, not buildable.
+pub fn render_pseudo_dockerfile(snapshot: &ImageSnapshot) -> String {
+    let mut out = format!(
+        "# Pseudo-Dockerfile reconstructed from {}:{}\n# Not guaranteed to build — base image and build context are unknown\n\n",
+        snapshot.image,
+        snapshot.tag.as_deref().unwrap_or("latest")
+    );
+
+    let mut pending_run: Vec<String> = Vec::new();
+
+    for (i, layer) in snapshot.layers.iter().enumerate() {
+        let digest_short = layer
+            .digest
+            .trim_start_matches("sha256:")
+            .chars()
+            .take(12)
+            .collect::<String>();
+        out.push_str(&format!(
+            "# Layer {}: {} ({})\n",
+            i + 1,
+            digest_short,
+            format_size(layer.size)
+        ));
 
-        println!("{}", table);
+        let keyword = layer.command.split_whitespace().next().unwrap_or("");
+        let instruction = if DOCKERFILE_INSTRUCTIONS.contains(&keyword) {
+            layer.command.clone()
+        } else {
+            format!("RUN {}", layer.command)
+        };
+
+        if let Some(body) = instruction.strip_prefix("RUN ") {
+            pending_run.push(body.trim().to_string());
+            let next_is_run = snapshot.layers.get(i + 1).is_some_and(|next| {
+                let next_keyword = next.command.split_whitespace().next().unwrap_or("");
+                next_keyword == "RUN" || !DOCKERFILE_INSTRUCTIONS.contains(&next_keyword)
+            });
+            if next_is_run {
+                continue;
+            }
+            out.push_str(&format!("RUN {}\n\n", pending_run.join(" \\\n    && ")));
+            pending_run.clear();
+        } else {
+            out.push_str(&format!("{}\n\n", instruction));
+        }
     }
+
+    out
+}
+
+/// Changed commands inline: removed words in red (struck through), added words in green.
+/// Without colour, git's `[-removed-]{+added+}` markers are used instead.
+pub fn word_diff(before: &str, after: &str) -> String {
+    let color = colored::control::SHOULD_COLORIZE.should_colorize();
+
+    TextDiff::from_words(before, after)
+        .iter_all_changes()
+        .map(|change| match (change.tag(), color) {
+            (ChangeTag::Equal, _) => change.value().to_string(),
+            (ChangeTag::Delete, true) => change.value().red().strikethrough().to_string(),
+            (ChangeTag::Insert, true) => change.value().green().to_string(),
+            (ChangeTag::Delete, false) => format!("[-{}-]", change.value()),
+            (ChangeTag::Insert, false) => format!("{{+{}+}}", change.value()),
+        })
+        .collect()
 }
 
-pub fn print_diff_table(diff: &SizeDiff) {
+pub fn print_diff_table(
+    out: &mut impl Write,
+    diff: &SizeDiff,
+    options: &DiffOptions,
+) -> Result<()> {
+    let output = options.output;
+    let layer_unit = options.layer_size_unit;
     let total_delta = diff.total_delta;
-    let total_percent = if diff.before.total_size > 0 {
-        (total_delta as f64 / diff.before.total_size as f64) * 100.0
-    } else {
-        0.0
-    };
+    let total_percent = diff.percent().unwrap_or(0.0);
+    let basis = diff.size_basis.suffix();
 
-    println!("\n{}", "Image Size Diff".bold().underline());
-    println!("Image: {}", diff.after.image.bright_cyan());
-    println!(
-        "Before ({}): {}",
+    writeln!(out, "\n{}", "Image Size Diff".bold().underline())?;
+    writeln!(out, "Image: {}", diff.after.image.bright_cyan())?;
+    writeln!(
+        out,
+        "Before ({}{}): {}{}",
         diff.before.commit_sha.chars().take(7).collect::<String>(),
-        format_size(diff.before.total_size)
-    );
-    println!(
-        "After ({}): {}",
+        origin_note(&diff.before),
+        format_size(diff.before_size()),
+        basis
+    )?;
+    writeln!(
+        out,
+        "After ({}{}): {}{}",
         diff.after.commit_sha.chars().take(7).collect::<String>(),
-        format_size(diff.after.total_size)
-    );
+        origin_note(&diff.after),
+        format_size(diff.after_size()),
+        basis
+    )?;
+    if diff.before.origin != diff.after.origin
+        && diff.before.origin != ImageOrigin::Unknown
+        && diff.after.origin != ImageOrigin::Unknown
+    {
+        writeln!(
+            out,
+            "{}",
+            format!(
+                "⚠️  Comparing a {} image with a {} one; compression and build args may differ",
+                diff.before.origin.as_str(),
+                diff.after.origin.as_str()
+            )
+            .yellow()
+        )?;
+    }
 
-    let delta_str = format_size_delta(total_delta);
-    let trend = if total_delta > 0 {
+    let trend = if output.plain {
+        ""
+    } else if total_delta > 0 {
         "📈"
     } else if total_delta < 0 {
         "📉"
@@ -87,94 +551,913 @@ pub fn print_diff_table(diff: &SizeDiff) {
         "✅"
     };
 
-    println!(
-        "Change: {} ({:+.1}%) {}",
-        delta_str.bold(),
-        total_percent,
+    let change = format!(
+        "Change: {}{} {}",
+        format_change(total_delta, total_percent, output.plain),
+        basis,
         trend
     );
+    writeln!(out, "{}", change.trim_end())?;
+
+    if let (Some(before), Some(after)) = (diff.before.file_count, diff.after.file_count) {
+        writeln!(
+            out,
+            "Files: {} → {} ({:+})",
+            before,
+            after,
+            after as i64 - before as i64
+        )?;
+    }
+
+    if let (Some(before), Some(after), Some(savings)) = (
+        diff.compressed_before,
+        diff.compressed_after,
+        diff.compression_savings(),
+    ) {
+        writeln!(
+            out,
+            "Compressed: {} → {} ({:.1}% saved)",
+            format_size(before),
+            format_size(after),
+            savings
+        )?;
+    }
+
+    writeln!(out, "\n{}", "Layer Changes".bold().underline())?;
 
-    println!("\n{}", "Layer Changes".bold().underline());
+    let changes: Vec<LayerChange> = match options.unchanged_layers {
+        UnchangedLayers::Show => diff.layer_changes.clone(),
+        UnchangedLayers::Summary | UnchangedLayers::Hide => diff
+            .layer_changes
+            .iter()
+            .filter(|c| !matches!(c, LayerChange::Unchanged(_)))
+            .cloned()
+            .collect(),
+    };
+    if options.unchanged_layers == UnchangedLayers::Summary {
+        if let Some(line) = unchanged_summary(&diff.layer_changes) {
+            writeln!(out, "{}", line.dimmed())?;
+        }
+    }
+
+    let limited = limit_changes(&changes, options.max_changes);
 
     let mut builder = Builder::default();
     builder.push_record(["Status", "Size", "Delta", "Command"]);
 
-    for change in &diff.layer_changes {
-        let status = match change {
-            LayerChange::Added(_) => "Added".green().to_string(),
-            LayerChange::Removed(_) => "Removed".red().to_string(),
-            LayerChange::Modified { .. } => "Modified".yellow().to_string(),
-            LayerChange::Unchanged(_) => "Unchanged".dimmed().to_string(),
-        };
-
+    for change in &limited.shown {
+        let status = layer_change_status(change);
         let layer = change.layer();
         let size_delta = change.size_delta();
+        let command = match change {
+            LayerChange::Modified { before, after }
+                if options.word_diff && before.command != after.command =>
+            {
+                word_diff(&before.command, &after.command)
+            }
+            _ => layer.command.clone(),
+        };
 
         builder.push_record([
             &status,
-            &format_size(layer.size),
-            &format_size_delta(size_delta),
-            &layer.command,
+            &layer_unit.format(layer.size),
+            &format_size_delta(size_delta, output.plain),
+            &command,
         ]);
     }
 
-    let mut table = builder.build();
-    table
-        .with(Style::rounded())
-        .with(Modify::new(Rows::first()).with(Alignment::center()));
+    // With unchanged layers summarized, an identical layer stack leaves only the header
+    if !limited.shown.is_empty() {
+        let table = render_table_with(builder, output.plain, |table| layer_unit.align(table));
+        writeln!(out, "{}", table)?;
+    }
+    if let Some(line) = limited.remainder_line(output.plain) {
+        writeln!(out, "{}", line.dimmed())?;
+    }
+
+    print_env_changes(out, diff, options.show_env_values)?;
+
+    Ok(())
+}
+
+/// How the diff layer table treats layers present on both sides
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnchangedLayers {
+    /// One "N unchanged layers" line above the table
+    #[default]
+    Summary,
+    /// A row per unchanged layer (`--show-unchanged`)
+    Show,
+    /// Nothing (`--hide-unchanged`)
+    Hide,
+}
+
+/// `12 unchanged layers (84.3 MB total, not shown)`, when there are any
+fn unchanged_summary(changes: &[LayerChange]) -> Option<String> {
+    let sizes: Vec<u64> = changes
+        .iter()
+        .filter_map(|c| match c {
+            LayerChange::Unchanged(layer) => Some(layer.size),
+            _ => None,
+        })
+        .collect();
+    (!sizes.is_empty()).then(|| {
+        format!(
+            "{} unchanged layer{} ({} total, not shown)",
+            sizes.len(),
+            if sizes.len() == 1 { "" } else { "s" },
+            format_size(sizes.iter().sum())
+        )
+    })
+}
+
+/// `--max-changes` when not given
+pub const DEFAULT_MAX_CHANGES: usize = 50;
+
+/// The layer changes a view shows, and a summary of the ones it leaves out
+#[derive(Debug)]
+pub struct LimitedChanges<'a> {
+    /// In their original order
+    pub shown: Vec<&'a LayerChange>,
+    pub hidden: usize,
+    pub hidden_delta: i64,
+}
+
+impl LimitedChanges<'_> {
+    /// `… and 263 more changes totaling +4.1 MB (...)`, when anything was left out
+    pub fn remainder_line(&self, plain: bool) -> Option<String> {
+        (self.hidden > 0).then(|| {
+            format!(
+                "… and {} more change{} totaling {} (run with --max-changes 0 for all)",
+                self.hidden,
+                if self.hidden == 1 { "" } else { "s" },
+                format_size_delta(self.hidden_delta, plain)
+            )
+        })
+    }
+}
+
+/// Keep the `max` changes with the largest absolute delta (earlier layers win ties);
+/// `max` 0 keeps everything
+pub fn limit_changes(changes: &[LayerChange], max: usize) -> LimitedChanges<'_> {
+    if max == 0 || changes.len() <= max {
+        return LimitedChanges {
+            shown: changes.iter().collect(),
+            hidden: 0,
+            hidden_delta: 0,
+        };
+    }
+
+    let mut by_significance: Vec<usize> = (0..changes.len()).collect();
+    by_significance.sort_by_key(|&i| std::cmp::Reverse(changes[i].size_delta().unsigned_abs()));
+    let mut keep = vec![false; changes.len()];
+    for &i in by_significance.iter().take(max) {
+        keep[i] = true;
+    }
+
+    let hidden_delta = changes
+        .iter()
+        .zip(&keep)
+        .filter(|(_, keep)| !**keep)
+        .map(|(change, _)| change.size_delta())
+        .sum();
+
+    LimitedChanges {
+        shown: changes.iter().zip(&keep).filter(|(_, keep)| **keep).map(|(c, _)| c).collect(),
+        hidden: changes.len() - max,
+        hidden_delta,
+    }
+}
+
+/// `, built` / `, pulled` after the commit in diff headers, when known
+fn origin_note(snapshot: &ImageSnapshot) -> String {
+    match snapshot.origin {
+        ImageOrigin::Unknown => String::new(),
+        origin => format!(", {}", origin.as_str()),
+    }
+}
+
+fn layer_change_status(change: &LayerChange) -> String {
+    match change {
+        LayerChange::Added(_) => "Added".green().to_string(),
+        LayerChange::Removed(_) => "Removed".red().to_string(),
+        LayerChange::Modified { .. } => "Modified".yellow().to_string(),
+        LayerChange::Unchanged(_) => "Unchanged".dimmed().to_string(),
+        LayerChange::Reordered { from, to, .. } => {
+            format!("Moved {}→{}", from + 1, to + 1).cyan().to_string()
+        }
+    }
+}
+
+/// Compact diff shown under `dit analyze --compare-to-previous`; unchanged layers are omitted
+pub fn print_previous_changes(
+    out: &mut impl Write,
+    diff: &SizeDiff,
+    output: OutputStyle,
+) -> Result<()> {
+    writeln!(out, "\n{}", "Changes from last snapshot".bold().underline())?;
+    writeln!(
+        out,
+        "Previous ({}, {} ago): {}",
+        diff.before.commit_sha.chars().take(7).collect::<String>(),
+        format_age(diff.before.timestamp),
+        format_size(diff.before_size())
+    )?;
+
+    writeln!(
+        out,
+        "Change: {}{}",
+        format_change(diff.total_delta, diff.percent().unwrap_or(0.0), output.plain),
+        diff.size_basis.suffix()
+    )?;
+
+    let changes: Vec<&LayerChange> = diff
+        .layer_changes
+        .iter()
+        .filter(|c| !matches!(c, LayerChange::Unchanged(_)))
+        .collect();
 
-    println!("{}", table);
+    if changes.is_empty() {
+        writeln!(out, "{}", "No layer changes".dimmed())?;
+        return Ok(());
+    }
+
+    let mut builder = Builder::default();
+    builder.push_record(["Status", "Size", "Delta", "Command"]);
+    for change in changes {
+        let layer = change.layer();
+        builder.push_record([
+            layer_change_status(change),
+            format_size(layer.size),
+            format_size_delta(change.size_delta(), output.plain),
+            layer.command.clone(),
+        ]);
+    }
+
+    writeln!(out, "{}", render_table(builder, output.plain))?;
+
+    Ok(())
 }
 
-pub fn print_history_table(snapshots: &[ImageSnapshot]) {
+fn print_env_changes(out: &mut impl Write, diff: &SizeDiff, show_env_values: bool) -> Result<()> {
+    writeln!(out, "\n{}", "Environment Changes".bold().underline())?;
+
+    match (&diff.before.env, &diff.after.env) {
+        (Some(before), Some(after)) => {
+            let changes = diff_env(before, after);
+            if changes.is_empty() {
+                writeln!(out, "{}", "No environment changes".dimmed())?;
+            }
+            for change in changes {
+                writeln!(out, "  {}", change.render(show_env_values))?;
+            }
+        }
+        _ => writeln!(out, "{}", "Env not recorded".dimmed())?,
+    }
+
+    Ok(())
+}
+
+pub fn print_history_table(
+    out: &mut impl Write,
+    snapshots: &[ImageSnapshot],
+    show_id: bool,
+    metric: Metric,
+    output: OutputStyle,
+) -> Result<()> {
     if snapshots.is_empty() {
-        println!("No history found");
-        return;
+        writeln!(out, "No history found")?;
+        return Ok(());
     }
 
-    println!("\n{}", "Image Size History".bold().underline());
-    println!("Image: {}", snapshots[0].image.bright_cyan());
+    writeln!(out, "\n{}", format!("Image {} History", metric.label()).bold().underline())?;
+    writeln!(out, "Image: {}", snapshots[0].image.bright_cyan())?;
+    writeln!(out, "{}", history_table(snapshots, show_id, metric, output))?;
+
+    Ok(())
+}
+
+/// `dit history --group-by-branch`: one table per branch, each oldest first
+pub fn print_branch_history_tables(
+    out: &mut impl Write,
+    image: &str,
+    series: &[BranchSeries],
+    show_id: bool,
+    metric: Metric,
+    output: OutputStyle,
+) -> Result<()> {
+    writeln!(out, "\n{}", format!("Image {} History", metric.label()).bold().underline())?;
+    writeln!(out, "Image: {}", image.bright_cyan())?;
+
+    for branch in series {
+        let heading = if branch.branch.is_empty() {
+            "No branch (tracked outside git)".to_string()
+        } else {
+            format!("Branch: {}", branch.branch)
+        };
+        writeln!(out, "\n{}", heading.bold())?;
+        writeln!(out, "{}", history_table(&branch.snapshots, show_id, metric, output))?;
+    }
+
+    Ok(())
+}
+
+/// Snapshots without `metric` are left out
+fn history_table(
+    snapshots: &[ImageSnapshot],
+    show_id: bool,
+    metric: Metric,
+    output: OutputStyle,
+) -> String {
+    // Multi-platform builds get a Platform column and deltas within each platform
+    let grouped = has_groups(snapshots);
+    // B(uilt) / P(ulled), once any snapshot recorded where it came from
+    let origins = snapshots.iter().any(|s| s.origin != ImageOrigin::Unknown);
+    let mut header = vec!["Commit", "Branch", "Date", metric.label(), "Delta", "Trend"];
+    if grouped {
+        header.insert(3, "Platform");
+    }
+    if origins {
+        header.insert(1, "Origin");
+    }
+    if show_id {
+        header.insert(1, "Image ID");
+    }
 
     let mut builder = Builder::default();
-    builder.push_record(["Commit", "Branch", "Date", "Size", "Delta", "Trend"]);
+    builder.push_record(header);
 
-    let mut prev_size: Option<u64> = None;
+    let mut prev_sizes: HashMap<String, u64> = HashMap::new();
 
     for snapshot in snapshots {
+        let Some(value) = metric.value(snapshot) else {
+            continue;
+        };
+        let platform = if grouped { snapshot.platform() } else { String::new() };
+        let prev_size = prev_sizes.get(&platform).copied();
         let commit_short = snapshot.commit_sha.chars().take(7).collect::<String>();
-        let date = snapshot.timestamp.format("%Y-%m-%d %H:%M").to_string();
-        let size = format_size(snapshot.total_size);
+        let date = format_timestamp(snapshot.timestamp, "%Y-%m-%d %H:%M", output.time_zone);
+        let size = metric.format(value);
 
         let (delta_str, trend) = if let Some(prev) = prev_size {
-            let delta = snapshot.total_size as i64 - prev as i64;
-            let trend = if delta > 0 {
+            let delta = value as i64 - prev as i64;
+            let trend = if output.plain {
+                ""
+            } else if delta > 0 {
                 "📈"
             } else if delta < 0 {
                 "📉"
             } else {
                 "✅"
             };
-            (format_size_delta(delta), trend)
+            (metric.format_delta(delta, output.plain), trend)
         } else {
             ("—".to_string(), "—")
         };
 
+        let mut row = vec![
+            commit_short,
+            snapshot.branch.clone(),
+            date,
+            size,
+            delta_str,
+            trend.to_string(),
+        ];
+        if grouped {
+            row.insert(3, platform.clone());
+        }
+        if origins {
+            let origin = if output.plain {
+                snapshot.origin.as_str()
+            } else {
+                snapshot.origin.marker()
+            };
+            row.insert(1, origin.to_string());
+        }
+        if show_id {
+            row.insert(1, snapshot.image_id.as_deref().map_or("—".to_string(), short_image_id));
+        }
+        builder.push_record(row);
+
+        prev_sizes.insert(platform, value);
+    }
+
+    render_table(builder, output.plain)
+}
+
+pub fn print_diff_history_table(
+    out: &mut impl Write,
+    diffs: &[SizeDiff],
+    output: OutputStyle,
+) -> Result<()> {
+    writeln!(out, "\n{}", "Saved Diffs".bold().underline())?;
+
+    if diffs.is_empty() {
+        writeln!(
+            out,
+            "{}",
+            "No saved diffs (record them with dit diff --save-diff-to-history)".dimmed()
+        )?;
+        return Ok(());
+    }
+
+    let mut builder = Builder::default();
+    builder.push_record(["From", "To", "Date", "Before", "After", "Delta", "Layers", "Largest change"]);
+
+    for diff in diffs {
+        let changes: Vec<&LayerChange> = diff
+            .layer_changes
+            .iter()
+            .filter(|c| !matches!(c, LayerChange::Unchanged(_)))
+            .collect();
+        let largest = changes
+            .iter()
+            .max_by_key(|c| c.size_delta().abs())
+            .map(|c| {
+                format!(
+                    "{} {}",
+                    format_size_delta(c.size_delta(), output.plain),
+                    middle_ellipsis(&c.layer().command, 40)
+                )
+            })
+            .unwrap_or_else(|| "—".to_string());
+
         builder.push_record([
-            &commit_short,
-            &snapshot.branch,
-            &date,
-            &size,
-            &delta_str,
-            trend,
+            diff.before.commit_sha.chars().take(7).collect(),
+            diff.after.commit_sha.chars().take(7).collect(),
+            format_timestamp(diff.after.timestamp, "%Y-%m-%d %H:%M", output.time_zone),
+            format_size(diff.before_size()),
+            format_size(diff.after_size()),
+            format!(
+                "{}{}",
+                format_size_delta(diff.total_delta, output.plain),
+                diff.size_basis.suffix()
+            ),
+            changes.len().to_string(),
+            largest,
         ]);
+    }
 
-        prev_size = Some(snapshot.total_size);
+    writeln!(out, "{}", render_table(builder, output.plain))?;
+
+    Ok(())
+}
+
+pub fn print_branch_comparison_table(
+    out: &mut impl Write,
+    image: &str,
+    series: &[BranchSeries],
+    output: OutputStyle,
+) -> Result<()> {
+    writeln!(out, "\n{}", "Branch Comparison".bold().underline())?;
+    writeln!(out, "Image: {}", image.bright_cyan())?;
+    writeln!(out, "{}", branch_comparison_table(series, output.plain))?;
+
+    Ok(())
+}
+
+/// Rows from the oldest snapshot shown up to the branch heads, with the pairwise deltas;
+/// branches with fewer snapshots are padded with "—"
+fn branch_comparison_table(series: &[BranchSeries], plain: bool) -> String {
+    // Align rows on the branch heads so the last row compares current state
+    let rows = series.iter().map(|s| s.snapshots.len()).max().unwrap_or(0);
+    let pairs: Vec<(usize, usize)> = (0..series.len())
+        .flat_map(|a| (a + 1..series.len()).map(move |b| (a, b)))
+        .collect();
+
+    let mut header = vec!["#".to_string()];
+    header.extend(series.iter().map(|s| s.branch.clone()));
+    header.extend(
+        pairs
+            .iter()
+            .map(|&(a, b)| format!("{} vs {}", series[b].branch, series[a].branch)),
+    );
+
+    let mut builder = Builder::default();
+    builder.push_record(header);
+
+    for row in 0..rows {
+        let back = rows - 1 - row;
+        let entries: Vec<Option<&ImageSnapshot>> = series
+            .iter()
+            .map(|s| s.snapshots.len().checked_sub(back + 1).map(|i| &s.snapshots[i]))
+            .collect();
+
+        let mut record = vec![if back == 0 {
+            "head".to_string()
+        } else {
+            format!("head~{}", back)
+        }];
+
+        for entry in &entries {
+            record.push(match entry {
+                Some(snapshot) => format!(
+                    "{} {}",
+                    snapshot.commit_sha.chars().take(7).collect::<String>(),
+                    format_size(snapshot.size())
+                ),
+                None => "—".to_string(),
+            });
+        }
+
+        for &(a, b) in &pairs {
+            record.push(match (entries[a], entries[b]) {
+                (Some(before), Some(after)) => {
+                    format_size_delta(after.size() as i64 - before.size() as i64, plain)
+                }
+                _ => "—".to_string(),
+            });
+        }
+
+        builder.push_record(record);
     }
 
-    let mut table = builder.build();
-    table
-        .with(Style::rounded())
-        .with(Modify::new(Rows::first()).with(Alignment::center()));
+    render_table(builder, plain)
+}
+
+/// Coarse age of a timestamp relative to now (e.g. "3d", "5h", "12m")
+pub fn format_age(timestamp: DateTime<Utc>) -> String {
+    let age = Utc::now().signed_duration_since(timestamp);
+
+    if age.num_days() > 0 {
+        format!("{}d", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{}h", age.num_hours())
+    } else {
+        format!("{}m", age.num_minutes().max(0))
+    }
+}
+
+pub fn print_baseline_trace(out: &mut impl Write, selection: &BaselineSelection) -> Result<()> {
+    writeln!(
+        out,
+        "\n{} {}",
+        "Baseline selection:".bold(),
+        selection.image.bright_cyan()
+    )?;
+    writeln!(
+        out,
+        "  Snapshots in history: {}, for this image: {}",
+        selection.history_snapshots, selection.image_snapshots
+    )?;
+    writeln!(
+        out,
+        "  Branch filter: {}",
+        selection
+            .base_branch
+            .as_deref()
+            .unwrap_or("none (latest snapshot wins)")
+    )?;
+
+    if !selection.candidates.is_empty() {
+        writeln!(out, "  Candidates (newest first):")?;
+        for candidate in &selection.candidates {
+            let decision = match candidate.decision {
+                BaselineDecision::Selected => candidate.decision.describe().green().to_string(),
+                _ => candidate.decision.describe().dimmed().to_string(),
+            };
+            writeln!(
+                out,
+                "    {}  {}  {} ago  {}  {}",
+                candidate.commit_sha.chars().take(7).collect::<String>(),
+                candidate.branch,
+                format_age(candidate.timestamp),
+                format_size(candidate.total_size),
+                decision
+            )?;
+        }
+    }
+
+    match selection.selected() {
+        Some(winner) => writeln!(
+            out,
+            "  → Selected {} on {} ({} old, {})",
+            winner.commit_sha.chars().take(7).collect::<String>().bright_cyan(),
+            winner.branch,
+            format_age(winner.timestamp),
+            format_size(winner.total_size)
+        )?,
+        None => writeln!(out, "  → {}", "No baseline found".yellow())?,
+    }
+
+    Ok(())
+}
+
+/// `dit analyze --deep`: final-image bytes under each `[path_budgets]` pattern
+pub fn print_path_usage_table(
+    out: &mut impl Write,
+    usages: &[PathUsage],
+    output: OutputStyle,
+) -> Result<()> {
+    writeln!(out, "\n{}", "Path Budgets".bold())?;
 
-    println!("{}", table);
+    if usages.is_empty() {
+        writeln!(out, "{}", "No [path_budgets] in .dit.toml".dimmed())?;
+        return Ok(());
+    }
+
+    let mut builder = Builder::default();
+    builder.push_record(["Path", "Size", "Budget", "Files", "Status"]);
+    for usage in usages {
+        builder.push_record([
+            usage.pattern.clone(),
+            format_size(usage.bytes),
+            format_size(usage.budget),
+            usage.files.to_string(),
+            if usage.exceeded() {
+                "over budget".red().to_string()
+            } else {
+                "ok".green().to_string()
+            },
+        ]);
+    }
+
+    writeln!(out, "{}", render_table(builder, output.plain))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, snapshot, tracked};
+    use crate::reference::ImageRef;
+    use crate::store::sanitize;
+
+    const LONG: &str =
+        "europe-west1-docker.pkg.dev/very-long-project/team-repo/service-name:feature-branch-with-a-long-name";
+
+    #[test]
+    fn timestamps_show_in_the_chosen_time_zone() {
+        let noon = crate::fixtures::at(12 * 60);
+        assert_eq!(format_timestamp(noon, "%Y-%m-%d %H:%M", None), "2024-01-01 12:00");
+        assert_eq!(
+            format_timestamp(noon, "%Y-%m-%d %H:%M", Some(chrono_tz::America::New_York)),
+            "2024-01-01 07:00"
+        );
+        assert_eq!(format_timestamp(noon, "%Y-%m-%d", Some(chrono_tz::Asia::Tokyo)), "2024-01-01");
+    }
+
+    #[test]
+    fn short_references_are_left_alone() {
+        assert_eq!(shorten_reference("myapp:latest", NAME_WIDTH), "myapp:latest");
+        assert_eq!(shorten_reference("ghcr.io/org/app:v1", 18), "ghcr.io/org/app:v1");
+    }
+
+    #[test]
+    fn long_references_keep_the_host_initial_last_segment_and_tag() {
+        let short = shorten_reference(LONG, NAME_WIDTH);
+
+        assert!(short.chars().count() <= NAME_WIDTH, "{}", short);
+        assert!(short.starts_with("e…/service-name:"), "{}", short);
+        assert!(short.ends_with("long-name"), "{}", short);
+    }
+
+    #[test]
+    fn the_middle_segments_go_first() {
+        assert_eq!(
+            shorten_reference("registry.example.com/a/b/c/d/app:v1", 30),
+            "registry.example.com/…/app:v1"
+        );
+    }
+
+    #[test]
+    fn registry_ports_are_not_tags() {
+        let short = shorten_reference("localhost:5000/some/deeply/nested/path/to/service", 30);
+
+        assert!(short.chars().count() <= 30, "{}", short);
+        assert!(short.starts_with("localhost:5000/…/") || short.starts_with("l…/"), "{}", short);
+        assert!(short.ends_with("service"), "{}", short);
+    }
+
+    #[test]
+    fn adversarial_references_never_exceed_the_width() {
+        let references = [
+            "a".repeat(200),
+            format!("{}:{}", "a".repeat(100), "t".repeat(100)),
+            format!("{}/{}", "h".repeat(100), "n".repeat(100)),
+            "/".repeat(60),
+            ":".repeat(60),
+            format!("{}/app@sha256:{}", "r".repeat(50), "f".repeat(64)),
+            "é".repeat(80),
+        ];
+
+        for reference in &references {
+            for max in [3, 12, 20, NAME_WIDTH] {
+                let short = shorten_reference(reference, max);
+                assert!(short.chars().count() <= max, "{:?} at {} → {:?}", reference, max, short);
+            }
+        }
+    }
+
+    #[test]
+    fn middle_ellipsis_keeps_both_ends() {
+        assert_eq!(middle_ellipsis("abcdefghij", 5), "ab…ij");
+        assert_eq!(middle_ellipsis("abc", 5), "abc");
+        // Too narrow to elide anything sensibly
+        assert_eq!(middle_ellipsis("abcdefghij", 2), "abcdefghij");
+    }
+
+    #[test]
+    fn names_that_shorten_alike_stay_apart_in_history() {
+        let one = "europe-west1-docker.pkg.dev/project-one/team-repo/service-name";
+        let two = "europe-west1-docker.pkg.dev/project-two/team-repo/service-name";
+
+        assert_eq!(shorten_reference(one, 20), shorten_reference(two, 20));
+        assert!(!ImageRef::parse_lenient(one).matches(&snapshot(two, "latest", vec![])));
+        assert!(ImageRef::parse_lenient(one).matches(&snapshot(one, "latest", vec![])));
+        assert_ne!(sanitize(one), sanitize(two));
+    }
+
+    fn strip_ansi(text: &str) -> String {
+        regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap().replace_all(text, "").into_owned()
+    }
+
+    /// `count` added layers of 1 kiB, 2 kiB, … in layer order
+    fn growing(count: u64) -> Vec<LayerChange> {
+        (1..=count)
+            .map(|i| LayerChange::Added(layer(&format!("sha256:{}", i), i * 1024, "COPY . .")))
+            .collect()
+    }
+
+    fn sizes(limited: &LimitedChanges) -> Vec<i64> {
+        limited.shown.iter().map(|c| c.size_delta()).collect()
+    }
+
+    #[test]
+    fn short_lists_are_shown_whole() {
+        let changes = growing(DEFAULT_MAX_CHANGES as u64);
+        let limited = limit_changes(&changes, DEFAULT_MAX_CHANGES);
+
+        assert_eq!(limited.shown.len(), DEFAULT_MAX_CHANGES);
+        assert_eq!(limited.hidden, 0);
+        assert_eq!(limited.remainder_line(false), None);
+        assert!(limit_changes(&[], 5).shown.is_empty());
+    }
+
+    #[test]
+    fn zero_shows_everything() {
+        let changes = growing(313);
+        let limited = limit_changes(&changes, 0);
+
+        assert_eq!(limited.shown.len(), 313);
+        assert_eq!(limited.remainder_line(false), None);
+    }
+
+    #[test]
+    fn the_largest_changes_are_kept_in_layer_order() {
+        let changes = growing(313);
+        let limited = limit_changes(&changes, 50);
+
+        let expected: Vec<i64> = (264..=313).map(|i| i * 1024).collect();
+        assert_eq!(sizes(&limited), expected);
+        assert_eq!(limited.hidden, 263);
+        assert_eq!(limited.hidden_delta, (1..=263).sum::<i64>() * 1024);
+    }
+
+    #[test]
+    fn significance_is_by_absolute_delta() {
+        let changes = vec![
+            LayerChange::Added(layer("sha256:a", 10, "")),
+            LayerChange::Removed(layer("sha256:b", 500, "")),
+            LayerChange::Unchanged(layer("sha256:c", 9000, "")),
+            LayerChange::Modified {
+                before: layer("sha256:d", 1000, ""),
+                after: layer("sha256:e", 700, ""),
+            },
+            LayerChange::Added(layer("sha256:f", 400, "")),
+        ];
+        let limited = limit_changes(&changes, 2);
+
+        // Unchanged layers have no delta however big they are
+        assert_eq!(sizes(&limited), vec![-500, 400]);
+        assert_eq!(limited.hidden, 3);
+        assert_eq!(limited.hidden_delta, 10 - 300);
+    }
+
+    #[test]
+    fn earlier_layers_win_ties() {
+        let changes: Vec<LayerChange> = (0..100)
+            .map(|i| LayerChange::Added(layer(&format!("sha256:{}", i), 64, "")))
+            .collect();
+        let limited = limit_changes(&changes, 3);
+
+        let digests: Vec<&str> = limited.shown.iter().map(|c| c.layer().digest.as_str()).collect();
+        assert_eq!(digests, vec!["sha256:0", "sha256:1", "sha256:2"]);
+    }
+
+    #[test]
+    fn remainder_line_counts_and_totals_what_was_left_out() {
+        let changes = growing(313);
+
+        let line = strip_ansi(&limit_changes(&changes, 50).remainder_line(false).unwrap());
+        assert_eq!(line, "… and 263 more changes totaling +33.9 MiB (run with --max-changes 0 for all)");
+
+        let line = strip_ansi(&limit_changes(&changes, 312).remainder_line(false).unwrap());
+        assert_eq!(line, "… and 1 more change totaling +1.0 kiB (run with --max-changes 0 for all)");
+    }
+
+    fn with_origin(mut snapshot: ImageSnapshot, origin: ImageOrigin) -> ImageSnapshot {
+        snapshot.origin = origin;
+        snapshot
+    }
+
+    #[test]
+    fn history_marks_built_and_pulled_snapshots() {
+        let snapshots = vec![
+            tracked("myapp", "1", "a1", "main", 0, vec![layer("sha256:a", 100, "")]),
+            with_origin(tracked("myapp", "1", "a2", "main", 1, vec![layer("sha256:a", 100, "")]), ImageOrigin::Built),
+            with_origin(tracked("myapp", "1", "a3", "main", 2, vec![layer("sha256:a", 120, "")]), ImageOrigin::Pulled),
+        ];
+        let table = history_table(&snapshots, false, Metric::Size, OutputStyle::default());
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert!(lines.iter().any(|l| l.contains("Commit") && l.contains("Origin")), "{}", table);
+        let marker = |commit: &str| {
+            let row = lines.iter().find(|l| l.contains(commit)).unwrap();
+            row.split('│').nth(2).unwrap().trim().to_string()
+        };
+        assert_eq!(marker("a1"), "?");
+        assert_eq!(marker("a2"), "B");
+        assert_eq!(marker("a3"), "P");
+    }
+
+    #[test]
+    fn history_without_origins_has_no_origin_column() {
+        let snapshots = vec![tracked("myapp", "1", "a1", "main", 0, vec![layer("sha256:a", 100, "")])];
+        assert!(!history_table(&snapshots, false, Metric::Size, OutputStyle::default()).contains("Origin"));
+    }
+
+    #[test]
+    fn diff_headers_name_known_origins() {
+        let built = with_origin(snapshot("myapp", "1", vec![]), ImageOrigin::Built);
+        assert_eq!(origin_note(&built), ", built");
+        assert_eq!(origin_note(&with_origin(built, ImageOrigin::Pulled)), ", pulled");
+        assert_eq!(origin_note(&snapshot("myapp", "1", vec![])), "");
+    }
+
+    #[test]
+    fn snapshots_recorded_before_origins_are_unknown() {
+        let mut json = serde_json::to_value(snapshot("myapp", "1", vec![])).unwrap();
+        json.as_object_mut().unwrap().remove("origin");
+        let old: ImageSnapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(old.origin, ImageOrigin::Unknown);
+    }
+
+    #[test]
+    fn plain_sizes_spell_out_binary_units() {
+        assert_eq!(spell_size(0), "0 bytes");
+        assert_eq!(spell_size(1), "1 byte");
+        assert_eq!(spell_size(899), "899 bytes");
+        assert_eq!(spell_size(2048), "2.0 kibibytes");
+        assert_eq!(spell_size(13_002_342), "12.4 mebibytes");
+        assert_eq!(spell_size(3 * 1024 * 1024 * 1024), "3.0 gibibytes");
+        assert_eq!(spell_size(5 * 1024u64.pow(4)), "5.0 tebibytes");
+    }
+
+    fn series(branch: &str, sizes: &[u64]) -> BranchSeries {
+        BranchSeries {
+            branch: branch.to_string(),
+            snapshots: sizes
+                .iter()
+                .enumerate()
+                .map(|(i, size)| {
+                    let commit = format!("{}{}", branch.replace('/', ""), i);
+                    tracked("myapp", "1", &commit, branch, i as i64, vec![layer("sha256:a", *size, "")])
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn two_branch_comparison_aligns_heads() {
+        let series = [series("main", &[100, 200]), series("release", &[150, 160])];
+        let table = branch_comparison_table(&series, false);
+        let lines: Vec<&str> = table.lines().collect();
+
+        let header = lines.iter().find(|l| l.contains("release vs main")).unwrap();
+        assert!(header.contains("main") && header.contains("release"));
+        let head = lines.iter().find(|l| l.contains("head ")).unwrap();
+        // 160 - 200
+        assert!(head.contains("-40 B"), "{}", head);
+        assert!(lines.iter().any(|l| l.contains("head~1") && l.contains("+50 B")), "{}", table);
+    }
+
+    #[test]
+    fn three_branch_comparison_pads_short_branches() {
+        let table = branch_comparison_table(&[
+            series("main", &[100, 110, 120]),
+            series("release/2.x", &[90]),
+            series("develop", &[]),
+        ], false);
+
+        assert!(table.contains("release/2.x vs main"));
+        assert!(table.contains("develop vs main"));
+        assert!(table.contains("develop vs release/2.x"));
+        let rows: Vec<&str> = table.lines().filter(|l| l.contains("head")).collect();
+        assert_eq!(rows.len(), 3, "{}", table);
+        // Only the heads of main and release/2.x line up; develop has nothing to compare
+        assert!(rows[2].contains("-30 B"), "{}", rows[2]);
+        assert!(rows[0].contains("head~2") && rows[0].matches('—').count() == 5, "{}", rows[0]);
+    }
 }
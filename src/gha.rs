@@ -0,0 +1,468 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::build_metadata::read_build_metadata;
+use crate::ci::{
+    image_budgets, parse_size, run_ci, trigger_paths, CiConfig, CiOutputFormat, CommentMode,
+    ImageSources, DEFAULT_WARN_AT,
+};
+use crate::config::DitConfig;
+use crate::docker::ImageInspector;
+use crate::format::{OutputStyle, DEFAULT_MAX_CHANGES};
+use crate::labels::ImageSelector;
+use crate::path_budget::path_budgets;
+use crate::report_link::parse_report_link;
+use crate::store::Store;
+
+/// Every input `dit gha` understands, as declared in action.yml
+pub const INPUTS: &[(&str, &str)] = &[
+    ("images", "Images to track, separated by commas or newlines"),
+    ("image", "Alias for images"),
+    ("filter", "Track local images matching a name or label:KEY[=VALUE]"),
+    ("tag-regex", "Only track images whose tag matches this regex"),
+    ("compose", "Track the images of this docker-compose file"),
+    ("project-name", "Compose project name for the compose images"),
+    ("iidfile", "Also track the image whose ID is in this docker build --iidfile file"),
+    ("as", "Name:tag to record the iidfile image under"),
+    ("budget", "Maximum total size (e.g. 500MB)"),
+    ("budget-increase", "Maximum increase percentage per image"),
+    ("warn-at", "Warn once the total reaches this percentage of the budget"),
+    ("strict-warnings", "Fail on budget warnings too"),
+    ("comment", "Post or update the PR comment"),
+    ("comment-mode", "on-change or always"),
+    ("fail-on-increase", "Fail if any image grew"),
+    ("fail-if-base-image-changed", "Fail when an image's base image changed"),
+    ("warn-large-single-layer", "Flag layers bigger than this size"),
+    ("fail-on-large-layer", "Fail instead of warn on large layers"),
+    ("base", "Baseline branch"),
+    ("paths", "Skip images when no file matching these globs changed (comma-separated)"),
+    ("multi-platform", "Compare every platform of each image's registry index"),
+    ("platforms", "separate, max or sum"),
+    ("explain-baseline", "Print why each baseline was picked"),
+    ("show-env-values", "Show ENV values in environment changes"),
+    ("basis", "auto, compressed or uncompressed sizes for comparisons"),
+    ("max-changes", "Layer changes listed per image in the report (0 for all)"),
+    ("baseline-origin", "same or any: require baselines built or pulled like the current image"),
+    ("dry-run", "Run every check without commenting, recording history or failing"),
+    ("build-metadata", "docker buildx build --metadata-file output; reports the build time change"),
+    ("report-link", "Footer link to the full report; {run_id}, {run_number}, {repository} and {sha} are filled in"),
+    ("profile", "[profiles.<name>] section of .dit.toml to apply"),
+    ("token", "GitHub token; the action passes it to dit as GITHUB_TOKEN"),
+];
+
+/// An action input that is unknown or could not be parsed
+#[derive(Debug, PartialEq)]
+pub struct InputError {
+    pub input: String,
+    pub message: String,
+}
+
+/// `dit ci` settings from action inputs. `config.images`, `cost` and the budgets
+/// are filled in by [`run_gha`] once the sources are resolved and .dit.toml is read.
+#[derive(Debug)]
+pub struct GhaInputs {
+    pub sources: ImageSources,
+    pub config: CiConfig,
+    pub warn_at: Option<f64>,
+    pub profile: Option<String>,
+}
+
+/// Action inputs from `INPUT_*` variables, keyed by their action.yml name. The runner keeps
+/// hyphens (`INPUT_BUDGET-INCREASE`); underscores are accepted too. Empty inputs are unset.
+pub fn read_inputs(vars: impl IntoIterator<Item = (String, String)>) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter_map(|(key, value)| {
+            let name = key.strip_prefix("INPUT_")?.to_lowercase().replace('_', "-");
+            let value = value.trim();
+            (!value.is_empty()).then(|| (name, value.to_string()))
+        })
+        .collect()
+}
+
+/// Parses inputs one by one, collecting every error instead of stopping at the first
+struct InputParser<'a> {
+    inputs: &'a BTreeMap<String, String>,
+    errors: Vec<InputError>,
+}
+
+impl InputParser<'_> {
+    fn error(&mut self, input: &str, message: impl Into<String>) {
+        self.errors.push(InputError {
+            input: input.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn value(&self, name: &str) -> Option<String> {
+        self.inputs.get(name).cloned()
+    }
+
+    fn parse<T>(&mut self, name: &str, parser: impl Fn(&str) -> Result<T, String>) -> Option<T> {
+        let value = self.inputs.get(name)?;
+        match parser(value) {
+            Ok(parsed) => Some(parsed),
+            Err(message) => {
+                self.error(name, message);
+                None
+            }
+        }
+    }
+
+    /// `true` / `false`, case-insensitive; unset is `false`
+    fn flag(&mut self, name: &str) -> bool {
+        self.parse(name, |value| match value.to_lowercase().as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("expected true or false, got '{}'", value)),
+        })
+        .unwrap_or(false)
+    }
+
+    fn size(&mut self, name: &str) -> Option<u64> {
+        self.parse(name, |value| parse_size(value).map_err(|e| e.to_string()))
+    }
+
+    fn number(&mut self, name: &str) -> Option<f64> {
+        self.parse(name, |value| {
+            value
+                .parse()
+                .map_err(|_| format!("expected a number, got '{}'", value))
+        })
+    }
+
+    fn choice<T: ValueEnum>(&mut self, name: &str) -> Option<T> {
+        self.parse(name, |value| T::from_str(value, true))
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        self.inputs.contains_key(name)
+    }
+}
+
+/// Map action inputs onto `dit ci` settings with the same parsing and checks as the flags.
+/// Every problem is collected so the user sees them all in one run.
+pub fn ci_config_from_inputs(inputs: &BTreeMap<String, String>) -> Result<GhaInputs, Vec<InputError>> {
+    let mut p = InputParser {
+        inputs,
+        errors: Vec::new(),
+    };
+
+    for name in inputs.keys() {
+        if !INPUTS.iter().any(|(known, _)| known == name) {
+            p.error(name, "unknown input");
+        }
+    }
+
+    let images: Vec<String> = ["images", "image"]
+        .iter()
+        .filter_map(|name| p.value(name))
+        .flat_map(|value| {
+            value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let filter = p.parse("filter", |value| value.parse::<ImageSelector>());
+    let tag_regex = p.parse("tag-regex", |value| Regex::new(value).map_err(|e| e.to_string()));
+    let compose = p.value("compose");
+    let project_name = p.value("project-name");
+    let iidfile = p.value("iidfile");
+    let as_name = p.value("as");
+
+    // The same conflicts and requirements as the `dit ci` flags
+    let selected = p.is_set("filter") || p.is_set("tag-regex");
+    if !images.is_empty() && selected {
+        p.error("filter", "cannot be combined with images");
+    }
+    if compose.is_some() && (!images.is_empty() || selected) {
+        p.error("compose", "cannot be combined with images, filter or tag-regex");
+    }
+    if project_name.is_some() && compose.is_none() {
+        p.error("project-name", "requires compose");
+    }
+    if iidfile.is_some() != as_name.is_some() {
+        let missing = if iidfile.is_some() { "as" } else { "iidfile" };
+        p.error(missing, "iidfile and as must be set together");
+    }
+    if images.is_empty() && !selected && compose.is_none() && iidfile.is_none() {
+        p.error("images", "one of images, filter, tag-regex, compose or iidfile is required");
+    }
+
+    let warn_at = p.number("warn-at");
+    let multi_platform = p.flag("multi-platform");
+    if multi_platform && iidfile.is_some() {
+        p.error("multi-platform", "cannot be combined with iidfile");
+    }
+    let build_metadata = p.parse("build-metadata", |path| {
+        read_build_metadata(path).map_err(|e| format!("{:#}", e))
+    });
+    if multi_platform && build_metadata.is_some() {
+        p.error("build-metadata", "cannot be combined with multi-platform");
+    }
+    let config = CiConfig {
+        images: Vec::new(),
+        budget_bytes: p.size("budget"),
+        budget_increase_percent: p.number("budget-increase"),
+        github_comment: p.flag("comment"),
+        base_branch: p.value("base"),
+        fail_on_increase: p.flag("fail-on-increase"),
+        fail_if_base_image_changed: p.flag("fail-if-base-image-changed"),
+        warn_large_layer_bytes: p.size("warn-large-single-layer"),
+        fail_on_large_layer: p.flag("fail-on-large-layer"),
+        explain_baseline: p.flag("explain-baseline"),
+        warn_at_percent: warn_at.unwrap_or(DEFAULT_WARN_AT),
+        strict_warnings: p.flag("strict-warnings"),
+        show_env_values: p.flag("show-env-values"),
+        paths: p
+            .value("paths")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|path| path.trim().to_string())
+                    .filter(|path| !path.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        trigger_paths: Vec::new(),
+        format: CiOutputFormat::Markdown,
+        report_template: None,
+        print_template_context: false,
+        cost: None,
+        output_path: None,
+        output_append: false,
+        output_header: false,
+        comment_mode: p.choice("comment-mode").unwrap_or(CommentMode::OnChange),
+        multi_platform,
+        platforms: p.choice("platforms").unwrap_or_default(),
+        image_budgets: Vec::new(),
+        github_actions: true,
+        path_budgets: Vec::new(),
+        basis: p.choice("basis").unwrap_or_default(),
+        max_changes: p
+            .parse("max-changes", |value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("expected a whole number, got '{}'", value))
+            })
+            .unwrap_or(DEFAULT_MAX_CHANGES),
+        baseline_origin: p.choice("baseline-origin").unwrap_or_default(),
+        dry_run: p.flag("dry-run"),
+        build_metadata,
+        report_link: p.parse("report-link", parse_report_link),
+        output: OutputStyle::default(),
+    };
+
+    if !p.errors.is_empty() {
+        return Err(p.errors);
+    }
+
+    Ok(GhaInputs {
+        sources: ImageSources {
+            images,
+            iidfile,
+            as_name,
+            filter,
+            tag_regex,
+            compose,
+            project_name,
+        },
+        config,
+        warn_at,
+        profile: p.value("profile"),
+    })
+}
+
+/// `dit gha`: `dit ci` configured from action inputs, writing step outputs, the job
+/// summary and annotations. Returns `true` when a check failed.
+pub async fn run_gha(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    store: &Store,
+    profile: Option<String>,
+    output: OutputStyle,
+) -> Result<bool> {
+    let inputs = read_inputs(std::env::vars());
+
+    let GhaInputs {
+        sources,
+        mut config,
+        warn_at,
+        profile: input_profile,
+    } = match ci_config_from_inputs(&inputs) {
+        Ok(settings) => settings,
+        Err(errors) => {
+            for error in &errors {
+                writeln!(out, "::error title=Invalid input '{}'::{}", error.input, error.message)?;
+            }
+            bail!("{} invalid action input(s)", errors.len());
+        }
+    };
+
+    let dit_config = DitConfig::load()?;
+    let profile = input_profile.or(profile);
+    config.cost = dit_config.cost.clone();
+    config.output = output;
+    config.image_budgets = image_budgets(&dit_config, profile.as_deref(), warn_at)?;
+    config.path_budgets = path_budgets(&dit_config.path_budgets)?;
+    config.trigger_paths = trigger_paths(&dit_config)?;
+    config.images = sources.resolve().await?;
+
+    if let Ok(summary) = std::env::var("GITHUB_STEP_SUMMARY") {
+        config.output_path = Some(summary);
+        config.output_append = true;
+        config.output_header = true;
+    }
+
+    run_ci(out, docker, store, config).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::BaselineOrigin;
+    use crate::diff::BasisChoice;
+    use crate::docker::LabelFilter;
+    use crate::platforms::PlatformMode;
+
+    /// An input, the `INPUT_*` variables that set it and what it should produce
+    type Row<'a> = (&'a str, Vec<(&'a str, &'a str)>, fn(&GhaInputs) -> bool);
+
+    /// Inputs that pick images; every other row also gets `INPUT_IMAGES`
+    const SOURCES: &[&str] = &["images", "image", "filter", "tag-regex", "compose", "iidfile"];
+
+    fn parse(vars: &[(&str, &str)]) -> Result<GhaInputs, Vec<InputError>> {
+        let inputs = read_inputs(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        ci_config_from_inputs(&inputs)
+    }
+
+    #[test]
+    fn every_input_reaches_the_ci_config() {
+        let metadata = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(metadata.path(), r#"{"containerimage.config.digest": "sha256:abc"}"#).unwrap();
+        let metadata_path = metadata.path().to_str().unwrap().to_string();
+
+        let table: Vec<Row> = vec![
+            ("images", vec![("INPUT_IMAGES", "a:1, b:2\nc:3")], |g| g.sources.images == ["a:1", "b:2", "c:3"]),
+            ("image", vec![("INPUT_IMAGE", "a:1")], |g| g.sources.images == ["a:1"]),
+            ("filter", vec![("INPUT_FILTER", "label:team=web")], |g| {
+                matches!(&g.sources.filter, Some(ImageSelector::Label(LabelFilter { key, value })) if key == "team" && value.as_deref() == Some("web"))
+            }),
+            ("tag-regex", vec![("INPUT_TAG_REGEX", "^v\\d")], |g| g.sources.tag_regex.as_ref().is_some_and(|r| r.as_str() == "^v\\d")),
+            ("compose", vec![("INPUT_COMPOSE", "compose.yml")], |g| g.sources.compose.as_deref() == Some("compose.yml")),
+            ("project-name", vec![("INPUT_COMPOSE", "compose.yml"), ("INPUT_PROJECT-NAME", "shop")], |g| {
+                g.sources.project_name.as_deref() == Some("shop")
+            }),
+            ("iidfile", vec![("INPUT_IIDFILE", "iid.txt"), ("INPUT_AS", "app:ci")], |g| g.sources.iidfile.as_deref() == Some("iid.txt")),
+            ("as", vec![("INPUT_IIDFILE", "iid.txt"), ("INPUT_AS", "app:ci")], |g| g.sources.as_name.as_deref() == Some("app:ci")),
+            ("budget", vec![("INPUT_BUDGET", "500MB")], |g| g.config.budget_bytes == parse_size("500MB").ok()),
+            ("budget-increase", vec![("INPUT_BUDGET-INCREASE", "10")], |g| g.config.budget_increase_percent == Some(10.0)),
+            ("warn-at", vec![("INPUT_WARN_AT", "75")], |g| g.warn_at == Some(75.0) && g.config.warn_at_percent == 75.0),
+            ("strict-warnings", vec![("INPUT_STRICT_WARNINGS", "true")], |g| g.config.strict_warnings),
+            ("comment", vec![("INPUT_COMMENT", "TRUE")], |g| g.config.github_comment),
+            ("comment-mode", vec![("INPUT_COMMENT_MODE", "always")], |g| g.config.comment_mode == CommentMode::Always),
+            ("fail-on-increase", vec![("INPUT_FAIL_ON_INCREASE", "true")], |g| g.config.fail_on_increase),
+            ("fail-if-base-image-changed", vec![("INPUT_FAIL_IF_BASE_IMAGE_CHANGED", "true")], |g| {
+                g.config.fail_if_base_image_changed
+            }),
+            ("warn-large-single-layer", vec![("INPUT_WARN_LARGE_SINGLE_LAYER", "100MB")], |g| {
+                g.config.warn_large_layer_bytes == parse_size("100MB").ok()
+            }),
+            ("fail-on-large-layer", vec![("INPUT_FAIL_ON_LARGE_LAYER", "true")], |g| g.config.fail_on_large_layer),
+            ("base", vec![("INPUT_BASE", "main")], |g| g.config.base_branch.as_deref() == Some("main")),
+            ("paths", vec![("INPUT_PATHS", "src/**, Dockerfile,")], |g| g.config.paths == ["src/**", "Dockerfile"]),
+            ("multi-platform", vec![("INPUT_MULTI_PLATFORM", "true")], |g| g.config.multi_platform),
+            ("platforms", vec![("INPUT_PLATFORMS", "max")], |g| g.config.platforms == PlatformMode::Max),
+            ("explain-baseline", vec![("INPUT_EXPLAIN_BASELINE", "true")], |g| g.config.explain_baseline),
+            ("show-env-values", vec![("INPUT_SHOW_ENV_VALUES", "true")], |g| g.config.show_env_values),
+            ("basis", vec![("INPUT_BASIS", "compressed")], |g| g.config.basis == BasisChoice::Compressed),
+            ("max-changes", vec![("INPUT_MAX_CHANGES", "5")], |g| g.config.max_changes == 5),
+            ("baseline-origin", vec![("INPUT_BASELINE_ORIGIN", "same")], |g| g.config.baseline_origin == BaselineOrigin::Same),
+            ("dry-run", vec![("INPUT_DRY_RUN", "true")], |g| g.config.dry_run),
+            ("build-metadata", vec![("INPUT_BUILD_METADATA", metadata_path.as_str())], |g| {
+                g.config.build_metadata.as_ref().and_then(|m| m.config_digest.as_deref()) == Some("sha256:abc")
+            }),
+            ("report-link", vec![("INPUT_REPORT_LINK", "https://ci.example.com/{run_id}")], |g| {
+                g.config.report_link.as_deref() == Some("https://ci.example.com/{run_id}")
+            }),
+            ("profile", vec![("INPUT_PROFILE", "release")], |g| g.profile.as_deref() == Some("release")),
+        ];
+
+        let covered: Vec<&str> = table.iter().map(|(input, _, _)| *input).collect();
+        let expected: Vec<&str> = INPUTS.iter().map(|(name, _)| *name).filter(|name| *name != "token").collect();
+        assert_eq!(covered, expected, "one row per input, in INPUTS order");
+
+        for (input, mut vars, check) in table {
+            let names = read_inputs(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+            if !names.keys().any(|name| SOURCES.contains(&name.as_str())) {
+                vars.push(("INPUT_IMAGES", "app:1"));
+            }
+            let inputs = parse(&vars).unwrap_or_else(|errors| panic!("{}: {:?}", input, errors));
+            assert!(check(&inputs), "{} didn't reach the config", input);
+        }
+    }
+
+    #[test]
+    fn unset_inputs_keep_the_ci_defaults() {
+        let inputs = parse(&[("INPUT_IMAGES", "app:1"), ("INPUT_BUDGET", "  ")]).unwrap();
+
+        assert_eq!(inputs.config.budget_bytes, None);
+        assert!(!inputs.config.github_comment);
+        assert_eq!(inputs.config.comment_mode, CommentMode::OnChange);
+        assert_eq!(inputs.config.warn_at_percent, DEFAULT_WARN_AT);
+        assert_eq!(inputs.config.max_changes, DEFAULT_MAX_CHANGES);
+        assert!(inputs.config.github_actions);
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let errors = parse(&[
+            ("INPUT_IMAGES", "app:1"),
+            ("INPUT_FILTER", "app"),
+            ("INPUT_BUDGET", "lots"),
+            ("INPUT_COMMENT", "yes"),
+            ("INPUT_IIDFILE", "iid.txt"),
+            ("INPUT_TYPO", "1"),
+        ])
+        .unwrap_err();
+
+        let inputs: Vec<&str> = errors.iter().map(|e| e.input.as_str()).collect();
+        for expected in ["typo", "filter", "as", "budget", "comment"] {
+            assert!(inputs.contains(&expected), "no error for {} in {:?}", expected, errors);
+        }
+    }
+
+    #[test]
+    fn action_yml_declares_and_maps_every_input() {
+        let action: serde_yaml::Value =
+            serde_yaml::from_str(include_str!("../action/action.yml")).unwrap();
+        let declared = action["inputs"].as_mapping().unwrap();
+        let step = action["runs"]["steps"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .find(|step| step["id"].as_str() == Some("dit"))
+            .unwrap();
+
+        for (name, _) in INPUTS {
+            assert!(declared.contains_key(*name), "action.yml doesn't declare '{}'", name);
+
+            let variable = match *name {
+                "token" => "GITHUB_TOKEN".to_string(),
+                _ => format!("INPUT_{}", name.to_uppercase().replace('-', "_")),
+            };
+            assert_eq!(
+                step["env"][variable.as_str()].as_str(),
+                Some(format!("${{{{ inputs.{} }}}}", name).as_str()),
+                "action.yml doesn't pass '{}' as {}",
+                name,
+                variable
+            );
+        }
+        assert_eq!(declared.len(), INPUTS.len(), "action.yml declares inputs dit gha doesn't know");
+    }
+}
@@ -1,9 +1,10 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::io::Write;
 
 const GITHUB_API_BASE: &str = "https://api.github.com";
-const DIT_MARKER: &str = "<!-- dit-report -->";
+pub const DIT_MARKER: &str = "<!-- dit-report -->";
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -41,6 +42,34 @@ impl GitHubContext {
         })
     }
     
+    /// Like [`from_env`](Self::from_env), but outside GitHub Actions (no `GITHUB_TOKEN` or
+    /// `GITHUB_REPOSITORY`) returns an empty context that [`is_available`](Self::is_available)
+    /// reports as unusable instead of failing
+    pub fn from_env_safe() -> Self {
+        let (Ok(token), Ok(repo)) = (env::var("GITHUB_TOKEN"), env::var("GITHUB_REPOSITORY")) else {
+            return Self {
+                token: String::new(),
+                repo: String::new(),
+                pr_number: None,
+                sha: String::new(),
+                ref_name: String::new(),
+            };
+        };
+
+        Self {
+            token,
+            repo,
+            pr_number: Self::extract_pr_number().unwrap_or(None),
+            sha: env::var("GITHUB_SHA").unwrap_or_else(|_| "unknown".to_string()),
+            ref_name: env::var("GITHUB_REF").unwrap_or_else(|_| "unknown".to_string()),
+        }
+    }
+
+    /// Whether there are credentials and a repository to post to
+    pub fn is_available(&self) -> bool {
+        !self.token.is_empty() && !self.repo.is_empty()
+    }
+
     fn extract_pr_number() -> Result<Option<u64>> {
         let event_path = match env::var("GITHUB_EVENT_PATH") {
             Ok(p) => p,
@@ -78,6 +107,20 @@ struct CreateComment {
     body: String,
 }
 
+#[derive(Debug, Serialize)]
+struct UpdateRelease {
+    body: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    id: u64,
+    draft: bool,
+    name: Option<String>,
+    tag_name: String,
+    body: Option<String>,
+}
+
 pub struct GitHubClient {
     client: reqwest::Client,
     token: String,
@@ -90,7 +133,13 @@ impl GitHubClient {
         Self { client, token, repo }
     }
     
-    pub async fn post_or_update_pr_comment(&self, pr_number: u64, body: String) -> Result<()> {
+    #[tracing::instrument(name = "dit.github.comment", skip(self, out, body))]
+    pub async fn post_or_update_pr_comment(
+        &self,
+        out: &mut impl Write,
+        pr_number: u64,
+        body: String,
+    ) -> Result<()> {
         // Add marker to the comment body
         let marked_body = format!("{}\n{}", DIT_MARKER, body);
         
@@ -100,11 +149,11 @@ impl GitHubClient {
         if let Some(comment_id) = existing_comment {
             // Update existing comment
             self.update_comment(comment_id, marked_body).await?;
-            println!("✅ Updated existing PR comment");
+            writeln!(out, "✅ Updated existing PR comment")?;
         } else {
             // Create new comment
             self.create_comment(pr_number, marked_body).await?;
-            println!("✅ Posted new PR comment");
+            writeln!(out, "✅ Posted new PR comment")?;
         }
         
         Ok(())
@@ -196,4 +245,63 @@ impl GitHubClient {
         
         Ok(())
     }
+    
+    /// Append `text` to the body of the newest draft release
+    #[tracing::instrument(name = "dit.github.release", skip_all)]
+    pub async fn append_to_draft_release(&self, out: &mut impl Write, text: &str) -> Result<()> {
+        let url = format!("{}/repos/{}/releases", GITHUB_API_BASE, self.repo);
+        
+        let response = self.client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("User-Agent", "dit-docker-image-tracker")
+            .header("Accept", "application/vnd.github.v3+json")
+            .send()
+            .await
+            .context("Failed to fetch releases")?;
+        
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to list releases: {} - {}", status, text);
+        }
+        
+        // Newest first; drafts are only listed for tokens with push access
+        let releases: Vec<Release> = response.json().await?;
+        let release = releases
+            .into_iter()
+            .find(|r| r.draft)
+            .context("No draft release found to append the changelog entry to")?;
+        let name = release.name.clone().unwrap_or(release.tag_name.clone());
+        
+        let existing = release.body.unwrap_or_default();
+        if existing.contains(text.trim()) {
+            writeln!(out, "Draft release '{}' already contains this entry", name)?;
+            return Ok(());
+        }
+        let body = if existing.trim().is_empty() {
+            text.to_string()
+        } else {
+            format!("{}\n\n{}", existing.trim_end(), text)
+        };
+        
+        let response = self.client
+            .patch(format!("{}/{}", url, release.id))
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("User-Agent", "dit-docker-image-tracker")
+            .header("Accept", "application/vnd.github.v3+json")
+            .json(&UpdateRelease { body })
+            .send()
+            .await
+            .context("Failed to update release")?;
+        
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to update release: {} - {}", status, text);
+        }
+        
+        writeln!(out, "✅ Appended changelog entry to draft release '{}'", name)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+use crate::models::ImageSnapshot;
+
+/// Write `timestamp size` rows (Unix seconds, bytes) with `#` comments naming the image
+/// and marking where the branch changes
+pub fn write_gnuplot_data(path: &str, image: &str, snapshots: &[ImageSnapshot]) -> Result<()> {
+    let mut branches: Vec<&str> = snapshots.iter().map(|s| s.branch.as_str()).collect();
+    branches.sort();
+    branches.dedup();
+
+    let mut out = String::new();
+    out.push_str(&format!("# dit history: {}\n", image));
+    out.push_str(&format!("# branches: {}\n", branches.join(", ")));
+    out.push_str("# columns: unix_timestamp total_size_bytes\n");
+
+    let mut current_branch: Option<&str> = None;
+    for snapshot in snapshots {
+        if current_branch != Some(snapshot.branch.as_str()) {
+            out.push_str(&format!("# branch: {}\n", snapshot.branch));
+            current_branch = Some(&snapshot.branch);
+        }
+        out.push_str(&format!(
+            "{} {}\n",
+            snapshot.timestamp.timestamp(),
+            snapshot.size()
+        ));
+    }
+
+    fs::write(path, out).context(format!("Failed to write gnuplot data: {}", path))
+}
+
+/// Write a gnuplot script that plots `data_path` as size over time with byte-scaled labels
+pub fn write_gnuplot_script(path: &str, data_path: &str, image: &str) -> Result<()> {
+    let script = format!(
+        r#"# Generated by dit: gnuplot {script}
+set title "{title} image size"
+set xdata time
+set timefmt "%s"
+set format x "%Y-%m-%d"
+set xlabel "Date"
+set ylabel "Size"
+set format y "%.1s %cB"
+set grid
+set key off
+plot "{data}" using 1:2 with linespoints pointtype 7
+"#,
+        script = path,
+        title = escape(image),
+        data = escape(data_path),
+    );
+
+    fs::write(path, script).context(format!("Failed to write gnuplot script: {}", path))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,309 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use tabled::builder::Builder;
+
+use crate::analyze::OutputFormat;
+use crate::format::{format_size, middle_ellipsis, render_table, shorten_reference, OutputStyle};
+use crate::labels::ImageSelector;
+use crate::models::ImageSnapshot;
+use crate::store::Store;
+
+#[derive(Debug)]
+pub struct GrepOptions {
+    /// Treat the pattern as a regular expression instead of literal text
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Search every snapshot instead of the latest per image
+    pub all: bool,
+    /// Only search images whose name contains this, or with `label:KEY[=VALUE]`
+    pub filter: Option<ImageSelector>,
+    pub format: OutputFormat,
+    pub output: OutputStyle,
+}
+
+/// One layer whose command matched
+#[derive(Debug, Serialize)]
+pub struct GrepMatch {
+    pub image: String,
+    pub tag: Option<String>,
+    pub commit_sha: String,
+    /// Position in the snapshot's layer list, oldest first
+    pub layer_index: usize,
+    pub layer_size: u64,
+    pub command: String,
+    /// Byte ranges of each match within `command`
+    pub matches: Vec<(usize, usize)>,
+}
+
+pub fn build_matcher(pattern: &str, options: &GrepOptions) -> Result<Regex> {
+    let pattern = if options.regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .context(format!("Invalid pattern '{}'", pattern))
+}
+
+/// Non-overlapping match ranges, leftmost first
+pub fn match_ranges(matcher: &Regex, text: &str) -> Vec<Range<usize>> {
+    matcher
+        .find_iter(text)
+        .filter(|m| !m.is_empty())
+        .map(|m| m.range())
+        .collect()
+}
+
+/// Highlight `ranges` of `text`. Ranges come from the plain text, so colouring
+/// never splits an escape sequence.
+pub fn highlight(text: &str, ranges: &[Range<usize>]) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+
+    for range in ranges {
+        out.push_str(&text[last..range.start]);
+        out.push_str(&text[range.clone()].red().bold().to_string());
+        last = range.end;
+    }
+    out.push_str(&text[last..]);
+
+    out
+}
+
+/// Search tracked layer commands (latest snapshot per image unless `all`)
+pub fn search(history: &[ImageSnapshot], matcher: &Regex, options: &GrepOptions) -> Vec<GrepMatch> {
+    let candidates = history
+        .iter()
+        .filter(|s| options.filter.as_ref().is_none_or(|filter| filter.matches(s)));
+
+    let snapshots: Vec<&ImageSnapshot> = if options.all {
+        candidates.collect()
+    } else {
+        let mut latest: HashMap<(&str, Option<&str>), &ImageSnapshot> = HashMap::new();
+        for snapshot in candidates {
+            let entry = latest
+                .entry((&snapshot.image, snapshot.tag.as_deref()))
+                .or_insert(snapshot);
+            if snapshot.timestamp > entry.timestamp {
+                *entry = snapshot;
+            }
+        }
+        latest.into_values().collect()
+    };
+
+    let mut results = Vec::new();
+    for snapshot in snapshots {
+        for (index, layer) in snapshot.layers.iter().enumerate() {
+            let ranges = match_ranges(matcher, &layer.command);
+            if ranges.is_empty() {
+                continue;
+            }
+
+            results.push(GrepMatch {
+                image: snapshot.image.clone(),
+                tag: snapshot.tag.clone(),
+                commit_sha: snapshot.commit_sha.clone(),
+                layer_index: index,
+                layer_size: layer.size,
+                command: layer.command.clone(),
+                matches: ranges.iter().map(|r| (r.start, r.end)).collect(),
+            });
+        }
+    }
+
+    results.sort_by(|a, b| {
+        (&a.image, &a.tag, &a.commit_sha, a.layer_index)
+            .cmp(&(&b.image, &b.tag, &b.commit_sha, b.layer_index))
+    });
+    results
+}
+
+/// `dit grep`: returns false when nothing matched
+pub async fn grep_history(
+    out: &mut impl Write,
+    store: &Store,
+    pattern: &str,
+    options: &GrepOptions,
+) -> Result<bool> {
+    options.format.ensure_not_csv("grep")?;
+    let matcher = build_matcher(pattern, options)?;
+    let history = store.load_history()?;
+    let results = search(&history, &matcher, options);
+
+    match options.format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&results)?)?,
+        OutputFormat::Table if results.is_empty() => {
+            writeln!(out, "No layer commands match '{}'", pattern)?
+        }
+        OutputFormat::Table => {
+            let mut builder = Builder::default();
+            builder.push_record(["Image", "Tag", "Commit", "Layer", "Size", "Command"]);
+
+            for result in &results {
+                let ranges: Vec<Range<usize>> =
+                    result.matches.iter().map(|&(start, end)| start..end).collect();
+                builder.push_record([
+                    shorten_reference(&result.image, options.output.name_width()),
+                    middle_ellipsis(result.tag.as_deref().unwrap_or("latest"), 20),
+                    result.commit_sha.chars().take(7).collect(),
+                    result.layer_index.to_string(),
+                    format_size(result.layer_size),
+                    highlight(&result.command, &ranges),
+                ]);
+            }
+
+            writeln!(out, "{}", render_table(builder, options.output.plain))?;
+            writeln!(out, "{} matching layer(s)", results.len())?;
+        }
+        OutputFormat::Csv => unreachable!("rejected by ensure_not_csv"),
+    }
+
+    Ok(!results.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+
+    fn options() -> GrepOptions {
+        GrepOptions {
+            regex: false,
+            case_sensitive: false,
+            all: false,
+            filter: None,
+            format: OutputFormat::Table,
+            output: OutputStyle::default(),
+        }
+    }
+
+    fn ranges(pattern: &str, options: &GrepOptions, text: &str) -> Vec<Range<usize>> {
+        match_ranges(&build_matcher(pattern, options).unwrap(), text)
+    }
+
+    /// Drop SGR escape sequences, whether or not colouring is on
+    fn strip_ansi(text: &str) -> String {
+        Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(text, "").into_owned()
+    }
+
+    #[test]
+    fn literal_patterns_are_escaped() {
+        let text = "RUN apt-get install -y libmagick++ imagemagick";
+        assert_eq!(ranges("libmagick++", &options(), text), vec![23..34]);
+        assert!(ranges("apt.get", &options(), "RUN aptXget").is_empty());
+    }
+
+    #[test]
+    fn matching_is_case_insensitive_by_default() {
+        let text = "RUN apk add ImageMagick";
+        assert_eq!(ranges("imagemagick", &options(), text), vec![12..23]);
+
+        let sensitive = GrepOptions { case_sensitive: true, ..options() };
+        assert!(ranges("imagemagick", &sensitive, text).is_empty());
+    }
+
+    #[test]
+    fn regex_mode_compiles_the_pattern() {
+        let regex = GrepOptions { regex: true, ..options() };
+        assert_eq!(ranges("py(thon)?3", &regex, "RUN apk add python3 py3-pip"), vec![12..19, 20..23]);
+        assert!(build_matcher("(unclosed", &regex).is_err());
+        // The same text is a valid literal
+        assert!(build_matcher("(unclosed", &options()).is_ok());
+    }
+
+    #[test]
+    fn overlapping_matches_are_reported_once_leftmost_first() {
+        assert_eq!(ranges("aa", &options(), "aaaaa"), vec![0..2, 2..4]);
+        assert_eq!(ranges("ana", &options(), "banana"), vec![1..4]);
+    }
+
+    #[test]
+    fn empty_matches_are_dropped() {
+        let regex = GrepOptions { regex: true, ..options() };
+        assert!(ranges("x*", &regex, "RUN true").is_empty());
+    }
+
+    #[test]
+    fn highlighting_only_adds_escape_sequences() {
+        let text = "RUN echo 'héllo' && echo HÉLLO";
+        let found = ranges("héllo", &options(), text);
+        assert_eq!(found.len(), 2);
+
+        let highlighted = highlight(text, &found);
+        assert_eq!(strip_ansi(&highlighted), text);
+        for range in &found {
+            assert!(highlighted.contains(&text[range.clone()]));
+        }
+        assert_eq!(highlight(text, &[]), text);
+    }
+
+    fn history() -> Vec<ImageSnapshot> {
+        vec![
+            tracked("api", "v1", "a1", "main", 0, vec![layer("sha256:1", 10, "RUN apk add imagemagick")]),
+            tracked("api", "v1", "a2", "main", 5, vec![
+                layer("sha256:2", 20, "RUN apk add curl"),
+                layer("sha256:3", 30, "RUN apk add ImageMagick"),
+            ]),
+            tracked("web", "v1", "w1", "main", 1, vec![layer("sha256:4", 40, "RUN npm ci")]),
+            tracked("myorg/worker", "v1", "k1", "main", 2, vec![layer("sha256:5", 50, "RUN apt-get install imagemagick")]),
+        ]
+    }
+
+    fn found(matches: &[GrepMatch]) -> Vec<(&str, &str, usize)> {
+        matches
+            .iter()
+            .map(|m| (m.image.as_str(), m.commit_sha.as_str(), m.layer_index))
+            .collect()
+    }
+
+    #[test]
+    fn search_uses_the_latest_snapshot_per_image() {
+        let matcher = build_matcher("imagemagick", &options()).unwrap();
+        let matches = search(&history(), &matcher, &options());
+        assert_eq!(found(&matches), vec![("api", "a2", 1), ("myorg/worker", "k1", 0)]);
+        assert_eq!(matches[0].layer_size, 30);
+        assert_eq!(matches[0].matches, vec![(12, 23)]);
+    }
+
+    #[test]
+    fn search_all_covers_every_snapshot() {
+        let all = GrepOptions { all: true, ..options() };
+        let matcher = build_matcher("imagemagick", &all).unwrap();
+        assert_eq!(
+            found(&search(&history(), &matcher, &all)),
+            vec![("api", "a1", 0), ("api", "a2", 1), ("myorg/worker", "k1", 0)]
+        );
+    }
+
+    #[test]
+    fn search_filter_narrows_by_image_name() {
+        let filtered = GrepOptions { filter: Some("myorg/".parse().unwrap()), ..options() };
+        let matcher = build_matcher("imagemagick", &filtered).unwrap();
+        assert_eq!(found(&search(&history(), &matcher, &filtered)), vec![("myorg/worker", "k1", 0)]);
+    }
+
+    #[test]
+    fn search_filter_selects_by_label() {
+        let mut history = history();
+        history[1].labels = Some([("team".to_string(), "core".to_string())].into());
+        history[3].labels = Some([("team".to_string(), "jobs".to_string())].into());
+        let matcher = build_matcher("imagemagick", &options()).unwrap();
+
+        let core = GrepOptions { filter: Some("label:team=core".parse().unwrap()), ..options() };
+        assert_eq!(found(&search(&history, &matcher, &core)), vec![("api", "a2", 1)]);
+
+        let any_team = GrepOptions { filter: Some("label:team".parse().unwrap()), ..options() };
+        assert_eq!(
+            found(&search(&history, &matcher, &any_team)),
+            vec![("api", "a2", 1), ("myorg/worker", "k1", 0)]
+        );
+    }
+}
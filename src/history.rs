@@ -1,22 +1,177 @@
 use anyhow::{bail, Result};
+use std::io::Write;
 
-use crate::format::print_history_table;
-use crate::track::load_history;
+use crate::format::{
+    print_branch_comparison_table, print_branch_history_tables, print_diff_history_table,
+    print_history_table, OutputStyle,
+};
+use crate::metric::Metric;
+use crate::models::ImageSnapshot;
+use crate::platforms::{aggregate, PlatformMode};
+use crate::reference::ImageRef;
+use crate::store::Store;
 
-pub async fn show_history(image: &str, last: Option<usize>) -> Result<()> {
-    let history = load_history()?;
+/// Snapshots of one image on a single branch, oldest first
+#[derive(Debug, Clone)]
+pub struct BranchSeries {
+    pub branch: String,
+    pub snapshots: Vec<ImageSnapshot>,
+}
 
-    if history.is_empty() {
-        bail!("No history found. Run 'dit track' first.");
+impl BranchSeries {
+    pub fn head(&self) -> Option<&ImageSnapshot> {
+        self.snapshots.last()
     }
+}
 
-    // Filter history for the specified image
-    let mut image_history: Vec<_> = history
+/// Split an image's history into per-branch series, keeping the last `last` snapshots of each.
+///
+/// When `branches` is empty, every branch the image was tracked on is returned (sorted by name).
+/// Requested branches with no snapshots yield an empty series so callers can pad their output.
+pub fn branch_series(
+    history: &[ImageSnapshot],
+    image: &str,
+    branches: &[String],
+    last: usize,
+) -> Vec<BranchSeries> {
+    let reference = ImageRef::parse_lenient(image);
+    let mut image_history: Vec<_> = history.iter().filter(|s| reference.matches(s)).collect();
+    image_history.sort_by_key(|s| s.timestamp);
+
+    let branch_names: Vec<String> = if branches.is_empty() {
+        let mut names: Vec<String> = image_history.iter().map(|s| s.branch.clone()).collect();
+        names.sort();
+        names.dedup();
+        names
+    } else {
+        branches.to_vec()
+    };
+
+    branch_names
         .into_iter()
-        .filter(|s| s.image == image)
-        .collect();
+        .map(|branch| {
+            let snapshots: Vec<ImageSnapshot> = image_history
+                .iter()
+                .filter(|s| s.branch == branch)
+                .map(|s| (*s).clone())
+                .collect();
+            let start = snapshots.len().saturating_sub(last);
+
+            BranchSeries {
+                branch,
+                snapshots: snapshots[start..].to_vec(),
+            }
+        })
+        .collect()
+}
 
+#[derive(Debug, Default)]
+pub struct HistoryOptions {
+    /// Limit to the last N snapshots (per branch with `group_by_branch`)
+    pub last: Option<usize>,
+    pub platforms: PlatformMode,
+    pub group_by_branch: bool,
+    /// Add an Image ID column
+    pub show_id: bool,
+    /// Tabulated value; snapshots that didn't record it are left out
+    pub metric: Metric,
+    pub output: OutputStyle,
+}
+
+pub async fn show_history(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    options: &HistoryOptions,
+) -> Result<()> {
+    // Aggregate before limiting so --last counts builds, not platforms
+    let image_history = aggregate(load_image_history(store, image, None)?, options.platforms);
+    let mut image_history = options.metric.recorded(&image_history);
     if image_history.is_empty() {
+        bail!(
+            "No build times recorded for '{}'; track it with --build-metadata <buildx metadata file>",
+            image
+        );
+    }
+
+    if options.group_by_branch {
+        print_branch_history_tables(
+            out,
+            image,
+            &group_by_branch_name(image_history, options.last),
+            options.show_id,
+            options.metric,
+            options.output,
+        )?;
+        return Ok(());
+    }
+
+    if let Some(n) = options.last {
+        image_history.drain(..image_history.len().saturating_sub(n));
+    }
+
+    // Display history
+    print_history_table(out, &image_history, options.show_id, options.metric, options.output)?;
+
+    Ok(())
+}
+
+/// `--group-by-branch`: one series per branch (sorted by name), keeping the last `last`
+/// snapshots of each. Snapshots tracked without a branch come last, in their own series.
+pub fn group_by_branch_name(snapshots: Vec<ImageSnapshot>, last: Option<usize>) -> Vec<BranchSeries> {
+    let mut series: Vec<BranchSeries> = Vec::new();
+    for snapshot in snapshots {
+        match series.iter_mut().find(|s| s.branch == snapshot.branch) {
+            Some(existing) => existing.snapshots.push(snapshot),
+            None => series.push(BranchSeries {
+                branch: snapshot.branch.clone(),
+                snapshots: vec![snapshot],
+            }),
+        }
+    }
+
+    series.sort_by(|a, b| (a.branch.is_empty(), &a.branch).cmp(&(b.branch.is_empty(), &b.branch)));
+    for branch in &mut series {
+        branch.snapshots.sort_by_key(|s| s.timestamp);
+        if let Some(n) = last {
+            branch.snapshots.drain(..branch.snapshots.len().saturating_sub(n));
+        }
+    }
+
+    series
+}
+
+/// `--show-diffs`: diffs saved by `dit diff --save-diff-to-history`, read back without
+/// recomputing (their snapshots may no longer exist in Docker)
+pub fn show_diff_history(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    last: Option<usize>,
+    output: OutputStyle,
+) -> Result<()> {
+    let mut diffs = store.load_diff_history(image)?;
+    if let Some(n) = last {
+        diffs.drain(..diffs.len().saturating_sub(n));
+    }
+
+    print_diff_history_table(out, &diffs, output)?;
+
+    Ok(())
+}
+
+/// Snapshots of `image`, oldest first, limited to the last `last`
+pub fn load_image_history(
+    store: &Store,
+    image: &str,
+    last: Option<usize>,
+) -> Result<Vec<ImageSnapshot>> {
+    let mut image_history = store.load_history_for_image(image)?;
+
+    if image_history.is_empty() {
+        if store.load_history()?.is_empty() {
+            bail!("No history found. Run 'dit track' first.");
+        }
         bail!("No history found for image '{}'", image);
     }
 
@@ -29,8 +184,114 @@ pub async fn show_history(image: &str, last: Option<usize>) -> Result<()> {
         image_history = image_history[start..].to_vec();
     }
 
-    // Display history
-    print_history_table(&image_history);
+    Ok(image_history)
+}
+
+/// Show the latest snapshots of several branches side by side
+pub async fn show_branch_comparison(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    branches: &[String],
+    last: Option<usize>,
+    output: OutputStyle,
+) -> Result<()> {
+    if branches.len() < 2 {
+        bail!("--compare-branches needs at least two branches (e.g. main,release/2.x)");
+    }
+
+    let history = store.load_history()?;
+
+    if history.is_empty() {
+        bail!("No history found. Run 'dit track' first.");
+    }
+
+    let reference = ImageRef::parse_lenient(image);
+    if !history.iter().any(|s| reference.matches(s)) {
+        bail!("No history found for image '{}'", image);
+    }
+
+    let series = branch_series(&history, image, branches, last.unwrap_or(10));
+
+    if series.iter().all(|s| s.snapshots.is_empty()) {
+        bail!(
+            "No snapshots found for branches {} of image '{}'",
+            branches.join(", "),
+            image
+        );
+    }
+
+    print_branch_comparison_table(out, image, &series, output)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+
+    fn history() -> Vec<ImageSnapshot> {
+        let snapshot = |branch: &str, commit: &str, minutes: i64, size: u64| {
+            tracked("myapp", "1", commit, branch, minutes, vec![layer("sha256:a", size, "")])
+        };
+        vec![
+            snapshot("main", "m1", 1, 100),
+            snapshot("release/2.x", "r1", 2, 90),
+            snapshot("main", "m2", 3, 110),
+            tracked("other", "1", "o1", "main", 4, vec![]),
+            snapshot("main", "m3", 5, 120),
+            snapshot("feature", "f1", 6, 130),
+            snapshot("release/2.x", "r2", 7, 95),
+        ]
+    }
+
+    fn commits(series: &BranchSeries) -> Vec<&str> {
+        series.snapshots.iter().map(|s| s.commit_sha.as_str()).collect()
+    }
+
+    #[test]
+    fn two_requested_branches_keep_their_last_snapshots_in_order() {
+        let series = branch_series(&history(), "myapp", &["main".into(), "release/2.x".into()], 2);
+
+        assert_eq!(series.len(), 2);
+        assert_eq!(commits(&series[0]), ["m2", "m3"]);
+        assert_eq!(commits(&series[1]), ["r1", "r2"]);
+        assert_eq!(series[1].head().unwrap().total_size, 95);
+    }
+
+    #[test]
+    fn three_branches_pad_missing_and_short_ones() {
+        let series = branch_series(
+            &history(),
+            "myapp",
+            &["main".into(), "release/2.x".into(), "hotfix".into()],
+            5,
+        );
+
+        assert_eq!(commits(&series[0]), ["m1", "m2", "m3"]);
+        assert_eq!(commits(&series[1]), ["r1", "r2"]);
+        assert_eq!(series[2].branch, "hotfix");
+        assert!(series[2].head().is_none());
+    }
+
+    #[test]
+    fn every_tracked_branch_when_none_are_requested() {
+        let series = branch_series(&history(), "myapp", &[], 10);
+
+        let names: Vec<&str> = series.iter().map(|s| s.branch.as_str()).collect();
+        assert_eq!(names, ["feature", "main", "release/2.x"]);
+        assert!(series.iter().flat_map(|s| &s.snapshots).all(|s| s.image == "myapp"));
+    }
+
+    #[test]
+    fn grouping_puts_snapshots_without_a_branch_last() {
+        let mut snapshots = history();
+        snapshots.push(tracked("myapp", "1", "x1", "", 8, vec![]));
+
+        let series = group_by_branch_name(snapshots, Some(1));
+        let names: Vec<&str> = series.iter().map(|s| s.branch.as_str()).collect();
+        assert_eq!(names, ["feature", "main", "release/2.x", ""]);
+        assert_eq!(commits(&series[1]), ["m3"]);
+    }
+}
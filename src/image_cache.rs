@@ -0,0 +1,165 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::store::Store;
+
+const IMAGE_CACHE_FILE: &str = "images.cache";
+
+/// Older caches are ignored rather than suggesting images long since removed
+const MAX_AGE_DAYS: i64 = 7;
+
+/// Local `repo:tag` names as of the last command that listed images, so shell completion
+/// never waits on the Docker daemon
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageCache {
+    pub updated: DateTime<Utc>,
+    pub images: Vec<String>,
+}
+
+impl ImageCache {
+    pub fn is_stale(&self, now: DateTime<Utc>) -> bool {
+        now - self.updated > TimeDelta::days(MAX_AGE_DAYS)
+    }
+}
+
+/// Replace the cache in `dir` with `images`. Best effort: the history directory is never
+/// created for it, and a failed write only leaves the old cache behind.
+pub fn record_images(dir: &Path, images: &[String]) {
+    if dir.is_dir() {
+        write_image_cache(dir, images, Utc::now());
+    }
+}
+
+/// Write `<dir>/images.cache` as taken at `updated`
+fn write_image_cache(dir: &Path, images: &[String], updated: DateTime<Utc>) {
+    let mut images = images.to_vec();
+    images.sort();
+    images.dedup();
+    let cache = ImageCache { updated, images };
+    let Ok(json) = serde_json::to_string(&cache) else {
+        return;
+    };
+
+    // Renamed into place so a completion running alongside never reads half a file
+    let path = dir.join(IMAGE_CACHE_FILE);
+    let partial = dir.join(format!("{}.{}", IMAGE_CACHE_FILE, std::process::id()));
+    if fs::write(&partial, json).is_err() || fs::rename(&partial, &path).is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+}
+
+/// The cache in `dir`, or `None` when it's missing or unreadable
+pub fn read_image_cache(dir: &Path) -> Option<ImageCache> {
+    let content = fs::read_to_string(dir.join(IMAGE_CACHE_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// `dit __complete images <prefix>`: cached local images plus every tracked image, by
+/// name and by `name:tag`. A missing or stale cache leaves only the tracked names.
+pub fn complete_images(store: &Store, prefix: &str) -> Vec<String> {
+    let mut names = BTreeSet::new();
+
+    if let Some(cache) = read_image_cache(store.dir()) {
+        if !cache.is_stale(Utc::now()) {
+            names.extend(cache.images);
+        }
+    }
+
+    // A broken history shouldn't turn into an error printed under the prompt
+    for snapshot in store.load_history().unwrap_or_default() {
+        if let Some(tag) = &snapshot.tag {
+            names.insert(format!("{}:{}", snapshot.image, tag));
+        }
+        names.insert(snapshot.image);
+    }
+
+    names.into_iter().filter(|name| name.starts_with(prefix)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::snapshot;
+    use tempfile::TempDir;
+
+    fn images(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// A store whose directory exists but holds no history yet
+    fn empty_store() -> (TempDir, Store) {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        fs::create_dir(store.dir()).unwrap();
+        (dir, store)
+    }
+
+    #[test]
+    fn cache_round_trips_sorted_and_deduplicated() {
+        let (_dir, store) = empty_store();
+        let updated = Utc::now();
+        write_image_cache(store.dir(), &images(&["nginx:1.25", "alpine:3.19", "nginx:1.25"]), updated);
+
+        let cache = read_image_cache(store.dir()).unwrap();
+        assert_eq!(cache.updated, updated);
+        assert_eq!(cache.images, images(&["alpine:3.19", "nginx:1.25"]));
+        // Only the cache itself is left behind
+        assert_eq!(fs::read_dir(store.dir()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn missing_or_corrupt_caches_read_as_none() {
+        let (_dir, store) = empty_store();
+        assert!(read_image_cache(store.dir()).is_none());
+
+        fs::write(store.dir().join(IMAGE_CACHE_FILE), "{\"updated\": ").unwrap();
+        assert!(read_image_cache(store.dir()).is_none());
+    }
+
+    #[test]
+    fn caches_go_stale_after_a_week() {
+        let now = Utc::now();
+        let cache = |days| ImageCache {
+            updated: now - TimeDelta::days(days),
+            images: Vec::new(),
+        };
+
+        assert!(!cache(0).is_stale(now));
+        assert!(!cache(MAX_AGE_DAYS).is_stale(now));
+        assert!(cache(MAX_AGE_DAYS + 1).is_stale(now));
+    }
+
+    #[test]
+    fn completions_filter_cached_and_tracked_images_by_prefix() {
+        let (_dir, store) = empty_store();
+        write_image_cache(store.dir(), &images(&["myapp:dev", "nginx:1.25", "mysql:8"]), Utc::now());
+        store.save_snapshot(&snapshot("myapp", "v1", Vec::new())).unwrap();
+
+        assert_eq!(complete_images(&store, "my"), images(&["myapp", "myapp:dev", "myapp:v1", "mysql:8"]));
+        assert_eq!(complete_images(&store, "myapp:"), images(&["myapp:dev", "myapp:v1"]));
+        assert_eq!(complete_images(&store, "redis"), Vec::<String>::new());
+        assert_eq!(complete_images(&store, "").len(), 5);
+    }
+
+    #[test]
+    fn stale_or_missing_caches_fall_back_to_tracked_images() {
+        let (_dir, store) = empty_store();
+        store.save_snapshot(&snapshot("myapp", "v1", Vec::new())).unwrap();
+        assert_eq!(complete_images(&store, "my"), images(&["myapp", "myapp:v1"]));
+
+        let long_ago = Utc::now() - TimeDelta::days(MAX_AGE_DAYS + 1);
+        write_image_cache(store.dir(), &images(&["mysql:8"]), long_ago);
+        assert_eq!(complete_images(&store, "my"), images(&["myapp", "myapp:v1"]));
+    }
+
+    #[test]
+    fn nothing_tracked_and_no_cache_completes_nothing() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+
+        assert!(complete_images(&store, "").is_empty());
+    }
+}
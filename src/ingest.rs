@@ -0,0 +1,391 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+
+use crate::docker::{clean_command, parse_image_reference};
+use crate::models::{oci_annotations, ImageOrigin, ImageSnapshot, LayerInfo, MISSING_DIGEST};
+use crate::store::Store;
+use crate::track::{get_git_context, warn_revision_mismatch};
+
+/// Subset of `docker inspect` output (field names differ from bollard's)
+#[derive(Debug, Deserialize)]
+struct CliInspect {
+    #[serde(rename = "Id", default)]
+    id: Option<String>,
+    #[serde(rename = "RepoTags", default)]
+    repo_tags: Option<Vec<String>>,
+    #[serde(rename = "RepoDigests", default)]
+    repo_digests: Option<Vec<String>>,
+    #[serde(rename = "Size", default)]
+    size: Option<u64>,
+    #[serde(rename = "Os", default)]
+    os: Option<String>,
+    #[serde(rename = "Architecture", default)]
+    architecture: Option<String>,
+    #[serde(rename = "Variant", default)]
+    variant: Option<String>,
+    #[serde(rename = "Config", default)]
+    config: Option<CliImageConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CliImageConfig {
+    #[serde(rename = "Env", default)]
+    env: Option<Vec<String>>,
+    #[serde(rename = "Labels", default)]
+    labels: Option<BTreeMap<String, String>>,
+    #[serde(rename = "User", default)]
+    user: Option<String>,
+}
+
+/// One line of `docker history --format json` (or `--format '{{json .}}'`)
+#[derive(Debug, Deserialize)]
+struct CliHistoryEntry {
+    #[serde(rename = "ID", default)]
+    id: String,
+    #[serde(rename = "CreatedAt", default)]
+    created_at: String,
+    #[serde(rename = "CreatedBy", default)]
+    created_by: String,
+    #[serde(rename = "Size", default)]
+    size: String,
+}
+
+pub async fn ingest_snapshot(
+    out: &mut impl Write,
+    store: &Store,
+    inspect_path: &str,
+    history_path: &str,
+    image: Option<&str>,
+    commit: Option<String>,
+    branch: Option<String>,
+) -> Result<()> {
+    let inspect_content = fs::read_to_string(inspect_path)
+        .context(format!("Failed to read inspect file: {}", inspect_path))?;
+    let history_content = fs::read_to_string(history_path)
+        .context(format!("Failed to read history file: {}", history_path))?;
+
+    let inspect = parse_inspect(&inspect_content)?;
+    let history = parse_history(&history_content)?;
+
+    let image = match image {
+        Some(i) => i.to_string(),
+        None => inspect
+            .repo_tags
+            .as_ref()
+            .and_then(|t| t.first().cloned())
+            .context("Inspect output has no RepoTags. Use --image to name the image.")?,
+    };
+
+    let mut snapshot = build_snapshot(&image, inspect, history)?;
+
+    // Git context from the current repo, if any, then explicit overrides
+    if let Ok(git_context) = get_git_context() {
+        snapshot.commit_sha = git_context.commit_sha;
+        snapshot.branch = git_context.branch;
+        snapshot.commit_message = git_context.commit_message;
+        snapshot.author = git_context.author;
+    }
+    if let Some(c) = commit {
+        snapshot.commit_sha = c;
+    }
+    if let Some(b) = branch {
+        snapshot.branch = b;
+    }
+    snapshot.timestamp = Utc::now();
+
+    if snapshot.commit_sha.is_empty() {
+        bail!("No git context available. Use --commit (and --branch) to attribute the snapshot.");
+    }
+    warn_revision_mismatch(&snapshot);
+
+    store.save_snapshot(&snapshot)?;
+
+    writeln!(
+        out,
+        "✅ Ingested snapshot for {} at commit {}",
+        snapshot.image,
+        snapshot.commit_sha.chars().take(7).collect::<String>()
+    )?;
+    writeln!(out, "Branch: {}", snapshot.branch)?;
+    writeln!(out, "Size: {} bytes ({} layers)", snapshot.total_size, snapshot.layer_count)?;
+
+    Ok(())
+}
+
+/// Accepts both `docker inspect` (array-wrapped) and `--format '{{json .}}'` (single object)
+fn parse_inspect(content: &str) -> Result<CliInspect> {
+    let value: serde_json::Value =
+        serde_json::from_str(content.trim()).context("Failed to parse inspect JSON")?;
+
+    let object = match value {
+        serde_json::Value::Array(mut items) => {
+            if items.len() != 1 {
+                bail!("Inspect file must describe exactly one image, found {}", items.len());
+            }
+            items.remove(0)
+        }
+        other => other,
+    };
+
+    serde_json::from_value(object).context("Inspect JSON is missing expected image fields")
+}
+
+/// Accepts one JSON object per line (docker CLI output) or a single JSON array
+fn parse_history(content: &str) -> Result<Vec<CliHistoryEntry>> {
+    let trimmed = content.trim();
+
+    if trimmed.starts_with('[') {
+        return serde_json::from_str(trimmed).context("Failed to parse history JSON array");
+    }
+
+    trimmed
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .context(format!("Failed to parse history JSON on line {}", i + 1))
+        })
+        .collect()
+}
+
+fn build_snapshot(
+    image: &str,
+    inspect: CliInspect,
+    history: Vec<CliHistoryEntry>,
+) -> Result<ImageSnapshot> {
+    let (image_name, tag) = parse_image_reference(image);
+
+    // docker history lists newest first; snapshots store base layer first
+    let mut layers = Vec::new();
+    for entry in history.iter().rev() {
+        let size = parse_cli_size(&entry.size)
+            .context(format!("Invalid layer size '{}' in history", entry.size))?;
+
+        let command = if entry.created_by.is_empty() {
+            "<unknown>".to_string()
+        } else {
+            entry.created_by.clone()
+        };
+
+        let created = DateTime::parse_from_rfc3339(&entry.created_at)
+            .map(|d| d.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+
+        let digest = if entry.id.is_empty() {
+            MISSING_DIGEST.to_string()
+        } else {
+            entry.id.clone()
+        };
+
+        layers.push(LayerInfo {
+            digest,
+            size,
+            command: clean_command(&command),
+            created,
+            extract_ms: None,
+            file_count: None,
+        });
+    }
+
+    // Older CLIs report Size on inspect; fall back to summing layers
+    let total_size = inspect
+        .size
+        .unwrap_or_else(|| layers.iter().map(|l| l.size).sum());
+    let layer_count = layers.len();
+    let config = inspect.config.unwrap_or_default();
+
+    Ok(ImageSnapshot {
+        image: image_name,
+        tag,
+        digest: inspect.repo_digests.and_then(|d| d.first().cloned()),
+        image_id: inspect.id,
+        commit_sha: String::new(),
+        branch: String::new(),
+        commit_message: String::new(),
+        author: String::new(),
+        timestamp: Utc::now(),
+        total_size,
+        compressed_size: None,
+        registry_only: false,
+        layer_count,
+        layers,
+        os: inspect.os.unwrap_or_else(|| "linux".to_string()),
+        arch: inspect.architecture.unwrap_or_else(|| "amd64".to_string()),
+        variant: inspect.variant.filter(|v| !v.is_empty()),
+        group_id: None,
+        host_platform: None,
+        env: Some(config.env.unwrap_or_default()),
+        oci_annotations: oci_annotations(config.labels.as_ref(), None),
+        labels: Some(config.labels.unwrap_or_default()),
+        user: Some(config.user.unwrap_or_default()),
+        annotations: BTreeMap::new(),
+        extract_ms: None,
+        file_count: None,
+        watched: false,
+        origin: ImageOrigin::Unknown,
+        build_duration_ms: None,
+    })
+}
+
+/// Parse a docker CLI size: raw bytes (`--human=false`) or go-units decimal form ("7.05MB", "0B")
+fn parse_cli_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+
+    if let Ok(bytes) = s.parse::<u64>() {
+        return Ok(bytes);
+    }
+
+    let split = s
+        .find(|c: char| c.is_ascii_alphabetic())
+        .context("Missing size unit")?;
+    let (num_str, unit) = s.split_at(split);
+
+    let multiplier = match unit.to_uppercase().as_str() {
+        "B" => 1u64,
+        "KB" => 1000,
+        "MB" => 1000 * 1000,
+        "GB" => 1000 * 1000 * 1000,
+        "TB" => 1000 * 1000 * 1000 * 1000,
+        other => bail!("Unknown size unit '{}'", other),
+    };
+
+    let num: f64 = num_str.trim().parse().context("Failed to parse size number")?;
+
+    Ok((num * multiplier as f64).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `docker inspect` and `docker history --format '{{json .}}'` output, in the shapes
+    /// Docker 20.10 (array, human sizes), 24.0 (`--format '{{json .}}'` inspect,
+    /// `--human=false` sizes) and 27.1 (containerd image store, arm64) produce
+    macro_rules! fixture {
+        ($version:literal) => {
+            (
+                include_str!(concat!("../tests/fixtures/ingest/docker-", $version, "/inspect.json")),
+                include_str!(concat!("../tests/fixtures/ingest/docker-", $version, "/history.json")),
+            )
+        };
+    }
+
+    fn ingest(fixture: (&str, &str), image: &str) -> ImageSnapshot {
+        let inspect = parse_inspect(fixture.0).unwrap();
+        let history = parse_history(fixture.1).unwrap();
+        build_snapshot(image, inspect, history).unwrap()
+    }
+
+    #[test]
+    fn docker_20_array_inspect_and_human_sizes() {
+        let snapshot = ingest(fixture!("20.10"), "myapp:1.0");
+
+        assert_eq!((snapshot.image.as_str(), snapshot.tag.as_deref()), ("myapp", Some("1.0")));
+        assert_eq!(snapshot.total_size, 19_245_811);
+        let sizes: Vec<u64> = snapshot.layers.iter().map(|l| l.size).collect();
+        assert_eq!(sizes, [7_330_000, 0, 0, 11_800_000, 0]);
+        assert!(snapshot.layers[0].command.contains("ADD file:"), "{}", snapshot.layers[0].command);
+        assert_eq!(snapshot.layers[0].digest, "<missing>");
+        assert_eq!(snapshot.layers[4].digest, snapshot.image_id.clone().unwrap());
+        assert_eq!(snapshot.layers[0].created.to_rfc3339(), "2023-05-09T23:11:10+00:00");
+        assert_eq!(snapshot.digest, None);
+        assert_eq!(snapshot.user.as_deref(), Some("app"));
+        assert!(snapshot.env.unwrap().contains(&"APP_ENV=production".to_string()));
+        assert_eq!(
+            snapshot.oci_annotations.get("org.opencontainers.image.revision").map(String::as_str),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+    }
+
+    #[test]
+    fn docker_24_single_object_inspect_and_byte_sizes() {
+        let snapshot = ingest(fixture!("24.0"), "registry.example.com/team/myapp:2.1");
+
+        assert_eq!(snapshot.image, "registry.example.com/team/myapp");
+        assert_eq!(snapshot.tag.as_deref(), Some("2.1"));
+        assert_eq!(snapshot.total_size, 52_718_243);
+        let sizes: Vec<u64> = snapshot.layers.iter().map(|l| l.size).collect();
+        assert_eq!(sizes, [27_552_419, 0, 0, 24_117_248, 1_048_576]);
+        assert_eq!(snapshot.layer_count, 5);
+        assert!(snapshot.digest.unwrap().contains("@sha256:4c2f8b6d"));
+        // `"Labels": null` is recorded as no labels, not as unknown
+        assert_eq!(snapshot.labels, Some(BTreeMap::new()));
+        assert_eq!(snapshot.env.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn docker_27_arm64_variant() {
+        let snapshot = ingest(fixture!("27.1"), "myapp:arm");
+
+        assert_eq!(snapshot.platform(), "linux/arm64/v8");
+        assert_eq!(snapshot.total_size, 4_093_112);
+        assert_eq!(snapshot.layers.len(), 2);
+        assert_eq!(snapshot.layers[0].size, 4_090_000);
+        assert_eq!(snapshot.user.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn history_may_be_a_json_array() {
+        let lines = fixture!("27.1").1;
+        let array = format!("[{}]", lines.trim().lines().collect::<Vec<_>>().join(","));
+
+        let history = parse_history(&array).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].id, "<missing>");
+    }
+
+    #[test]
+    fn inspect_output_for_several_images_is_rejected() {
+        let two = r#"[{"Id": "sha256:a"}, {"Id": "sha256:b"}]"#;
+        let error = parse_inspect(two).unwrap_err();
+        assert!(error.to_string().contains("exactly one image, found 2"));
+    }
+
+    #[test]
+    fn bad_history_lines_are_reported_by_number() {
+        let error = parse_history("{\"ID\": \"a\", \"Size\": \"0B\"}\nnot json\n").unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+
+    #[test]
+    fn cli_sizes() {
+        assert_eq!(parse_cli_size("0B").unwrap(), 0);
+        assert_eq!(parse_cli_size("7049688").unwrap(), 7_049_688);
+        assert_eq!(parse_cli_size("7.05MB").unwrap(), 7_050_000);
+        assert_eq!(parse_cli_size("1.2kB").unwrap(), 1_200);
+        assert_eq!(parse_cli_size(" 2GB ").unwrap(), 2_000_000_000);
+        assert!(parse_cli_size("7MiB").is_err());
+        assert!(parse_cli_size("big").is_err());
+    }
+
+    #[tokio::test]
+    async fn ingest_saves_with_the_given_commit_and_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/ingest/docker-20.10");
+        let store = Store::new(dir.path().join(".dit"));
+
+        ingest_snapshot(
+            &mut Vec::new(),
+            &store,
+            &format!("{}/inspect.json", fixtures),
+            &format!("{}/history.json", fixtures),
+            None,
+            Some("feedface".to_string()),
+            Some("airgap".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let history = store.load_history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].image.as_str(), history[0].tag.as_deref()), ("myapp", Some("1.0")));
+        assert_eq!(history[0].commit_sha, "feedface");
+        assert_eq!(history[0].branch, "airgap");
+    }
+}
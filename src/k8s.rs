@@ -0,0 +1,220 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::process::Command;
+
+/// Where to find the image currently deployed: a live resource via kubectl, or a manifest file
+#[derive(Debug)]
+pub struct K8sTarget {
+    /// `deployment/myapp`, `statefulset/db`, `pod/web-0`, ...
+    pub resource: String,
+    pub namespace: Option<String>,
+    /// kubeconfig context (`kubectl --context`)
+    pub context: Option<String>,
+    /// Required when the pod template has more than one container
+    pub container: Option<String>,
+    /// Read this manifest instead of asking the cluster
+    pub manifest: Option<String>,
+}
+
+/// Resolve the image reference the target is running
+pub fn resolve_deployed_image(target: &K8sTarget) -> Result<String> {
+    let containers = match &target.manifest {
+        Some(path) => read_manifest_containers(path)?,
+        None => parse_container_images(&kubectl_get_containers(target)?),
+    };
+
+    select_container(&containers, target.container.as_deref())
+        .context(format!("Could not pick a container from {}", target.resource))
+}
+
+/// `kubectl get -o jsonpath`, printing one `name<TAB>image` line per container
+fn kubectl_get_containers(target: &K8sTarget) -> Result<String> {
+    let kind = target
+        .resource
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let pod_spec = match kind.as_str() {
+        "pod" | "pods" | "po" => ".spec",
+        "cronjob" | "cronjobs" | "cj" => ".spec.jobTemplate.spec.template.spec",
+        _ => ".spec.template.spec",
+    };
+    let jsonpath = format!(
+        "jsonpath={{range {}.containers[*]}}{{.name}}{{\"\\t\"}}{{.image}}{{\"\\n\"}}{{end}}",
+        pod_spec
+    );
+
+    let mut command = Command::new("kubectl");
+    if let Some(context) = &target.context {
+        command.args(["--context", context]);
+    }
+    if let Some(namespace) = &target.namespace {
+        command.args(["--namespace", namespace]);
+    }
+    command.args(["get", &target.resource, "-o", &jsonpath]);
+
+    let output = command
+        .output()
+        .context("kubectl: failed to execute. Is kubectl installed and on PATH?")?;
+
+    if !output.status.success() {
+        bail!(
+            "kubectl: 'get {}' failed: {}",
+            target.resource,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `name<TAB>image` lines from the jsonpath template
+pub fn parse_container_images(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let (name, image) = line.split_once('\t')?;
+            let image = image.trim();
+            (!image.is_empty()).then(|| (name.trim().to_string(), image.to_string()))
+        })
+        .collect()
+}
+
+/// Containers from a Deployment/StatefulSet/DaemonSet/Job/CronJob/Pod manifest (YAML or JSON)
+fn read_manifest_containers(path: &str) -> Result<Vec<(String, String)>> {
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read manifest: {}", path))?;
+    let manifest: serde_yaml::Value = serde_yaml::from_str(&content)
+        .context(format!("Failed to parse manifest: {}", path))?;
+
+    let pod_spec_paths: [&[&str]; 3] = [
+        &["spec", "template", "spec"],
+        &["spec", "jobTemplate", "spec", "template", "spec"],
+        &["spec"],
+    ];
+
+    for pod_spec_path in pod_spec_paths {
+        let containers = pod_spec_path
+            .iter()
+            .try_fold(&manifest, |value, key| value.get(key))
+            .and_then(|spec| spec.get("containers"))
+            .and_then(|c| c.as_sequence());
+
+        if let Some(containers) = containers {
+            return Ok(containers
+                .iter()
+                .filter_map(|c| {
+                    let name = c.get("name")?.as_str()?.to_string();
+                    let image = c.get("image")?.as_str()?.to_string();
+                    Some((name, image))
+                })
+                .collect());
+        }
+    }
+
+    bail!("No containers found in manifest: {}", path)
+}
+
+fn select_container(containers: &[(String, String)], name: Option<&str>) -> Result<String> {
+    let names = || {
+        containers
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    match (name, containers) {
+        (_, []) => bail!("No containers found"),
+        (Some(name), _) => containers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, image)| image.clone())
+            .context(format!("No container named '{}' (found: {})", name, names())),
+        (None, [(_, image)]) => Ok(image.clone()),
+        (None, _) => bail!(
+            "Multiple containers ({}); choose one with --container NAME",
+            names()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(name: &str) -> String {
+        format!("{}/tests/fixtures/k8s/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn containers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, image)| (name.to_string(), image.to_string())).collect()
+    }
+
+    #[test]
+    fn jsonpath_lines_parse_into_containers() {
+        let output = "app\tregistry.example.com/team/myapp:2.1\n\n  proxy\t envoy:v1.29.1 \n";
+        assert_eq!(
+            parse_container_images(output),
+            containers(&[
+                ("app", "registry.example.com/team/myapp:2.1"),
+                ("proxy", "envoy:v1.29.1"),
+            ])
+        );
+
+        // No tab, or no image yet (a pod still being scheduled)
+        assert!(parse_container_images("app registry.example.com/team/myapp:2.1").is_empty());
+        assert!(parse_container_images("app\t\n").is_empty());
+        assert!(parse_container_images("").is_empty());
+    }
+
+    #[test]
+    fn manifests_list_the_pod_template_containers() {
+        for (file, expected) in [
+            (
+                "deployment.yaml",
+                containers(&[
+                    ("app", "registry.example.com/team/myapp:2.1"),
+                    ("proxy", "envoyproxy/envoy:v1.29.1"),
+                ]),
+            ),
+            ("statefulset.yaml", containers(&[("postgres", "postgres:16.2")])),
+            ("cronjob.yaml", containers(&[("report", "registry.example.com/team/report:nightly")])),
+            ("pod.json", containers(&[("web", "nginx:1.27")])),
+        ] {
+            assert_eq!(read_manifest_containers(&manifest(file)).unwrap(), expected, "{}", file);
+        }
+    }
+
+    #[test]
+    fn manifests_without_containers_are_errors() {
+        let error = read_manifest_containers(&manifest("service.yaml")).unwrap_err();
+        assert!(error.to_string().starts_with("No containers found in manifest"), "{}", error);
+
+        let error = read_manifest_containers(&manifest("missing.yaml")).unwrap_err();
+        assert!(error.to_string().starts_with("Failed to read manifest"), "{}", error);
+    }
+
+    #[test]
+    fn a_single_container_is_picked_without_a_name() {
+        let single = containers(&[("postgres", "postgres:16.2")]);
+        assert_eq!(select_container(&single, None).unwrap(), "postgres:16.2");
+        assert!(select_container(&[], None).is_err());
+    }
+
+    #[test]
+    fn several_containers_need_a_name() {
+        let pod = containers(&[("app", "myapp:2.1"), ("proxy", "envoy:v1.29.1")]);
+        assert_eq!(select_container(&pod, Some("proxy")).unwrap(), "envoy:v1.29.1");
+
+        let error = select_container(&pod, None).unwrap_err().to_string();
+        assert_eq!(error, "Multiple containers (app, proxy); choose one with --container NAME");
+
+        let error = select_container(&pod, Some("sidecar")).unwrap_err().to_string();
+        assert_eq!(error, "No container named 'sidecar' (found: app, proxy)");
+    }
+}
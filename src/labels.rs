@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::docker::LabelFilter;
+use crate::models::ImageSnapshot;
+
+/// Group name for snapshots without the grouping label
+pub const NO_LABEL: &str = "(none)";
+
+const LABEL_PREFIX: &str = "label:";
+
+/// A `label:KEY` selector, as used by `--group-by` and `--columns`
+#[derive(Debug, Clone)]
+pub struct LabelKey(pub String);
+
+impl FromStr for LabelKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = s
+            .strip_prefix(LABEL_PREFIX)
+            .ok_or_else(|| format!("expected 'label:KEY', got '{}'", s))?
+            .trim();
+
+        if key.is_empty() || key.contains('=') {
+            return Err(format!("expected 'label:KEY', got '{}'", s));
+        }
+
+        Ok(LabelKey(key.to_string()))
+    }
+}
+
+/// A `--filter` value: an image name substring, or `label:KEY[=VALUE]`
+#[derive(Debug, Clone)]
+pub enum ImageSelector {
+    Name(String),
+    Label(LabelFilter),
+}
+
+impl FromStr for ImageSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(LABEL_PREFIX) {
+            Some(label) => label.parse().map(ImageSelector::Label),
+            None => Ok(ImageSelector::Name(s.to_string())),
+        }
+    }
+}
+
+impl ImageSelector {
+    /// Whether a tracked snapshot is selected: its name contains the substring, or it
+    /// carries the label (with the value, if given)
+    pub fn matches(&self, snapshot: &ImageSnapshot) -> bool {
+        match self {
+            ImageSelector::Name(name) => snapshot.image.contains(name.as_str()),
+            ImageSelector::Label(filter) => match (snapshot.label(&filter.key), &filter.value) {
+                (Some(label), Some(value)) => label == value,
+                (label, None) => label.is_some(),
+                (None, Some(_)) => false,
+            },
+        }
+    }
+}
+
+/// Split `items` into groups by the value of label `key` on each item's snapshot,
+/// ordered by label value; items without the label land in `(none)`
+pub fn group_by_label<T>(
+    items: Vec<T>,
+    key: &LabelKey,
+    snapshot: impl Fn(&T) -> &ImageSnapshot,
+) -> Vec<(String, Vec<T>)> {
+    let mut groups: BTreeMap<String, Vec<T>> = BTreeMap::new();
+
+    for item in items {
+        let value = snapshot(&item).label(&key.0).unwrap_or(NO_LABEL).to_string();
+        groups.entry(value).or_default().push(item);
+    }
+
+    groups.into_iter().collect()
+}
+
+/// Cell values for `--columns label:KEY` columns
+pub fn label_cells(snapshot: &ImageSnapshot, columns: &[LabelKey]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|key| snapshot.label(&key.0).unwrap_or("—").to_string())
+        .collect()
+}
+
+/// Header names for `--columns label:KEY` columns
+pub fn label_headers(columns: &[LabelKey]) -> Vec<String> {
+    columns.iter().map(|key| key.0.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::snapshot;
+
+    fn labelled(image: &str, labels: &[(&str, &str)]) -> ImageSnapshot {
+        let mut snapshot = snapshot(image, "v1", Vec::new());
+        if !labels.is_empty() {
+            let labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+            snapshot.labels = Some(labels.collect());
+        }
+        snapshot
+    }
+
+    #[test]
+    fn label_keys_need_the_prefix_and_no_value() {
+        assert_eq!("label:team".parse::<LabelKey>().unwrap().0, "team");
+        let key: LabelKey = "label: org.opencontainers.image.title ".parse().unwrap();
+        assert_eq!(key.0, "org.opencontainers.image.title");
+
+        for invalid in ["team", "label:", "label:  ", "label:team=web"] {
+            let error = invalid.parse::<LabelKey>().unwrap_err();
+            assert_eq!(error, format!("expected 'label:KEY', got '{}'", invalid));
+        }
+    }
+
+    #[test]
+    fn selectors_are_names_unless_prefixed_with_label() {
+        assert!(matches!("myorg/".parse(), Ok(ImageSelector::Name(name)) if name == "myorg/"));
+        assert!(matches!(
+            "label:team=web".parse(),
+            Ok(ImageSelector::Label(LabelFilter { key, value }))
+                if key == "team" && value.as_deref() == Some("web")
+        ));
+        assert!(matches!(
+            "label:team".parse(),
+            Ok(ImageSelector::Label(LabelFilter { key, value: None })) if key == "team"
+        ));
+        assert!("label:".parse::<ImageSelector>().is_err());
+    }
+
+    #[test]
+    fn selectors_match_by_name_or_label() {
+        let web = labelled("myorg/web", &[("team", "web")]);
+        let api = labelled("myorg/api", &[("team", "core")]);
+        let bare = labelled("nginx", &[]);
+        let select = |selector: &str| -> Vec<String> {
+            let selector: ImageSelector = selector.parse().unwrap();
+            [&web, &api, &bare]
+                .into_iter()
+                .filter(|s| selector.matches(s))
+                .map(|s| s.image.clone())
+                .collect()
+        };
+
+        assert_eq!(select("myorg/"), vec!["myorg/web", "myorg/api"]);
+        assert_eq!(select("label:team"), vec!["myorg/web", "myorg/api"]);
+        assert_eq!(select("label:team=core"), vec!["myorg/api"]);
+        assert!(select("label:tier").is_empty());
+    }
+
+    #[test]
+    fn groups_are_ordered_by_label_value_with_unlabelled_images_in_none() {
+        let images = vec![
+            labelled("web", &[("team", "web")]),
+            labelled("nginx", &[]),
+            labelled("api", &[("team", "core")]),
+            labelled("worker", &[("team", "core")]),
+        ];
+
+        let groups = group_by_label(images, &"label:team".parse().unwrap(), |s| s);
+
+        let names: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(value, items)| {
+                (value.as_str(), items.iter().map(|s| s.image.as_str()).collect())
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![("(none)", vec!["nginx"]), ("core", vec!["api", "worker"]), ("web", vec!["web"])]
+        );
+    }
+
+    #[test]
+    fn label_columns_show_a_dash_when_the_label_is_missing() {
+        let columns: Vec<LabelKey> =
+            vec!["label:team".parse().unwrap(), "label:tier".parse().unwrap()];
+
+        assert_eq!(label_headers(&columns), vec!["team", "tier"]);
+        let api = labelled("api", &[("team", "core")]);
+        assert_eq!(label_cells(&api, &columns), vec!["core", "—"]);
+        assert_eq!(label_cells(&labelled("nginx", &[]), &columns), vec!["—", "—"]);
+    }
+}
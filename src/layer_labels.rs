@@ -0,0 +1,54 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::docker::clean_command;
+use crate::models::LayerInfo;
+
+/// `--layer-label` map: human-readable names for layers, keyed by layer digest or by a
+/// command prefix. Prefixes keep matching after a rebuild changes the digests.
+#[derive(Debug, Default)]
+pub struct LayerLabels {
+    by_digest: HashMap<String, String>,
+    /// Cleaned like recorded commands (`/bin/sh -c #(nop)` dropped), longest first
+    by_prefix: Vec<(String, String)>,
+}
+
+impl LayerLabels {
+    pub fn new(map: HashMap<String, String>) -> Self {
+        let mut labels = LayerLabels::default();
+        for (key, label) in map {
+            if key.starts_with("sha256:") {
+                labels.by_digest.insert(key, label);
+            } else {
+                labels.by_prefix.push((clean_command(&key), label));
+            }
+        }
+        labels
+            .by_prefix
+            .sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        labels
+    }
+
+    /// The digest's label, else the label of the longest matching command prefix
+    pub fn label(&self, layer: &LayerInfo) -> Option<&str> {
+        if let Some(label) = self.by_digest.get(&layer.digest) {
+            return Some(label);
+        }
+        self.by_prefix
+            .iter()
+            .find(|(prefix, _)| layer.command.starts_with(prefix.as_str()))
+            .map(|(_, label)| label.as_str())
+    }
+}
+
+/// Read a JSON object of `"digest or command prefix": "label"` entries
+pub fn read_layer_labels(path: &str) -> Result<LayerLabels> {
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read layer labels: {}", path))?;
+    let map: HashMap<String, String> = serde_json::from_str(&content).context(format!(
+        "Failed to parse layer labels {} (expected a JSON object of string labels)",
+        path
+    ))?;
+    Ok(LayerLabels::new(map))
+}
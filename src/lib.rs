@@ -0,0 +1,63 @@
+//! Docker Image Tracker: track Docker image sizes over time. The `dit` binary parses
+//! arguments, sets output options and maps results to exit codes; everything else is here.
+
+pub mod analyze;
+pub mod analyze_all;
+pub mod annotations;
+pub mod baseline;
+pub mod bisect;
+pub mod budgets;
+pub mod build_metadata;
+pub mod changelog;
+pub mod chart;
+pub mod ci;
+pub mod compose;
+pub mod completions;
+pub mod config;
+pub mod cost;
+pub mod daemon;
+pub mod diff;
+pub mod docker;
+pub mod env;
+pub mod estimate;
+pub mod exclude;
+pub mod extract;
+#[cfg(test)]
+mod fixtures;
+pub mod format;
+pub mod gha;
+pub mod gnuplot;
+pub mod grep;
+pub mod github;
+pub mod history;
+pub mod image_cache;
+pub mod k8s;
+pub mod ingest;
+pub mod labels;
+pub mod layer_labels;
+pub mod merge;
+pub mod metric;
+pub mod migrate;
+pub mod models;
+pub mod output;
+pub mod path_budget;
+pub mod paths;
+pub mod platforms;
+pub mod progress;
+pub mod prune;
+pub mod rebase;
+pub mod reference;
+pub mod remote;
+pub mod reproducibility;
+pub mod report;
+pub mod report_link;
+pub mod schema;
+pub mod storage;
+pub mod store;
+pub mod summary;
+pub mod telemetry;
+pub mod top;
+pub mod track;
+pub mod track_all;
+pub mod version;
+pub mod watch;
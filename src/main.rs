@@ -1,31 +1,68 @@
-mod analyze;
-mod analyze_all;
-mod chart;
-mod ci;
-mod compose;
-mod diff;
-mod docker;
-mod format;
-mod github;
-mod history;
-mod models;
-mod summary;
-mod track;
-mod track_all;
-
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-
-use analyze::{analyze_image, OutputFormat};
-use analyze_all::analyze_all_images;
-use chart::{show_chart, show_chart_all};
-use ci::{parse_size, run_ci, CiConfig, CiOutputFormat};
-use compose::{compose_analyze, compose_history, compose_track};
-use diff::diff_images;
-use history::show_history;
-use summary::show_summary;
-use track::track_image;
-use track_all::track_all_images;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use chrono::{NaiveDate, TimeDelta};
+use chrono_tz::Tz;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use regex::Regex;
+
+use dit::analyze::{analyze_image, AnalyzeOptions, OutputFormat};
+use dit::analyze_all::{analyze_all_images, AnalyzeAllOptions};
+use dit::annotations::{parse_annotation, read_annotation_file};
+use dit::bisect::{bisect, BisectOptions};
+use dit::changelog::{changelog_entry, ChangelogFormat, ChangelogOptions};
+use dit::budgets::print_resolution;
+use dit::build_metadata::read_build_metadata;
+use dit::chart::{show_chart, show_chart_all, show_chart_by_branch, SparklineStyle};
+use dit::ci::{
+    image_budgets, parse_size, run_ci, trigger_paths, BaselineOrigin, CiConfig, CiOutputFormat,
+    CommentMode, ImageSources, DEFAULT_WARN_AT,
+};
+use dit::compose::{compose_analyze, compose_history, compose_track};
+use dit::completions::{print_completions, CompletionKind};
+use dit::config::DitConfig;
+use dit::daemon::{parse_interval, run_daemon, DaemonOptions};
+use dit::docker::{CachingInspector, DockerClient, ImageFilter, ImageSpec, LabelFilter};
+use dit::diff::{diff_images, exit_code_for_delta, BasisChoice, DiffAlgorithm, DiffOptions};
+use dit::estimate::size_estimate;
+use dit::exclude::SnapshotExclusions;
+use dit::format::{
+    LayerOrder, LayerSizeUnit, OutputStyle, UnchangedLayers, DEFAULT_MAX_CHANGES,
+};
+use dit::gha::run_gha;
+use dit::gnuplot::{write_gnuplot_data, write_gnuplot_script};
+use dit::grep::{grep_history, GrepOptions};
+use dit::history::{
+    load_image_history, show_branch_comparison, show_diff_history, show_history, HistoryOptions,
+};
+use dit::k8s::K8sTarget;
+use dit::path_budget::path_budgets;
+use dit::platforms::PlatformMode;
+use dit::progress::{reporter_for, ProgressMode};
+use dit::prune::{parse_age, prune_before, prune_history, PruneOptions};
+use dit::labels::{ImageSelector, LabelKey};
+use dit::layer_labels::read_layer_labels;
+use dit::merge::{merge_history, MergeStrategy};
+use dit::metric::Metric;
+use dit::migrate::migrate_refs;
+use dit::output::{TextOutput, BROKEN_PIPE_EXIT};
+use dit::rebase::{read_mapping_file, rebase_history, validate_sha};
+use dit::report::ReportTemplate;
+use dit::remote::RemoteBackend;
+use dit::report_link::parse_report_link;
+use dit::reproducibility::{check_reproducibility, ReproducibilityFormat, ReproducibilityOptions};
+use dit::schema::{run_schema, SchemaKind};
+use dit::storage::StorageKind;
+use dit::store::Store;
+use dit::image_cache::complete_images;
+use dit::ingest::ingest_snapshot;
+use dit::summary::{parse_csv_delimiter, show_summary, SummaryOptions};
+use dit::top::{show_top, TopOptions};
+use dit::track::{track_image, track_index};
+use dit::track_all::{track_all_images, TrackAllOptions};
+use dit::version::self_check;
+use dit::watch::{add_watched, check_watched, list_watched, remove_watched, WatchCheckOptions, WatchFormat};
 
 #[derive(Parser)]
 #[command(name = "dit")]
@@ -35,6 +72,55 @@ use track_all::track_all_images;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Read history.json once per run instead of on every lookup (also `cache_history` in .dit.toml)
+    #[arg(long, global = true)]
+    cache_history: bool,
+
+    /// Where history is kept: .dit/history.json, or .dit/history.db (also `storage` in .dit.toml)
+    #[arg(long, global = true, value_enum)]
+    storage: Option<StorageKind>,
+
+    /// Inspect an image again each time a command asks for it, instead of reusing the
+    /// first inspection of that image ID during the run
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Screen-reader-friendly output (also `plain = true` in .dit.toml): "Header: value"
+    /// records instead of tables, deltas in words, sentences instead of sparklines, no color
+    #[arg(long, global = true)]
+    plain: bool,
+
+    /// `[profiles.<name>]` section of .dit.toml to overlay on its defaults (also DIT_PROFILE).
+    /// Command-line flags win over profile values, which win over the defaults.
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Show timestamps in this IANA time zone, e.g. America/New_York (also DIT_TZ).
+    /// JSON output stays in UTC.
+    #[arg(long, global = true, value_parser = parse_time_zone)]
+    time_zone: Option<Tz>,
+
+    /// Never write to .dit (assumed when it isn't writable): commands that only read work,
+    /// optional saves are skipped, and commands that must record history exit with code 2
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// Hide snapshots whose author matches this glob (e.g. 'dependabot*') from history, chart
+    /// and summary, and from diff / ci baselines with --exclude-bots-from-baseline.
+    /// Adds to `exclude_authors` in .dit.toml.
+    #[arg(long, global = true)]
+    exclude_author: Vec<String>,
+
+    /// Hide snapshots whose commit message matches this regex (e.g. '\[bot\]'), like
+    /// --exclude-author. Adds to `exclude_messages` in .dit.toml.
+    #[arg(long, global = true)]
+    exclude_message: Vec<String>,
+
+    /// Export spans and metrics for dit's own operations to this OTLP/HTTP collector,
+    /// e.g. http://localhost:4318 (also OTEL_EXPORTER_OTLP_ENDPOINT)
+    #[arg(long, global = true)]
+    otel_endpoint: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -42,51 +128,384 @@ enum Commands {
     /// Analyze a Docker image and show layer breakdown
     Analyze {
         /// Docker image to analyze (e.g., myapp:latest)
-        image: String,
+        #[arg(required_unless_present = "iidfile")]
+        image: Option<String>,
+
+        /// Read the image ID from a `docker build --iidfile` file
+        #[arg(long, conflicts_with = "image", requires = "as_name")]
+        iidfile: Option<String>,
+
+        /// Record the image under this name:tag (required with --iidfile)
+        #[arg(long = "as")]
+        as_name: Option<String>,
 
         /// Output format
         #[arg(short, long, value_enum, default_value = "table")]
         format: OutputFormat,
+
+        /// Warn about any single layer larger than this size (e.g., 100MB)
+        #[arg(long)]
+        warn_large_single_layer: Option<String>,
+
+        /// Print a pseudo-Dockerfile reconstructed from the layer history
+        #[arg(long)]
+        show_as_dockerfile: bool,
+
+        /// Export the image and measure per-layer extraction time and file count
+        #[arg(long)]
+        measure_extract: bool,
+
+        /// Print only the total size (e.g. "142 MB")
+        #[arg(long, conflicts_with = "show_as_dockerfile")]
+        total_only: bool,
+
+        /// Also show what changed since the last tracked snapshot of this image
+        #[arg(long, conflicts_with_all = ["show_as_dockerfile", "total_only"])]
+        compare_to_previous: bool,
+
+        /// Platform you expect the image to be (e.g. linux/amd64); defaults to the Docker host's
+        #[arg(long)]
+        platform: Option<String>,
+
+        /// Also save the snapshot to history, like `dit track`
+        #[arg(long, alias = "auto-track-after-analyze")]
+        auto_track: bool,
+
+        /// With --auto-track: record the snapshot without git context
+        #[arg(long, requires = "auto_track")]
+        no_git: bool,
+
+        /// Export the image and report the final filesystem against `[path_budgets]` in .dit.toml
+        #[arg(long, conflicts_with_all = ["show_as_dockerfile", "total_only"])]
+        deep: bool,
+
+        /// Fixed unit for the layer table's Size column (bytes, KB or MB); totals stay auto-scaled
+        #[arg(long, value_enum, ignore_case = true, default_value = "auto", alias = "size-unit-in-layers")]
+        layer_size_unit: LayerSizeUnit,
+
+        /// Group layers by instruction: FROM, RUN and COPY/ADD (largest first), then metadata
+        #[arg(long)]
+        sort_by_command: bool,
+
+        /// List layers largest first
+        #[arg(long, conflicts_with = "sort_by_command")]
+        sort_by_size: bool,
+
+        /// JSON file naming layers by digest or command prefix, shown in a Label column
+        #[arg(long, value_name = "MAP_FILE")]
+        layer_label: Option<String>,
     },
 
     /// Analyze all local Docker images at once
     AnalyzeAll {
-        /// Filter images by name (substring match)
+        /// Filter images by name (substring match) or label (label:KEY or label:KEY=VALUE)
+        #[arg(long)]
+        filter: Option<ImageSelector>,
+
+        /// Only include images whose tag matches this regex (e.g., "^v[0-9]+")
+        #[arg(long, value_parser = parse_regex)]
+        tag_regex: Option<Regex>,
+
+        /// Only include images with this label (KEY=VALUE or KEY); repeat to require several
         #[arg(long)]
-        filter: Option<String>,
+        label_filter: Vec<LabelFilter>,
 
         /// Output format
         #[arg(short, long, value_enum, default_value = "table")]
         format: OutputFormat,
+
+        /// Progress output (default: dots on a terminal, none otherwise)
+        #[arg(long, value_enum)]
+        progress: Option<ProgressMode>,
+
+        /// Group table rows by a label with subtotals (e.g. label:com.acme.team)
+        #[arg(long)]
+        group_by: Option<LabelKey>,
+
+        /// Extra label columns (comma-separated, e.g. label:org.opencontainers.image.version)
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<LabelKey>,
+
+        /// Show full image names instead of shortening long ones
+        #[arg(long)]
+        wide: bool,
+
+        /// Add a User column and flag images that run as root
+        #[arg(long)]
+        warn_root_user: bool,
+
+        /// Add an Image ID column (changes on every rebuild, even when the content doesn't)
+        #[arg(long)]
+        include_image_id: bool,
     },
 
     /// Track an image snapshot with git context
     Track {
         /// Docker image to track (e.g., myapp:latest)
-        image: String,
+        #[arg(required_unless_present = "iidfile")]
+        image: Option<String>,
+
+        /// Read the image ID from a `docker build --iidfile` file
+        #[arg(long, conflicts_with = "image", requires = "as_name")]
+        iidfile: Option<String>,
+
+        /// Record the image under this name:tag (required with --iidfile)
+        #[arg(long = "as")]
+        as_name: Option<String>,
+
+        /// Export the image and record per-layer extraction time and file count
+        #[arg(long)]
+        measure_extract: bool,
+
+        /// Attach metadata to the snapshot (KEY=VALUE); repeatable
+        #[arg(long, value_parser = parse_annotation)]
+        annotation: Vec<(String, String)>,
+
+        /// Read annotations from a JSON object; nested keys flatten to "key.subkey"
+        #[arg(long)]
+        annotation_file: Option<String>,
+
+        /// Read the registry index (e.g. a `buildx --push` build) and record one snapshot per platform
+        #[arg(long, conflicts_with_all = ["iidfile", "measure_extract"])]
+        multi_platform: bool,
+
+        /// Record the build time from a `docker buildx build --metadata-file` file
+        #[arg(long, conflicts_with = "multi_platform")]
+        build_metadata: Option<String>,
+
+        /// Also upload the snapshot to the S3 bucket from DIT_S3_BUCKET or [remote] in .dit.toml
+        #[arg(long)]
+        remote: bool,
     },
 
     /// Track all local Docker images at once
     TrackAll {
-        /// Filter images by name (substring match)
+        /// Filter images by name (substring match) or label (label:KEY or label:KEY=VALUE)
+        #[arg(long)]
+        filter: Option<ImageSelector>,
+
+        /// Only include images whose tag matches this regex (e.g., "^v[0-9]+")
+        #[arg(long, value_parser = parse_regex)]
+        tag_regex: Option<Regex>,
+
+        /// Only include images with this label (KEY=VALUE or KEY); repeat to require several
+        #[arg(long)]
+        label_filter: Vec<LabelFilter>,
+
+        /// Progress output (default: dots on a terminal, none otherwise)
+        #[arg(long, value_enum)]
+        progress: Option<ProgressMode>,
+
+        /// Print a compact summary of the tracked images when done
+        #[arg(long)]
+        summary_after_track_all: bool,
+
+        /// Show each image's ID as it is tracked, to tell rebuilds from cache hits
         #[arg(long)]
-        filter: Option<String>,
+        include_image_id: bool,
+    },
+
+    /// Keep running and record a snapshot whenever a matching image is rebuilt
+    Daemon {
+        /// How often to scan local images (e.g. 30s, 10m, 1h)
+        #[arg(long, alias = "watch-interval", default_value = "10m", value_parser = parse_interval)]
+        interval: std::time::Duration,
+
+        /// Filter images by name (substring match) or label (label:KEY or label:KEY=VALUE)
+        #[arg(long)]
+        filter: Option<ImageSelector>,
+
+        /// Only include images whose tag matches this regex (e.g., "^v[0-9]+")
+        #[arg(long, value_parser = parse_regex)]
+        tag_regex: Option<Regex>,
+
+        /// Only include images with this label (KEY=VALUE or KEY); repeat to require several
+        #[arg(long)]
+        label_filter: Vec<LabelFilter>,
+
+        /// Shell command to run on each change; {image}, {tag}, {delta}, {size} and
+        /// {previous_size} are substituted
+        #[arg(long)]
+        on_change: Option<String>,
+
+        /// Alert when a scanned image is larger than this (e.g. 500MB)
+        #[arg(long)]
+        watch_budget: Option<String>,
+
+        /// Shell command to run on each alert; gets the image as $1 and its size in bytes as $2
+        #[arg(long, requires = "watch_budget")]
+        alert_cmd: Option<String>,
+
+        /// Minimum time between alerts for an image that stays over budget (e.g. 300, 30m)
+        #[arg(long, default_value = "1h", value_parser = parse_interval)]
+        alert_cooldown: std::time::Duration,
+    },
+
+    /// Create a snapshot from exported `docker inspect` / `docker history` JSON
+    Ingest {
+        /// Path to `docker inspect <image>` output
+        #[arg(long)]
+        inspect: String,
+
+        /// Path to `docker history --no-trunc --format json <image>` output
+        #[arg(long)]
+        history: String,
+
+        /// Image reference to record (defaults to the first RepoTag)
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Commit SHA to attribute the snapshot to (overrides git)
+        #[arg(long)]
+        commit: Option<String>,
+
+        /// Branch to attribute the snapshot to (overrides git)
+        #[arg(long)]
+        branch: Option<String>,
     },
 
     /// Compare two image snapshots
     Diff {
-        /// Docker image to compare (e.g., myapp:latest)
-        image: String,
+        /// Docker image to compare (e.g., myapp:latest); with --left / --right it defaults
+        /// to the snapshot file's image
+        #[arg(required_unless_present_any = ["left", "right"])]
+        image: Option<String>,
 
-        /// First commit SHA (optional)
+        /// First commit SHA (optional); with one of --left / --right, the history snapshot to use
         commit_a: Option<String>,
 
         /// Second commit SHA (optional)
+        #[arg(conflicts_with_all = ["left", "right"])]
         commit_b: Option<String>,
 
+        /// Older side from a `dit analyze --format json` file instead of history
+        #[arg(long, value_name = "FILE", alias = "left-file")]
+        left: Option<String>,
+
+        /// Newer side from a `dit analyze --format json` file instead of history
+        #[arg(long, value_name = "FILE", alias = "right-file")]
+        right: Option<String>,
+
         /// Compare against latest snapshot from this branch
         #[arg(long)]
         base: Option<String>,
+
+        /// Print how the --base snapshot was selected
+        #[arg(long, requires = "base")]
+        explain_baseline: bool,
+
+        /// Ignore snapshots matching --exclude-author / --exclude-message when picking snapshots
+        #[arg(long)]
+        exclude_bots_from_baseline: bool,
+
+        /// Show ENV values in environment changes (secret-like values stay masked)
+        #[arg(long)]
+        show_env_values: bool,
+
+        /// Match layers by position too, reporting moved layers as reordered
+        #[arg(long)]
+        layer_order_matters: bool,
+
+        /// Pair layers by digest, or by position when digests change on every build
+        #[arg(long, value_enum, default_value = "digest", conflicts_with = "layer_order_matters")]
+        diff_algorithm: DiffAlgorithm,
+
+        /// Only layers with the same digest count as the same layer (the default)
+        #[arg(long, conflicts_with = "match_by_command")]
+        strict_digest_match: bool,
+
+        /// Also pair a removed and an added layer with the same command, reporting a
+        /// modified layer instead of a removal plus an addition
+        #[arg(long, alias = "fuzzy-match")]
+        match_by_command: bool,
+
+        /// Show only the N layer changes with the largest deltas (0 shows all)
+        #[arg(long, default_value_t = DEFAULT_MAX_CHANGES)]
+        max_changes: usize,
+
+        /// Print only the total size change (e.g. "+2.4 MB")
+        #[arg(long)]
+        total_only: bool,
+
+        /// Print nothing when the size changed by less than this (e.g. 1MB)
+        #[arg(long, value_name = "BYTES")]
+        ignore_below_threshold: Option<String>,
+
+        /// Print nothing when the size changed by less than this percentage
+        #[arg(long, value_name = "PERCENT")]
+        ignore_below_pct: Option<f64>,
+
+        /// Print a one-line note instead of nothing when the change is below the threshold
+        #[arg(long)]
+        verbose_if_below_threshold: bool,
+
+        /// Highlight added (green) and removed (red) words in modified layers' commands
+        #[arg(long, conflicts_with = "total_only")]
+        word_diff: bool,
+
+        /// Also save the computed diff to .dit/diffs/ (see dit history --show-diffs)
+        #[arg(long)]
+        save_diff_to_history: bool,
+
+        /// Summarize unchanged layers in one line above the layer table (the default)
+        #[arg(long, conflicts_with_all = ["show_unchanged", "hide_unchanged"])]
+        show_unchanged_summary: bool,
+
+        /// List unchanged layers in the layer table
+        #[arg(long, conflicts_with = "hide_unchanged")]
+        show_unchanged: bool,
+
+        /// Leave unchanged layers out of the layer table entirely
+        #[arg(long)]
+        hide_unchanged: bool,
+
+        /// Fixed unit for the layer table's Size column (bytes, KB or MB); totals stay auto-scaled
+        #[arg(long, value_enum, ignore_case = true, default_value = "auto", alias = "size-unit-in-layers")]
+        layer_size_unit: LayerSizeUnit,
+
+        /// Size to compare: compressed when both sides have it (auto), or force one;
+        /// forcing compressed fails when either side lacks a compressed size
+        #[arg(long, value_enum, default_value = "auto")]
+        basis: BasisChoice,
+
+        /// Exit 1 when the image grew, 2 when it shrank, 0 when unchanged (3 on errors)
+        #[arg(long)]
+        emit_exit_code_for_increase: bool,
+
+        /// With --emit-exit-code-for-increase: only exit 1 for increases above this (e.g. 5MB)
+        #[arg(long, value_name = "BYTES", requires = "emit_exit_code_for_increase")]
+        fail_threshold: Option<String>,
+
+        /// Compare the snapshot for HEAD~1 with HEAD's (or the latest)
+        #[arg(long, conflicts_with_all = ["commit_a", "base", "left", "right"])]
+        compare_with_parent_commit: bool,
+
+        /// Compare against the image a Kubernetes workload runs (e.g. deployment/myapp)
+        #[arg(
+            long,
+            conflicts_with_all = ["commit_a", "base", "compare_with_parent_commit", "left", "right"]
+        )]
+        against_k8s: Option<String>,
+
+        /// Namespace of the --against-k8s workload
+        #[arg(short = 'n', long, requires = "against_k8s")]
+        namespace: Option<String>,
+
+        /// kubeconfig context to query
+        #[arg(long, requires = "against_k8s")]
+        against_k8s_context: Option<String>,
+
+        /// Container to compare when the pod has several
+        #[arg(long, requires = "against_k8s")]
+        container: Option<String>,
+
+        /// Read the workload from this manifest instead of querying the cluster
+        #[arg(long, requires = "against_k8s", conflicts_with = "against_k8s_context")]
+        k8s_manifest: Option<String>,
+
+        /// Also fetch this image's snapshots that are only in the S3 bucket (see dit track --remote)
+        #[arg(long)]
+        remote: bool,
     },
 
     /// Show image size history
@@ -97,6 +516,42 @@ enum Commands {
         /// Limit to last N snapshots
         #[arg(long)]
         last: Option<usize>,
+
+        /// Compare branches side by side (comma-separated, e.g. main,release/2.x)
+        #[arg(long, value_delimiter = ',')]
+        compare_branches: Option<Vec<String>>,
+
+        /// Write a gnuplot data file (Unix timestamp, size in bytes)
+        #[arg(long, conflicts_with = "compare_branches")]
+        export_gnuplot: Option<String>,
+
+        /// Write a gnuplot script that plots the --export-gnuplot file
+        #[arg(long, requires = "export_gnuplot")]
+        export_gnuplot_script: Option<String>,
+
+        /// Multi-platform builds: one row per platform, or the largest / combined size
+        #[arg(long, value_enum, default_value = "separate")]
+        platforms: PlatformMode,
+
+        /// Also list diffs saved with dit diff --save-diff-to-history
+        #[arg(long, conflicts_with = "compare_branches")]
+        show_diffs: bool,
+
+        /// One table per branch; --last then limits each branch
+        #[arg(long, conflicts_with = "compare_branches")]
+        group_by_branch: bool,
+
+        /// Add an Image ID column, to tell rebuilds from cache hits
+        #[arg(long, conflicts_with = "compare_branches")]
+        show_id: bool,
+
+        /// What to list: image size, or build time recorded with --build-metadata
+        #[arg(long, value_enum, default_value = "size", conflicts_with = "compare_branches")]
+        metric: Metric,
+
+        /// Also fetch snapshots that are only in the S3 bucket (see dit track --remote)
+        #[arg(long, conflicts_with = "compare_branches")]
+        remote: bool,
     },
 
     /// Show ASCII trend chart for image(s)
@@ -104,50 +559,348 @@ enum Commands {
         /// Docker image to show chart for (e.g., myapp:latest)
         image: Option<String>,
 
-        /// Show charts for all tracked images
+        /// Show sparklines for all tracked images (the default without an image)
         #[arg(long)]
         all: bool,
 
-        /// Limit to last N snapshots
-        #[arg(long, default_value = "20")]
+        /// Limit to last N snapshots [default: 20 for one image, 10 per image with --all]
+        #[arg(long)]
         last: Option<usize>,
+
+        /// Plot one series per branch
+        #[arg(long, conflicts_with = "all")]
+        by_branch: bool,
+
+        /// Draw with ASCII characters only (also DIT_ASCII=1)
+        #[arg(long)]
+        ascii: bool,
+
+        /// What to plot: image size, or build time recorded with --build-metadata
+        #[arg(long, value_enum, default_value = "size", conflicts_with = "by_branch")]
+        metric: Metric,
+    },
+
+    /// Find where between two tracked commits an image grew the most
+    Bisect {
+        /// Docker image to inspect (e.g., myapp)
+        image: String,
+
+        /// Commit with an acceptable size
+        #[arg(long)]
+        good: String,
+
+        /// Commit where the image is too big
+        #[arg(long)]
+        bad: String,
+
+        /// Build command to run at each step of a real `git bisect` over the largest jump
+        #[arg(long)]
+        rebuild: Option<String>,
+
+        /// Growth over the good size that counts as bad (e.g. 50MB; default: half the jump)
+        #[arg(long, requires = "rebuild")]
+        threshold: Option<String>,
+    },
+
+    /// Summarize an image's size change since a tag or commit, for CHANGELOG.md or a release
+    ChangelogEntry {
+        /// Docker image (e.g., myapp:latest)
+        image: String,
+
+        /// Tag or commit to measure from (must have a tracked snapshot)
+        #[arg(long)]
+        since: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "markdown")]
+        format: ChangelogFormat,
+
+        /// Number of layer changes to list
+        #[arg(long, default_value = "5")]
+        top: usize,
+
+        /// Append the markdown entry to the latest draft GitHub release (needs GITHUB_TOKEN
+        /// and GITHUB_REPOSITORY)
+        #[arg(long)]
+        github_release: bool,
+    },
+
+    /// Check whether rebuilds of the same commit produce identical layers
+    Reproducibility {
+        /// Docker image to check (e.g., myapp:latest)
+        image: String,
+
+        /// Inspect the image this many times, rebuilding it in between (instead of tracked snapshots)
+        #[arg(long, conflicts_with = "commit")]
+        runs: Option<usize>,
+
+        /// Commit whose tracked snapshots to compare (default: the latest commit tracked twice)
+        #[arg(long)]
+        commit: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        format: ReproducibilityFormat,
+    },
+
+    /// Search tracked layer commands (e.g. which images install imagemagick)
+    Grep {
+        /// Text to look for (case-insensitive)
+        pattern: String,
+
+        /// Treat the pattern as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Match case exactly
+        #[arg(long)]
+        case_sensitive: bool,
+
+        /// Search every tracked snapshot, not just the latest per image
+        #[arg(long, conflicts_with = "latest_only")]
+        all: bool,
+
+        /// Search only the latest snapshot per image (the default)
+        #[arg(long)]
+        latest_only: bool,
+
+        /// Only search images whose name contains this, or with label:KEY or label:KEY=VALUE
+        #[arg(long)]
+        filter: Option<ImageSelector>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Rewrite snapshot commit SHAs after a git rebase
+    RebaseHistory {
+        /// Commit SHA recorded before the rebase
+        #[arg(long, requires = "new_sha", conflicts_with = "mapping_file")]
+        old_sha: Option<String>,
+
+        /// Commit SHA after the rebase
+        #[arg(long, requires = "old_sha")]
+        new_sha: Option<String>,
+
+        /// File of "<old-sha> <new-sha>" lines (e.g. .git/rebase-merge/rewritten-list)
+        #[arg(long, required_unless_present = "old_sha")]
+        mapping_file: Option<String>,
+    },
+
+    /// Rewrite history to canonical image references (myapp, docker.io/library/myapp
+    /// and myapp:latest all become myapp:latest)
+    MigrateRefs {
+        /// List the renames and merges without changing history
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Resolve merge conflict markers in .dit/history.json
+    #[command(group(clap::ArgGroup::new("strategy").required(true).args(["ours", "theirs", "union"])))]
+    MergeHistory {
+        /// Keep the side between <<<<<<< and ======= (during a rebase, the branch being rebased onto)
+        #[arg(long)]
+        ours: bool,
+
+        /// Keep the side between ======= and >>>>>>>
+        #[arg(long)]
+        theirs: bool,
+
+        /// Keep snapshots from both sides, dropping ones recorded on both
+        #[arg(long)]
+        union: bool,
+    },
+
+    /// Delete old snapshots, keeping the newest per image and every snapshot on protected branches
+    Prune {
+        /// Snapshots to keep per image, tag and platform
+        #[arg(long, required_unless_present_any = ["before", "older_than"])]
+        keep_last: Option<usize>,
+
+        /// Delete snapshots older than this (e.g. 30d, 6w, 12h); with --keep-last, a
+        /// snapshot kept by either policy stays
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Option<TimeDelta>,
+
+        /// Only prune snapshots of this image (e.g. myapp or myapp:latest)
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Never prune snapshots on these branches (e.g. main,develop)
+        #[arg(long, value_delimiter = ',')]
+        keep_branches: Vec<String>,
+
+        /// Instead delete every snapshot recorded before this date (YYYY-MM-DD, UTC), on any branch
+        #[arg(long, conflicts_with_all = ["keep_last", "keep_branches", "older_than", "image"])]
+        before: Option<NaiveDate>,
+
+        /// List what would be deleted without changing history
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Estimate an image's download and on-disk size from its registry manifest, without pulling
+    SizeEstimate {
+        /// Image reference (e.g. ghcr.io/org/app:1.2)
+        image: String,
+
+        /// Platform to size in a multi-platform index (e.g. linux/arm64); defaults to the Docker host's
+        #[arg(long)]
+        platform: Option<String>,
+    },
+
+    /// Check this dit against the history schema and .dit.toml settings it will read
+    #[command(alias = "doctor")]
+    SelfCheck,
+
+    /// Print the JSON Schema for dit's JSON output, or validate a file against it
+    Schema {
+        /// Which document to describe
+        #[arg(value_enum, default_value = "snapshot")]
+        kind: SchemaKind,
+
+        /// Check this JSON file against the schema instead of printing it
+        #[arg(long)]
+        validate: Option<String>,
     },
 
     /// Docker Compose support
     #[command(subcommand)]
     Compose(ComposeCommands),
 
+    /// Size and digest gates for third-party images we consume but don't build
+    #[command(subcommand)]
+    WatchList(WatchListCommands),
+
+    /// Inspect the per-image budgets in .dit.toml
+    #[command(subcommand)]
+    Budgets(BudgetsCommands),
+
+    /// Largest individual layers across local images, shared layers listed once
+    Top {
+        /// Filter images by name (substring match) or label (label:KEY or label:KEY=VALUE)
+        #[arg(long)]
+        filter: Option<ImageSelector>,
+
+        /// Only include images whose tag matches this regex
+        #[arg(long, value_parser = parse_regex)]
+        tag_regex: Option<Regex>,
+
+        /// Only include images with this label (KEY=VALUE or KEY); repeat to require several
+        #[arg(long)]
+        label_filter: Vec<LabelFilter>,
+
+        /// Layers to show (0 shows all)
+        #[arg(long, default_value = "20")]
+        limit: usize,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
     /// Show summary dashboard of all tracked images
-    Summary,
+    Summary {
+        /// Group rows by a label with subtotals (e.g. label:com.acme.team)
+        #[arg(long)]
+        group_by: Option<LabelKey>,
+
+        /// Nest tag variants (myapp:prod, myapp:staging) under a total row per base image
+        #[arg(long, conflicts_with = "group_by")]
+        group_by_image: bool,
+
+        /// Extra label columns (comma-separated, e.g. label:org.opencontainers.image.version)
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<LabelKey>,
+
+        /// Show full image names instead of shortening long ones
+        #[arg(long)]
+        wide: bool,
+
+        /// Multi-platform builds: one row per platform, or the largest / combined size
+        #[arg(long, value_enum, default_value = "separate")]
+        platforms: PlatformMode,
+
+        /// Draw sparklines and table borders with ASCII characters only (also DIT_ASCII=1)
+        #[arg(long)]
+        ascii: bool,
+
+        /// Output format; csv is for spreadsheet imports
+        #[arg(long, alias = "output-format", value_enum, default_value = "table")]
+        format: OutputFormat,
+
+        /// Field separator for --format csv, e.g. ";" for locales with decimal commas
+        #[arg(long, value_name = "CHAR", default_value = ",", value_parser = parse_csv_delimiter)]
+        csv_delimiter: char,
+
+        /// Latest value and trend of image size, or build time recorded with --build-metadata
+        #[arg(long, value_enum, default_value = "size")]
+        metric: Metric,
+    },
 
     /// CI mode - track, compare, and report (GitHub Actions optimized)
     Ci {
         /// Docker image(s) to track
-        #[arg(required_unless_present_any = ["filter", "compose"])]
+        #[arg(required_unless_present_any = ["image", "filter", "tag_regex", "compose", "iidfile"])]
         images: Vec<String>,
 
-        /// Filter images by name (use with --filter)
-        #[arg(long, conflicts_with = "images")]
-        filter: Option<String>,
+        /// Image to track, as a flag; repeatable and combined with the positional images
+        #[arg(long, value_name = "IMAGE")]
+        image: Vec<String>,
+
+        /// Also track the image whose ID is in this `docker build --iidfile` file
+        #[arg(long, requires = "as_name")]
+        iidfile: Option<String>,
+
+        /// Name:tag to record the --iidfile image under
+        #[arg(long = "as", requires = "iidfile")]
+        as_name: Option<String>,
+
+        /// Filter images by name or label (label:KEY or label:KEY=VALUE)
+        #[arg(long, conflicts_with_all = ["images", "image"])]
+        filter: Option<ImageSelector>,
+
+        /// Only include images whose tag matches this regex
+        #[arg(long, conflicts_with_all = ["images", "image"], value_parser = parse_regex)]
+        tag_regex: Option<Regex>,
 
         /// Read images from docker-compose file
-        #[arg(long, conflicts_with_all = ["images", "filter"])]
+        #[arg(long, conflicts_with_all = ["images", "image", "filter", "tag_regex"])]
         compose: Option<String>,
 
+        /// Compose project name for --compose images (default: COMPOSE_PROJECT_NAME, then the
+        /// compose file's x-dit-project or name:, then its directory)
+        #[arg(long, requires = "compose", conflicts_with_all = ["images", "image", "filter", "tag_regex"])]
+        project_name: Option<String>,
+
         /// Maximum allowed total size (e.g., 500MB, 2GB)
         #[arg(long)]
         budget: Option<String>,
 
         /// Maximum allowed increase percentage (e.g., 10)
-        #[arg(long)]
+        #[arg(long, visible_alias = "max-increase")]
         budget_increase: Option<f64>,
 
-        /// Post results as GitHub PR comment
+        /// Warn once total size reaches this percentage of --budget [default: 90; overrides
+        /// `warn_at` in .dit.toml budgets]
+        #[arg(long)]
+        warn_at: Option<f64>,
+
+        /// Exit with non-zero code on budget warnings, not just failures
+        #[arg(long)]
+        strict_warnings: bool,
+
+        /// Post results as GitHub PR comment (skipped with a warning without GITHUB_TOKEN / GITHUB_REPOSITORY)
         #[arg(long)]
         github_comment: bool,
 
+        /// When to update the PR comment: on-change skips it if no image changed
+        #[arg(long, value_enum, default_value = "on-change")]
+        comment_mode: CommentMode,
+
         /// Compare against latest snapshot from this branch
-        #[arg(long)]
+        #[arg(long, visible_alias = "base-branch")]
         base: Option<String>,
 
         /// Output format
@@ -157,14 +910,294 @@ enum Commands {
         /// Exit with non-zero code if ANY image increased in size
         #[arg(long)]
         fail_on_increase: bool,
+
+        /// Exit with non-zero code if an image's base image differs from its baseline's
+        #[arg(long)]
+        fail_if_base_image_changed: bool,
+
+        /// Warn about any single layer larger than this size (e.g., 100MB)
+        #[arg(long)]
+        warn_large_single_layer: Option<String>,
+
+        /// Exit with non-zero code if a layer exceeds --warn-large-single-layer
+        #[arg(long, requires = "warn_large_single_layer")]
+        fail_on_large_layer: bool,
+
+        /// Print how each baseline snapshot was selected
+        #[arg(long)]
+        explain_baseline: bool,
+
+        /// Never pick a baseline from snapshots matching --exclude-author / --exclude-message
+        #[arg(long)]
+        exclude_bots_from_baseline: bool,
+
+        /// Size to compare: compressed when both sides have it (auto), or force one;
+        /// forcing compressed fails when either side lacks a compressed size
+        #[arg(long, value_enum, default_value = "auto")]
+        basis: BasisChoice,
+
+        /// Layer changes listed per image in the report details, largest deltas first (0 lists all)
+        #[arg(long, default_value_t = DEFAULT_MAX_CHANGES)]
+        max_changes: usize,
+
+        /// same: skip baselines built locally when the image was pulled, and vice versa
+        #[arg(long, value_enum, default_value = "any")]
+        baseline_origin: BaselineOrigin,
+
+        /// Run every check and print the report, but don't post a comment, record
+        /// history or exit non-zero; failed checks print as warnings
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Show ENV values in environment changes (secret-like values stay masked)
+        #[arg(long)]
+        show_env_values: bool,
+
+        /// Only analyze images when files matching these globs changed since the baseline
+        #[arg(long, value_delimiter = ',')]
+        paths: Vec<String>,
+
+        /// Render the report with this minijinja template instead of the built-in layout
+        #[arg(long)]
+        report_template: Option<String>,
+
+        /// Print the JSON context passed to report templates instead of the report
+        #[arg(long, conflicts_with = "github_comment")]
+        print_template_context: bool,
+
+        /// Also write the markdown report to this file
+        #[arg(long)]
+        output_path: Option<String>,
+
+        /// Append to --output-path as a new `---` section instead of overwriting it
+        #[arg(long, requires = "output_path")]
+        output_append: bool,
+
+        /// Repeat the report heading on appended sections
+        #[arg(long, requires = "output_append")]
+        output_header: bool,
+
+        /// Read each image's registry index and compare every platform (`buildx --push` builds)
+        #[arg(long, conflicts_with = "iidfile")]
+        multi_platform: bool,
+
+        /// Multi-platform builds: compare and budget each platform, or the largest / combined size
+        #[arg(long, value_enum, default_value = "separate")]
+        platforms: PlatformMode,
+
+        /// Record build time from a `docker buildx build --metadata-file` file and report its change
+        #[arg(long, conflicts_with = "multi_platform")]
+        build_metadata: Option<String>,
+
+        /// Footer link to the full report (e.g. https://ci.example.com/runs/{run_id}); {run_id},
+        /// {run_number}, {repository} and {sha} come from the CI environment
+        #[arg(long, value_name = "URL", value_parser = parse_report_link)]
+        report_link: Option<String>,
+    },
+
+    /// Run `dit ci` configured from GitHub Action inputs (INPUT_* variables); writes step
+    /// outputs (total-size, total-delta, failed, report), the job summary and annotations
+    Gha,
+
+    /// Print a shell completion script; bash and zsh also suggest image names
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Suggestions for the completion scripts, read from .dit without asking Docker
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(value_enum)]
+        kind: CompletionKind,
+
+        /// Only suggestions starting with this
+        #[arg(default_value = "", allow_hyphen_values = true)]
+        prefix: String,
     },
 }
 
+/// What a command does with the history store
+enum HistoryWrites {
+    None,
+    /// Saves as a side effect, skipped in read-only mode
+    Optional,
+    /// Recording history is the point of the command
+    Required(&'static str),
+}
+
+impl Commands {
+    fn history_writes(&self) -> HistoryWrites {
+        match self {
+            Commands::Track { .. } => HistoryWrites::Required("track"),
+            Commands::TrackAll { .. } => HistoryWrites::Required("track-all"),
+            Commands::Daemon { .. } => HistoryWrites::Required("daemon"),
+            Commands::Ingest { .. } => HistoryWrites::Required("ingest"),
+            Commands::RebaseHistory { .. } => HistoryWrites::Required("rebase-history"),
+            Commands::MergeHistory { .. } => HistoryWrites::Required("merge-history"),
+            Commands::MigrateRefs { dry_run: false } => HistoryWrites::Required("migrate-refs"),
+            Commands::Prune { dry_run: false, .. } => HistoryWrites::Required("prune"),
+            Commands::Compose(ComposeCommands::Track { .. }) => HistoryWrites::Required("compose track"),
+            Commands::WatchList(WatchListCommands::Add { .. }) => HistoryWrites::Required("watch-list add"),
+            Commands::WatchList(WatchListCommands::Remove { .. }) => {
+                HistoryWrites::Required("watch-list remove")
+            }
+            Commands::Analyze { auto_track: true, .. }
+            | Commands::Diff {
+                save_diff_to_history: true,
+                ..
+            }
+            | Commands::Ci { .. }
+            | Commands::Gha
+            | Commands::WatchList(WatchListCommands::Check { .. }) => HistoryWrites::Optional,
+            _ => HistoryWrites::None,
+        }
+    }
+
+    /// Whether `--remote` shares this command's snapshots through S3
+    fn uses_remote(&self) -> bool {
+        matches!(
+            self,
+            Commands::Track { remote: true, .. }
+                | Commands::Diff { remote: true, .. }
+                | Commands::History { remote: true, .. }
+        )
+    }
+
+    /// Whether `--exclude-author` / `--exclude-message` filter what this command reads
+    fn uses_exclusions(&self) -> bool {
+        match self {
+            Commands::History { .. } | Commands::Chart { .. } | Commands::Summary { .. } => true,
+            Commands::Diff {
+                exclude_bots_from_baseline,
+                ..
+            }
+            | Commands::Ci {
+                exclude_bots_from_baseline,
+                ..
+            } => *exclude_bots_from_baseline,
+            _ => false,
+        }
+    }
+}
+
+/// Combine CLI selection flags; label filters fall back to `.dit.toml` when none are given
+fn image_filter(
+    selector: Option<ImageSelector>,
+    tag_regex: Option<Regex>,
+    labels: Vec<LabelFilter>,
+) -> Result<ImageFilter> {
+    let mut labels = if labels.is_empty() {
+        DitConfig::load()?
+            .label_filters
+            .iter()
+            .map(|l| l.parse().map_err(anyhow::Error::msg))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        labels
+    };
+
+    let name = match selector {
+        Some(ImageSelector::Name(name)) => Some(name),
+        Some(ImageSelector::Label(label)) => {
+            labels.push(label);
+            None
+        }
+        None => None,
+    };
+
+    Ok(ImageFilter {
+        name,
+        tag_regex,
+        labels,
+    })
+}
+
+/// Docker for commands that inspect images: each image ID is inspected once per run,
+/// unless --no-cache asks for a fresh inspection every time. Listings refresh the
+/// completion image cache unless the store is read-only.
+fn docker(no_cache: bool, store: &Store) -> Result<CachingInspector<DockerClient>> {
+    let mut docker = CachingInspector::new(DockerClient::new()?);
+    if !store.is_read_only() {
+        docker = docker.recording_images_in(store.dir());
+    }
+    Ok(if no_cache { docker.without_cache() } else { docker })
+}
+
+fn parse_time_zone(s: &str) -> Result<Tz, String> {
+    s.parse()
+        .map_err(|_| format!("unknown time zone '{}' (expected an IANA name like Europe/Berlin)", s))
+}
+
+fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| format!("invalid regex: {}", e))
+}
+
 #[derive(Debug, Clone, clap::ValueEnum)]
 enum CiFormat {
     Table,
     Json,
     Markdown,
+    /// One line per image, e.g. "🐋 myapp:latest: 142 MB (+2.4 MB, +1.7%)"
+    MarkdownSummary,
+}
+
+#[derive(Subcommand)]
+enum BudgetsCommands {
+    /// Show which [budgets] key applies to an image and which others it outranked
+    Resolve {
+        /// Image reference (e.g. ghcr.io/org/api:1.2)
+        image: String,
+
+        /// Warning threshold override, as on dit ci
+        #[arg(long)]
+        warn_at: Option<f64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WatchListCommands {
+    /// Watch an image (e.g. postgres:16), or change its budget
+    Add {
+        /// Image reference as pulled
+        image: String,
+
+        /// Maximum size (e.g. 450MB)
+        #[arg(long)]
+        budget: Option<String>,
+    },
+
+    /// Stop watching an image
+    Remove {
+        /// Image reference as added
+        image: String,
+    },
+
+    /// List watched images and their budgets
+    List,
+
+    /// Observe every watched image, compare it with the last observation and record it
+    Check {
+        /// Read digests and compressed sizes from the registry instead of the pulled images
+        #[arg(long)]
+        remote: bool,
+
+        /// Platform to size with --remote (e.g. linux/arm64); defaults to the Docker host's
+        #[arg(long, requires = "remote")]
+        platform: Option<String>,
+
+        /// Largest allowed growth since the last observation, in percent
+        #[arg(long, default_value = "10")]
+        tolerance: f64,
+
+        /// Fail, not just warn, when a watched tag now points at a different digest
+        #[arg(long)]
+        fail_on_digest_change: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        format: WatchFormat,
+    },
 }
 
 #[derive(Subcommand)]
@@ -174,6 +1207,15 @@ enum ComposeCommands {
         /// Path to docker-compose file
         #[arg(long)]
         file: Option<String>,
+
+        /// Compose project name (default: COMPOSE_PROJECT_NAME, then the compose file's
+        /// x-dit-project or name:, then its directory)
+        #[arg(long, short = 'p')]
+        project_name: Option<String>,
+
+        /// Show each service's Dockerfile (build context + dockerfile)
+        #[arg(long)]
+        show_dockerfile_path: bool,
     },
 
     /// Track all compose-built images
@@ -181,6 +1223,11 @@ enum ComposeCommands {
         /// Path to docker-compose file
         #[arg(long)]
         file: Option<String>,
+
+        /// Compose project name (default: COMPOSE_PROJECT_NAME, then the compose file's
+        /// x-dit-project or name:, then its directory)
+        #[arg(long, short = 'p')]
+        project_name: Option<String>,
     },
 
     /// Show history for all compose images
@@ -188,86 +1235,732 @@ enum ComposeCommands {
         /// Path to docker-compose file
         #[arg(long)]
         file: Option<String>,
+
+        /// Compose project name (default: COMPOSE_PROJECT_NAME, then the compose file's
+        /// x-dit-project or name:, then its directory)
+        #[arg(long, short = 'p')]
+        project_name: Option<String>,
+
+        /// Show each service's Dockerfile (build context + dockerfile)
+        #[arg(long)]
+        show_dockerfile_path: bool,
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut stdout = TextOutput::new(io::stdout());
+    let result = runtime.block_on(run(&mut stdout)).and_then(|code| {
+        stdout.flush()?;
+        Ok(code)
+    });
+
+    match result {
+        // The reader closed the pipe early; stop quietly like a writer SIGPIPE killed
+        _ if stdout.is_closed() => Ok(ExitCode::from(BROKEN_PIPE_EXIT)),
+        result => result,
+    }
+}
+
+async fn run(out: &mut TextOutput<impl Write>) -> Result<ExitCode> {
     let cli = Cli::parse();
+    let profile = cli.profile.or_else(|| std::env::var("DIT_PROFILE").ok());
+
+    let time_zone = match (cli.time_zone, std::env::var("DIT_TZ")) {
+        (Some(zone), _) => Some(zone),
+        (None, Ok(zone)) => Some(parse_time_zone(&zone).map_err(|e| anyhow::anyhow!("DIT_TZ: {}", e))?),
+        (None, Err(_)) => None,
+    };
+    let plain = cli.plain || DitConfig::load().map(|c| c.plain).unwrap_or(false);
+    if plain {
+        // Nothing may rely on color
+        out.strip_color();
+    }
+    let output = OutputStyle { time_zone, plain };
+
+    // Flushed when dropped, on every return from main
+    let _telemetry = dit::telemetry::init(cli.otel_endpoint.clone())?;
+
+    // A broken .dit.toml is reported by the commands that need it
+    let cache_history = cli.cache_history
+        || DitConfig::load().map(|c| c.cache_history).unwrap_or(false);
+    let storage = cli
+        .storage
+        .or_else(|| DitConfig::load().ok().map(|c| c.storage))
+        .unwrap_or_default();
+    let mut store = Store::default().with_storage(storage);
+    if cache_history {
+        store = store.with_history_cache();
+    }
+
+    // Checked before any work so a shared read-only store never ends up half-written.
+    // Only commands that write pay for the probe.
+    let writes = cli.command.history_writes();
+    let probe_failed = || !matches!(writes, HistoryWrites::None) && !store.is_writable();
+    if cli.read_only || probe_failed() {
+        match writes {
+            HistoryWrites::Required(command) => {
+                eprintln!(
+                    "❌ dit {} records history, but {} is read-only",
+                    command,
+                    store.dir().display()
+                );
+                return Ok(ExitCode::from(2));
+            }
+            HistoryWrites::Optional => eprintln!(
+                "ℹ️  {} is read-only; nothing will be saved to history",
+                store.dir().display()
+            ),
+            HistoryWrites::None => {}
+        }
+        store = store.read_only();
+    }
+
+    if cli.command.uses_remote() {
+        store = store.with_remote(RemoteBackend::from_config(&DitConfig::load()?)?);
+    }
+
+    if cli.command.uses_exclusions() {
+        let exclusions =
+            SnapshotExclusions::from_config(&DitConfig::load()?, &cli.exclude_author, &cli.exclude_message)?;
+        store = store.with_exclusions(exclusions);
+        // A broken history file is reported by the command itself
+        if let Ok(excluded) = store.excluded_count() {
+            if excluded > 0 {
+                eprintln!("Excluded {} snapshot(s) by author or commit message", excluded);
+            }
+        }
+    }
 
     match cli.command {
-        Commands::Analyze { image, format } => {
-            analyze_image(&image, format).await?;
+        Commands::Analyze {
+            image,
+            iidfile,
+            as_name,
+            format,
+            warn_large_single_layer,
+            show_as_dockerfile,
+            measure_extract,
+            total_only,
+            compare_to_previous,
+            platform,
+            auto_track,
+            no_git,
+            deep,
+            layer_size_unit,
+            sort_by_command,
+            sort_by_size,
+            layer_label,
+        } => {
+            if total_only && matches!(format, OutputFormat::Json) {
+                anyhow::bail!("--total-only cannot be combined with --format json");
+            }
+            // Before any inspection, like the other flag checks
+            format.ensure_not_csv("analyze")?;
+            let warn_large_layer = warn_large_single_layer
+                .as_deref()
+                .map(parse_size)
+                .transpose()?;
+            let options = AnalyzeOptions {
+                format,
+                warn_large_layer,
+                show_as_dockerfile,
+                measure_extract,
+                total_only,
+                compare_to_previous,
+                platform,
+                auto_track,
+                no_git,
+                deep: if deep {
+                    Some(path_budgets(&DitConfig::load()?.path_budgets)?)
+                } else {
+                    None
+                },
+                layer_size_unit,
+                layer_order: if sort_by_command {
+                    LayerOrder::Command
+                } else if sort_by_size {
+                    LayerOrder::Size
+                } else {
+                    LayerOrder::Chronological
+                },
+                layer_labels: layer_label.as_deref().map(read_layer_labels).transpose()?,
+                output,
+            };
+            let spec = ImageSpec::from_args(image, iidfile.as_deref(), as_name)?;
+            analyze_image(out, &docker(cli.no_cache, &store)?, &store, &spec, options).await?;
         }
-        Commands::AnalyzeAll { filter, format } => {
-            analyze_all_images(filter.as_deref(), format).await?;
+        Commands::AnalyzeAll {
+            filter,
+            tag_regex,
+            label_filter,
+            format,
+            progress,
+            group_by,
+            columns,
+            wide,
+            warn_root_user,
+            include_image_id,
+        } => {
+            let image_filter = image_filter(filter, tag_regex, label_filter)?;
+            let options = AnalyzeAllOptions {
+                format,
+                group_by,
+                columns,
+                wide,
+                warn_root_user,
+                include_image_id,
+                output,
+            };
+            let docker = docker(cli.no_cache, &store)?;
+            let reporter = reporter_for(progress);
+            analyze_all_images(out, &docker, &image_filter, reporter, &options).await?;
         }
-        Commands::Track { image } => {
-            track_image(&image).await?;
+        Commands::Top {
+            filter,
+            tag_regex,
+            label_filter,
+            limit,
+            format,
+        } => {
+            let image_filter = image_filter(filter, tag_regex, label_filter)?;
+            let options = TopOptions { limit, format, output };
+            show_top(out, &docker(cli.no_cache, &store)?, &image_filter, &options).await?;
+        }
+        Commands::Track {
+            image,
+            iidfile,
+            as_name,
+            measure_extract,
+            annotation,
+            annotation_file,
+            multi_platform,
+            build_metadata,
+            remote: _,
+        } => {
+            // Explicit --annotation flags win over the file
+            let mut annotations = match annotation_file {
+                Some(path) => read_annotation_file(&path)?,
+                None => Default::default(),
+            };
+            annotations.extend(annotation);
+
+            if multi_platform {
+                let Some(reference) = image else {
+                    anyhow::bail!("--multi-platform needs an image reference");
+                };
+                track_index(out, &store, &reference, annotations).await?;
+            } else {
+                let spec = ImageSpec::from_args(image, iidfile.as_deref(), as_name)?;
+                let build_metadata = build_metadata.as_deref().map(read_build_metadata).transpose()?;
+                track_image(
+                    out,
+                    &docker(cli.no_cache, &store)?,
+                    &store,
+                    &spec,
+                    measure_extract,
+                    annotations,
+                    build_metadata.as_ref(),
+                )
+                .await?;
+            }
+        }
+        Commands::TrackAll {
+            filter,
+            tag_regex,
+            label_filter,
+            progress,
+            summary_after_track_all,
+            include_image_id,
+        } => {
+            let image_filter = image_filter(filter, tag_regex, label_filter)?;
+            let options = TrackAllOptions {
+                summary_after: summary_after_track_all,
+                include_image_id,
+                output,
+            };
+            track_all_images(
+                out,
+                &docker(cli.no_cache, &store)?,
+                &store,
+                &image_filter,
+                reporter_for(progress),
+                &options,
+            )
+            .await?;
+        }
+        Commands::Daemon {
+            interval,
+            filter,
+            tag_regex,
+            label_filter,
+            on_change,
+            watch_budget,
+            alert_cmd,
+            alert_cooldown,
+        } => {
+            let image_filter = image_filter(filter, tag_regex, label_filter)?;
+            let options = DaemonOptions {
+                interval,
+                on_change,
+                watch_budget: watch_budget.as_deref().map(parse_size).transpose()?,
+                alert_cmd,
+                alert_cooldown,
+            };
+            run_daemon(out, &docker(cli.no_cache, &store)?, &store, &image_filter, &options).await?;
         }
-        Commands::TrackAll { filter } => {
-            track_all_images(filter.as_deref()).await?;
+        Commands::Ingest {
+            inspect,
+            history,
+            image,
+            commit,
+            branch,
+        } => {
+            let image = image.as_deref();
+            ingest_snapshot(out, &store, &inspect, &history, image, commit, branch).await?;
         }
         Commands::Diff {
             image,
             commit_a,
             commit_b,
+            left,
+            right,
             base,
+            explain_baseline,
+            exclude_bots_from_baseline: _,
+            show_env_values,
+            layer_order_matters,
+            diff_algorithm,
+            strict_digest_match: _,
+            match_by_command,
+            max_changes,
+            total_only,
+            ignore_below_threshold,
+            ignore_below_pct,
+            verbose_if_below_threshold,
+            word_diff,
+            save_diff_to_history,
+            show_unchanged_summary: _,
+            show_unchanged,
+            hide_unchanged,
+            layer_size_unit,
+            basis,
+            emit_exit_code_for_increase,
+            fail_threshold,
+            compare_with_parent_commit,
+            against_k8s,
+            namespace,
+            against_k8s_context,
+            container,
+            k8s_manifest,
+            remote: _,
         } => {
-            diff_images(&image, commit_a, commit_b, base).await?;
+            let against_k8s = against_k8s.map(|resource| K8sTarget {
+                resource,
+                namespace,
+                context: against_k8s_context,
+                container,
+                manifest: k8s_manifest,
+            });
+            let options = DiffOptions {
+                base_branch: base,
+                explain_baseline,
+                show_env_values,
+                layer_order_matters,
+                algorithm: diff_algorithm,
+                match_by_command,
+                max_changes,
+                total_only,
+                compare_with_parent_commit,
+                against_k8s,
+                ignore_below: ignore_below_threshold.as_deref().map(parse_size).transpose()?,
+                ignore_below_pct,
+                verbose_if_below_threshold,
+                word_diff,
+                save_to_history: save_diff_to_history,
+                layer_size_unit,
+                basis,
+                unchanged_layers: if show_unchanged {
+                    UnchangedLayers::Show
+                } else if hide_unchanged {
+                    UnchangedLayers::Hide
+                } else {
+                    UnchangedLayers::Summary
+                },
+                left_file: left,
+                right_file: right,
+                output,
+            };
+            if total_only {
+                // Captured by scripts
+                out.strip_color();
+            }
+            let fail_threshold = fail_threshold.as_deref().map(parse_size).transpose()?;
+            let image = image.as_deref();
+            let result = diff_images(out, &store, image, commit_a, commit_b, options).await;
+            if emit_exit_code_for_increase {
+                // 1 and 2 carry meaning here, so errors exit 3
+                match result {
+                    Ok(delta) => return Ok(ExitCode::from(exit_code_for_delta(delta, fail_threshold))),
+                    Err(e) => {
+                        eprintln!("Error: {:?}", e);
+                        return Ok(ExitCode::from(3));
+                    }
+                }
+            }
+            result?;
         }
-        Commands::History { image, last } => {
-            show_history(&image, last).await?;
+        Commands::History {
+            image,
+            last,
+            compare_branches,
+            export_gnuplot,
+            export_gnuplot_script,
+            platforms,
+            show_diffs,
+            group_by_branch,
+            show_id,
+            metric,
+            remote: _,
+        } => {
+            if let Some(branches) = compare_branches {
+                show_branch_comparison(out, &store, &image, &branches, last, output).await?;
+            } else {
+                let options = HistoryOptions {
+                    last,
+                    platforms,
+                    group_by_branch,
+                    show_id,
+                    metric,
+                    output,
+                };
+                show_history(out, &store, &image, &options).await?;
+            }
+
+            if show_diffs {
+                show_diff_history(out, &store, &image, last, output)?;
+            }
+
+            if let Some(data_path) = export_gnuplot {
+                let snapshots = load_image_history(&store, &image, last)?;
+                write_gnuplot_data(&data_path, &image, &snapshots)?;
+                writeln!(out, "\n📈 Wrote {} data points to {}", snapshots.len(), data_path)?;
+
+                if let Some(script_path) = export_gnuplot_script {
+                    write_gnuplot_script(&script_path, &data_path, &image)?;
+                    writeln!(out, "   Plot with: gnuplot -p {}", script_path)?;
+                }
+            }
+        }
+        Commands::Chart {
+            image,
+            all,
+            last,
+            by_branch,
+            ascii,
+            metric,
+        } => {
+            let style = SparklineStyle::select(ascii, plain);
+            match image {
+                Some(img) if !all => {
+                    if by_branch {
+                        show_chart_by_branch(out, &store, &img, last, style).await?;
+                    } else {
+                        show_chart(out, &store, &img, last, style, metric).await?;
+                    }
+                }
+                _ if by_branch => anyhow::bail!("--by-branch needs an image name"),
+                _ => show_chart_all(out, &store, last, style, metric).await?,
+            }
+        }
+        Commands::Bisect {
+            image,
+            good,
+            bad,
+            rebuild,
+            threshold,
+        } => {
+            let options = BisectOptions {
+                good,
+                bad,
+                rebuild,
+                threshold: threshold.as_deref().map(parse_size).transpose()?,
+                output,
+            };
+            bisect(out, &store, &image, &options).await?;
+        }
+        Commands::ChangelogEntry {
+            image,
+            since,
+            format,
+            top,
+            github_release,
+        } => {
+            let options = ChangelogOptions {
+                since,
+                format,
+                top,
+                github_release,
+                output,
+            };
+            changelog_entry(out, &store, &image, &options).await?;
+        }
+        Commands::Reproducibility {
+            image,
+            runs,
+            commit,
+            format,
+        } => {
+            let options = ReproducibilityOptions {
+                runs,
+                commit,
+                format,
+                output,
+            };
+            check_reproducibility(out, &store, &image, &options).await?;
+        }
+        Commands::Grep {
+            pattern,
+            regex,
+            case_sensitive,
+            all,
+            latest_only: _,
+            filter,
+            format,
+        } => {
+            let options = GrepOptions {
+                regex,
+                case_sensitive,
+                all,
+                filter,
+                format,
+                output,
+            };
+            // Like grep(1): exit 1 when nothing matched
+            if !grep_history(out, &store, &pattern, &options).await? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Commands::RebaseHistory {
+            old_sha,
+            new_sha,
+            mapping_file,
+        } => {
+            let mappings = match (old_sha, new_sha, mapping_file) {
+                (Some(old), Some(new), _) => vec![(validate_sha(&old)?, validate_sha(&new)?)],
+                (_, _, Some(path)) => read_mapping_file(&path)?,
+                _ => anyhow::bail!("Provide --old-sha/--new-sha or --mapping-file"),
+            };
+            rebase_history(out, &store, &mappings).await?;
+        }
+        Commands::MigrateRefs { dry_run } => {
+            migrate_refs(out, &store, dry_run).await?;
         }
-        Commands::Chart { image, all, last } => {
-            if all {
-                show_chart_all(last).await?;
-            } else if let Some(img) = image {
-                show_chart(&img, last).await?;
+        Commands::MergeHistory { ours, theirs, .. } => {
+            let strategy = if ours {
+                MergeStrategy::Ours
+            } else if theirs {
+                MergeStrategy::Theirs
             } else {
-                anyhow::bail!("Must provide either an image name or --all flag");
+                MergeStrategy::Union
+            };
+            merge_history(out, &store, strategy).await?;
+        }
+        Commands::Prune {
+            keep_last,
+            older_than,
+            image,
+            keep_branches,
+            before,
+            dry_run,
+        } => {
+            if let Some(date) = before {
+                prune_before(out, &store, date, dry_run)?;
+            } else {
+                let options = PruneOptions {
+                    keep_last,
+                    older_than,
+                    image,
+                    keep_branches,
+                    dry_run,
+                    output,
+                };
+                prune_history(out, &store, &options).await?;
+            }
+        }
+        Commands::SizeEstimate { image, platform } => {
+            size_estimate(out, &image, platform.as_deref()).await?;
+        }
+        Commands::SelfCheck => {
+            if !self_check(out, &store)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Commands::Completions { shell } => print_completions(out, shell, &Cli::command())?,
+        Commands::Complete {
+            kind: CompletionKind::Images,
+            prefix,
+        } => {
+            for name in complete_images(&store, &prefix) {
+                writeln!(out, "{}", name)?;
+            }
+        }
+        Commands::Schema { kind, validate } => {
+            if !run_schema(out, kind, validate.as_deref())? {
+                return Ok(ExitCode::from(1));
             }
         }
         Commands::Compose(compose_cmd) => match compose_cmd {
-            ComposeCommands::Analyze { file } => {
-                compose_analyze(file.as_deref()).await?;
+            ComposeCommands::Analyze {
+                file,
+                project_name,
+                show_dockerfile_path,
+            } => {
+                compose_analyze(
+                    out,
+                    &docker(cli.no_cache, &store)?,
+                    file.as_deref(),
+                    project_name.as_deref(),
+                    show_dockerfile_path,
+                    output,
+                )
+                .await?;
+            }
+            ComposeCommands::Track { file, project_name } => {
+                let docker = docker(cli.no_cache, &store)?;
+                let (file, project_name) = (file.as_deref(), project_name.as_deref());
+                compose_track(out, &docker, &store, file, project_name).await?;
+            }
+            ComposeCommands::History {
+                file,
+                project_name,
+                show_dockerfile_path,
+            } => {
+                compose_history(
+                    out,
+                    &store,
+                    file.as_deref(),
+                    project_name.as_deref(),
+                    show_dockerfile_path,
+                    output,
+                )
+                .await?;
             }
-            ComposeCommands::Track { file } => {
-                compose_track(file.as_deref()).await?;
+        },
+        Commands::Budgets(BudgetsCommands::Resolve { image, warn_at }) => {
+            let budgets = image_budgets(&DitConfig::load()?, profile.as_deref(), warn_at)?;
+            print_resolution(out, &budgets, &image)?;
+        }
+        Commands::WatchList(watch_cmd) => match watch_cmd {
+            WatchListCommands::Add { image, budget } => {
+                add_watched(out, &store, &image, budget.as_deref().map(parse_size).transpose()?)?;
             }
-            ComposeCommands::History { file } => {
-                compose_history(file.as_deref()).await?;
+            WatchListCommands::Remove { image } => remove_watched(out, &store, &image)?,
+            WatchListCommands::List => list_watched(out, &store)?,
+            WatchListCommands::Check {
+                remote,
+                platform,
+                tolerance,
+                fail_on_digest_change,
+                format,
+            } => {
+                let options = WatchCheckOptions {
+                    remote,
+                    platform,
+                    tolerance,
+                    fail_on_digest_change,
+                    format,
+                    output,
+                };
+                if check_watched(out, &store, &options).await? {
+                    return Ok(ExitCode::from(1));
+                }
             }
         },
-        Commands::Summary => {
-            show_summary().await?;
+        Commands::Summary {
+            group_by,
+            group_by_image,
+            columns,
+            wide,
+            platforms,
+            ascii,
+            format,
+            csv_delimiter,
+            metric,
+        } => {
+            let options = SummaryOptions {
+                group_by,
+                columns,
+                wide,
+                group_by_image,
+                platforms,
+                style: SparklineStyle::select(ascii, plain),
+                format,
+                csv_delimiter,
+                metric,
+                output,
+            };
+            show_summary(out, &store, options).await?;
         }
         Commands::Ci {
-            images,
+            mut images,
+            image,
+            iidfile,
+            as_name,
             filter,
+            tag_regex,
             compose,
+            project_name,
             budget,
             budget_increase,
+            warn_at,
+            strict_warnings,
             github_comment,
+            comment_mode,
             base,
             format,
             fail_on_increase,
+            fail_if_base_image_changed,
+            warn_large_single_layer,
+            fail_on_large_layer,
+            explain_baseline,
+            exclude_bots_from_baseline: _,
+            basis,
+            max_changes,
+            baseline_origin,
+            dry_run,
+            show_env_values,
+            paths,
+            report_template,
+            print_template_context,
+            output_path,
+            output_append,
+            output_header,
+            multi_platform,
+            platforms,
+            build_metadata,
+            report_link,
         } => {
-            // Determine which images to track
-            let target_images = if !images.is_empty() {
-                images
-            } else if let Some(filter_str) = filter {
-                // Get all images matching filter
-                use docker::DockerClient;
-                let docker = DockerClient::new()?;
-                docker.list_all_images(Some(&filter_str)).await?
-            } else if let Some(compose_file) = compose {
-                // Read from compose file
-                use compose::parse_compose_file;
-                parse_compose_file(Some(&compose_file))?
-            } else {
-                anyhow::bail!("Must provide images, --filter, or --compose");
-            };
+            // Compile the template first so mistakes fail before any Docker or GitHub calls
+            let report_template = report_template
+                .as_deref()
+                .map(ReportTemplate::load)
+                .transpose()?;
+            let dit_config = DitConfig::load()?;
+            let cost = dit_config.cost.clone();
+
+            images.extend(image);
+            let target_images = ImageSources {
+                images,
+                iidfile,
+                as_name,
+                filter,
+                tag_regex,
+                compose,
+                project_name,
+            }
+            .resolve()
+            .await?;
 
             let budget_bytes = if let Some(b) = budget {
                 Some(parse_size(&b)?)
@@ -275,10 +1968,18 @@ async fn main() -> Result<()> {
                 None
             };
 
+            let image_budgets = image_budgets(&dit_config, profile.as_deref(), warn_at)?;
+
+            let warn_large_layer_bytes = warn_large_single_layer
+                .as_deref()
+                .map(parse_size)
+                .transpose()?;
+
             let output_format = match format {
                 CiFormat::Table => CiOutputFormat::Table,
                 CiFormat::Json => CiOutputFormat::Json,
                 CiFormat::Markdown => CiOutputFormat::Markdown,
+                CiFormat::MarkdownSummary => CiOutputFormat::MarkdownSummary,
             };
 
             // Auto-detect format: if --github-comment is used, default to markdown
@@ -295,12 +1996,47 @@ async fn main() -> Result<()> {
                 github_comment,
                 base_branch: base,
                 fail_on_increase,
+                fail_if_base_image_changed,
+                warn_large_layer_bytes,
+                fail_on_large_layer,
+                explain_baseline,
+                warn_at_percent: warn_at.unwrap_or(DEFAULT_WARN_AT),
+                strict_warnings,
+                show_env_values,
+                paths,
+                trigger_paths: trigger_paths(&dit_config)?,
                 format: final_format,
+                report_template,
+                print_template_context,
+                cost,
+                output_path,
+                output_append,
+                output_header,
+                comment_mode,
+                multi_platform,
+                platforms,
+                image_budgets,
+                github_actions: false,
+                path_budgets: path_budgets(&dit_config.path_budgets)?,
+                basis,
+                max_changes,
+                baseline_origin,
+                dry_run,
+                build_metadata: build_metadata.as_deref().map(read_build_metadata).transpose()?,
+                report_link,
+                output,
             };
 
-            run_ci(config).await?;
+            if run_ci(out, &docker(cli.no_cache, &store)?, &store, config).await? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Commands::Gha => {
+            if run_gha(out, &docker(cli.no_cache, &store)?, &store, profile, output).await? {
+                return Ok(ExitCode::from(1));
+            }
         }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,290 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+
+use crate::models::ImageSnapshot;
+use crate::storage::StorageKind;
+use crate::store::Store;
+
+const OURS_MARKER: &str = "<<<<<<<";
+const BASE_MARKER: &str = "|||||||";
+const SEPARATOR: &str = "=======";
+const THEIRS_MARKER: &str = ">>>>>>>";
+
+/// How `dit merge-history` resolves a conflicted history.json
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    /// Keep the side between `<<<<<<<` and `=======`
+    Ours,
+    /// Keep the side between `=======` and `>>>>>>>`
+    Theirs,
+    /// Keep snapshots from both sides, dropping duplicates
+    Union,
+}
+
+/// 1-based line of the first conflict marker, if any
+pub fn find_conflict_marker(content: &str) -> Option<usize> {
+    content
+        .lines()
+        .position(|line| {
+            line.starts_with(OURS_MARKER)
+                || line.starts_with(SEPARATOR)
+                || line.starts_with(THEIRS_MARKER)
+        })
+        .map(|i| i + 1)
+}
+
+/// Rebuild both versions of a conflicted file: shared lines go to both,
+/// conflict hunks to their side (diff3 base sections are dropped)
+pub fn split_conflict(content: &str) -> Result<(String, String)> {
+    #[derive(PartialEq)]
+    enum Section {
+        Both,
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let mut ours = String::new();
+    let mut theirs = String::new();
+    let mut section = Section::Both;
+
+    for (i, line) in content.lines().enumerate() {
+        let unexpected = || format!("Unexpected conflict marker on line {}: {}", i + 1, line);
+
+        if line.starts_with(OURS_MARKER) {
+            if section != Section::Both {
+                bail!(unexpected());
+            }
+            section = Section::Ours;
+        } else if line.starts_with(BASE_MARKER) && section == Section::Ours {
+            section = Section::Base;
+        } else if line.starts_with(SEPARATOR) && section != Section::Both {
+            if section == Section::Theirs {
+                bail!(unexpected());
+            }
+            section = Section::Theirs;
+        } else if line.starts_with(THEIRS_MARKER) {
+            if section != Section::Theirs {
+                bail!(unexpected());
+            }
+            section = Section::Both;
+        } else {
+            if matches!(section, Section::Both | Section::Ours) {
+                ours.push_str(line);
+                ours.push('\n');
+            }
+            if matches!(section, Section::Both | Section::Theirs) {
+                theirs.push_str(line);
+                theirs.push('\n');
+            }
+        }
+    }
+
+    if section != Section::Both {
+        bail!("Conflict starting with {} is never closed", OURS_MARKER);
+    }
+
+    Ok((ours, theirs))
+}
+
+/// Both sides' snapshots in timestamp order; a snapshot recorded on both sides
+/// (same image, tag, platform, commit and timestamp) is kept once
+pub fn union(ours: Vec<ImageSnapshot>, theirs: Vec<ImageSnapshot>) -> Vec<ImageSnapshot> {
+    let mut seen = HashSet::new();
+    let mut merged: Vec<ImageSnapshot> = ours
+        .into_iter()
+        .chain(theirs)
+        .filter(|s| {
+            seen.insert((
+                s.image.clone(),
+                s.tag.clone(),
+                s.platform(),
+                s.commit_sha.clone(),
+                s.timestamp,
+            ))
+        })
+        .collect();
+
+    merged.sort_by_key(|s| s.timestamp);
+    merged
+}
+
+/// `dit merge-history`: resolve conflict markers in history.json
+pub async fn merge_history(
+    out: &mut impl Write,
+    store: &Store,
+    strategy: MergeStrategy,
+) -> Result<()> {
+    if store.storage() == StorageKind::Sqlite {
+        bail!("merge-history resolves conflicts in history.json; --storage sqlite keeps history in history.db instead");
+    }
+    let path = store.history_path();
+    let content =
+        fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+
+    if find_conflict_marker(&content).is_none() {
+        writeln!(out, "✅ {} has no merge conflicts", path.display())?;
+        return Ok(());
+    }
+
+    let (ours, theirs) = split_conflict(&content)?;
+    let parse = |side: &str, json: &str| -> Result<Vec<ImageSnapshot>> {
+        serde_json::from_str(json).context(format!(
+            "The {} side of the conflict is not valid history JSON",
+            side
+        ))
+    };
+    let (ours, theirs) = (parse("ours", &ours)?, parse("theirs", &theirs)?);
+    let (ours_count, theirs_count) = (ours.len(), theirs.len());
+
+    let merged = match strategy {
+        MergeStrategy::Ours => ours,
+        MergeStrategy::Theirs => theirs,
+        MergeStrategy::Union => union(ours, theirs),
+    };
+
+    let backup = store.backup_history()?;
+    store.save_history(&merged)?;
+
+    writeln!(
+        out,
+        "✅ Resolved {} with --{} (ours: {}, theirs: {}, result: {} snapshot(s)); \
+         conflicted file saved to {}",
+        path.display(),
+        format!("{:?}", strategy).to_lowercase(),
+        ours_count,
+        theirs_count,
+        merged.len(),
+        backup.display()
+    )?;
+    writeln!(out, "Run 'git add {}' to mark it resolved", path.display())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/merge").join(name);
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn commits(history: &[ImageSnapshot]) -> Vec<&str> {
+        history.iter().map(|s| s.commit_sha.as_str()).collect()
+    }
+
+    /// A store whose history.json is the fixture `name`
+    fn store_with(name: &str) -> (TempDir, Store) {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        fs::create_dir_all(dir.path().join(".dit")).unwrap();
+        fs::write(store.history_path(), fixture(name)).unwrap();
+        (dir, store)
+    }
+
+    #[test]
+    fn conflict_markers_are_found_by_line() {
+        assert_eq!(find_conflict_marker(&fixture("conflicted.json")), Some(37));
+        assert_eq!(find_conflict_marker("[]\n"), None);
+        // Markers only count at the start of a line
+        assert_eq!(find_conflict_marker("[{\"commit_message\": \"fix <<<<<<< parsing\"}]"), None);
+    }
+
+    #[test]
+    fn both_sides_of_a_conflict_parse() {
+        let (ours, theirs) = split_conflict(&fixture("conflicted.json")).unwrap();
+        let ours: Vec<ImageSnapshot> = serde_json::from_str(&ours).unwrap();
+        let theirs: Vec<ImageSnapshot> = serde_json::from_str(&theirs).unwrap();
+        assert_eq!(commits(&ours), vec!["aaaa111", "bbbb222"]);
+        assert_eq!(commits(&theirs), vec!["aaaa111", "cccc333"]);
+    }
+
+    #[test]
+    fn diff3_base_sections_are_dropped() {
+        let (ours, theirs) = split_conflict(&fixture("conflicted-diff3.json")).unwrap();
+        let ours: Vec<ImageSnapshot> = serde_json::from_str(&ours).unwrap();
+        let theirs: Vec<ImageSnapshot> = serde_json::from_str(&theirs).unwrap();
+        assert_eq!(commits(&ours), vec!["aaaa111", "bbbb222"]);
+        assert_eq!(commits(&theirs), vec!["aaaa111", "bbbb222", "cccc333"]);
+    }
+
+    #[test]
+    fn malformed_conflicts_are_rejected() {
+        assert!(split_conflict("<<<<<<< HEAD\n[]\n=======\n[]\n").is_err());
+        assert!(split_conflict("[]\n>>>>>>> feature\n").is_err());
+        assert!(split_conflict("<<<<<<< HEAD\n<<<<<<< HEAD\n").is_err());
+        assert!(split_conflict("<<<<<<< a\n=======\n=======\n>>>>>>> b\n").is_err());
+    }
+
+    #[test]
+    fn union_keeps_each_snapshot_once_in_time_order() {
+        let a = tracked("api", "v1", "a", "main", 0, vec![layer("sha256:1", 1, "RUN a")]);
+        let b = tracked("api", "v1", "b", "main", 10, vec![layer("sha256:2", 2, "RUN b")]);
+        let c = tracked("api", "v1", "c", "feature", 5, vec![layer("sha256:3", 3, "RUN c")]);
+        // Same commit tracked again later is a separate snapshot
+        let b_again = tracked("api", "v1", "b", "main", 11, vec![layer("sha256:2", 2, "RUN b")]);
+
+        let merged = union(
+            vec![a.clone(), b.clone()],
+            vec![a, c, b, b_again],
+        );
+        assert_eq!(commits(&merged), vec!["a", "c", "b", "b"]);
+    }
+
+    #[test]
+    fn union_tells_platforms_apart() {
+        let amd64 = tracked("api", "v1", "a", "main", 0, Vec::new());
+        let mut arm64 = amd64.clone();
+        arm64.arch = "arm64".to_string();
+        assert_eq!(union(vec![amd64], vec![arm64]).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn merge_history_resolves_each_strategy() {
+        for (strategy, expected) in [
+            (MergeStrategy::Ours, vec!["aaaa111", "bbbb222"]),
+            (MergeStrategy::Theirs, vec!["aaaa111", "bbbb222", "cccc333"]),
+            (MergeStrategy::Union, vec!["aaaa111", "bbbb222", "cccc333"]),
+        ] {
+            let (dir, store) = store_with("conflicted-diff3.json");
+            merge_history(&mut Vec::new(), &store, strategy).await.unwrap();
+
+            assert_eq!(commits(&store.load_history().unwrap()), expected, "{:?}", strategy);
+            let backups = fs::read_dir(dir.path().join(".dit"))
+                .unwrap()
+                .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().contains(".bak-"))
+                .count();
+            assert_eq!(backups, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn union_merges_a_two_way_conflict() {
+        let (_dir, store) = store_with("conflicted.json");
+        merge_history(&mut Vec::new(), &store, MergeStrategy::Union).await.unwrap();
+        assert_eq!(commits(&store.load_history().unwrap()), vec!["aaaa111", "bbbb222", "cccc333"]);
+    }
+
+    #[test]
+    fn conflicted_history_points_at_merge_history() {
+        let (_dir, store) = store_with("conflicted.json");
+        let error = format!("{:#}", store.load_history().unwrap_err());
+        assert!(error.contains("merge conflict markers (line 37)"), "{}", error);
+        assert!(error.contains("dit merge-history --union"), "{}", error);
+    }
+
+    #[test]
+    fn lfs_pointer_history_points_at_git_lfs_pull() {
+        let (_dir, store) = store_with("lfs-pointer.json");
+        let error = format!("{:#}", store.load_history().unwrap_err());
+        assert!(error.contains("Git LFS pointer"), "{}", error);
+        assert!(error.contains("git lfs pull"), "{}", error);
+    }
+}
@@ -0,0 +1,93 @@
+use clap::ValueEnum;
+use colored::Colorize;
+
+use crate::format::{format_size, format_size_delta};
+use crate::models::ImageSnapshot;
+
+/// `--metric`: what history, chart and summary plot
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Metric {
+    /// Image size
+    #[default]
+    Size,
+    /// Build time recorded with `--build-metadata`
+    BuildTime,
+}
+
+impl Metric {
+    /// This metric for `snapshot`; `None` when it wasn't recorded. Sizes of registry-only
+    /// snapshots are their registry sizes.
+    pub fn value(self, snapshot: &ImageSnapshot) -> Option<u64> {
+        match self {
+            Metric::Size => Some(snapshot.size()),
+            Metric::BuildTime => snapshot.build_duration_ms,
+        }
+    }
+
+    /// Snapshots that recorded this metric
+    pub fn recorded(self, snapshots: &[ImageSnapshot]) -> Vec<ImageSnapshot> {
+        snapshots
+            .iter()
+            .filter(|s| self.value(s).is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// Column heading
+    pub fn label(self) -> &'static str {
+        match self {
+            Metric::Size => "Size",
+            Metric::BuildTime => "Build Time",
+        }
+    }
+
+    pub fn format(self, value: u64) -> String {
+        match self {
+            Metric::Size => format_size(value),
+            Metric::BuildTime => format_duration(value),
+        }
+    }
+
+    /// Too small a change to call a trend: under 10 KiB, or a second of build time
+    pub fn is_stable(self, delta: i64) -> bool {
+        match self {
+            Metric::Size => delta.abs() < 10 * 1024,
+            Metric::BuildTime => delta.abs() < 1000,
+        }
+    }
+
+    /// Colored like size deltas: growth red, savings green
+    pub fn format_delta(self, delta: i64, plain: bool) -> String {
+        match self {
+            Metric::Size => format_size_delta(delta, plain),
+            Metric::BuildTime => format_duration_delta(delta, plain),
+        }
+    }
+}
+
+/// `850 ms`, `48.2 s` or `3m 05s`
+pub fn format_duration(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{} ms", ms)
+    } else if ms < 60_000 {
+        format!("{:.1} s", ms as f64 / 1000.0)
+    } else {
+        let seconds = ms / 1000;
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+/// `+48.2 s` / `-48.2 s`; spelled out with `--plain`
+pub fn format_duration_delta(delta_ms: i64, plain: bool) -> String {
+    let duration = format_duration(delta_ms.unsigned_abs());
+    if delta_ms == 0 {
+        "unchanged".to_string()
+    } else if plain {
+        let direction = if delta_ms > 0 { "slower" } else { "faster" };
+        format!("{} {}", duration, direction)
+    } else if delta_ms > 0 {
+        format!("+{}", duration).red().to_string()
+    } else {
+        format!("-{}", duration).green().to_string()
+    }
+}
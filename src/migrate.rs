@@ -0,0 +1,151 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::reference::ImageRef;
+use crate::store::Store;
+
+/// Rewrite every snapshot's image name and tag to canonical form, merging histories
+/// that were recorded under different spellings of the same reference
+pub async fn migrate_refs(out: &mut impl Write, store: &Store, dry_run: bool) -> Result<()> {
+    let mut history = store.load_history()?;
+
+    if history.is_empty() {
+        bail!("No history found. Run 'dit track' first.");
+    }
+
+    // canonical name:tag -> (old name:tag -> snapshot count)
+    let mut renames: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    let mut updated = 0;
+
+    for snapshot in &mut history {
+        let old = format!(
+            "{}:{}",
+            snapshot.image,
+            snapshot.tag.as_deref().unwrap_or("latest")
+        );
+        let reference = ImageRef::parse_lenient(&old);
+        let (image, tag) = (reference.name(), reference.tag_or_latest().to_string());
+        let new = format!("{}:{}", image, tag);
+
+        *renames.entry(new.clone()).or_default().entry(old.clone()).or_default() += 1;
+
+        if old != new || snapshot.tag.is_none() {
+            if !dry_run {
+                snapshot.image = image;
+                snapshot.tag = Some(tag);
+            }
+            updated += 1;
+        }
+    }
+
+    let mut changed = false;
+    for (new, sources) in &renames {
+        if sources.len() == 1 && sources.contains_key(new) {
+            continue;
+        }
+        changed = true;
+
+        let verb = if sources.len() > 1 { "merge" } else { "rename" };
+        writeln!(out, "{} → {}", verb, new)?;
+        for (old, count) in sources {
+            writeln!(out, "    {} ({} snapshot(s))", old, count)?;
+        }
+    }
+
+    if !changed && updated == 0 {
+        writeln!(out, "✅ All {} snapshot(s) already use canonical references", history.len())?;
+        return Ok(());
+    }
+
+    if dry_run {
+        writeln!(out, "\nDry run: {} snapshot(s) would be rewritten", updated)?;
+        return Ok(());
+    }
+
+    let backup = store.backup_history()?;
+    store.save_history(&history)?;
+
+    writeln!(
+        out,
+        "\n✅ Rewrote {} snapshot(s); previous history saved to {}",
+        updated,
+        backup.display()
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::tracked;
+    use tempfile::TempDir;
+
+    fn store_with(history: &[(&str, Option<&str>)]) -> (TempDir, Store) {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        let snapshots: Vec<_> = history
+            .iter()
+            .enumerate()
+            .map(|(i, (image, tag))| {
+                let mut snapshot = tracked(image, "latest", &format!("c{}", i), "main", i as i64, Vec::new());
+                snapshot.tag = tag.map(str::to_string);
+                snapshot
+            })
+            .collect();
+        store.save_history(&snapshots).unwrap();
+        (dir, store)
+    }
+
+    fn references(store: &Store) -> Vec<(String, Option<String>)> {
+        store
+            .load_history()
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.image, s.tag))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn spellings_merge_into_the_canonical_reference() {
+        let (dir, store) = store_with(&[
+            ("myapp", None),
+            ("docker.io/library/myapp", Some("latest")),
+            ("GHCR.io/org/app", Some("v1")),
+        ]);
+
+        migrate_refs(&mut Vec::new(), &store, false).await.unwrap();
+
+        assert_eq!(
+            references(&store),
+            vec![
+                ("myapp".to_string(), Some("latest".to_string())),
+                ("myapp".to_string(), Some("latest".to_string())),
+                ("ghcr.io/org/app".to_string(), Some("v1".to_string())),
+            ]
+        );
+        let backups = std::fs::read_dir(dir.path().join(".dit"))
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().contains(".bak-"))
+            .count();
+        assert_eq!(backups, 1);
+    }
+
+    #[tokio::test]
+    async fn dry_run_leaves_history_alone() {
+        let (_dir, store) = store_with(&[("myapp", None), ("docker.io/myapp", Some("latest"))]);
+        let before = references(&store);
+
+        migrate_refs(&mut Vec::new(), &store, true).await.unwrap();
+
+        assert_eq!(references(&store), before);
+    }
+
+    #[tokio::test]
+    async fn empty_history_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        assert!(migrate_refs(&mut Vec::new(), &store, false).await.is_err());
+    }
+}
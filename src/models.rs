@@ -1,12 +1,17 @@
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ImageSnapshot {
     // Identity
     pub image: String,
     pub tag: Option<String>,
     pub digest: Option<String>,
+    /// Local image ID (`sha256:…`); changes on every rebuild even when the tag doesn't
+    #[serde(default)]
+    pub image_id: Option<String>,
 
     // Git context
     pub commit_sha: String,
@@ -16,32 +21,358 @@ pub struct ImageSnapshot {
     pub timestamp: DateTime<Utc>,
 
     // Metrics
+    /// Uncompressed size; 0 for `registry_only` snapshots, which don't know it
     pub total_size: u64,
+    /// Registry (compressed) size, when known; local inspection only sees uncompressed sizes
+    #[serde(default)]
+    pub compressed_size: Option<u64>,
+    /// Sizes read from a registry manifest (`--multi-platform`, watched remotes): only
+    /// `compressed_size` is known, and layer sizes are compressed blob sizes
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub registry_only: bool,
     pub layer_count: usize,
     pub layers: Vec<LayerInfo>,
 
     // Metadata
     pub os: String,
     pub arch: String,
+    /// CPU variant (`v8` for `linux/arm64/v8`), when the platform has one
+    #[serde(default)]
+    pub variant: Option<String>,
+    /// Index digest shared by the per-platform snapshots of one multi-platform build
+    #[serde(default)]
+    pub group_id: Option<String>,
+    /// `os/arch` of the Docker host that inspected the image, when known
+    #[serde(default)]
+    pub host_platform: Option<String>,
+
+    /// `KEY=value` entries from the image config; `None` for snapshots recorded before env capture
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+
+    /// Image config labels (e.g. `org.opencontainers.image.version`)
+    #[serde(default)]
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Default container user from `USER` (empty means root); `None` for older snapshots
+    #[serde(default)]
+    pub user: Option<String>,
+
+    /// Free-form metadata from `dit track --annotation` / `--annotation-file`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+
+    /// `org.opencontainers.image.*` provenance (revision, source, created, ...) from the
+    /// manifest annotations, or from the config labels when only those are available
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub oci_annotations: BTreeMap<String, String>,
+
+    /// Total layer extraction time from `--measure-extract` (machine-dependent)
+    #[serde(default)]
+    pub extract_ms: Option<u64>,
+    /// Total files across layers from `--measure-extract`
+    #[serde(default)]
+    pub file_count: Option<u64>,
+
+    /// Observation of a third-party image from `dit watch-list check`, not a build of ours
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub watched: bool,
+
+    /// Built locally or pulled from a registry (best-effort; see `docker::detect_origin`)
+    #[serde(default)]
+    pub origin: ImageOrigin,
+
+    /// Build time from a buildx `--metadata-file` (`--build-metadata`), in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_duration_ms: Option<u64>,
+}
+
+/// Where the inspected image came from. Comparing a local build with a pulled copy of the
+/// same tag explains many surprising deltas (compression accounting, build args).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageOrigin {
+    Built,
+    Pulled,
+    /// Not detected, or recorded before dit did
+    #[default]
+    Unknown,
+}
+
+impl ImageOrigin {
+    /// One-letter marker for the history table
+    pub fn marker(self) -> &'static str {
+        match self {
+            ImageOrigin::Built => "B",
+            ImageOrigin::Pulled => "P",
+            ImageOrigin::Unknown => "?",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageOrigin::Built => "built",
+            ImageOrigin::Pulled => "pulled",
+            ImageOrigin::Unknown => "unknown",
+        }
+    }
+}
+
+impl ImageSnapshot {
+    /// Layers whose size exceeds `threshold` bytes
+    pub fn layers_larger_than(&self, threshold: u64) -> impl Iterator<Item = &LayerInfo> {
+        self.layers.iter().filter(move |l| l.size > threshold)
+    }
+
+    /// Value of image label `key`, if recorded
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels.as_ref()?.get(key).map(String::as_str)
+    }
+
+    /// Uncompressed size, unless only the registry size is known
+    pub fn uncompressed_size(&self) -> Option<u64> {
+        (!self.registry_only).then_some(self.total_size)
+    }
+
+    /// Size to show and budget: uncompressed, or the registry size when that's all there is
+    pub fn size(&self) -> u64 {
+        match self.uncompressed_size() {
+            Some(size) => size,
+            None => self.compressed_size.unwrap_or_default(),
+        }
+    }
+
+    /// `os/arch[/variant]`
+    pub fn platform(&self) -> String {
+        match &self.variant {
+            Some(variant) => format!("{}/{}/{}", self.os, self.arch, variant),
+            None => format!("{}/{}", self.os, self.arch),
+        }
+    }
+
+    /// Whether both snapshots have the same filesystem layers in the same order
+    /// (see [`LayerInfo::same_content`]; `docker history` only has IDs for some entries).
+    /// Empty layers (ENV, LABEL, ...) are ignored, so a metadata-only rebuild counts as the same.
+    pub fn same_layers(&self, other: &ImageSnapshot) -> bool {
+        let content = |s: &ImageSnapshot| -> Vec<LayerInfo> {
+            s.layers.iter().filter(|l| l.size > 0).cloned().collect()
+        };
+        let (ours, theirs) = (content(self), content(other));
+
+        ours.len() == theirs.len() && ours.iter().zip(&theirs).all(|(a, b)| a.same_content(b))
+    }
+
+    /// The base image as recorded: the `org.opencontainers.image.base.name` label
+    /// (plus `base.digest`) when set, otherwise the first non-empty layer
+    pub fn base_image(&self) -> Option<String> {
+        if let Some(name) = self.label(BASE_NAME_LABEL) {
+            return Some(match self.label(BASE_DIGEST_LABEL) {
+                Some(digest) => format!("{}@{}", name, digest),
+                None => name.to_string(),
+            });
+        }
+
+        self.base_layer().map(|layer| layer.command.clone())
+    }
+
+    /// Whether `newer` was built on a different base image. Compares the base labels
+    /// when both snapshots have them, the first non-empty layer otherwise.
+    pub fn base_image_changed(&self, newer: &ImageSnapshot) -> bool {
+        if self.label(BASE_NAME_LABEL).is_some() && newer.label(BASE_NAME_LABEL).is_some() {
+            return self.base_image() != newer.base_image();
+        }
+
+        match (self.base_layer(), newer.base_layer()) {
+            (Some(a), Some(b)) => !a.same_content(b),
+            _ => false,
+        }
+    }
+
+    fn base_layer(&self) -> Option<&LayerInfo> {
+        self.layers.iter().find(|l| l.size > 0)
+    }
+
+    /// Whether containers start as root (no `USER`, `root` or UID 0); `None` if not recorded
+    pub fn runs_as_root(&self) -> Option<bool> {
+        let user = self.user.as_deref()?.trim();
+        let name = user.split(':').next().unwrap_or_default();
+        Some(name.is_empty() || name == "root" || name == "0")
+    }
+
+    /// `org.opencontainers.image.revision`, if the image carries one
+    pub fn revision(&self) -> Option<&str> {
+        self.oci_annotations.get(REVISION_ANNOTATION).map(String::as_str)
+    }
+
+    /// Warning when the image says it was built from a different commit than the one it is
+    /// tracked at, usually a stale locally cached image. Non-SHA revisions aren't compared.
+    pub fn revision_mismatch(&self) -> Option<String> {
+        let revision = self.revision()?.trim().to_lowercase();
+        let commit = self.commit_sha.trim().to_lowercase();
+
+        let is_sha = |s: &str| s.len() >= 7 && s.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_sha(&revision) || !is_sha(&commit) {
+            return None;
+        }
+        if revision.starts_with(&commit) || commit.starts_with(&revision) {
+            return None;
+        }
+
+        Some(format!(
+            "image was built from {} but you're tracking it at {}",
+            &revision[..7],
+            &commit[..7]
+        ))
+    }
+}
+
+/// Provenance entries for [`ImageSnapshot::oci_annotations`]: `org.opencontainers.image.*`
+/// config labels, overridden by manifest annotations of the same name
+pub fn oci_annotations(
+    labels: Option<&BTreeMap<String, String>>,
+    manifest: Option<&BTreeMap<String, String>>,
+) -> BTreeMap<String, String> {
+    labels
+        .into_iter()
+        .chain(manifest)
+        .flatten()
+        .filter(|(key, _)| key.starts_with(OCI_ANNOTATION_PREFIX))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Placeholder digest for history entries Docker reports without an ID
+pub const MISSING_DIGEST: &str = "<missing>";
+
+pub const OCI_ANNOTATION_PREFIX: &str = "org.opencontainers.image.";
+const REVISION_ANNOTATION: &str = "org.opencontainers.image.revision";
+pub const SOURCE_ANNOTATION: &str = "org.opencontainers.image.source";
+const BASE_NAME_LABEL: &str = "org.opencontainers.image.base.name";
+const BASE_DIGEST_LABEL: &str = "org.opencontainers.image.base.digest";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LayerInfo {
     pub digest: String,
     pub size: u64,
     pub command: String,
     pub created: DateTime<Utc>,
+    #[serde(default)]
+    pub extract_ms: Option<u64>,
+    #[serde(default)]
+    pub file_count: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl LayerInfo {
+    /// Same digest when both layers have one; the same command and size when either
+    /// digest is missing
+    pub fn same_content(&self, other: &LayerInfo) -> bool {
+        if self.digest != MISSING_DIGEST && other.digest != MISSING_DIGEST {
+            self.digest == other.digest
+        } else {
+            self.same_build_step(other)
+        }
+    }
+
+    /// Same command and size, whatever the digests say
+    pub fn same_build_step(&self, other: &LayerInfo) -> bool {
+        self.command == other.command && self.size == other.size
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SizeDiff {
     pub before: ImageSnapshot,
     pub after: ImageSnapshot,
     pub total_delta: i64,
     pub layer_changes: Vec<LayerChange>,
+    #[serde(default)]
+    pub compressed_before: Option<u64>,
+    #[serde(default)]
+    pub compressed_after: Option<u64>,
+    /// Which size `total_delta` compares; older diffs are uncompressed
+    #[serde(default)]
+    pub size_basis: SizeBasis,
+    /// Always false: `layer_changes` is complete even when `--max-changes` shortens the
+    /// table and markdown views
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Which size a comparison uses. Compressed and uncompressed sizes of the same image
+/// differ by 2-3x, so both sides of a comparison must use the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeBasis {
+    /// `total_size`, as Docker reports it locally
+    #[default]
+    Uncompressed,
+    /// Registry (compressed) size, when recorded
+    Compressed,
+}
+
+impl SizeBasis {
+    /// `snapshot`'s size on this basis, if recorded
+    pub fn size_of(self, snapshot: &ImageSnapshot) -> Option<u64> {
+        match self {
+            SizeBasis::Uncompressed => snapshot.uncompressed_size(),
+            SizeBasis::Compressed => snapshot.compressed_size,
+        }
+    }
+
+    /// Note appended to rendered deltas; empty for the default basis
+    pub fn suffix(self) -> &'static str {
+        match self {
+            SizeBasis::Uncompressed => "",
+            SizeBasis::Compressed => " (compressed)",
+        }
+    }
+}
+
+impl SizeDiff {
+    pub fn before_size(&self) -> u64 {
+        self.size_basis
+            .size_of(&self.before)
+            .unwrap_or(self.before.total_size)
+    }
+
+    pub fn after_size(&self) -> u64 {
+        self.size_basis
+            .size_of(&self.after)
+            .unwrap_or(self.after.total_size)
+    }
+
+    /// Compare on `basis` from now on
+    pub fn set_basis(&mut self, basis: SizeBasis) {
+        self.size_basis = basis;
+        self.total_delta = self.after_size() as i64 - self.before_size() as i64;
+    }
+
+    /// `total_delta` as a percentage of the before size
+    pub fn percent(&self) -> Option<f64> {
+        match self.before_size() {
+            0 => None,
+            before => Some(self.total_delta as f64 / before as f64 * 100.0),
+        }
+    }
+
+    /// No size change and the same layers; typical of a rebuild that only touched metadata
+    pub fn is_unchanged(&self) -> bool {
+        self.total_delta == 0 && self.before.same_layers(&self.after)
+    }
+
+    /// Percentage of compressed size saved by the change (negative when it grew).
+    /// `None` unless both sides have a compressed size.
+    pub fn compression_savings(&self) -> Option<f64> {
+        match (self.compressed_before, self.compressed_after) {
+            (Some(before), Some(after)) if before > 0 => {
+                Some((before as f64 - after as f64) / before as f64 * 100.0)
+            }
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum LayerChange {
     Added(LayerInfo),
     Removed(LayerInfo),
@@ -50,6 +381,12 @@ pub enum LayerChange {
         after: LayerInfo,
     },
     Unchanged(LayerInfo),
+    /// Same digest at a different position (0-based), which invalidates the build cache
+    Reordered {
+        layer: LayerInfo,
+        from: usize,
+        to: usize,
+    },
 }
 
 impl LayerChange {
@@ -58,7 +395,7 @@ impl LayerChange {
             LayerChange::Added(layer) => layer.size as i64,
             LayerChange::Removed(layer) => -(layer.size as i64),
             LayerChange::Modified { before, after } => after.size as i64 - before.size as i64,
-            LayerChange::Unchanged(_) => 0,
+            LayerChange::Unchanged(_) | LayerChange::Reordered { .. } => 0,
         }
     }
 
@@ -68,16 +405,101 @@ impl LayerChange {
             LayerChange::Removed(layer) => layer,
             LayerChange::Modified { after, .. } => after,
             LayerChange::Unchanged(layer) => layer,
+            LayerChange::Reordered { layer, .. } => layer,
         }
     }
 
-    #[allow(dead_code)]
     pub fn kind(&self) -> &str {
         match self {
             LayerChange::Added(_) => "added",
             LayerChange::Removed(_) => "removed",
             LayerChange::Modified { .. } => "modified",
             LayerChange::Unchanged(_) => "unchanged",
+            LayerChange::Reordered { .. } => "reordered",
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::compute_diff_positional;
+    use crate::fixtures::{layer, snapshot};
+
+    #[test]
+    fn layers_with_digests_compare_by_digest() {
+        let built = layer("sha256:a", 100, "RUN make");
+
+        assert!(built.same_content(&layer("sha256:a", 100, "RUN make")));
+        assert!(!built.same_content(&layer("sha256:b", 100, "RUN make")));
+        // Recorded by a provider that reports a different command for the same layer
+        assert!(built.same_content(&layer("sha256:a", 100, "/bin/sh -c make")));
+    }
+
+    #[test]
+    fn layers_without_a_digest_compare_by_command_and_size() {
+        let missing = layer(MISSING_DIGEST, 100, "RUN make");
+
+        assert!(missing.same_content(&layer(MISSING_DIGEST, 100, "RUN make")));
+        assert!(missing.same_content(&layer("sha256:a", 100, "RUN make")));
+        assert!(layer("sha256:a", 100, "RUN make").same_content(&missing));
+        assert!(!missing.same_content(&layer(MISSING_DIGEST, 150, "RUN make")));
+        assert!(!missing.same_content(&layer(MISSING_DIGEST, 100, "RUN make install")));
+    }
+
+    #[test]
+    fn rebuilt_layers_with_new_digests_are_not_the_same_layers() {
+        let before = snapshot("myapp", "1.0", vec![layer("sha256:base", 100, "FROM"), layer("sha256:a", 10, "COPY")]);
+        let after = snapshot("myapp", "1.0", vec![layer("sha256:base2", 100, "FROM"), layer("sha256:b", 10, "COPY")]);
+
+        assert!(!before.same_layers(&after));
+        assert!(before.base_image_changed(&after));
+        // What `--comment-mode on-change` checks: same total, different layers
+        let diff = compute_diff_positional(before.clone(), after);
+        assert_eq!(diff.total_delta, 0);
+        assert!(!diff.is_unchanged());
+
+        let metadata_only = snapshot("myapp", "1.0", before.layers.clone());
+        assert!(before.same_layers(&metadata_only));
+        assert!(!before.base_image_changed(&metadata_only));
+    }
+
+    /// Tracked at `commit`, with `revision` in the image's OCI annotations if given
+    fn at_revision(commit: &str, revision: Option<&str>) -> ImageSnapshot {
+        let mut snapshot = snapshot("myapp", "1.0", Vec::new());
+        snapshot.commit_sha = commit.to_string();
+        if let Some(revision) = revision {
+            snapshot.oci_annotations.insert(REVISION_ANNOTATION.to_string(), revision.to_string());
+        }
+        snapshot
+    }
+
+    const COMMIT: &str = "c5e0005f3a1b2c3d4e5f60718293a4b5c6d7e8f9";
+
+    #[test]
+    fn matching_revisions_are_quiet() {
+        assert_eq!(at_revision(COMMIT, Some(COMMIT)).revision_mismatch(), None);
+        // Either side may be abbreviated, and case doesn't matter
+        assert_eq!(at_revision(COMMIT, Some("C5E0005F")).revision_mismatch(), None);
+        assert_eq!(at_revision("c5e0005", Some(COMMIT)).revision_mismatch(), None);
+        assert_eq!(at_revision(COMMIT, Some(&format!(" {}\n", COMMIT))).revision_mismatch(), None);
+    }
+
+    #[test]
+    fn different_revisions_warn_with_both_short_shas() {
+        let stale = at_revision(COMMIT, Some("0123456789abcdef0123456789abcdef01234567"));
+        assert_eq!(
+            stale.revision_mismatch().as_deref(),
+            Some("image was built from 0123456 but you're tracking it at c5e0005")
+        );
+    }
+
+    #[test]
+    fn missing_or_non_sha_revisions_are_not_compared() {
+        assert_eq!(at_revision(COMMIT, None).revision_mismatch(), None);
+        assert_eq!(at_revision(COMMIT, Some("v2.1.0")).revision_mismatch(), None);
+        assert_eq!(at_revision(COMMIT, Some("abc12")).revision_mismatch(), None);
+        // Tracked with --no-git
+        assert_eq!(at_revision("", Some(COMMIT)).revision_mismatch(), None);
+    }
+}
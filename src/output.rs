@@ -0,0 +1,183 @@
+use std::io::{self, Write};
+
+/// Exit status once stdout is closed early (`dit analyze-all | head -5`): 128 + SIGPIPE,
+/// what a shell reports for a writer the signal killed
+pub const BROKEN_PIPE_EXIT: u8 = 141;
+
+/// Where commands write their output. A write that fails with
+/// [`io::ErrorKind::BrokenPipe`] marks the output closed, so the command stops at its
+/// next line of output and `main` exits with [`BROKEN_PIPE_EXIT`] instead of an error.
+pub struct TextOutput<W> {
+    inner: W,
+    closed: bool,
+    strip_color: bool,
+    escape: Escape,
+}
+
+/// Where the color filter is within an ANSI escape sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Escape {
+    None,
+    /// After ESC
+    Start,
+    /// Inside `ESC [ ...`, up to its final byte
+    Csi,
+}
+
+impl<W: Write> TextOutput<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            closed: false,
+            strip_color: false,
+            escape: Escape::None,
+        }
+    }
+
+    /// Drop ANSI escape sequences from everything written from now on (`--plain`, and
+    /// output meant for scripts)
+    pub fn strip_color(&mut self) {
+        self.strip_color = true;
+    }
+
+    /// Whether the reader went away (`dit ... | head`)
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// `buf` without escape sequences; a sequence split across writes is still dropped
+    fn uncolored(&mut self, buf: &[u8]) -> Vec<u8> {
+        let mut text = Vec::with_capacity(buf.len());
+        for &byte in buf {
+            self.escape = match (self.escape, byte) {
+                (Escape::None, 0x1b) => Escape::Start,
+                (Escape::None, _) => {
+                    text.push(byte);
+                    Escape::None
+                }
+                (Escape::Start, b'[') => Escape::Csi,
+                (Escape::Start, _) => Escape::None,
+                (Escape::Csi, 0x40..=0x7e) => Escape::None,
+                (Escape::Csi, _) => Escape::Csi,
+            };
+        }
+        text
+    }
+
+    fn track<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(e) = &result {
+            if e.kind() == io::ErrorKind::BrokenPipe {
+                self.closed = true;
+            }
+        }
+        result
+    }
+}
+
+impl<W: Write> Write for TextOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.strip_color {
+            let result = self.inner.write(buf);
+            return self.track(result);
+        }
+
+        let text = self.uncolored(buf);
+        let result = self.inner.write_all(&text);
+        self.track(result).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.track(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts `capacity` bytes, then fails every write with `kind`
+    struct Pipe {
+        written: Vec<u8>,
+        capacity: usize,
+        kind: io::ErrorKind,
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written.len() + buf.len() > self.capacity {
+                return Err(io::Error::from(self.kind));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn pipe(capacity: usize, kind: io::ErrorKind) -> TextOutput<Pipe> {
+        TextOutput::new(Pipe { written: Vec::new(), capacity, kind })
+    }
+
+    #[test]
+    fn writes_pass_through_while_the_reader_is_there() {
+        let mut out = TextOutput::new(Vec::new());
+        writeln!(out, "myapp:1.0").unwrap();
+
+        assert!(!out.is_closed());
+        assert_eq!(out.into_inner(), b"myapp:1.0\n");
+    }
+
+    #[test]
+    fn a_broken_pipe_closes_the_output() {
+        let mut out = pipe(10, io::ErrorKind::BrokenPipe);
+        writeln!(out, "myapp:1.0").unwrap();
+        let error = writeln!(out, "myapp:2.0").unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        assert!(out.is_closed());
+        assert_eq!(out.into_inner().written, b"myapp:1.0\n");
+    }
+
+    #[test]
+    fn stripping_color_drops_escape_sequences() {
+        let mut out = TextOutput::new(Vec::new());
+        out.strip_color();
+        write!(out, "\x1b[1;31m+2.0 MiB\x1b[0m and \x1b[32m-1.0 MiB\x1b[0m").unwrap();
+
+        assert_eq!(out.into_inner(), b"+2.0 MiB and -1.0 MiB");
+    }
+
+    #[test]
+    fn escape_sequences_split_across_writes_are_dropped() {
+        let mut out = TextOutput::new(Vec::new());
+        out.strip_color();
+        for part in ["size \x1b", "[1;3", "1mgrew\x1b[", "0m"] {
+            out.write_all(part.as_bytes()).unwrap();
+        }
+
+        assert_eq!(out.into_inner(), b"size grew");
+    }
+
+    #[test]
+    fn color_is_kept_unless_stripped() {
+        let mut out = TextOutput::new(Vec::new());
+        write!(out, "\x1b[31mred\x1b[0m").unwrap();
+
+        assert_eq!(out.into_inner(), b"\x1b[31mred\x1b[0m");
+    }
+
+    #[test]
+    fn other_write_errors_leave_it_open() {
+        let mut out = pipe(0, io::ErrorKind::PermissionDenied);
+
+        assert!(writeln!(out, "myapp:1.0").is_err());
+        assert!(!out.is_closed());
+    }
+}
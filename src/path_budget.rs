@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::ci::parse_size;
+use crate::paths::{build_globset, is_glob};
+
+/// Removes the named file or directory from lower layers
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Hides everything lower layers put in this entry's directory
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// A `[path_budgets]` entry: at most `bytes` of files matching `pattern` in the final image
+#[derive(Debug, Clone)]
+pub struct PathBudget {
+    pub pattern: String,
+    pub bytes: u64,
+    matcher: GlobSet,
+}
+
+impl PathBudget {
+    /// `pattern` is a glob (`/root/**`) or a directory (`/usr/share/doc`), which covers
+    /// everything below it. Paths are absolute; a missing leading `/` is added.
+    pub fn new(pattern: &str, size: &str) -> Result<Self> {
+        let trimmed = pattern.trim().trim_end_matches('/');
+        let absolute = if trimmed.starts_with('/') {
+            trimmed.to_string()
+        } else {
+            format!("/{}", trimmed)
+        };
+
+        let globs = if is_glob(&absolute) {
+            vec![absolute]
+        } else {
+            vec![absolute.clone(), format!("{}/**", absolute)]
+        };
+
+        Ok(PathBudget {
+            pattern: pattern.to_string(),
+            bytes: parse_size(size)
+                .context(format!("Invalid path budget '{}' for {}", size, pattern))?,
+            matcher: build_globset(&globs)?,
+        })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.matcher.is_match(path)
+    }
+}
+
+/// `[path_budgets]` from `.dit.toml`, in pattern order
+pub fn path_budgets(config: &BTreeMap<String, String>) -> Result<Vec<PathBudget>> {
+    config
+        .iter()
+        .map(|(pattern, size)| PathBudget::new(pattern, size))
+        .collect()
+}
+
+/// How much of the final image one path budget covers
+#[derive(Debug, Clone, Serialize)]
+pub struct PathUsage {
+    pub pattern: String,
+    pub budget: u64,
+    pub bytes: u64,
+    /// Non-empty files matching the pattern
+    pub files: u64,
+}
+
+impl PathUsage {
+    pub fn exceeded(&self) -> bool {
+        self.bytes > self.budget
+    }
+}
+
+/// The final filesystem (absolute path → size) after applying `layers` base first.
+/// Whiteouts remove files from lower layers, so content deleted later doesn't count here
+/// even though it still takes space in the image.
+pub fn final_files(layers: &[Vec<(PathBuf, u64)>]) -> BTreeMap<String, u64> {
+    let mut files: BTreeMap<String, u64> = BTreeMap::new();
+
+    for layer in layers {
+        // A whiteout only hides lower layers, so apply them before this layer's own files
+        for (path, _) in layer {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let parent = absolute(path.parent().unwrap_or(Path::new("")));
+
+            if name == OPAQUE_WHITEOUT {
+                let prefix = format!("{}/", parent.trim_end_matches('/'));
+                files.retain(|file, _| !file.starts_with(&prefix));
+            } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+                let target = format!("{}/{}", parent.trim_end_matches('/'), hidden);
+                let prefix = format!("{}/", target);
+                files.retain(|file, _| *file != target && !file.starts_with(&prefix));
+            }
+        }
+
+        for (path, size) in layer {
+            let is_whiteout = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(WHITEOUT_PREFIX));
+            if !is_whiteout {
+                files.insert(absolute(path), *size);
+            }
+        }
+    }
+
+    files
+}
+
+/// Sum the final image's files under each budget's pattern
+pub fn path_usage(files: &BTreeMap<String, u64>, budgets: &[PathBudget]) -> Vec<PathUsage> {
+    budgets
+        .iter()
+        .map(|budget| {
+            let matching = files
+                .iter()
+                .filter(|(path, size)| **size > 0 && budget.matches(path));
+            let (bytes, count) = matching.fold((0, 0), |(bytes, count), (_, size)| (bytes + size, count + 1));
+            PathUsage {
+                pattern: budget.pattern.clone(),
+                budget: budget.bytes,
+                bytes,
+                files: count,
+            }
+        })
+        .collect()
+}
+
+/// `./usr/share/../share/doc` → `/usr/share/doc`
+fn absolute(path: &Path) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Normal(part) => parts.extend(part.to_str()),
+            _ => {}
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(files: &[(&str, u64)]) -> Vec<(PathBuf, u64)> {
+        files.iter().map(|(path, size)| (PathBuf::from(path), *size)).collect()
+    }
+
+    fn paths(files: &BTreeMap<String, u64>) -> Vec<&str> {
+        files.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn later_layers_replace_files_and_paths_are_absolute() {
+        let files = final_files(&[
+            layer(&[("etc/os-release", 100), ("./usr/bin/app", 10)]),
+            layer(&[("usr/bin/app", 20), ("usr/share/../share/doc/README", 5)]),
+        ]);
+
+        assert_eq!(paths(&files), vec!["/etc/os-release", "/usr/bin/app", "/usr/share/doc/README"]);
+        assert_eq!(files["/usr/bin/app"], 20);
+    }
+
+    #[test]
+    fn whiteouts_remove_a_file_or_a_whole_directory() {
+        let files = final_files(&[
+            layer(&[
+                ("var/cache/apt/a.bin", 10),
+                ("var/cache/apt/b.bin", 10),
+                ("tmp/build.log", 1),
+            ]),
+            layer(&[("var/cache/.wh.apt", 0), ("tmp/.wh.build.log", 0)]),
+        ]);
+
+        assert!(files.is_empty(), "{:?}", files);
+    }
+
+    #[test]
+    fn whiteouts_only_match_the_exact_name() {
+        let files = final_files(&[
+            layer(&[("opt/app", 1), ("opt/app-data/state", 2), ("opt/app/bin", 3)]),
+            layer(&[("opt/.wh.app", 0)]),
+        ]);
+
+        assert_eq!(paths(&files), vec!["/opt/app-data/state"]);
+    }
+
+    #[test]
+    fn opaque_whiteouts_hide_lower_contents_but_keep_their_own_layer() {
+        let files = final_files(&[
+            layer(&[("srv/www/old.html", 10), ("srv/www/assets/old.css", 5), ("srv/other", 1)]),
+            layer(&[("srv/www/.wh..wh..opq", 0), ("srv/www/index.html", 7)]),
+        ]);
+
+        assert_eq!(paths(&files), vec!["/srv/other", "/srv/www/index.html"]);
+    }
+
+    #[test]
+    fn files_can_be_added_again_after_a_whiteout() {
+        let files = final_files(&[
+            layer(&[("app/config.yml", 10)]),
+            // Deleted and recreated in one layer, the way overlayfs records a replaced directory
+            layer(&[("app/.wh.config.yml", 0), ("app/config.yml", 12)]),
+            layer(&[("app/.wh.config.yml", 0)]),
+            layer(&[("app/config.yml", 15)]),
+        ]);
+
+        assert_eq!(paths(&files), vec!["/app/config.yml"]);
+        assert_eq!(files["/app/config.yml"], 15);
+    }
+
+    #[test]
+    fn directory_patterns_cover_everything_below_them() {
+        for pattern in ["/usr/share/doc", "usr/share/doc", "/usr/share/doc/", " usr/share/doc/ "] {
+            let budget = PathBudget::new(pattern, "50MB").unwrap();
+            assert_eq!(budget.pattern, pattern);
+            assert!(budget.matches("/usr/share/doc"), "{}", pattern);
+            assert!(budget.matches("/usr/share/doc/git/README"), "{}", pattern);
+            assert!(!budget.matches("/usr/share/docs/README"), "{}", pattern);
+            assert!(!budget.matches("/usr/share"), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn glob_patterns_match_as_written() {
+        let budget = PathBudget::new("root/**", "0").unwrap();
+        assert!(budget.matches("/root/.cache/pip/wheel"));
+        assert!(!budget.matches("/rootfs/file"));
+
+        let budget = PathBudget::new("/usr/lib/*.a", "1MB").unwrap();
+        assert!(budget.matches("/usr/lib/libc.a"));
+        assert!(!budget.matches("/usr/lib/libc.so"));
+    }
+
+    #[test]
+    fn invalid_sizes_name_the_pattern() {
+        let error = PathBudget::new("/usr/share/doc", "lots").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid path budget 'lots' for /usr/share/doc");
+    }
+
+    #[test]
+    fn usage_sums_non_empty_files_under_each_pattern() {
+        let files = final_files(&[layer(&[
+            ("usr/share/doc/a", 300),
+            ("usr/share/doc/b", 200),
+            ("usr/share/doc/empty", 0),
+            ("usr/bin/app", 1000),
+        ])]);
+        let budgets = vec![PathBudget::new("/usr/share/doc", "1KB").unwrap()];
+
+        let usage = path_usage(&files, &budgets);
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].bytes, usage[0].files), (500, 2));
+    }
+}
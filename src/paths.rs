@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+/// Compile glob patterns (e.g. `services/api/**`, `Dockerfile*`) into one matcher
+pub fn build_globset(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        builder.add(parse_glob(pattern)?);
+    }
+
+    builder.build().context("Failed to build glob set")
+}
+
+/// Parse one glob pattern, naming it in the error
+pub fn parse_glob(pattern: &str) -> Result<Glob> {
+    Glob::new(pattern.trim()).context(format!("Invalid glob pattern '{}'", pattern))
+}
+
+/// Whether `pattern` uses glob syntax rather than naming one thing literally
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// True if any of `paths` matches the glob set
+pub fn any_path_matches(globs: &GlobSet, paths: &[String]) -> bool {
+    paths.iter().any(|p| globs.is_match(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn globsets_match_any_of_their_patterns() {
+        let globs = build_globset(&strings(&["services/api/**", " Dockerfile*"])).unwrap();
+
+        assert!(globs.is_match("services/api/src/main.rs"));
+        assert!(globs.is_match("Dockerfile.prod"));
+        assert!(!globs.is_match("services/web/index.ts"));
+        assert!(!globs.is_match("docs/Dockerfile"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_named() {
+        let error = build_globset(&strings(&["src/**", "src/[oops"])).unwrap_err();
+        assert!(format!("{:#}", error).contains("'src/[oops'"), "{:#}", error);
+    }
+
+    #[test]
+    fn glob_syntax_is_detected() {
+        for glob in ["*.rs", "src/**", "file?.txt", "[ab].txt", "{a,b}.txt"] {
+            assert!(is_glob(glob), "{}", glob);
+        }
+        for literal in ["/usr/share/doc", "Dockerfile", "myapp:1.0"] {
+            assert!(!is_glob(literal), "{}", literal);
+        }
+    }
+
+    #[test]
+    fn any_path_matches_one_changed_file() {
+        let globs = build_globset(&strings(&["services/api/**"])).unwrap();
+
+        assert!(any_path_matches(&globs, &strings(&["README.md", "services/api/Cargo.toml"])));
+        assert!(!any_path_matches(&globs, &strings(&["README.md", "docs/ci.md"])));
+        assert!(!any_path_matches(&globs, &[]));
+    }
+}
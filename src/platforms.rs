@@ -0,0 +1,656 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::process::Command;
+
+use crate::diff::compute_diff;
+use crate::docker::{clean_command, parse_image_reference};
+use crate::models::{
+    oci_annotations, ImageOrigin, ImageSnapshot, LayerInfo, SizeDiff, MISSING_DIGEST,
+};
+
+/// How the per-platform snapshots of a multi-platform build are shown and budgeted
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum PlatformMode {
+    /// One row per platform; budgets apply to each platform
+    #[default]
+    Separate,
+    /// The largest platform stands for the group
+    Max,
+    /// All platforms added together
+    Sum,
+}
+
+/// Descriptor / index / manifest as printed by `docker buildx imagetools inspect`
+#[derive(Debug, Default, Deserialize)]
+struct Descriptor {
+    #[serde(default)]
+    digest: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    platform: Option<Platform>,
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+    #[serde(default)]
+    config: Option<Box<Descriptor>>,
+    #[serde(default)]
+    annotations: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Platform {
+    os: String,
+    architecture: String,
+    #[serde(default)]
+    variant: Option<String>,
+}
+
+impl Platform {
+    fn key(&self) -> String {
+        match &self.variant {
+            Some(variant) => format!("{}/{}/{}", self.os, self.architecture, variant),
+            None => format!("{}/{}", self.os, self.architecture),
+        }
+    }
+}
+
+/// OCI image config (the subset dit records)
+#[derive(Debug, Deserialize)]
+struct ImageConfig {
+    #[serde(default)]
+    config: RuntimeConfig,
+    #[serde(default)]
+    history: Vec<HistoryEntry>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RuntimeConfig {
+    #[serde(rename = "Env", default)]
+    env: Option<Vec<String>>,
+    #[serde(rename = "Labels", default)]
+    labels: Option<BTreeMap<String, String>>,
+    #[serde(rename = "User", default)]
+    user: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryEntry {
+    #[serde(default)]
+    created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    created_by: String,
+    #[serde(default)]
+    empty_layer: bool,
+}
+
+/// Read the registry index behind `reference` and build one snapshot per platform,
+/// all sharing the index digest as `group_id`. The registry only knows compressed sizes,
+/// so the snapshots are `registry_only`.
+pub fn inspect_index(reference: &str) -> Result<Vec<ImageSnapshot>> {
+    let index: Descriptor =
+        serde_json::from_str(&imagetools(&[reference, "--format", "{{json .Manifest}}"])?)
+            .context(format!("Failed to parse the index of '{}'", reference))?;
+
+    // Attestation manifests are listed with platform unknown/unknown
+    let manifests: Vec<&Descriptor> = index
+        .manifests
+        .iter()
+        .filter(|m| m.platform.as_ref().is_some_and(|p| p.os != "unknown"))
+        .collect();
+
+    if manifests.is_empty() {
+        bail!(
+            "'{}' is not a multi-platform image index; track it without --multi-platform",
+            reference
+        );
+    }
+
+    let configs: BTreeMap<String, ImageConfig> =
+        serde_json::from_str(&imagetools(&[reference, "--format", "{{json .Image}}"])?)
+            .context(format!("Failed to parse the image configs of '{}'", reference))?;
+
+    let (image, tag) = parse_image_reference(reference);
+    let mut snapshots = Vec::new();
+
+    for descriptor in manifests {
+        let platform = descriptor.platform.as_ref().unwrap();
+        let config = configs
+            .get(&platform.key())
+            .context(format!("No image config for {} in '{}'", platform.key(), reference))?;
+
+        let pinned = format!("{}@{}", image, descriptor.digest);
+        let manifest: Descriptor = serde_json::from_str(&imagetools(&["--raw", &pinned])?)
+            .context(format!("Failed to parse manifest {}", pinned))?;
+
+        let layers = platform_layers(config, &manifest.layers);
+        let compressed_size: u64 = layers.iter().map(|l| l.size).sum();
+
+        snapshots.push(ImageSnapshot {
+            image: image.clone(),
+            tag: tag.clone(),
+            digest: Some(descriptor.digest.clone()),
+            image_id: None,
+            commit_sha: String::new(),
+            branch: String::new(),
+            commit_message: String::new(),
+            author: String::new(),
+            timestamp: Utc::now(),
+            total_size: 0,
+            compressed_size: Some(compressed_size),
+            registry_only: true,
+            layer_count: layers.len(),
+            layers,
+            os: platform.os.clone(),
+            arch: platform.architecture.clone(),
+            variant: platform.variant.clone(),
+            group_id: Some(index.digest.clone()),
+            host_platform: None,
+            env: Some(config.config.env.clone().unwrap_or_default()),
+            labels: Some(config.config.labels.clone().unwrap_or_default()),
+            user: Some(config.config.user.clone().unwrap_or_default()),
+            annotations: Default::default(),
+            oci_annotations: oci_annotations(
+                config.config.labels.as_ref(),
+                manifest.annotations.as_ref(),
+            ),
+            extract_ms: None,
+            file_count: None,
+            watched: false,
+            origin: ImageOrigin::Pulled,
+            build_duration_ms: None,
+        });
+    }
+
+    Ok(snapshots)
+}
+
+/// Pair config history entries with manifest layers, oldest first. Without usable
+/// history (e.g. squashed images) the layers are listed with unknown commands.
+fn platform_layers(config: &ImageConfig, blobs: &[Descriptor]) -> Vec<LayerInfo> {
+    let non_empty = config.history.iter().filter(|h| !h.empty_layer).count();
+    let layer = |digest: &str, size: u64, command: &str, created: Option<DateTime<Utc>>| LayerInfo {
+        digest: digest.to_string(),
+        size,
+        command: command.to_string(),
+        created: created.unwrap_or_else(Utc::now),
+        extract_ms: None,
+        file_count: None,
+    };
+
+    if non_empty != blobs.len() {
+        return blobs
+            .iter()
+            .map(|b| layer(&b.digest, b.size, "<unknown>", None))
+            .collect();
+    }
+
+    let mut blobs = blobs.iter();
+    config
+        .history
+        .iter()
+        .map(|entry| {
+            let command = clean_command(&entry.created_by);
+            let blob = if entry.empty_layer { None } else { blobs.next() };
+            match blob {
+                Some(blob) => layer(&blob.digest, blob.size, &command, entry.created),
+                None => layer(MISSING_DIGEST, 0, &command, entry.created),
+            }
+        })
+        .collect()
+}
+
+/// Compressed download size of `reference` from its registry manifest (config blob plus
+/// layers), without pulling. An index resolves to its `platform` (`os/arch[/variant]`) manifest.
+pub fn manifest_download_size(reference: &str, platform: &str) -> Result<u64> {
+    let mut manifest: Descriptor = serde_json::from_str(&imagetools(&["--raw", reference])?)
+        .context(format!("Failed to parse the manifest of '{}'", reference))?;
+
+    if !manifest.manifests.is_empty() {
+        let available: Vec<String> = manifest
+            .manifests
+            .iter()
+            .filter_map(|m| m.platform.as_ref())
+            .filter(|p| p.os != "unknown")
+            .map(Platform::key)
+            .collect();
+        let Some(descriptor) = manifest
+            .manifests
+            .iter()
+            .find(|m| {
+                // `linux/arm64` also picks the `linux/arm64/v8` image
+                m.platform.as_ref().is_some_and(|p| {
+                    p.key() == platform || format!("{}/{}", p.os, p.architecture) == platform
+                })
+            })
+        else {
+            bail!(
+                "'{}' has no {} image (available: {})",
+                reference,
+                platform,
+                available.join(", ")
+            );
+        };
+
+        let (image, _) = parse_image_reference(reference);
+        let pinned = format!("{}@{}", image, descriptor.digest);
+        manifest = serde_json::from_str(&imagetools(&["--raw", &pinned])?)
+            .context(format!("Failed to parse manifest {}", pinned))?;
+    }
+
+    let config = manifest.config.as_ref().map_or(0, |c| c.size);
+    Ok(config + manifest.layers.iter().map(|l| l.size).sum::<u64>())
+}
+
+/// Registry digest `reference` currently points at (the index digest for multi-platform images)
+pub fn manifest_digest(reference: &str) -> Result<String> {
+    let manifest: Descriptor =
+        serde_json::from_str(&imagetools(&[reference, "--format", "{{json .Manifest}}"])?)
+            .context(format!("Failed to parse the manifest of '{}'", reference))?;
+
+    if manifest.digest.is_empty() {
+        bail!("Registry returned no digest for '{}'", reference);
+    }
+    Ok(manifest.digest)
+}
+
+#[tracing::instrument(name = "dit.registry", fields(command = "imagetools inspect"))]
+fn imagetools(args: &[&str]) -> Result<String> {
+    let output = Command::new("docker")
+        .args(["buildx", "imagetools", "inspect"])
+        .args(args)
+        .output()
+        .context("Failed to run 'docker buildx imagetools'. Is Docker Buildx installed?")?;
+
+    if !output.status.success() {
+        bail!(
+            "docker buildx imagetools inspect failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// One-line notice when `snapshot` was built for a different platform than the one
+/// `requested` (`os/arch[/variant]`), or by default the Docker host's
+pub fn platform_mismatch(snapshot: &ImageSnapshot, requested: Option<&str>) -> Option<String> {
+    let image = snapshot.platform();
+
+    if let Some(requested) = requested {
+        return (!same_platform(&image, requested))
+            .then(|| format!("analyzing {} image, but {} was requested", image, requested));
+    }
+
+    let host = snapshot.host_platform.as_deref()?;
+    (!same_platform(&image, host)).then(|| format!("analyzing {} image on {} host", image, host))
+}
+
+/// Compare `os/arch[/variant]` strings; the variant only counts when both have one
+fn same_platform(a: &str, b: &str) -> bool {
+    let parts = |p: &str| -> Vec<String> {
+        p.split('/')
+            .enumerate()
+            .map(|(i, part)| match (i, part.to_lowercase().as_str()) {
+                (1, "x86_64" | "x86-64") => "amd64".to_string(),
+                (1, "aarch64") => "arm64".to_string(),
+                (_, part) => part.to_string(),
+            })
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+
+    a.iter().zip(&b).all(|(x, y)| x == y) && a.len().min(b.len()) >= 2
+}
+
+pub fn has_groups(snapshots: &[ImageSnapshot]) -> bool {
+    snapshots.iter().any(|s| s.group_id.is_some())
+}
+
+/// Collapse each snapshot group into one snapshot per `mode`; ungrouped snapshots pass
+/// through. The largest platform stands in for the group and its arch lists every
+/// platform (`amd64+arm64`).
+pub fn aggregate(snapshots: Vec<ImageSnapshot>, mode: PlatformMode) -> Vec<ImageSnapshot> {
+    if mode == PlatformMode::Separate {
+        return snapshots;
+    }
+
+    let mut slots: Vec<Vec<ImageSnapshot>> = Vec::new();
+    let mut slot_of: HashMap<String, usize> = HashMap::new();
+
+    for snapshot in snapshots {
+        match &snapshot.group_id {
+            Some(id) => {
+                let slot = *slot_of.entry(id.clone()).or_insert_with(|| {
+                    slots.push(Vec::new());
+                    slots.len() - 1
+                });
+                slots[slot].push(snapshot);
+            }
+            None => slots.push(vec![snapshot]),
+        }
+    }
+
+    slots
+        .into_iter()
+        .map(|members| combine(members, mode))
+        .collect()
+}
+
+/// Aggregate `(current, diff)` pairs per group. A group counts as new if any platform is.
+pub fn aggregate_comparisons(
+    comparisons: Vec<(ImageSnapshot, Option<SizeDiff>)>,
+    mode: PlatformMode,
+) -> Vec<(ImageSnapshot, Option<SizeDiff>)> {
+    if mode == PlatformMode::Separate {
+        return comparisons;
+    }
+
+    let mut slots: Vec<Vec<(ImageSnapshot, Option<SizeDiff>)>> = Vec::new();
+    let mut slot_of: HashMap<String, usize> = HashMap::new();
+
+    for (current, diff) in comparisons {
+        match current.group_id.clone() {
+            Some(id) => {
+                let slot = *slot_of.entry(id).or_insert_with(|| {
+                    slots.push(Vec::new());
+                    slots.len() - 1
+                });
+                slots[slot].push((current, diff));
+            }
+            None => slots.push(vec![(current, diff)]),
+        }
+    }
+
+    slots
+        .into_iter()
+        .map(|members| {
+            if members.len() == 1 {
+                return members.into_iter().next().unwrap();
+            }
+
+            let befores: Option<Vec<ImageSnapshot>> = members
+                .iter()
+                .map(|(_, diff)| diff.as_ref().map(|d| d.before.clone()))
+                .collect();
+            let current = combine(members.into_iter().map(|(c, _)| c).collect(), mode);
+            let diff = befores.map(|b| compute_diff(combine(b, mode), current.clone()));
+
+            (current, diff)
+        })
+        .collect()
+}
+
+fn combine(mut members: Vec<ImageSnapshot>, mode: PlatformMode) -> ImageSnapshot {
+    if members.len() == 1 {
+        return members.pop().unwrap();
+    }
+
+    members.sort_by_key(|s| s.platform());
+    let arch = members
+        .iter()
+        .map(|s| match &s.variant {
+            Some(variant) => format!("{}/{}", s.arch, variant),
+            None => s.arch.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("+");
+    let sizes = members.iter().map(|s| s.total_size);
+    let total_size = match mode {
+        PlatformMode::Sum => sizes.sum(),
+        _ => sizes.max().unwrap_or(0),
+    };
+    let registry_only = members.iter().any(|s| s.registry_only);
+    let compressed: Option<Vec<u64>> = members.iter().map(|s| s.compressed_size).collect();
+    let compressed_size = compressed.map(|sizes| match mode {
+        PlatformMode::Sum => sizes.iter().sum(),
+        _ => sizes.into_iter().max().unwrap_or(0),
+    });
+
+    let mut combined = members
+        .into_iter()
+        .max_by_key(|s| s.size())
+        .unwrap();
+    combined.total_size = if registry_only { 0 } else { total_size };
+    combined.compressed_size = compressed_size;
+    combined.registry_only = registry_only;
+    combined.arch = arch;
+    combined.variant = None;
+
+    combined
+}
+
+/// Total size per platform for budget checks: each platform's total counts its grouped
+/// snapshots plus every ungrouped one. Without groups there is a single `None` total.
+/// Registry-only snapshots count with their registry size.
+pub fn platform_totals(snapshots: &[&ImageSnapshot]) -> Vec<(Option<String>, u64)> {
+    let platforms: BTreeSet<String> = snapshots
+        .iter()
+        .filter(|s| s.group_id.is_some())
+        .map(|s| s.platform())
+        .collect();
+
+    if platforms.is_empty() {
+        return vec![(None, snapshots.iter().map(|s| s.size()).sum())];
+    }
+
+    platforms
+        .into_iter()
+        .map(|platform| {
+            let total = snapshots
+                .iter()
+                .filter(|s| s.group_id.is_none() || s.platform() == platform)
+                .map(|s| s.size())
+                .sum();
+            (Some(platform), total)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, snapshot};
+
+    /// One platform of the `sha256:index` build of myapp:1.0
+    fn platform(arch: &str, size: u64, compressed: Option<u64>) -> ImageSnapshot {
+        let mut snapshot = snapshot("myapp", "1.0", vec![layer(&format!("sha256:{}", arch), size, "COPY")]);
+        let mut parts = arch.split('/');
+        snapshot.arch = parts.next().unwrap().to_string();
+        snapshot.variant = parts.next().map(str::to_string);
+        snapshot.compressed_size = compressed;
+        snapshot.group_id = Some("sha256:index".to_string());
+        snapshot
+    }
+
+    /// What `inspect_index` records: registry sizes only
+    fn registry_platform(arch: &str, compressed: u64) -> ImageSnapshot {
+        let mut snapshot = platform(arch, 0, Some(compressed));
+        snapshot.total_size = 0;
+        snapshot.registry_only = true;
+        snapshot
+    }
+
+    fn two_platforms() -> Vec<ImageSnapshot> {
+        vec![
+            platform("amd64", 300, Some(120)),
+            platform("arm64/v8", 200, Some(100)),
+            snapshot("worker", "1.0", vec![layer("sha256:w", 50, "COPY")]),
+        ]
+    }
+
+    #[test]
+    fn separate_keeps_every_platform() {
+        let snapshots = aggregate(two_platforms(), PlatformMode::Separate);
+
+        let platforms: Vec<String> = snapshots.iter().map(|s| s.platform()).collect();
+        assert_eq!(platforms, ["linux/amd64", "linux/arm64/v8", "linux/amd64"]);
+    }
+
+    #[test]
+    fn max_stands_the_largest_platform_in_for_the_group() {
+        let snapshots = aggregate(two_platforms(), PlatformMode::Max);
+
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].arch, "amd64+arm64/v8");
+        assert_eq!(snapshots[0].variant, None);
+        assert_eq!((snapshots[0].total_size, snapshots[0].compressed_size), (300, Some(120)));
+        assert_eq!(snapshots[0].layers[0].digest, "sha256:amd64");
+        assert_eq!((snapshots[1].image.as_str(), snapshots[1].total_size), ("worker", 50));
+    }
+
+    #[test]
+    fn sum_adds_the_platforms_up() {
+        let snapshots = aggregate(two_platforms(), PlatformMode::Sum);
+
+        assert_eq!((snapshots[0].total_size, snapshots[0].compressed_size), (500, Some(220)));
+    }
+
+    #[test]
+    fn a_platform_without_a_compressed_size_leaves_the_group_without_one() {
+        let combined = combine(vec![platform("amd64", 300, Some(120)), platform("arm64", 200, None)], PlatformMode::Sum);
+
+        assert_eq!(combined.total_size, 500);
+        assert_eq!(combined.compressed_size, None);
+    }
+
+    #[test]
+    fn registry_only_groups_combine_their_registry_sizes() {
+        let members = vec![registry_platform("amd64", 120), registry_platform("arm64", 100)];
+        let combined = combine(members, PlatformMode::Max);
+
+        assert!(combined.registry_only);
+        assert_eq!(combined.total_size, 0);
+        assert_eq!(combined.uncompressed_size(), None);
+        assert_eq!(combined.size(), 120);
+    }
+
+    #[test]
+    fn registry_only_snapshots_keep_compressed_out_of_the_uncompressed_size() {
+        let registry = registry_platform("amd64", 120);
+
+        assert_eq!(registry.uncompressed_size(), None);
+        assert_eq!(registry.compressed_size, Some(120));
+        assert_eq!(registry.size(), 120);
+        assert_eq!(platform("amd64", 300, Some(120)).size(), 300);
+    }
+
+    #[test]
+    fn totals_are_per_platform_with_ungrouped_images_in_each() {
+        let snapshots = two_platforms();
+        let refs: Vec<&ImageSnapshot> = snapshots.iter().collect();
+
+        assert_eq!(
+            platform_totals(&refs),
+            [(Some("linux/amd64".to_string()), 350), (Some("linux/arm64/v8".to_string()), 250)]
+        );
+    }
+
+    #[test]
+    fn totals_without_groups_are_one_sum() {
+        let snapshots = [
+            snapshot("web", "1.0", vec![layer("sha256:a", 70, "COPY")]),
+            registry_platform("amd64", 30),
+        ];
+        let mut ungrouped = snapshots[1].clone();
+        ungrouped.group_id = None;
+        let refs = [&snapshots[0], &ungrouped];
+
+        assert_eq!(platform_totals(&refs), [(None, 100)]);
+    }
+
+    #[test]
+    fn comparisons_aggregate_per_group() {
+        let before = [platform("amd64", 250, Some(110)), platform("arm64", 150, Some(90))];
+        let after = [platform("amd64", 300, Some(120)), platform("arm64", 200, Some(100))];
+        let comparisons = before
+            .iter()
+            .zip(&after)
+            .map(|(b, a)| (a.clone(), Some(compute_diff(b.clone(), a.clone()))))
+            .collect();
+
+        let aggregated = aggregate_comparisons(comparisons, PlatformMode::Sum);
+
+        assert_eq!(aggregated.len(), 1);
+        let (current, diff) = &aggregated[0];
+        assert_eq!(current.total_size, 500);
+        assert_eq!(diff.as_ref().unwrap().compressed_before, Some(200));
+        assert_eq!(diff.as_ref().unwrap().total_delta, 20);
+    }
+
+    #[test]
+    fn a_group_with_a_new_platform_counts_as_new() {
+        let comparisons = vec![
+            (platform("amd64", 300, None), Some(compute_diff(platform("amd64", 250, None), platform("amd64", 300, None)))),
+            (platform("arm64", 200, None), None),
+        ];
+
+        let aggregated = aggregate_comparisons(comparisons, PlatformMode::Max);
+
+        assert_eq!(aggregated.len(), 1);
+        assert!(aggregated[0].1.is_none());
+    }
+
+    /// myapp built for `platform`, analyzed on a `host` Docker daemon (if recorded)
+    fn built_for(platform: &str, host: Option<&str>) -> ImageSnapshot {
+        let mut snapshot = snapshot("myapp", "1.0", Vec::new());
+        let parts: Vec<&str> = platform.split('/').collect();
+        snapshot.os = parts[0].to_string();
+        snapshot.arch = parts[1].to_string();
+        snapshot.variant = parts.get(2).map(|v| v.to_string());
+        snapshot.host_platform = host.map(str::to_string);
+        snapshot
+    }
+
+    #[test]
+    fn platforms_compare_by_os_arch_and_variant_when_both_have_one() {
+        for (a, b) in [
+            ("linux/amd64", "linux/amd64"),
+            ("linux/amd64", "linux/x86_64"),
+            ("linux/arm64", "Linux/AArch64"),
+            ("linux/arm64/v8", "linux/arm64"),
+            ("linux/arm/v7", "linux/arm"),
+        ] {
+            assert!(same_platform(a, b), "{} vs {}", a, b);
+            assert!(same_platform(b, a), "{} vs {}", b, a);
+        }
+        for (a, b) in [
+            ("linux/amd64", "linux/arm64"),
+            ("linux/arm/v7", "linux/arm/v8"),
+            ("linux/amd64", "windows/amd64"),
+            ("linux", "linux/amd64"),
+        ] {
+            assert!(!same_platform(a, b), "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn mismatches_are_checked_against_the_requested_platform_first() {
+        let arm = built_for("linux/arm/v7", Some("linux/arm/v7"));
+
+        assert_eq!(platform_mismatch(&arm, Some("linux/arm/v7")), None);
+        assert_eq!(
+            platform_mismatch(&arm, Some("linux/arm/v8")).as_deref(),
+            Some("analyzing linux/arm/v7 image, but linux/arm/v8 was requested")
+        );
+        assert_eq!(
+            platform_mismatch(&arm, Some("linux/amd64")).as_deref(),
+            Some("analyzing linux/arm/v7 image, but linux/amd64 was requested")
+        );
+    }
+
+    #[test]
+    fn without_a_request_the_host_platform_decides() {
+        assert_eq!(platform_mismatch(&built_for("linux/amd64", Some("linux/x86_64")), None), None);
+        assert_eq!(
+            platform_mismatch(&built_for("linux/amd64", Some("linux/arm64")), None).as_deref(),
+            Some("analyzing linux/amd64 image on linux/arm64 host")
+        );
+        // Snapshots tracked before the host was recorded never warn
+        assert_eq!(platform_mismatch(&built_for("linux/arm64", None), None), None);
+    }
+}
@@ -0,0 +1,119 @@
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{IsTerminal, Write};
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ProgressMode {
+    None,
+    Bar,
+    Dots,
+}
+
+/// Reports per-image progress for multi-image operations
+pub trait ProgressReporter {
+    fn start(&mut self, total: usize);
+    fn item_started(&mut self, name: &str);
+    fn item_succeeded(&mut self, name: &str, detail: &str);
+    fn item_failed(&mut self, name: &str, error: &str);
+    fn finish(&mut self);
+}
+
+/// Pick a reporter for the requested mode; defaults to dots when stderr is a TTY and silence
+/// otherwise
+pub fn reporter_for(mode: Option<ProgressMode>) -> Box<dyn ProgressReporter> {
+    let mode = mode.unwrap_or_else(|| {
+        if std::io::stderr().is_terminal() {
+            ProgressMode::Dots
+        } else {
+            ProgressMode::None
+        }
+    });
+
+    match mode {
+        ProgressMode::None => Box::new(Silent),
+        ProgressMode::Bar => Box::new(Bar::default()),
+        ProgressMode::Dots => Box::new(Dots),
+    }
+}
+
+/// Progress bar via indicatif
+#[derive(Default)]
+pub struct Bar {
+    bar: Option<ProgressBar>,
+}
+
+impl ProgressReporter for Bar {
+    fn start(&mut self, total: usize) {
+        let bar = ProgressBar::new(total as u64);
+        bar.set_style(
+            ProgressStyle::with_template("  [{bar:30}] {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        self.bar = Some(bar);
+    }
+
+    fn item_started(&mut self, name: &str) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(name.to_string());
+        }
+    }
+
+    fn item_succeeded(&mut self, _name: &str, _detail: &str) {
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+
+    fn item_failed(&mut self, name: &str, error: &str) {
+        if let Some(bar) = &self.bar {
+            bar.println(format!("  ❌ {} — {}", name, error));
+            bar.inc(1);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// One line per image on stderr, printed as each one completes, so piped stdout stays clean
+pub struct Dots;
+
+impl ProgressReporter for Dots {
+    fn start(&mut self, _total: usize) {}
+
+    fn item_started(&mut self, name: &str) {
+        eprint!("  {} ... ", name);
+        let _ = std::io::stderr().flush();
+    }
+
+    fn item_succeeded(&mut self, _name: &str, detail: &str) {
+        eprintln!("✅ {}", detail);
+    }
+
+    fn item_failed(&mut self, _name: &str, error: &str) {
+        eprintln!("❌ {}", error);
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// No progress output; failures still go to stderr
+pub struct Silent;
+
+impl ProgressReporter for Silent {
+    fn start(&mut self, _total: usize) {}
+
+    fn item_started(&mut self, _name: &str) {}
+
+    fn item_succeeded(&mut self, _name: &str, _detail: &str) {}
+
+    fn item_failed(&mut self, name: &str, error: &str) {
+        eprintln!("⚠️  {}: {}", name, error);
+    }
+
+    fn finish(&mut self) {}
+}
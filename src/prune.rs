@@ -0,0 +1,205 @@
+use anyhow::{bail, Result};
+use chrono::{NaiveDate, NaiveTime, TimeDelta, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::Write;
+
+use crate::format::{format_size, format_timestamp, OutputStyle};
+use crate::models::ImageSnapshot;
+use crate::reference::ImageRef;
+use crate::store::Store;
+
+#[derive(Debug)]
+pub struct PruneOptions {
+    /// Snapshots kept per image, tag and platform, newest first
+    pub keep_last: Option<usize>,
+    /// Snapshots newer than this are kept. With `keep_last` too, a snapshot is only
+    /// deleted when both policies would delete it.
+    pub older_than: Option<TimeDelta>,
+    /// Only prune snapshots of this image
+    pub image: Option<String>,
+    /// Snapshots on these branches are never pruned
+    pub keep_branches: Vec<String>,
+    pub dry_run: bool,
+    pub output: OutputStyle,
+}
+
+/// Parse `12h`, `30d` or `6w`
+pub fn parse_age(s: &str) -> Result<TimeDelta, String> {
+    let s = s.trim();
+    let invalid = || format!("invalid age '{}' (expected e.g. 12h, 30d, 6w)", s);
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?);
+    let value: i64 = number.parse().map_err(|_| invalid())?;
+
+    match unit {
+        "h" => Ok(TimeDelta::hours(value)),
+        "d" => Ok(TimeDelta::days(value)),
+        "w" => Ok(TimeDelta::weeks(value)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Indices of snapshots to delete: per image, tag and platform, everything past the
+/// newest `keep_last` and older than `older_than` (whichever policies are set), minus
+/// any snapshot on a protected branch.
+/// Returns (to delete, protected by branch).
+pub fn select_pruned(history: &[ImageSnapshot], options: &PruneOptions) -> (BTreeSet<usize>, usize) {
+    let scope = options.image.as_deref().map(ImageRef::parse_lenient);
+    let cutoff = options.older_than.map(|age| Utc::now() - age);
+
+    let mut series: HashMap<(&str, Option<&str>, String), Vec<usize>> = HashMap::new();
+    for (index, snapshot) in history.iter().enumerate() {
+        if scope.as_ref().is_some_and(|image| !image.matches(snapshot)) {
+            continue;
+        }
+        series
+            .entry((&snapshot.image, snapshot.tag.as_deref(), snapshot.platform()))
+            .or_default()
+            .push(index);
+    }
+
+    let mut expired: BTreeSet<usize> = BTreeSet::new();
+    for indices in series.values_mut() {
+        indices.sort_by_key(|&i| std::cmp::Reverse(history[i].timestamp));
+        expired.extend(
+            indices
+                .iter()
+                .skip(options.keep_last.unwrap_or(0))
+                .filter(|&&i| cutoff.is_none_or(|cutoff| history[i].timestamp < cutoff)),
+        );
+    }
+
+    let (protected, delete): (BTreeSet<usize>, BTreeSet<usize>) = expired
+        .into_iter()
+        .partition(|&i| options.keep_branches.contains(&history[i].branch));
+
+    (delete, protected.len())
+}
+
+/// `dit prune`
+pub async fn prune_history(
+    out: &mut impl Write,
+    store: &Store,
+    options: &PruneOptions,
+) -> Result<()> {
+    if options.keep_last == Some(0) {
+        bail!("--keep-last must be at least 1");
+    }
+
+    let history = store.load_history()?;
+
+    if history.is_empty() {
+        bail!("No history found. Run 'dit track' first.");
+    }
+
+    let (delete, protected) = select_pruned(&history, options);
+
+    if delete.is_empty() {
+        writeln!(
+            out,
+            "Nothing to prune ({} snapshot(s), {} kept on protected branches)",
+            history.len(),
+            protected
+        )?;
+        return Ok(());
+    }
+
+    if options.dry_run {
+        for &i in &delete {
+            let snapshot = &history[i];
+            writeln!(
+                out,
+                "would delete {}:{} {} ({}, {})",
+                snapshot.image,
+                snapshot.tag.as_deref().unwrap_or("latest"),
+                snapshot.commit_sha.chars().take(7).collect::<String>(),
+                snapshot.branch,
+                format_timestamp(snapshot.timestamp, "%Y-%m-%d %H:%M", options.output.time_zone)
+            )?;
+        }
+        writeln!(
+            out,
+            "\nDry run: {} of {} snapshot(s) would be deleted ({} kept on protected branches)",
+            delete.len(),
+            history.len(),
+            protected
+        )?;
+        return Ok(());
+    }
+
+    let kept: Vec<ImageSnapshot> = history
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !delete.contains(i))
+        .map(|(_, s)| s.clone())
+        .collect();
+
+    let size_before = history_file_size(store);
+    let backup = store.backup_history()?;
+    store.save_history(&kept)?;
+    let reclaimed = size_before.saturating_sub(history_file_size(store));
+
+    writeln!(
+        out,
+        "✅ Deleted {} snapshot(s), kept {} ({} on protected branches), reclaimed {}; \
+         previous history saved to {}",
+        delete.len(),
+        kept.len(),
+        protected,
+        format_size(reclaimed),
+        backup.display()
+    )?;
+
+    Ok(())
+}
+
+/// Bytes the history takes on disk; 0 when it can't be read. SQLite files only shrink
+/// after a VACUUM, so their deletes reclaim nothing here.
+fn history_file_size(store: &Store) -> u64 {
+    fs::metadata(store.history_path()).map(|m| m.len()).unwrap_or(0)
+}
+
+/// `dit prune --before`: delete every snapshot recorded before `date` (UTC midnight)
+pub fn prune_before(
+    out: &mut impl Write,
+    store: &Store,
+    date: NaiveDate,
+    dry_run: bool,
+) -> Result<()> {
+    let cutoff = date.and_time(NaiveTime::MIN).and_utc();
+    let history = store.load_history()?;
+
+    if history.is_empty() {
+        bail!("No history found. Run 'dit track' first.");
+    }
+
+    let expired = history.iter().filter(|s| s.timestamp < cutoff).count();
+    if expired == 0 {
+        writeln!(out, "Nothing to prune: no snapshot was recorded before {}", date)?;
+        return Ok(());
+    }
+
+    if dry_run {
+        writeln!(
+            out,
+            "Dry run: {} of {} snapshot(s) recorded before {} would be deleted",
+            expired,
+            history.len(),
+            date
+        )?;
+        return Ok(());
+    }
+
+    let backup = store.backup_history()?;
+    let deleted = store.delete_snapshots_before(cutoff)?;
+
+    writeln!(
+        out,
+        "✅ Deleted {} snapshot(s) recorded before {}; previous history saved to {}",
+        deleted,
+        date,
+        backup.display()
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::Write;
+
+use crate::store::Store;
+
+/// Rewrite snapshot commit SHAs after a rebase. `mappings` are (old, new) pairs;
+/// old SHAs may be abbreviated (7+ characters).
+pub async fn rebase_history(
+    out: &mut impl Write,
+    store: &Store,
+    mappings: &[(String, String)],
+) -> Result<()> {
+    if mappings.is_empty() {
+        bail!("No SHA mappings given");
+    }
+
+    let mut history = store.load_history()?;
+
+    if history.is_empty() {
+        bail!("No history found. Run 'dit track' first.");
+    }
+
+    let mut updated = 0;
+    for snapshot in &mut history {
+        if let Some((_, new)) = mappings
+            .iter()
+            .find(|(old, _)| snapshot.commit_sha.starts_with(old.as_str()))
+        {
+            snapshot.commit_sha = new.clone();
+            updated += 1;
+        }
+    }
+
+    if updated == 0 {
+        writeln!(out, "No snapshots matched the given commits; history unchanged")?;
+        return Ok(());
+    }
+
+    store.save_history(&history)?;
+
+    writeln!(
+        out,
+        "✅ Updated {} snapshot(s) across {} commit mapping(s)",
+        updated,
+        mappings.len()
+    )?;
+
+    Ok(())
+}
+
+/// Read `<old-sha> <new-sha>` lines, as in `.git/rebase-merge/rewritten-list`
+/// or the stdin of a post-rewrite hook
+pub fn read_mapping_file(path: &str) -> Result<Vec<(String, String)>> {
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read mapping file: {}", path))?;
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some(old), Some(new)) => Ok((
+                    validate_sha(old).context(format!("Line {}", i + 1))?,
+                    validate_sha(new).context(format!("Line {}", i + 1))?,
+                )),
+                _ => bail!("Line {}: expected '<old-sha> <new-sha>', got '{}'", i + 1, line),
+            }
+        })
+        .collect()
+}
+
+pub fn validate_sha(sha: &str) -> Result<String> {
+    if sha.len() < 7 || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!(
+            "'{}' is not a commit SHA (need at least 7 hex characters)",
+            sha
+        );
+    }
+
+    Ok(sha.to_lowercase())
+}
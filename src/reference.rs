@@ -0,0 +1,417 @@
+use std::str::FromStr;
+
+use crate::models::ImageSnapshot;
+
+const DEFAULT_DOMAIN: &str = "docker.io";
+const LEGACY_DEFAULT_DOMAIN: &str = "index.docker.io";
+const OFFICIAL_NAMESPACE: &str = "library/";
+const DEFAULT_TAG: &str = "latest";
+
+/// A parsed image reference, normalized the way Docker normalizes names.
+///
+/// History stores the familiar form: `myapp`, `docker.io/library/myapp` and `myapp:latest`
+/// all become `myapp` + `latest`; other registries keep their (lowercased) host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    /// Registry host (with port), lowercased; `None` for Docker Hub
+    pub domain: Option<String>,
+    /// Repository path without the Hub `library/` namespace
+    pub path: String,
+    /// Tag as written; `None` when the reference had no tag
+    pub tag: Option<String>,
+    /// `algorithm:hex` digest from a pinned (`@sha256:…`) reference
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    /// Repository name as recorded in history (`myapp`, `ghcr.io/org/app`)
+    pub fn name(&self) -> String {
+        match &self.domain {
+            Some(domain) => format!("{}/{}", domain, self.path),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Tag as recorded in history; untagged references mean `latest`
+    pub fn tag_or_latest(&self) -> &str {
+        self.tag.as_deref().unwrap_or(DEFAULT_TAG)
+    }
+
+    /// Parse for lookups: anything that isn't a valid reference (e.g. an image ID)
+    /// is kept verbatim as the name
+    pub fn parse_lenient(reference: &str) -> Self {
+        reference.parse().unwrap_or_else(|_| ImageRef {
+            domain: None,
+            path: reference.to_string(),
+            tag: None,
+            digest: None,
+        })
+    }
+
+    /// Whether `snapshot` belongs to this reference. The tag only has to match
+    /// when one was given, so `myapp` selects every tag of `myapp`. A pinned reference
+    /// (`app@sha256:…`, possibly abbreviated) selects snapshots recorded with that digest.
+    pub fn matches(&self, snapshot: &ImageSnapshot) -> bool {
+        let stored = match &snapshot.tag {
+            Some(tag) => ImageRef::parse_lenient(&format!("{}:{}", snapshot.image, tag)),
+            None => ImageRef::parse_lenient(&snapshot.image),
+        };
+        // Tracked as `app@sha256:…`: there is no tag, not even latest
+        let pinned = snapshot.tag.is_none() && snapshot.digest.is_some();
+
+        let tag_matches = match &self.tag {
+            Some(_) if pinned => false,
+            Some(tag) => stored.tag_or_latest() == tag,
+            None => true,
+        };
+        let digest_matches = match &self.digest {
+            // Repo digests are recorded as `name@sha256:…`, manifest digests bare
+            Some(digest) => snapshot
+                .digest
+                .as_deref()
+                .and_then(|d| d.rsplit('@').next())
+                .is_some_and(|d| d.starts_with(digest.as_str())),
+            None => true,
+        };
+
+        stored.name() == self.name() && tag_matches && digest_matches
+    }
+}
+
+impl FromStr for ImageRef {
+    type Err = String;
+
+    /// Follows the distribution reference grammar:
+    /// `[domain/]path[:tag][@digest]`, where the first component is a domain if it
+    /// contains `.` or `:`, is `localhost`, or has uppercase letters
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("image reference is empty".to_string());
+        }
+
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => (name, Some(validate_digest(digest)?)),
+            None => (s, None),
+        };
+
+        // A tag colon comes after the last slash; earlier colons belong to a registry port
+        let last_slash = name.rfind('/').map_or(0, |i| i + 1);
+        let (name, tag) = match name[last_slash..].rfind(':') {
+            Some(i) => {
+                let (name, tag) = name.split_at(last_slash + i);
+                (name, Some(validate_tag(&tag[1..])?))
+            }
+            None => (name, None),
+        };
+
+        let (domain, path) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains(['.', ':'])
+                    || first == "localhost"
+                    || first.chars().any(|c| c.is_ascii_uppercase()) =>
+            {
+                (first.to_lowercase(), rest)
+            }
+            _ => (DEFAULT_DOMAIN.to_string(), name),
+        };
+
+        let domain = if domain == LEGACY_DEFAULT_DOMAIN {
+            DEFAULT_DOMAIN.to_string()
+        } else {
+            domain
+        };
+
+        validate_path(path)?;
+
+        // Docker Hub official images live under library/, which Docker hides
+        let (domain, path) = if domain == DEFAULT_DOMAIN {
+            let path = match path.strip_prefix(OFFICIAL_NAMESPACE) {
+                Some(rest) if !rest.contains('/') => rest,
+                _ => path,
+            };
+            (None, path.to_string())
+        } else {
+            (Some(domain), path.to_string())
+        };
+
+        Ok(ImageRef {
+            domain,
+            path,
+            tag,
+            digest,
+        })
+    }
+}
+
+/// Path components are lowercase alphanumerics joined by `.`, `_`, `__` or runs of `-`
+fn validate_path(path: &str) -> Result<(), String> {
+    if path.is_empty() {
+        return Err("image reference has no repository name".to_string());
+    }
+    if path.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(format!("repository name must be lowercase: '{}'", path));
+    }
+
+    for component in path.split('/') {
+        let valid = !component.is_empty()
+            && component.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && component.ends_with(|c: char| c.is_ascii_alphanumeric())
+            && component
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c))
+            && !component.contains("..")
+            && !component.contains("___")
+            && !component.contains("._")
+            && !component.contains("_.");
+        if !valid {
+            return Err(format!("invalid repository name component '{}'", component));
+        }
+    }
+
+    Ok(())
+}
+
+/// Tags are up to 128 of `[A-Za-z0-9_.-]`, not starting with `.` or `-`
+fn validate_tag(tag: &str) -> Result<String, String> {
+    let valid = !tag.is_empty()
+        && tag.len() <= 128
+        && tag.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+
+    if valid {
+        Ok(tag.to_string())
+    } else {
+        Err(format!("invalid tag '{}'", tag))
+    }
+}
+
+/// Digests are `algorithm:hex`, e.g. `sha256:…`. Any length of hex is accepted so an
+/// abbreviated digest (`app@sha256:4bcff639`) works for lookups.
+fn validate_digest(digest: &str) -> Result<String, String> {
+    let valid = match digest.split_once(':') {
+        Some((algorithm, hex)) => {
+            !algorithm.is_empty()
+                && algorithm
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+._-".contains(c))
+                && !hex.is_empty()
+                && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => false,
+    };
+
+    if valid {
+        Ok(digest.to_lowercase())
+    } else {
+        Err(format!("invalid digest '{}'", digest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::snapshot;
+
+    const DIGEST: &str = "sha256:4bcff63911fcb4448bd4fdacec207030997caf25e9bea4045fa6c8c44de311d1";
+
+    fn canonical(reference: &str) -> String {
+        let parsed: ImageRef = reference.parse().unwrap();
+        format!("{}:{}", parsed.name(), parsed.tag_or_latest())
+    }
+
+    #[test]
+    fn docker_hub_spellings_share_one_name() {
+        for reference in [
+            "myapp",
+            "myapp:latest",
+            "library/myapp",
+            "docker.io/myapp",
+            "docker.io/library/myapp",
+            "docker.io/library/myapp:latest",
+            "index.docker.io/library/myapp",
+            "DOCKER.IO/library/myapp",
+        ] {
+            assert_eq!(canonical(reference), "myapp:latest", "{}", reference);
+        }
+    }
+
+    #[test]
+    fn hub_namespaces_other_than_library_are_kept() {
+        assert_eq!(canonical("org/app:v1"), "org/app:v1");
+        assert_eq!(canonical("docker.io/org/app:v1"), "org/app:v1");
+        // Only a single-component path under library/ is an official image
+        assert_eq!(canonical("library/org/app"), "library/org/app:latest");
+    }
+
+    #[test]
+    fn other_registries_keep_a_lowercased_host() {
+        assert_eq!(canonical("ghcr.io/org/app:v1"), "ghcr.io/org/app:v1");
+        assert_eq!(canonical("GHCR.io/org/app:v1"), "ghcr.io/org/app:v1");
+        assert_eq!(canonical("localhost/app"), "localhost/app:latest");
+        // A leading component with uppercase letters can only be a host
+        assert_eq!(canonical("MyRegistry/app"), "myregistry/app:latest");
+    }
+
+    #[test]
+    fn registry_ports_are_not_tags() {
+        let parsed: ImageRef = "localhost:5000/app".parse().unwrap();
+        assert_eq!(parsed.domain.as_deref(), Some("localhost:5000"));
+        assert_eq!(parsed.path, "app");
+        assert_eq!(parsed.tag, None);
+
+        let parsed: ImageRef = "registry.example.com:8443/team/app:1.2".parse().unwrap();
+        assert_eq!(parsed.name(), "registry.example.com:8443/team/app");
+        assert_eq!(parsed.tag.as_deref(), Some("1.2"));
+    }
+
+    #[test]
+    fn tags_and_digests_parse_together() {
+        let parsed: ImageRef = format!("ghcr.io/org/app:v1@{}", DIGEST).parse().unwrap();
+        assert_eq!(parsed.name(), "ghcr.io/org/app");
+        assert_eq!(parsed.tag.as_deref(), Some("v1"));
+        assert_eq!(parsed.digest.as_deref(), Some(DIGEST));
+    }
+
+    #[test]
+    fn tags_keep_their_case() {
+        assert_eq!(canonical("app:RC1"), "app:RC1");
+    }
+
+    #[test]
+    fn invalid_references_are_rejected() {
+        for reference in [
+            "",
+            "   ",
+            "App",
+            "org/App",
+            "app:",
+            "app:-bad",
+            "app:.bad",
+            "org//app",
+            "-app",
+            "app-",
+            "a..b",
+            "a___b",
+            "app@sha256:not-hex",
+            "app@nocolon",
+        ] {
+            assert!(reference.parse::<ImageRef>().is_err(), "{:?} parsed", reference);
+        }
+        assert!(format!("app:{}", "t".repeat(129)).parse::<ImageRef>().is_err());
+        assert!(format!("app:{}", "t".repeat(128)).parse::<ImageRef>().is_ok());
+    }
+
+    #[test]
+    fn lenient_parsing_keeps_image_ids_verbatim() {
+        let parsed = ImageRef::parse_lenient("Sha256:ABC");
+        assert_eq!(parsed.name(), "Sha256:ABC");
+        assert_eq!(parsed.tag, None);
+    }
+
+    #[test]
+    fn untagged_queries_match_every_tag() {
+        let query = ImageRef::parse_lenient("docker.io/library/myapp");
+        assert!(query.matches(&snapshot("myapp", "latest", Vec::new())));
+        assert!(query.matches(&snapshot("myapp", "v2", Vec::new())));
+        assert!(!query.matches(&snapshot("other", "latest", Vec::new())));
+    }
+
+    #[test]
+    fn tagged_queries_match_only_their_tag() {
+        let query = ImageRef::parse_lenient("myapp:v2");
+        assert!(query.matches(&snapshot("docker.io/library/myapp", "v2", Vec::new())));
+        assert!(!query.matches(&snapshot("myapp", "v1", Vec::new())));
+
+        // Untagged history entries from before normalization count as latest
+        let mut untagged = snapshot("myapp", "latest", Vec::new());
+        untagged.tag = None;
+        assert!(ImageRef::parse_lenient("myapp:latest").matches(&untagged));
+    }
+
+    fn parsed(reference: &str) -> (Option<String>, String, Option<String>, Option<String>) {
+        let parsed: ImageRef = reference.parse().unwrap();
+        (parsed.domain, parsed.path, parsed.tag, parsed.digest)
+    }
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn registry_references_parse() {
+        assert_eq!(parsed("ghcr.io/org/app:v1"), (some("ghcr.io"), "org/app".to_string(), some("v1"), None));
+        assert_eq!(
+            parsed("localhost:5000/myapp:1.2"),
+            (some("localhost:5000"), "myapp".to_string(), some("1.2"), None)
+        );
+    }
+
+    #[test]
+    fn digest_pinned_references_parse_without_a_tag() {
+        assert_eq!(parsed("app@sha256:abc"), (None, "app".to_string(), None, some("sha256:abc")));
+        assert_eq!(parsed(&format!("app@{}", DIGEST)), (None, "app".to_string(), None, some(DIGEST)));
+    }
+
+    #[test]
+    fn bare_names_are_official_hub_images() {
+        assert_eq!(parsed("ubuntu"), (None, "ubuntu".to_string(), None, None));
+        assert_eq!(canonical("ubuntu"), "ubuntu:latest");
+    }
+
+    #[test]
+    fn registry_references_match_their_tag() {
+        let query = ImageRef::parse_lenient("ghcr.io/org/app:v1");
+        assert!(query.matches(&snapshot("ghcr.io/org/app", "v1", Vec::new())));
+        assert!(query.matches(&snapshot("GHCR.io/org/app", "v1", Vec::new())));
+        assert!(!query.matches(&snapshot("ghcr.io/org/app", "v2", Vec::new())));
+        assert!(!query.matches(&snapshot("org/app", "v1", Vec::new())));
+
+        let query = ImageRef::parse_lenient("localhost:5000/myapp");
+        assert!(query.matches(&snapshot("localhost:5000/myapp", "1.2", Vec::new())));
+    }
+
+    #[test]
+    fn bare_names_match_every_hub_spelling() {
+        let query = ImageRef::parse_lenient("ubuntu");
+        for recorded in ["ubuntu", "library/ubuntu", "docker.io/library/ubuntu"] {
+            assert!(query.matches(&snapshot(recorded, "24.04", Vec::new())), "{}", recorded);
+        }
+        assert!(!query.matches(&snapshot("ghcr.io/ubuntu", "24.04", Vec::new())));
+    }
+
+    /// What `dit track app@sha256:…` records: no tag, the repo digest
+    fn pinned(image: &str, digest: &str) -> ImageSnapshot {
+        let mut pinned = snapshot(image, "latest", Vec::new());
+        pinned.tag = None;
+        pinned.digest = Some(format!("{}@{}", image, digest));
+        pinned
+    }
+
+    #[test]
+    fn digest_pinned_references_match_by_digest() {
+        let recorded = pinned("app", DIGEST);
+
+        assert!(ImageRef::parse_lenient(&format!("app@{}", DIGEST)).matches(&recorded));
+        assert!(ImageRef::parse_lenient("app@sha256:4bcff639").matches(&recorded));
+        assert!(!ImageRef::parse_lenient("app@sha256:abc").matches(&recorded));
+        assert!(!ImageRef::parse_lenient("other@sha256:4bcff639").matches(&recorded));
+
+        // A tagged snapshot with the same repo digest is the same image
+        let mut tagged = snapshot("app", "v1", Vec::new());
+        tagged.digest = Some(DIGEST.to_string());
+        assert!(ImageRef::parse_lenient("app@sha256:4bcff639").matches(&tagged));
+        assert!(!ImageRef::parse_lenient("app@sha256:abc").matches(&snapshot("app", "latest", Vec::new())));
+    }
+
+    #[test]
+    fn digest_pinned_snapshots_are_not_latest() {
+        let recorded = pinned("app", DIGEST);
+
+        assert!(!ImageRef::parse_lenient("app:latest").matches(&recorded));
+        assert!(!ImageRef::parse_lenient("app:v1").matches(&recorded));
+        assert!(ImageRef::parse_lenient("app").matches(&recorded));
+    }
+}
@@ -0,0 +1,295 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures_util::{StreamExt, TryStreamExt};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::env;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+use crate::config::DitConfig;
+use crate::models::ImageSnapshot;
+use crate::reference::ImageRef;
+use crate::storage::StorageBackend;
+use crate::store::{sanitize, snapshot_key};
+
+const BUCKET_ENV: &str = "DIT_S3_BUCKET";
+const PREFIX_ENV: &str = "DIT_S3_PREFIX";
+const ENDPOINT_ENV: &str = "DIT_S3_ENDPOINT";
+
+/// Key timestamps sort in recording order
+const KEY_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+
+/// Snapshots fetched at once
+const CONCURRENT_GETS: usize = 16;
+
+/// `[remote]` in .dit.toml; `DIT_S3_*` variables override each key. Credentials and the
+/// region come from the usual `AWS_*` variables.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    pub bucket: Option<String>,
+    /// Key prefix shared by every snapshot, e.g. `dit/myrepo`
+    pub prefix: Option<String>,
+    /// S3-compatible endpoint (MinIO, R2, ...); AWS when unset
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+}
+
+/// `--remote`: one object per snapshot under `<prefix>/<image>/<timestamp>-<commit>.json`,
+/// so stateless CI runners can share history and fetch one image's snapshots alone
+#[derive(Debug)]
+pub struct RemoteBackend {
+    store: Arc<dyn ObjectStore>,
+    bucket: String,
+    prefix: String,
+}
+
+impl RemoteBackend {
+    pub fn from_config(config: &DitConfig) -> Result<Self> {
+        let remote = config.remote.clone().unwrap_or_default();
+        let setting = |var: &str, key: Option<String>| env::var(var).ok().or(key);
+
+        let Some(bucket) = setting(BUCKET_ENV, remote.bucket) else {
+            bail!(
+                "--remote needs a bucket: set {} or `bucket` under [remote] in .dit.toml",
+                BUCKET_ENV
+            );
+        };
+        let prefix = setting(PREFIX_ENV, remote.prefix)
+            .unwrap_or_default()
+            .trim_matches('/')
+            .to_string();
+
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&bucket);
+        if let Some(endpoint) = setting(ENDPOINT_ENV, remote.endpoint) {
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint);
+        }
+        if let Some(region) = remote.region {
+            builder = builder.with_region(region);
+        }
+        let store: AmazonS3 = builder
+            .build()
+            .context(format!("Failed to configure S3 bucket '{}'", bucket))?;
+
+        Ok(Self {
+            store: Arc::new(store),
+            bucket,
+            prefix,
+        })
+    }
+
+    /// `<prefix>/<image>`, the directory holding every snapshot of an image
+    fn image_dir(&self, image: &str) -> ObjectPath {
+        self.dir_for(sanitize(&ImageRef::parse_lenient(image).name()))
+    }
+
+    /// Every directory an image's snapshots may be under: [`image_dir`](Self::image_dir),
+    /// then the one uploads used before slugs were hashed
+    fn image_dirs(&self, image: &str) -> Vec<ObjectPath> {
+        let name = ImageRef::parse_lenient(image).name();
+        let legacy = self.dir_for(name.replace(['/', ':', '@'], "_"));
+        let current = self.image_dir(image);
+        if legacy == current {
+            vec![current]
+        } else {
+            vec![current, legacy]
+        }
+    }
+
+    fn dir_for(&self, slug: String) -> ObjectPath {
+        match self.prefix.as_str() {
+            "" => ObjectPath::from(slug),
+            prefix => ObjectPath::from(format!("{}/{}", prefix, slug)),
+        }
+    }
+
+    fn key(&self, snapshot: &ImageSnapshot) -> ObjectPath {
+        self.image_dir(&snapshot.image).child(format!(
+            "{}-{}.json",
+            snapshot.timestamp.format(KEY_TIME_FORMAT),
+            snapshot_key(snapshot)
+        ))
+    }
+
+    /// `snapshot`'s key in each of [`image_dirs`](Self::image_dirs)
+    fn keys(&self, snapshot: &ImageSnapshot) -> Vec<ObjectPath> {
+        let file = self.key(snapshot).filename().unwrap_or_default().to_string();
+        self.image_dirs(&snapshot.image)
+            .into_iter()
+            .map(|dir| dir.child(file.as_str()))
+            .collect()
+    }
+
+    fn list(&self, dir: Option<&ObjectPath>) -> Result<Vec<ObjectPath>> {
+        let mut keys: Vec<ObjectPath> = block_on(
+            self.store
+                .list(dir)
+                .map_ok(|object| object.location)
+                .try_collect::<Vec<_>>(),
+        )
+        .context(format!("Failed to list {}", self.path().display()))?;
+        keys.retain(|key| key.extension() == Some("json"));
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn fetch(&self, keys: Vec<ObjectPath>) -> Result<Vec<ImageSnapshot>> {
+        let fetches = futures_util::stream::iter(keys)
+            .map(|key| async move {
+                let bytes = self
+                    .store
+                    .get(&key)
+                    .await
+                    .context(format!("Failed to fetch s3://{}/{}", self.bucket, key))?
+                    .bytes()
+                    .await
+                    .context(format!("Failed to fetch s3://{}/{}", self.bucket, key))?;
+                serde_json::from_slice::<ImageSnapshot>(&bytes)
+                    .context(format!("Failed to parse s3://{}/{}", self.bucket, key))
+            })
+            .buffered(CONCURRENT_GETS)
+            .try_collect::<Vec<_>>();
+
+        let mut snapshots = block_on(fetches)?;
+        snapshots.sort_by_key(|s| s.timestamp);
+        Ok(snapshots)
+    }
+
+    /// Snapshots matching `reference` that aren't in `local`; only those are downloaded
+    pub fn load_missing(
+        &self,
+        reference: &ImageRef,
+        local: &[ImageSnapshot],
+    ) -> Result<Vec<ImageSnapshot>> {
+        let known: HashSet<ObjectPath> = local.iter().flat_map(|s| self.keys(s)).collect();
+        let mut keys = Vec::new();
+        for dir in self.image_dirs(&reference.name()) {
+            keys.extend(self.list(Some(&dir))?.into_iter().filter(|key| !known.contains(key)));
+        }
+
+        let mut snapshots = self.fetch(keys)?;
+        snapshots.retain(|s| reference.matches(s));
+        Ok(snapshots)
+    }
+}
+
+/// The recording time in a snapshot's key, so deletes need no download
+fn key_timestamp(key: &ObjectPath) -> Option<DateTime<Utc>> {
+    let name = key.filename()?;
+    let stamp = name.split('-').next()?;
+    NaiveDateTime::parse_from_str(stamp, KEY_TIME_FORMAT)
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// The store API is synchronous; S3 calls run on the surrounding tokio runtime
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| Handle::current().block_on(future))
+}
+
+impl StorageBackend for RemoteBackend {
+    fn path(&self) -> PathBuf {
+        match self.prefix.as_str() {
+            "" => PathBuf::from(format!("s3://{}", self.bucket)),
+            prefix => PathBuf::from(format!("s3://{}/{}", self.bucket, prefix)),
+        }
+    }
+
+    fn load_history(&self) -> Result<Vec<ImageSnapshot>> {
+        let dir = (!self.prefix.is_empty()).then(|| ObjectPath::from(self.prefix.as_str()));
+        let keys = self.list(dir.as_ref())?;
+        self.fetch(keys)
+    }
+
+    fn load_history_for_image(&self, reference: &ImageRef) -> Result<Vec<ImageSnapshot>> {
+        self.load_missing(reference, &[])
+    }
+
+    fn save_snapshot(&self, snapshot: &ImageSnapshot) -> Result<()> {
+        let key = self.key(snapshot);
+        let json = serde_json::to_vec_pretty(snapshot)?;
+        block_on(self.store.put(&key, PutPayload::from(json)))
+            .context(format!("Failed to upload s3://{}/{}", self.bucket, key))?;
+        Ok(())
+    }
+
+    fn save_history(&self, _snapshots: &[ImageSnapshot]) -> Result<()> {
+        bail!(
+            "{} holds one object per snapshot and can't be rewritten as a whole",
+            self.path().display()
+        )
+    }
+
+    fn delete_snapshots_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let dir = (!self.prefix.is_empty()).then(|| ObjectPath::from(self.prefix.as_str()));
+        let expired: Vec<ObjectPath> = self
+            .list(dir.as_ref())?
+            .into_iter()
+            .filter(|key| key_timestamp(key).is_some_and(|t| t < cutoff))
+            .collect();
+
+        for key in &expired {
+            block_on(self.store.delete(key))
+                .context(format!("Failed to delete s3://{}/{}", self.bucket, key))?;
+        }
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+    use object_store::memory::InMemory;
+
+    fn backend() -> RemoteBackend {
+        RemoteBackend {
+            store: Arc::new(InMemory::new()),
+            bucket: "test".to_string(),
+            prefix: "dit/repo".to_string(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn images_with_colliding_legacy_slugs_keep_their_own_snapshots() {
+        let remote = backend();
+        let a_b = tracked("org/a_b", "1", "aaa", "main", 1, vec![layer("sha256:a", 10, "")]);
+        let a_slash_b = tracked("org/a/b", "1", "aaa", "main", 1, vec![layer("sha256:b", 20, "")]);
+        remote.save_snapshot(&a_b).unwrap();
+        remote.save_snapshot(&a_slash_b).unwrap();
+
+        assert_ne!(remote.key(&a_b), remote.key(&a_slash_b));
+        for (image, size) in [("org/a_b", 10), ("org/a/b", 20)] {
+            let snapshots = remote.load_history_for_image(&ImageRef::parse_lenient(image)).unwrap();
+            assert_eq!(snapshots.len(), 1, "{}", image);
+            assert_eq!(snapshots[0].total_size, size);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn snapshots_uploaded_under_the_old_slug_are_still_found() {
+        let remote = backend();
+        let old = tracked("ghcr.io/org/app", "1", "aaa", "main", 1, vec![layer("sha256:a", 10, "")]);
+        let legacy_key = ObjectPath::from("dit/repo/ghcr.io_org_app").child(
+            remote.key(&old).filename().unwrap(),
+        );
+        block_on(remote.store.put(&legacy_key, PutPayload::from(serde_json::to_vec(&old).unwrap()))).unwrap();
+        let new = tracked("ghcr.io/org/app", "1", "bbb", "main", 2, vec![layer("sha256:b", 20, "")]);
+        remote.save_snapshot(&new).unwrap();
+
+        let reference = ImageRef::parse_lenient("ghcr.io/org/app");
+        let all = remote.load_missing(&reference, &[]).unwrap();
+        assert_eq!(all.iter().map(|s| s.commit_sha.as_str()).collect::<Vec<_>>(), ["aaa", "bbb"]);
+
+        // Already in the local history under either key, so nothing is downloaded again
+        assert!(remote.load_missing(&reference, &all).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::{anyhow, Context, Result};
+use bytesize::ByteSize;
+use chrono::{DateTime, Utc};
+use minijinja::{Environment, UndefinedBehavior};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fs;
+
+use crate::ci::{budget_status, BudgetStatus};
+use crate::cost::StorageCostImpact;
+use crate::models::{ImageSnapshot, SizeBasis, SizeDiff};
+use crate::platforms::platform_mismatch;
+
+/// Everything a `--report-template` can see; dump it with `dit ci --print-template-context`
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReportContext {
+    pub images: Vec<ReportImage>,
+    pub totals: ReportTotals,
+    pub budget: Option<ReportBudget>,
+    pub git: Option<ReportGit>,
+    /// Present when `.dit.toml` has a `[cost]` table
+    pub storage_cost: Option<StorageCostImpact>,
+    pub meta: ReportMeta,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportImageStatus {
+    Changed,
+    /// Same size and layers as the baseline, e.g. a metadata-only rebuild
+    Unchanged,
+    /// No baseline yet
+    New,
+    Skipped,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReportImage {
+    /// `image:tag`
+    pub name: String,
+    /// `os/arch` for snapshots of a multi-platform build
+    pub platform: Option<String>,
+    /// Set when the image's platform differs from the Docker host's
+    pub platform_note: Option<String>,
+    pub status: ReportImageStatus,
+    pub previous_size: Option<u64>,
+    pub current_size: u64,
+    pub delta: i64,
+    pub percent: Option<f64>,
+    /// Size the sizes, delta and percent are on: compressed when both sides have one
+    pub size_basis: SizeBasis,
+    pub diff: Option<SizeDiff>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReportTotals {
+    pub previous_size: u64,
+    pub current_size: u64,
+    pub delta: i64,
+    pub percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReportBudget {
+    pub bytes: u64,
+    pub status: BudgetStatus,
+    pub warn_at_percent: f64,
+    pub headroom: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReportGit {
+    pub commit_sha: String,
+    pub commit_short: String,
+    pub branch: String,
+    pub commit_message: String,
+    pub author: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReportMeta {
+    pub dit_version: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl ReportContext {
+    pub fn new(
+        comparisons: &[(ImageSnapshot, Option<SizeDiff>)],
+        skipped: &[ImageSnapshot],
+        budget_bytes: Option<u64>,
+        warn_at_percent: f64,
+        git: Option<ReportGit>,
+        storage_cost: Option<StorageCostImpact>,
+    ) -> Self {
+        let mut images = Vec::new();
+
+        for (current, diff) in comparisons {
+            let image = match diff {
+                Some(diff) => ReportImage {
+                    name: display_name(current),
+                    platform: current.group_id.as_ref().map(|_| current.platform()),
+                    platform_note: platform_mismatch(current, None),
+                    status: if diff.is_unchanged() {
+                        ReportImageStatus::Unchanged
+                    } else {
+                        ReportImageStatus::Changed
+                    },
+                    previous_size: Some(diff.before_size()),
+                    current_size: diff.after_size(),
+                    delta: diff.total_delta,
+                    percent: diff.percent(),
+                    size_basis: diff.size_basis,
+                    diff: Some(diff.clone()),
+                },
+                None => ReportImage {
+                    name: display_name(current),
+                    platform: current.group_id.as_ref().map(|_| current.platform()),
+                    platform_note: platform_mismatch(current, None),
+                    status: ReportImageStatus::New,
+                    previous_size: None,
+                    current_size: current.size(),
+                    delta: 0,
+                    percent: None,
+                    size_basis: SizeBasis::Uncompressed,
+                    diff: None,
+                },
+            };
+            images.push(image);
+        }
+
+        for baseline in skipped {
+            images.push(ReportImage {
+                name: display_name(baseline),
+                platform: baseline.group_id.as_ref().map(|_| baseline.platform()),
+                platform_note: None,
+                status: ReportImageStatus::Skipped,
+                previous_size: Some(baseline.size()),
+                current_size: baseline.size(),
+                delta: 0,
+                percent: Some(0.0),
+                size_basis: SizeBasis::Uncompressed,
+                diff: None,
+            });
+        }
+
+        // Same accounting as the built-in report: first runs add to current only, and
+        // totals stay uncompressed since not every image has a compressed size
+        let previous_size: u64 = comparisons
+            .iter()
+            .filter_map(|(_, diff)| diff.as_ref().map(|d| d.before.size()))
+            .chain(skipped.iter().map(|s| s.size()))
+            .sum();
+        let current_size: u64 = comparisons
+            .iter()
+            .map(|(s, _)| s)
+            .chain(skipped)
+            .map(|s| s.size())
+            .sum();
+        let delta = current_size as i64 - previous_size as i64;
+
+        let budget = budget_bytes.map(|bytes| ReportBudget {
+            bytes,
+            status: budget_status(current_size, bytes, warn_at_percent),
+            warn_at_percent,
+            headroom: bytes.saturating_sub(current_size),
+        });
+
+        ReportContext {
+            images,
+            totals: ReportTotals {
+                previous_size,
+                current_size,
+                delta,
+                percent: percent(delta, previous_size),
+            },
+            budget,
+            git,
+            storage_cost,
+            meta: ReportMeta {
+                dit_version: env!("CARGO_PKG_VERSION").to_string(),
+                generated_at: Utc::now(),
+            },
+        }
+    }
+}
+
+/// A compiled minijinja template for the PR comment
+#[derive(Debug)]
+pub struct ReportTemplate {
+    path: String,
+    source: String,
+}
+
+impl ReportTemplate {
+    /// Read and compile the template so syntax errors surface before any work is done
+    pub fn load(path: &str) -> Result<Self> {
+        let source =
+            fs::read_to_string(path).context(format!("Failed to read report template: {}", path))?;
+
+        let template = ReportTemplate {
+            path: path.to_string(),
+            source,
+        };
+        template.environment()?;
+
+        Ok(template)
+    }
+
+    pub fn render(&self, context: &ReportContext) -> Result<String> {
+        let env = self.environment()?;
+        let template = env.get_template(&self.path).map_err(|e| self.error(e))?;
+
+        template.render(context).map_err(|e| self.error(e))
+    }
+
+    fn environment(&self) -> Result<Environment<'_>> {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::SemiStrict);
+        env.add_filter("size", |bytes: u64| ByteSize(bytes).to_string_as(true));
+        env.add_filter("size_delta", |delta: i64| {
+            let size = ByteSize(delta.unsigned_abs()).to_string_as(true);
+            match delta {
+                d if d > 0 => format!("+{}", size),
+                d if d < 0 => format!("-{}", size),
+                _ => "—".to_string(),
+            }
+        });
+        env.add_template(&self.path, &self.source)
+            .map_err(|e| self.error(e))?;
+
+        Ok(env)
+    }
+
+    /// Prefix a template error with `path:line:column`
+    fn error(&self, err: minijinja::Error) -> anyhow::Error {
+        let column = err.range().map(|range| {
+            let start = range.start.min(self.source.len());
+            let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+            self.source[line_start..start].chars().count() + 1
+        });
+
+        let message = match err.detail() {
+            Some(detail) => format!("{}: {}", err.kind(), detail),
+            None => err.kind().to_string(),
+        };
+
+        match (err.line(), column) {
+            (Some(line), Some(column)) => {
+                anyhow!("{}:{}:{}: {}", self.path, line, column, message)
+            }
+            (Some(line), None) => anyhow!("{}:{}: {}", self.path, line, message),
+            _ => anyhow!("{}: {}", self.path, message),
+        }
+    }
+}
+
+fn display_name(snapshot: &ImageSnapshot) -> String {
+    format!("{}:{}", snapshot.image, snapshot.tag.as_deref().unwrap_or("latest"))
+}
+
+fn percent(delta: i64, before: u64) -> Option<f64> {
+    if before > 0 {
+        Some(delta as f64 / before as f64 * 100.0)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,60 @@
+use regex::{Captures, Regex};
+use std::env;
+use std::sync::OnceLock;
+
+/// Variables a `--report-link` URL can use, each read from the first of these CI
+/// variables that is set (GitHub Actions, GitLab, Buildkite, CircleCI, Jenkins)
+const VARIABLES: &[(&str, &[&str])] = &[
+    (
+        "run_id",
+        &["GITHUB_RUN_ID", "CI_PIPELINE_ID", "BUILDKITE_BUILD_ID", "CIRCLE_WORKFLOW_ID", "BUILD_ID"],
+    ),
+    (
+        "run_number",
+        &["GITHUB_RUN_NUMBER", "CI_PIPELINE_IID", "BUILDKITE_BUILD_NUMBER", "CIRCLE_BUILD_NUM", "BUILD_NUMBER"],
+    ),
+    (
+        "repository",
+        &["GITHUB_REPOSITORY", "CI_PROJECT_PATH", "BUILDKITE_PIPELINE_SLUG", "CIRCLE_PROJECT_REPONAME"],
+    ),
+    ("sha", &["GITHUB_SHA", "CI_COMMIT_SHA", "BUILDKITE_COMMIT", "CIRCLE_SHA1", "GIT_COMMIT"]),
+];
+
+fn placeholder() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\{(\w+)\}").unwrap())
+}
+
+/// `--report-link`: a URL whose `{variables}` are all known, so typos fail up front
+pub fn parse_report_link(s: &str) -> Result<String, String> {
+    for captures in placeholder().captures_iter(s) {
+        let name = &captures[1];
+        if !VARIABLES.iter().any(|(known, _)| *known == name) {
+            let known: Vec<String> = VARIABLES.iter().map(|(known, _)| format!("{{{}}}", known)).collect();
+            return Err(format!("unknown variable {{{}}} (expected {})", name, known.join(", ")));
+        }
+    }
+    Ok(s.to_string())
+}
+
+/// The link with its variables filled in from the CI environment; `Err` names the first
+/// variable this run doesn't set
+pub fn resolve_report_link(template: &str) -> Result<String, String> {
+    let mut missing = None;
+    let url = placeholder().replace_all(template, |captures: &Captures| {
+        let name = &captures[1];
+        let value = VARIABLES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .and_then(|(_, vars)| vars.iter().find_map(|var| env::var(var).ok()));
+        value.unwrap_or_else(|| {
+            missing.get_or_insert_with(|| name.to_string());
+            String::new()
+        })
+    });
+
+    match missing {
+        Some(name) => Err(name),
+        None => Ok(url.into_owned()),
+    }
+}
@@ -0,0 +1,519 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+use tabled::builder::Builder;
+
+use crate::docker::DockerClient;
+use crate::format::{format_size, middle_ellipsis, render_table, OutputStyle};
+use crate::models::{ImageSnapshot, LayerInfo, MISSING_DIGEST};
+use crate::reference::ImageRef;
+use crate::store::Store;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReproducibilityFormat {
+    Table,
+    Json,
+    Markdown,
+}
+
+#[derive(Debug)]
+pub struct ReproducibilityOptions {
+    /// Inspect the image this many times, prompting for a rebuild in between
+    pub runs: Option<usize>,
+    /// Commit whose tracked snapshots are compared (default: the latest one tracked twice)
+    pub commit: Option<String>,
+    pub format: ReproducibilityFormat,
+    pub output: OutputStyle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerStability {
+    /// Same digest in every build
+    Stable,
+    /// Digest or size differs between builds
+    Unstable,
+    /// Same size, but at least one build has no recorded digest
+    Unverified,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LayerStabilityReport {
+    /// Position among the image's non-empty layers, oldest first
+    pub index: usize,
+    pub command: String,
+    pub stability: LayerStability,
+    /// Digest and size of this layer in each build
+    pub digests: Vec<String>,
+    pub sizes: Vec<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReproducibilityReport {
+    pub image: String,
+    pub tag: Option<String>,
+    pub builds: usize,
+    pub stable: usize,
+    pub unstable: usize,
+    pub unverified: usize,
+    /// Stable share of the layers that could be verified; `None` when none could
+    pub reproducibility_pct: Option<f64>,
+    /// Index of the first unstable layer; later layers build on top of it
+    pub first_unstable: Option<usize>,
+    pub layers: Vec<LayerStabilityReport>,
+}
+
+/// Compare the non-empty layers of `builds` position by position
+pub fn compare_builds(builds: &[&ImageSnapshot]) -> Result<ReproducibilityReport> {
+    if builds.len() < 2 {
+        bail!("Need at least two builds to compare, got {}", builds.len());
+    }
+
+    let layer_lists: Vec<Vec<&LayerInfo>> = builds
+        .iter()
+        .map(|s| s.layers.iter().filter(|l| l.size > 0).collect())
+        .collect();
+
+    let expected = layer_lists[0].len();
+    if let Some(other) = layer_lists.iter().find(|l| l.len() != expected) {
+        bail!(
+            "Builds have different layer counts ({} vs {}); were they built from the same Dockerfile?",
+            expected,
+            other.len()
+        );
+    }
+
+    let layers: Vec<LayerStabilityReport> = (0..expected)
+        .map(|index| {
+            let column: Vec<&LayerInfo> = layer_lists.iter().map(|l| l[index]).collect();
+            LayerStabilityReport {
+                index,
+                command: column[0].command.clone(),
+                stability: stability(&column),
+                digests: column.iter().map(|l| l.digest.clone()).collect(),
+                sizes: column.iter().map(|l| l.size).collect(),
+            }
+        })
+        .collect();
+
+    let count = |kind: LayerStability| layers.iter().filter(|l| l.stability == kind).count();
+    let (stable, unstable, unverified) = (
+        count(LayerStability::Stable),
+        count(LayerStability::Unstable),
+        count(LayerStability::Unverified),
+    );
+
+    let verified = stable + unstable;
+    let reproducibility_pct = if verified > 0 {
+        Some(stable as f64 / verified as f64 * 100.0)
+    } else {
+        None
+    };
+
+    Ok(ReproducibilityReport {
+        image: builds[0].image.clone(),
+        tag: builds[0].tag.clone(),
+        builds: builds.len(),
+        stable,
+        unstable,
+        unverified,
+        reproducibility_pct,
+        first_unstable: layers
+            .iter()
+            .position(|l| l.stability == LayerStability::Unstable),
+        layers,
+    })
+}
+
+fn stability(column: &[&LayerInfo]) -> LayerStability {
+    let first = column[0];
+    if column.iter().any(|l| l.size != first.size) {
+        return LayerStability::Unstable;
+    }
+
+    let digests: Vec<&str> = column
+        .iter()
+        .map(|l| l.digest.as_str())
+        .filter(|d| *d != MISSING_DIGEST)
+        .collect();
+
+    if digests.windows(2).any(|pair| pair[0] != pair[1]) {
+        LayerStability::Unstable
+    } else if digests.len() == column.len() {
+        LayerStability::Stable
+    } else {
+        LayerStability::Unverified
+    }
+}
+
+/// `dit reproducibility`: compare rebuilds of the same inputs
+pub async fn check_reproducibility(
+    out: &mut impl Write,
+    store: &Store,
+    image: &str,
+    options: &ReproducibilityOptions,
+) -> Result<()> {
+    let builds = match options.runs {
+        Some(runs) => inspect_rebuilds(image, runs).await?,
+        None => tracked_builds(store, image, options.commit.as_deref())?,
+    };
+
+    let builds: Vec<&ImageSnapshot> = builds.iter().collect();
+    let report = compare_builds(&builds)?;
+
+    match options.format {
+        ReproducibilityFormat::Table => print_report_table(out, &report, options.output)?,
+        ReproducibilityFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?,
+        ReproducibilityFormat::Markdown => write!(out, "{}", render_markdown(&report))?,
+    }
+
+    Ok(())
+}
+
+/// Tracked snapshots of `image` for `commit`, or for the latest commit tracked more than once
+fn tracked_builds(store: &Store, image: &str, commit: Option<&str>) -> Result<Vec<ImageSnapshot>> {
+    let reference = ImageRef::parse_lenient(image);
+    let mut image_history: Vec<ImageSnapshot> = store
+        .load_history()?
+        .into_iter()
+        .filter(|s| reference.matches(s))
+        .collect();
+    image_history.sort_by_key(|s| s.timestamp);
+
+    if image_history.is_empty() {
+        bail!("No history found for image '{}'", image);
+    }
+
+    let commit = match commit {
+        Some(commit) => commit.to_string(),
+        None => image_history
+            .iter()
+            .rev()
+            .map(|s| &s.commit_sha)
+            .find(|sha| image_history.iter().filter(|s| &s.commit_sha == *sha).count() > 1)
+            .cloned()
+            .context(format!(
+                "No commit of '{}' was tracked more than once; rebuild and track it again, or use --runs 2",
+                image
+            ))?,
+    };
+
+    let builds: Vec<ImageSnapshot> = image_history
+        .into_iter()
+        .filter(|s| s.commit_sha.starts_with(&commit))
+        .collect();
+
+    // `myapp` matches every tag; only compare builds of one of them
+    let tag = builds.last().and_then(|s| s.tag.clone());
+    let builds: Vec<ImageSnapshot> = builds.into_iter().filter(|s| s.tag == tag).collect();
+
+    if builds.len() < 2 {
+        bail!(
+            "Commit '{}' has {} snapshot(s) of '{}'; need at least two",
+            commit,
+            builds.len(),
+            image
+        );
+    }
+
+    Ok(builds)
+}
+
+/// Inspect `image` `runs` times, waiting for the user to rebuild it in between
+async fn inspect_rebuilds(image: &str, runs: usize) -> Result<Vec<ImageSnapshot>> {
+    if runs < 2 {
+        bail!("--runs must be at least 2");
+    }
+
+    let docker = DockerClient::new()?;
+    let mut builds = vec![docker.inspect_image(image).await?];
+
+    for run in 2..=runs {
+        eprint!(
+            "Rebuild {} (docker build --no-cache ...), then press Enter [run {} of {}] ",
+            image, run, runs
+        );
+        io::stderr().flush()?;
+        io::stdin()
+            .lock()
+            .read_line(&mut String::new())
+            .context("Failed to read from stdin")?;
+
+        let snapshot = docker.inspect_image(image).await?;
+        if snapshot.image_id.is_some() && builds.iter().any(|b| b.image_id == snapshot.image_id) {
+            eprintln!(
+                "⚠️  {} has the same image ID as an earlier run; was it rebuilt without the cache?",
+                image
+            );
+        }
+        builds.push(snapshot);
+    }
+
+    Ok(builds)
+}
+
+fn print_report_table(
+    out: &mut impl Write,
+    report: &ReproducibilityReport,
+    output: OutputStyle,
+) -> Result<()> {
+    writeln!(
+        out,
+        "\n🔁 Reproducibility of {}:{} across {} builds\n",
+        report.image,
+        report.tag.as_deref().unwrap_or("latest"),
+        report.builds
+    )?;
+
+    let mut builder = Builder::default();
+    builder.push_record(["#", "Size", "Status", "Command"]);
+
+    for layer in &report.layers {
+        let status = match layer.stability {
+            LayerStability::Stable => "stable".green().to_string(),
+            LayerStability::Unstable if Some(layer.index) == report.first_unstable => {
+                "UNSTABLE (first)".red().bold().to_string()
+            }
+            LayerStability::Unstable => "unstable".red().to_string(),
+            LayerStability::Unverified => "unverified".yellow().to_string(),
+        };
+        builder.push_record([
+            layer.index.to_string(),
+            size_range(&layer.sizes),
+            status,
+            middle_ellipsis(&layer.command, 60),
+        ]);
+    }
+
+    writeln!(out, "{}", render_table(builder, output.plain))?;
+
+    writeln!(
+        out,
+        "\nStable: {}  Unstable: {}  Unverified: {}",
+        report.stable, report.unstable, report.unverified
+    )?;
+    match report.reproducibility_pct {
+        Some(pct) => writeln!(out, "Reproducibility: {:.1}%", pct)?,
+        None => writeln!(out, "Reproducibility: unknown (no layer digests recorded)")?,
+    }
+
+    if let Some(first) = report.first_unstable {
+        writeln!(
+            out,
+            "{}",
+            format!(
+                "First unstable layer: #{} — {}\nEverything built on top of it changes too; \
+                 look for timestamps, package index updates or unpinned versions here first.",
+                first, report.layers[first].command
+            )
+            .red()
+        )?;
+    }
+    if report.unverified > 0 {
+        writeln!(
+            out,
+            "{} layer(s) have no recorded digest in every build and were compared by size only.",
+            report.unverified
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn render_markdown(report: &ReproducibilityReport) -> String {
+    let mut out = format!(
+        "## 🔁 Reproducibility: `{}:{}`\n\n",
+        report.image,
+        report.tag.as_deref().unwrap_or("latest")
+    );
+
+    out.push_str(&format!(
+        "**{}** across {} builds — {} stable, {} unstable, {} unverified\n\n",
+        match report.reproducibility_pct {
+            Some(pct) => format!("{:.1}%", pct),
+            None => "unknown".to_string(),
+        },
+        report.builds,
+        report.stable,
+        report.unstable,
+        report.unverified
+    ));
+
+    if let Some(first) = report.first_unstable {
+        out.push_str(&format!(
+            "> ⚠️ First unstable layer: #{} `{}`\n\n",
+            first, report.layers[first].command
+        ));
+    }
+
+    let unstable: Vec<&LayerStabilityReport> = report
+        .layers
+        .iter()
+        .filter(|l| l.stability == LayerStability::Unstable)
+        .collect();
+
+    if !unstable.is_empty() {
+        out.push_str("| # | Size | Command |\n|---|------|---------|\n");
+        for layer in unstable {
+            out.push_str(&format!(
+                "| {} | {} | `{}` |\n",
+                layer.index,
+                size_range(&layer.sizes),
+                layer.command.replace('|', "\\|")
+            ));
+        }
+    }
+
+    out
+}
+
+/// `12.0 MiB` when every build agrees, `12.0 MiB (±2.0 kiB)` otherwise
+fn size_range(sizes: &[u64]) -> String {
+    let min = sizes.iter().min().copied().unwrap_or(0);
+    let max = sizes.iter().max().copied().unwrap_or(0);
+
+    if min == max {
+        format_size(min)
+    } else {
+        format!("{} (±{})", format_size(min), format_size(max - min))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+    use tempfile::TempDir;
+
+    /// A build of `app:v1` at `commit` from `(digest, size)` layers, each with its own command
+    fn build(commit: &str, minutes: i64, layers: &[(&str, u64)]) -> ImageSnapshot {
+        let layers = layers
+            .iter()
+            .enumerate()
+            .map(|(i, (digest, size))| layer(digest, *size, &format!("RUN step {}", i)))
+            .collect();
+        tracked("app", "v1", commit, "main", minutes, layers)
+    }
+
+    fn stabilities(report: &ReproducibilityReport) -> Vec<LayerStability> {
+        report.layers.iter().map(|l| l.stability).collect()
+    }
+
+    #[test]
+    fn identical_builds_are_fully_reproducible() {
+        let a = build("c1", 0, &[("sha256:base", 100), ("sha256:app", 50)]);
+        let b = build("c1", 1, &[("sha256:base", 100), ("sha256:app", 50)]);
+        let report = compare_builds(&[&a, &b]).unwrap();
+
+        assert_eq!(report.stable, 2);
+        assert_eq!(report.unstable, 0);
+        assert_eq!(report.reproducibility_pct, Some(100.0));
+        assert_eq!(report.first_unstable, None);
+    }
+
+    #[test]
+    fn first_unstable_layer_is_flagged() {
+        let a = build("c1", 0, &[("sha256:base", 100), ("sha256:apt-1", 40), ("sha256:app-1", 50), ("sha256:cfg", 1)]);
+        let b = build("c1", 1, &[("sha256:base", 100), ("sha256:apt-2", 40), ("sha256:app-2", 50), ("sha256:cfg", 1)]);
+        let report = compare_builds(&[&a, &b]).unwrap();
+
+        use LayerStability::*;
+        assert_eq!(stabilities(&report), vec![Stable, Unstable, Unstable, Stable]);
+        assert_eq!(report.first_unstable, Some(1));
+        assert_eq!((report.stable, report.unstable, report.unverified), (2, 2, 0));
+        assert_eq!(report.reproducibility_pct, Some(50.0));
+    }
+
+    #[test]
+    fn a_size_change_is_unstable_even_without_digests() {
+        let a = build("c1", 0, &[(MISSING_DIGEST, 100), (MISSING_DIGEST, 50)]);
+        let b = build("c1", 1, &[(MISSING_DIGEST, 100), (MISSING_DIGEST, 51)]);
+        let report = compare_builds(&[&a, &b]).unwrap();
+
+        use LayerStability::*;
+        assert_eq!(stabilities(&report), vec![Unverified, Unstable]);
+        // Only the unstable layer could be verified
+        assert_eq!(report.reproducibility_pct, Some(0.0));
+    }
+
+    #[test]
+    fn unverifiable_builds_have_no_percentage() {
+        let a = build("c1", 0, &[(MISSING_DIGEST, 100)]);
+        let b = build("c1", 1, &[("sha256:base", 100)]);
+        let report = compare_builds(&[&a, &b]).unwrap();
+
+        assert_eq!(stabilities(&report), vec![LayerStability::Unverified]);
+        assert_eq!(report.reproducibility_pct, None);
+    }
+
+    #[test]
+    fn every_build_has_to_agree() {
+        let a = build("c1", 0, &[("sha256:base", 100)]);
+        let b = build("c1", 1, &[("sha256:base", 100)]);
+        let c = build("c1", 2, &[("sha256:other", 100)]);
+        let report = compare_builds(&[&a, &b, &c]).unwrap();
+
+        assert_eq!(report.builds, 3);
+        assert_eq!(stabilities(&report), vec![LayerStability::Unstable]);
+        assert_eq!(report.layers[0].digests.len(), 3);
+    }
+
+    #[test]
+    fn empty_layers_are_skipped() {
+        let a = build("c1", 0, &[("sha256:base", 100), ("sha256:env-1", 0), ("sha256:app", 50)]);
+        let b = build("c1", 1, &[("sha256:base", 100), ("sha256:app", 50)]);
+        let report = compare_builds(&[&a, &b]).unwrap();
+
+        assert_eq!(report.layers.len(), 2);
+        assert_eq!(report.stable, 2);
+    }
+
+    #[test]
+    fn mismatched_builds_are_rejected() {
+        let a = build("c1", 0, &[("sha256:base", 100)]);
+        let b = build("c1", 1, &[("sha256:base", 100), ("sha256:app", 50)]);
+        assert!(compare_builds(&[&a, &b]).is_err());
+        assert!(compare_builds(&[&a]).is_err());
+    }
+
+    #[test]
+    fn markdown_lists_unstable_layers() {
+        let a = build("c1", 0, &[("sha256:base", 100), ("sha256:app-1", 2000)]);
+        let b = build("c1", 1, &[("sha256:base", 100), ("sha256:app-2", 3000)]);
+        let markdown = render_markdown(&compare_builds(&[&a, &b]).unwrap());
+
+        assert!(markdown.starts_with("## 🔁 Reproducibility: `app:v1`\n\n"));
+        assert!(markdown.contains("**50.0%** across 2 builds — 1 stable, 1 unstable, 0 unverified"));
+        assert!(markdown.contains("> ⚠️ First unstable layer: #1 `RUN step 1`"));
+        assert!(markdown.contains("| 1 | 2.0 kiB (±1000 B) | `RUN step 1` |"), "{}", markdown);
+        assert!(!markdown.contains("RUN step 0"));
+    }
+
+    #[test]
+    fn tracked_builds_default_to_the_latest_commit_tracked_twice() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        let mut other_tag = build("c2", 4, &[("sha256:base", 100)]);
+        other_tag.tag = Some("v2".to_string());
+        store
+            .save_history(&[
+                build("c1", 0, &[("sha256:base", 100)]),
+                build("c1", 1, &[("sha256:base", 100)]),
+                build("c2", 2, &[("sha256:base", 100)]),
+                build("c2", 3, &[("sha256:base", 100)]),
+                other_tag,
+                build("c3", 5, &[("sha256:base", 100)]),
+            ])
+            .unwrap();
+
+        let builds = tracked_builds(&store, "app:v1", None).unwrap();
+        assert_eq!(builds.len(), 2);
+        assert!(builds.iter().all(|s| s.commit_sha == "c2" && s.tag.as_deref() == Some("v1")));
+
+        let builds = tracked_builds(&store, "app:v1", Some("c1")).unwrap();
+        assert!(builds.iter().all(|s| s.commit_sha == "c1"));
+        assert!(tracked_builds(&store, "app:v1", Some("c3")).is_err());
+    }
+}
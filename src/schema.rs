@@ -0,0 +1,216 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use schemars::{schema_for, Schema};
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+
+use crate::models::{ImageSnapshot, SizeDiff};
+use crate::report::ReportContext;
+
+/// Bumped whenever a published JSON shape changes incompatibly
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SchemaKind {
+    /// One snapshot (`dit analyze --format json`)
+    Snapshot,
+    /// `SizeDiff` as embedded in `dit ci --format json`
+    Diff,
+    /// `dit ci --print-template-context`
+    Report,
+    /// `.dit/history.json`
+    History,
+}
+
+/// JSON Schema for `kind`, generated from the same serde types that produce the output
+pub fn generate_schema(kind: SchemaKind) -> Value {
+    let mut schema: Schema = match kind {
+        SchemaKind::Snapshot => schema_for!(ImageSnapshot),
+        SchemaKind::Diff => schema_for!(SizeDiff),
+        SchemaKind::Report => schema_for!(ReportContext),
+        SchemaKind::History => schema_for!(Vec<ImageSnapshot>),
+    };
+    schema.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+
+    schema.to_value()
+}
+
+/// Print the schema, or validate `file` against it. Returns false when validation fails.
+pub fn run_schema(out: &mut impl Write, kind: SchemaKind, validate: Option<&str>) -> Result<bool> {
+    let schema = generate_schema(kind);
+
+    let Some(path) = validate else {
+        writeln!(out, "{}", serde_json::to_string_pretty(&schema)?)?;
+        return Ok(true);
+    };
+
+    let content = fs::read_to_string(path).context(format!("Failed to read {}", path))?;
+    let instance: Value =
+        serde_json::from_str(&content).context(format!("{} is not valid JSON", path))?;
+
+    let errors = validation_errors(&schema, &instance)?;
+    if errors.is_empty() {
+        writeln!(out, "✅ {} matches the {:?} schema (v{})", path, kind, SCHEMA_VERSION)?;
+        return Ok(true);
+    }
+
+    writeln!(
+        out,
+        "{}",
+        format!("❌ {} does not match the {:?} schema:", path, kind).red()
+    )?;
+    for (location, error) in &errors {
+        writeln!(out, "  {}: {}", location.bold(), error)?;
+    }
+
+    Ok(false)
+}
+
+/// `(JSON pointer, message)` for each place `instance` breaks `schema`
+fn validation_errors(schema: &Value, instance: &Value) -> Result<Vec<(String, String)>> {
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| anyhow!("Generated schema is invalid: {}", e))?;
+
+    Ok(validator
+        .iter_errors(instance)
+        .map(|error| {
+            let location = error.instance_path.to_string();
+            let location = if location.is_empty() { "/".to_string() } else { location };
+            (location, error.to_string())
+        })
+        .collect())
+}
+
+/// Read a snapshot exported with `dit analyze --format json` (with or without
+/// `--compare-to-previous`), checked against the snapshot schema first so a wrong or
+/// hand-edited file fails with the offending fields rather than a serde offset
+pub fn read_snapshot_file(path: &str) -> Result<ImageSnapshot> {
+    let content = fs::read_to_string(path).context(format!("Failed to read snapshot {}", path))?;
+    let mut value: Value =
+        serde_json::from_str(&content).context(format!("{} is not valid JSON", path))?;
+
+    if let Some(version) = value.get("schema_version").and_then(Value::as_u64) {
+        if version > u64::from(SCHEMA_VERSION) {
+            bail!(
+                "{} uses snapshot schema v{}, but this dit reads v{}. Upgrade dit to compare it.",
+                path,
+                version,
+                SCHEMA_VERSION
+            );
+        }
+    }
+    if let Some(snapshot) = value.get_mut("snapshot") {
+        value = snapshot.take();
+    }
+
+    let errors = validation_errors(&generate_schema(SchemaKind::Snapshot), &value)?;
+    if !errors.is_empty() {
+        let details: Vec<String> = errors
+            .iter()
+            .take(5)
+            .map(|(location, error)| format!("  {}: {}", location, error))
+            .collect();
+        bail!(
+            "{} is not a dit snapshot (expected `dit analyze --format json` output, schema v{}):\n{}{}",
+            path,
+            SCHEMA_VERSION,
+            details.join("\n"),
+            if errors.len() > 5 {
+                format!("\n  … and {} more", errors.len() - 5)
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    serde_json::from_value(value).context(format!("Failed to parse snapshot {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, snapshot};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn exported() -> Value {
+        serde_json::to_value(snapshot("myapp", "2.1", vec![layer("sha256:a", 100, "RUN make")])).unwrap()
+    }
+
+    fn read(value: &Value) -> Result<ImageSnapshot> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("snapshot.json");
+        fs::write(&path, value.to_string()).unwrap();
+        read_snapshot_file(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn plain_and_compare_to_previous_exports_are_read() {
+        let plain = read(&exported()).unwrap();
+        assert_eq!((plain.image.as_str(), plain.total_size), ("myapp", 100));
+
+        let compared = json!({
+            "schema_version": SCHEMA_VERSION,
+            "snapshot": exported(),
+            "changes_from_previous": null,
+            "platform_mismatch": false,
+        });
+        let compared = read(&compared).unwrap();
+        assert_eq!(serde_json::to_value(compared).unwrap(), serde_json::to_value(plain).unwrap());
+    }
+
+    #[test]
+    fn older_and_current_schema_versions_are_read() {
+        for version in [1, SCHEMA_VERSION] {
+            let mut value = exported();
+            value["schema_version"] = version.into();
+            assert!(read(&value).is_ok(), "v{}", version);
+        }
+    }
+
+    #[test]
+    fn newer_schema_versions_are_refused() {
+        let mut value = exported();
+        value["schema_version"] = (SCHEMA_VERSION + 1).into();
+        let error = read(&value).unwrap_err().to_string();
+
+        assert!(
+            error.contains(&format!("uses snapshot schema v{}, but this dit reads v{}", SCHEMA_VERSION + 1, SCHEMA_VERSION)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn invalid_snapshots_list_the_failing_fields() {
+        let mut value = exported();
+        value["total_size"] = json!("big");
+        value.as_object_mut().unwrap().remove("layers");
+        let error = read(&value).unwrap_err().to_string();
+
+        assert!(error.contains("is not a dit snapshot"), "{}", error);
+        assert!(error.contains("/total_size:"), "{}", error);
+        assert!(error.contains("\"layers\" is a required property"), "{}", error);
+    }
+
+    #[test]
+    fn only_the_first_five_failures_are_listed() {
+        let mut value = exported();
+        for field in ["image", "commit_sha", "branch", "os", "arch", "timestamp", "layers"] {
+            value[field] = json!(42);
+        }
+        let error = read(&value).unwrap_err().to_string();
+
+        assert_eq!(error.lines().filter(|l| l.starts_with("  /")).count(), 5, "{}", error);
+        assert!(error.contains("… and 2 more"), "{}", error);
+    }
+
+    #[test]
+    fn other_json_is_not_a_snapshot() {
+        assert!(read(&json!([exported()])).is_err());
+        assert!(read(&json!({ "images": [] })).is_err());
+        assert!(read_snapshot_file("no-such-snapshot.json").is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use rusqlite::{params, Connection, Transaction};
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::merge::find_conflict_marker;
+use crate::models::ImageSnapshot;
+use crate::reference::ImageRef;
+
+pub const HISTORY_FILE: &str = "history.json";
+const HISTORY_DB: &str = "history.db";
+const LFS_POINTER_PREFIX: &str = "version https://git-lfs.github.com/spec/";
+
+/// `--storage` / `storage` in .dit.toml: where a store keeps its snapshots
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// `.dit/history.json`, readable and diffable in git
+    #[default]
+    Json,
+    /// `.dit/history.db`; appends and single-image reads don't touch the whole history
+    Sqlite,
+}
+
+impl StorageKind {
+    pub fn backend(self, dir: PathBuf) -> Box<dyn StorageBackend> {
+        match self {
+            StorageKind::Json => Box::new(JsonBackend { dir }),
+            StorageKind::Sqlite => Box::new(SqliteBackend { dir }),
+        }
+    }
+}
+
+/// Snapshot storage behind a [`crate::store::Store`]. Schema checks, caching and
+/// exclusions stay in the store; backends only read and write snapshots.
+pub trait StorageBackend: Debug + Send + Sync {
+    /// The file holding the history
+    fn path(&self) -> PathBuf;
+
+    /// Every snapshot, in the order they were saved
+    fn load_history(&self) -> Result<Vec<ImageSnapshot>>;
+
+    /// Snapshots matching `reference`, in the order they were saved
+    fn load_history_for_image(&self, reference: &ImageRef) -> Result<Vec<ImageSnapshot>>;
+
+    fn save_snapshot(&self, snapshot: &ImageSnapshot) -> Result<()>;
+
+    /// Replace the stored history with `snapshots`
+    fn save_history(&self, snapshots: &[ImageSnapshot]) -> Result<()>;
+
+    /// Delete snapshots recorded before `cutoff`; returns how many were deleted
+    fn delete_snapshots_before(&self, cutoff: DateTime<Utc>) -> Result<usize>;
+}
+
+/// `history.json`: the whole history is read and rewritten on every access
+#[derive(Debug)]
+pub struct JsonBackend {
+    dir: PathBuf,
+}
+
+impl StorageBackend for JsonBackend {
+    fn path(&self) -> PathBuf {
+        self.dir.join(HISTORY_FILE)
+    }
+
+    fn load_history(&self) -> Result<Vec<ImageSnapshot>> {
+        let history_path = self.path();
+
+        if !history_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = fs::read_to_string(&history_path)
+            .context("Failed to read history.json")?;
+
+        // Committed history files can be mangled by git; say how instead of a serde offset
+        if content.starts_with(LFS_POINTER_PREFIX) {
+            bail!(
+                "{} is a Git LFS pointer, not the history itself. Run 'git lfs pull' to fetch it.",
+                history_path.display()
+            );
+        }
+        if let Some(line) = find_conflict_marker(&content) {
+            bail!(
+                "{} has unresolved merge conflict markers (line {}). Resolve them by hand, or run \
+                 'dit merge-history --union' to keep snapshots from both sides \
+                 (--ours / --theirs keep one side).",
+                history_path.display(),
+                line
+            );
+        }
+
+        let snapshots: Vec<ImageSnapshot> = serde_json::from_str(&content)
+            .context("Failed to parse history.json")?;
+
+        Ok(snapshots)
+    }
+
+    fn load_history_for_image(&self, reference: &ImageRef) -> Result<Vec<ImageSnapshot>> {
+        let mut history = self.load_history()?;
+        history.retain(|s| reference.matches(s));
+        Ok(history)
+    }
+
+    fn save_snapshot(&self, snapshot: &ImageSnapshot) -> Result<()> {
+        let mut snapshots = self.load_history()?;
+        snapshots.push(snapshot.clone());
+        self.save_history(&snapshots)
+    }
+
+    fn save_history(&self, snapshots: &[ImageSnapshot]) -> Result<()> {
+        let json = serde_json::to_string_pretty(snapshots)?;
+        fs::write(self.path(), json).context("Failed to write history.json")
+    }
+
+    fn delete_snapshots_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let mut snapshots = self.load_history()?;
+        let before = snapshots.len();
+        snapshots.retain(|s| s.timestamp >= cutoff);
+        let deleted = before - snapshots.len();
+        if deleted > 0 {
+            self.save_history(&snapshots)?;
+        }
+        Ok(deleted)
+    }
+}
+
+/// `history.db`: one row per snapshot, indexed by image so single-image reads skip the rest
+#[derive(Debug)]
+pub struct SqliteBackend {
+    dir: PathBuf,
+}
+
+/// `image` is the name as [`ImageRef::name`] normalizes it (`docker.io/library/myapp` is
+/// `myapp`), so every spelling of a reference finds the same rows. The snapshot itself is
+/// stored as JSON.
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS snapshots (
+        id INTEGER PRIMARY KEY,
+        image TEXT NOT NULL,
+        commit_sha TEXT NOT NULL,
+        branch TEXT NOT NULL,
+        timestamp_ms INTEGER NOT NULL,
+        snapshot TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS snapshots_by_commit ON snapshots (image, commit_sha, timestamp_ms);
+    CREATE INDEX IF NOT EXISTS snapshots_by_branch ON snapshots (image, branch, timestamp_ms);
+";
+
+impl SqliteBackend {
+    /// Open the database, creating it on first use. A new database starts with the
+    /// snapshots of an existing history.json, which is left in place.
+    fn open(&self) -> Result<Connection> {
+        let path = self.path();
+        let created = !path.exists();
+        let mut conn = Connection::open(&path)
+            .context(format!("Failed to open {}", path.display()))?;
+        conn.execute_batch(SQLITE_SCHEMA)
+            .context(format!("Failed to create the tables in {}", path.display()))?;
+
+        if created {
+            let json = self.json();
+            let snapshots = json.load_history()?;
+            if !snapshots.is_empty() {
+                let tx = conn.transaction()?;
+                insert(&tx, &snapshots)?;
+                tx.commit()?;
+                eprintln!(
+                    "📦 Copied {} snapshot(s) from {} into {}",
+                    snapshots.len(),
+                    json.path().display(),
+                    path.display()
+                );
+            }
+        }
+
+        Ok(conn)
+    }
+
+    /// history.json in the same directory
+    fn json(&self) -> JsonBackend {
+        JsonBackend {
+            dir: self.dir.clone(),
+        }
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<ImageSnapshot>> {
+        let conn = self.open()?;
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(params, |row| row.get::<_, String>(0))?;
+
+        let mut snapshots = Vec::new();
+        for json in rows {
+            let snapshot = serde_json::from_str(&json?)
+                .context(format!("Failed to parse a snapshot in {}", self.path().display()))?;
+            snapshots.push(snapshot);
+        }
+        Ok(snapshots)
+    }
+}
+
+fn insert(tx: &Transaction, snapshots: &[ImageSnapshot]) -> Result<()> {
+    let mut statement = tx.prepare(
+        "INSERT INTO snapshots (image, commit_sha, branch, timestamp_ms, snapshot)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for snapshot in snapshots {
+        statement.execute(params![
+            ImageRef::parse_lenient(&snapshot.image).name(),
+            snapshot.commit_sha,
+            snapshot.branch,
+            snapshot.timestamp.timestamp_millis(),
+            serde_json::to_string(snapshot)?,
+        ])?;
+    }
+    Ok(())
+}
+
+impl StorageBackend for SqliteBackend {
+    fn path(&self) -> PathBuf {
+        self.dir.join(HISTORY_DB)
+    }
+
+    fn load_history(&self) -> Result<Vec<ImageSnapshot>> {
+        // Reads never create the database, so read-only stores stay untouched
+        if !self.path().exists() {
+            return self.json().load_history();
+        }
+        self.query("SELECT snapshot FROM snapshots ORDER BY id", [])
+    }
+
+    fn load_history_for_image(&self, reference: &ImageRef) -> Result<Vec<ImageSnapshot>> {
+        if !self.path().exists() {
+            return self.json().load_history_for_image(reference);
+        }
+        let mut snapshots = self.query(
+            "SELECT snapshot FROM snapshots WHERE image = ?1 ORDER BY id",
+            [reference.name()],
+        )?;
+        // The tag isn't indexed
+        snapshots.retain(|s| reference.matches(s));
+        Ok(snapshots)
+    }
+
+    fn save_snapshot(&self, snapshot: &ImageSnapshot) -> Result<()> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        insert(&tx, std::slice::from_ref(snapshot))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn save_history(&self, snapshots: &[ImageSnapshot]) -> Result<()> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM snapshots", [])?;
+        insert(&tx, snapshots)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_snapshots_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let conn = self.open()?;
+        let deleted = conn.execute(
+            "DELETE FROM snapshots WHERE timestamp_ms < ?1",
+            [cutoff.timestamp_millis()],
+        )?;
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,608 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::exclude::SnapshotExclusions;
+use crate::format::short_image_id;
+use crate::models::{ImageSnapshot, SizeDiff};
+use crate::reference::ImageRef;
+use crate::remote::RemoteBackend;
+use crate::schema::SCHEMA_VERSION;
+use crate::storage::{StorageBackend, StorageKind, HISTORY_FILE};
+use crate::version::{
+    compatibility, newer_store_message, Compatibility, StoreMeta, DIT_VERSION,
+};
+use crate::watch::WatchedImage;
+
+const HISTORY_DIR: &str = ".dit";
+const DIFFS_DIR: &str = "diffs";
+const WATCH_LIST_FILE: &str = "watch-list.json";
+const META_FILE: &str = "store.json";
+
+/// In-process copy of the history, shared by clones of a caching `Store`
+type HistoryCache = Arc<Mutex<Option<Arc<Vec<ImageSnapshot>>>>>;
+
+/// Handle to a history location; every command reads and writes through one of these
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+    storage: StorageKind,
+    backend: Arc<dyn StorageBackend>,
+    /// `--remote`: snapshots are also uploaded there, and fetched when missing locally
+    remote: Option<Arc<RemoteBackend>>,
+    cache: Option<HistoryCache>,
+    read_only: bool,
+    exclusions: Option<SnapshotExclusions>,
+}
+
+impl Default for Store {
+    /// `.dit/` relative to the current directory
+    fn default() -> Self {
+        Self::new(HISTORY_DIR)
+    }
+}
+
+impl Store {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Self {
+            backend: StorageKind::default().backend(dir.clone()).into(),
+            storage: StorageKind::default(),
+            dir,
+            remote: None,
+            cache: None,
+            read_only: false,
+            exclusions: None,
+        }
+    }
+
+    /// Skip every write: saves succeed without touching the store (e.g. a shared read-only mount)
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether history can be written here: the directory, or the nearest existing parent
+    /// it would be created in, accepts a new file. A permissions check alone misses
+    /// read-only mounts, so this creates and removes a probe file.
+    pub fn is_writable(&self) -> bool {
+        // `.dit` has the empty path as its parent, meaning the current directory
+        let existing = self
+            .dir
+            .ancestors()
+            .map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir })
+            .find(|dir| dir.exists());
+        let Some(existing) = existing else {
+            return false;
+        };
+
+        let probe = existing.join(format!(".dit-write-test-{}", std::process::id()));
+        match OpenOptions::new().write(true).create_new(true).open(&probe) {
+            Ok(_) => {
+                let _ = fs::remove_file(&probe);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Keep snapshots in `storage` instead of history.json
+    pub fn with_storage(mut self, storage: StorageKind) -> Self {
+        self.backend = storage.backend(self.dir.clone()).into();
+        self.storage = storage;
+        self
+    }
+
+    pub fn storage(&self) -> StorageKind {
+        self.storage
+    }
+
+    /// Share snapshots through `remote` as well as the local history
+    pub fn with_remote(mut self, remote: RemoteBackend) -> Self {
+        self.remote = Some(Arc::new(remote));
+        self
+    }
+
+    /// Read the history at most once per process; writes through this store refresh the copy
+    pub fn with_history_cache(mut self) -> Self {
+        self.cache = Some(Arc::default());
+        self
+    }
+
+    /// Hide snapshots matching `exclusions` from [`Store::load_history`]. Writes still see
+    /// the full history, so nothing excluded is ever dropped from the file.
+    pub fn with_exclusions(mut self, exclusions: SnapshotExclusions) -> Self {
+        self.exclusions = (!exclusions.is_empty()).then_some(exclusions);
+        self
+    }
+
+    /// How many stored snapshots the exclusions hide
+    pub fn excluded_count(&self) -> Result<usize> {
+        let Some(exclusions) = &self.exclusions else {
+            return Ok(0);
+        };
+        let history = self.load_all_history()?;
+        Ok(history.iter().filter(|s| exclusions.excludes(s)).count())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// history.json, or history.db with `--storage sqlite`
+    pub fn history_path(&self) -> PathBuf {
+        self.backend.path()
+    }
+
+    pub fn save_snapshot(&self, snapshot: &ImageSnapshot) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        // Create the history directory if it doesn't exist
+        if !self.dir.exists() {
+            fs::create_dir_all(&self.dir).context("Failed to create .dit directory")?;
+        }
+
+        self.check_schema()?;
+        self.backend.save_snapshot(snapshot)?;
+        self.save_meta()?;
+
+        if let Some(cache) = &self.cache {
+            if let Some(history) = cache.lock().unwrap().as_mut() {
+                Arc::make_mut(history).push(snapshot.clone());
+            }
+        }
+
+        // The local save already succeeded; a failed upload shouldn't undo it
+        if let Some(remote) = &self.remote {
+            match remote.save_snapshot(snapshot) {
+                Ok(()) => eprintln!("☁️  Uploaded the snapshot to {}", remote.path().display()),
+                Err(e) => eprintln!(
+                    "⚠️  Snapshot saved locally but not to {}: {:#}",
+                    remote.path().display(),
+                    e
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the stored history with `snapshots`
+    pub fn save_history(&self, snapshots: &[ImageSnapshot]) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        if !self.dir.exists() {
+            fs::create_dir_all(&self.dir).context("Failed to create .dit directory")?;
+        }
+
+        self.check_schema()?;
+        self.backend.save_history(snapshots)?;
+        self.save_meta()?;
+
+        if let Some(cache) = &self.cache {
+            *cache.lock().unwrap() = Some(Arc::new(snapshots.to_vec()));
+        }
+
+        Ok(())
+    }
+
+    /// Delete snapshots recorded before `cutoff`, excluded ones included; returns how many
+    pub fn delete_snapshots_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        if self.read_only {
+            return Ok(0);
+        }
+
+        self.check_schema()?;
+        let deleted = self.backend.delete_snapshots_before(cutoff)?;
+
+        if let Some(cache) = &self.cache {
+            if let Some(history) = cache.lock().unwrap().as_mut() {
+                Arc::make_mut(history).retain(|s| s.timestamp >= cutoff);
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Rewritten only when the schema or writer changes, to keep git diffs quiet
+    fn save_meta(&self) -> Result<()> {
+        let meta = StoreMeta::current();
+        if self.load_meta()?.as_ref() != Some(&meta) {
+            let path = self.dir.join(META_FILE);
+            fs::write(&path, serde_json::to_string_pretty(&meta)?)
+                .context(format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// The file history is read from: a new sqlite store reads history.json until its
+    /// first write creates history.db. `None` before anything was tracked.
+    pub fn existing_history_path(&self) -> Option<PathBuf> {
+        [self.history_path(), self.dir.join(HISTORY_FILE)]
+            .into_iter()
+            .find(|path| path.exists())
+    }
+
+    /// Copy the history file aside (`history.json.bak-<timestamp>`) before a bulk rewrite
+    pub fn backup_history(&self) -> Result<PathBuf> {
+        let history_path = self
+            .existing_history_path()
+            .context("No history to back up")?;
+        let backup = self.dir.join(format!(
+            "{}.bak-{}",
+            history_path.file_name().unwrap_or_default().to_string_lossy(),
+            Utc::now().format("%Y%m%d%H%M%S")
+        ));
+        fs::copy(&history_path, &backup)
+            .context(format!("Failed to back up history to {}", backup.display()))?;
+
+        Ok(backup)
+    }
+
+    /// Persist `diff` as `diffs/<image>/<before>_<after>.json`; returns the file written,
+    /// or `None` for a read-only store
+    pub fn save_diff(&self, diff: &SizeDiff) -> Result<Option<PathBuf>> {
+        if self.read_only {
+            return Ok(None);
+        }
+
+        let dir = self.dir.join(DIFFS_DIR).join(sanitize(&diff.after.image));
+        fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+
+        let path = dir.join(format!(
+            "{}_{}.json",
+            snapshot_key(&diff.before),
+            snapshot_key(&diff.after)
+        ));
+        fs::write(&path, serde_json::to_string_pretty(diff)?)
+            .context(format!("Failed to write {}", path.display()))?;
+
+        Ok(Some(path))
+    }
+
+    /// Diffs saved with `dit diff --save-diff-to-history` for `image` (`myapp` or
+    /// `myapp:tag`), oldest first
+    pub fn load_diff_history(&self, image: &str) -> Result<Vec<SizeDiff>> {
+        let root = self.dir.join(DIFFS_DIR);
+        if !root.exists() {
+            return Ok(Vec::new());
+        }
+
+        let reference = ImageRef::parse_lenient(image);
+        let mut diffs = Vec::new();
+
+        // Every image directory is read: names recorded before `dit migrate-refs` may
+        // differ from the reference's canonical form
+        for dir in fs::read_dir(&root).context(format!("Failed to read {}", root.display()))? {
+            let dir = dir?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+
+            for file in fs::read_dir(&dir).context(format!("Failed to read {}", dir.display()))? {
+                let path = file?.path();
+                if path.extension().is_some_and(|e| e == "json") {
+                    let content = fs::read_to_string(&path)
+                        .context(format!("Failed to read {}", path.display()))?;
+                    let diff: SizeDiff = serde_json::from_str(&content)
+                        .context(format!("Failed to parse {}", path.display()))?;
+                    if reference.matches(&diff.after) {
+                        diffs.push(diff);
+                    }
+                }
+            }
+        }
+
+        diffs.sort_by_key(|d| d.after.timestamp);
+        Ok(diffs)
+    }
+
+    /// Schema version record; `None` for stores written before dit kept one
+    pub fn load_meta(&self) -> Result<Option<StoreMeta>> {
+        let path = self.dir.join(META_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content =
+            fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+        let meta = serde_json::from_str(&content)
+            .context(format!("Failed to parse {}", path.display()))?;
+        Ok(Some(meta))
+    }
+
+    /// Refuse history written with a schema newer than this binary understands
+    fn check_schema(&self) -> Result<()> {
+        if let Some(meta) = self.load_meta()? {
+            if let Compatibility::NewerStore { .. } = compatibility(SCHEMA_VERSION, &meta) {
+                let dir = self.dir.display().to_string();
+                bail!(newer_store_message(&dir, &meta, DIT_VERSION));
+            }
+        }
+        Ok(())
+    }
+
+    /// Third-party images registered with `dit watch-list add`
+    pub fn load_watch_list(&self) -> Result<Vec<WatchedImage>> {
+        let path = self.dir.join(WATCH_LIST_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content =
+            fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).context(format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save_watch_list(&self, watched: &[WatchedImage]) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir).context("Failed to create .dit directory")?;
+        let path = self.dir.join(WATCH_LIST_FILE);
+        fs::write(&path, serde_json::to_string_pretty(watched)?)
+            .context(format!("Failed to write {}", path.display()))
+    }
+
+    /// Stored snapshots, minus any hidden by [`Store::with_exclusions`]
+    pub fn load_history(&self) -> Result<Vec<ImageSnapshot>> {
+        let history = self.load_all_history()?;
+        Ok(match &self.exclusions {
+            Some(exclusions) => exclusions.apply(history),
+            None => history,
+        })
+    }
+
+    fn load_all_history(&self) -> Result<Vec<ImageSnapshot>> {
+        match &self.cache {
+            Some(_) => Ok(self.load_history_cached()?.to_vec()),
+            None => self.read_history(),
+        }
+    }
+
+    /// Shared history; only reads the file on first use when the cache is enabled.
+    /// Exclusions are not applied here.
+    pub fn load_history_cached(&self) -> Result<Arc<Vec<ImageSnapshot>>> {
+        let Some(cache) = &self.cache else {
+            return Ok(Arc::new(self.read_history()?));
+        };
+
+        let mut cached = cache.lock().unwrap();
+        if let Some(history) = cached.as_ref() {
+            return Ok(Arc::clone(history));
+        }
+
+        let history = Arc::new(self.read_history()?);
+        *cached = Some(Arc::clone(&history));
+        Ok(history)
+    }
+
+    /// Stored snapshots of `image` (`myapp` or `myapp:tag`), minus excluded ones. The
+    /// sqlite backend reads only this image's rows; with `--remote`, snapshots only the
+    /// remote has are added in recording order.
+    pub fn load_history_for_image(&self, image: &str) -> Result<Vec<ImageSnapshot>> {
+        let reference = ImageRef::parse_lenient(image);
+        let mut history = match &self.cache {
+            Some(_) => {
+                let mut history = self.load_history_cached()?.to_vec();
+                history.retain(|s| reference.matches(s));
+                history
+            }
+            None => {
+                self.check_schema()?;
+                self.backend.load_history_for_image(&reference)?
+            }
+        };
+        if let Some(remote) = &self.remote {
+            let fetched = remote.load_missing(&reference, &history)?;
+            if !fetched.is_empty() {
+                eprintln!(
+                    "☁️  Fetched {} snapshot(s) of {} from {}",
+                    fetched.len(),
+                    image,
+                    remote.path().display()
+                );
+                history.extend(fetched);
+                history.sort_by_key(|s| s.timestamp);
+            }
+        }
+        Ok(match &self.exclusions {
+            Some(exclusions) => exclusions.apply(history),
+            None => history,
+        })
+    }
+
+    fn read_history(&self) -> Result<Vec<ImageSnapshot>> {
+        self.check_schema()?;
+        self.backend.load_history()
+    }
+}
+
+/// Slugs longer than this keep their start and the hash
+const MAX_SLUG_LEN: usize = 100;
+
+/// Directory-safe form of an image name. Plain lowercase names stay as they are; any
+/// other gets its separators replaced and a hash of the full name appended
+/// (`ghcr.io/org/app` → `ghcr.io_org_app-<hash>`), since `org/a_b` and `org/a/b`
+/// would otherwise share a directory.
+pub fn sanitize(name: &str) -> String {
+    let plain = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-';
+    if !name.is_empty() && !name.starts_with('.') && name.len() <= MAX_SLUG_LEN && name.chars().all(plain) {
+        return name.to_string();
+    }
+
+    let readable: String = name
+        .trim_start_matches('.')
+        .chars()
+        .map(|c| if plain(c) || c.is_ascii_uppercase() { c } else { '_' })
+        .take(MAX_SLUG_LEN)
+        .collect();
+    format!("{}-{:016x}", readable, fnv1a(name))
+}
+
+/// 64-bit FNV-1a: stable across builds and platforms, unlike `DefaultHasher`
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Commit SHA, or the image ID for snapshots without git context
+pub fn snapshot_key(snapshot: &ImageSnapshot) -> String {
+    if !snapshot.commit_sha.is_empty() {
+        return snapshot.commit_sha.clone();
+    }
+
+    match &snapshot.image_id {
+        Some(id) => short_image_id(id),
+        None => "untracked".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::compute_diff;
+    use crate::fixtures::{layer, tracked};
+    use std::collections::HashSet;
+
+    #[test]
+    fn plain_names_are_their_own_slug() {
+        assert_eq!(sanitize("myapp"), "myapp");
+        assert_eq!(sanitize("my-app.v2"), "my-app.v2");
+    }
+
+    #[test]
+    fn names_differing_only_in_separators_get_different_slugs() {
+        let names = [
+            "org/a_b",
+            "org/a/b",
+            "org_a/b",
+            "org:a/b",
+            "org@a/b",
+            "org_a_b",
+            "org_a_b-0000000000000000",
+            "Org/a/b",
+            "ORG/A/B",
+            "localhost:5000/app",
+            "localhost/5000/app",
+        ];
+
+        let slugs: HashSet<String> = names.iter().map(|name| sanitize(name)).collect();
+        assert_eq!(slugs.len(), names.len(), "{:?}", slugs);
+        // Case-insensitive filesystems too
+        let folded: HashSet<String> = slugs.iter().map(|slug| slug.to_lowercase()).collect();
+        assert_eq!(folded.len(), names.len());
+
+        assert!(sanitize("ghcr.io/org/app").starts_with("ghcr.io_org_app-"));
+        assert_eq!(sanitize("org/a_b"), sanitize("org/a_b"));
+    }
+
+    #[test]
+    fn slugs_stay_inside_the_diffs_directory() {
+        for name in ["..", ".", "", "../../etc", "a/../../b", ".hidden"] {
+            let slug = sanitize(name);
+            assert!(!slug.is_empty() && !slug.starts_with('.'), "{:?} → {:?}", name, slug);
+            assert!(!slug.contains('/'), "{:?} → {:?}", name, slug);
+        }
+    }
+
+    #[test]
+    fn long_names_differing_in_the_middle_or_the_end_get_different_slugs() {
+        let prefix = "europe-west1-docker.pkg.dev/very-long-project";
+        let suffix = "team-repo/service-name";
+        let filler = "x".repeat(120);
+        let names = [
+            format!("{}/{}a/{}", prefix, filler, suffix),
+            format!("{}/{}b/{}", prefix, filler, suffix),
+            format!("{}/{}/{}-one", prefix, filler, suffix),
+            format!("{}/{}/{}-two", prefix, filler, suffix),
+            "a".repeat(150),
+            "a".repeat(151),
+        ];
+
+        let slugs: HashSet<String> = names.iter().map(|name| sanitize(name)).collect();
+        assert_eq!(slugs.len(), names.len());
+        assert!(slugs.iter().all(|slug| slug.len() <= MAX_SLUG_LEN + 17));
+    }
+
+    #[test]
+    fn diffs_of_colliding_names_are_kept_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+
+        for (image, size) in [("org/a_b", 10), ("org/a/b", 20)] {
+            let before = tracked(image, "1", "aaa", "main", 1, vec![layer("sha256:a", 1, "")]);
+            let after = tracked(image, "1", "bbb", "main", 2, vec![layer("sha256:b", size, "")]);
+            store.save_diff(&compute_diff(before, after)).unwrap();
+        }
+
+        for (image, size) in [("org/a_b", 10), ("org/a/b", 20)] {
+            let diffs = store.load_diff_history(image).unwrap();
+            assert_eq!(diffs.len(), 1, "{}", image);
+            assert_eq!(diffs[0].after.total_size, size);
+        }
+        assert_eq!(fs::read_dir(dir.path().join(".dit").join(DIFFS_DIR)).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn a_fresh_directory_is_writable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // Probed in the parent, since .dit doesn't exist yet
+        assert!(Store::new(dir.path().join(".dit")).is_writable());
+        assert!(!dir.path().join(".dit").exists());
+        // The probe cleans up after itself
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn a_path_under_a_file_is_not_writable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("mount"), "").unwrap();
+        assert!(!Store::new(dir.path().join("mount").join(".dit")).is_writable());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_read_only_directory_is_not_writable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let shared = dir.path().join("shared");
+        fs::create_dir(&shared).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Root ignores directory permissions, so there is nothing to detect
+        let bypassed = fs::write(shared.join("probe"), "").is_ok();
+        if !bypassed {
+            assert!(!Store::new(shared.join(".dit")).is_writable());
+        }
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn read_only_stores_skip_every_save() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit")).read_only();
+        assert!(store.is_read_only());
+
+        let snapshot = tracked("app", "v1", "c1", "main", 0, vec![layer("sha256:a", 1, "RUN a")]);
+        store.save_snapshot(&snapshot).unwrap();
+        store.save_history(std::slice::from_ref(&snapshot)).unwrap();
+        let diff = compute_diff(snapshot.clone(), snapshot);
+        assert_eq!(store.save_diff(&diff).unwrap(), None);
+        assert!(!dir.path().join(".dit").exists());
+    }
+}
+
@@ -1,91 +1,536 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use colored::Colorize;
-use std::collections::HashMap;
-use tabled::{
-    builder::Builder,
-    settings::{object::Rows, Alignment, Modify, Style},
-};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use tabled::{builder::Builder, settings::Style};
 
-use crate::chart::calculate_trend_with_sparkline;
-use crate::format::format_size;
+use crate::analyze::OutputFormat;
+use crate::chart::{calculate_trend_with_sparkline, SparklineStyle};
+use crate::docker::parse_image_reference;
+use crate::format::{
+    format_size, format_size_delta, format_timestamp, render_table_with, shorten_reference,
+    OutputStyle,
+};
+use crate::labels::{group_by_label, label_cells, label_headers, LabelKey};
+use crate::metric::Metric;
 use crate::models::ImageSnapshot;
-use crate::track::load_history;
+use crate::platforms::{aggregate, has_groups, PlatformMode};
+use crate::store::Store;
+
+/// Changes under this much count as stable in `trend_description`
+const STABLE_BYTES: i64 = 10 * 1024;
+
+#[derive(Debug)]
+pub struct SummaryOptions {
+    pub group_by: Option<LabelKey>,
+    pub columns: Vec<LabelKey>,
+    /// Show full image references instead of shortening long ones
+    pub wide: bool,
+    /// Nest tag variants under their base image with a total row
+    pub group_by_image: bool,
+    /// Multi-platform builds: a row per platform, or one aggregated row
+    pub platforms: PlatformMode,
+    /// Sparkline characters and table borders
+    pub style: SparklineStyle,
+    /// `json` prints a [`SummaryEntry`] array and `csv` prints
+    /// `image,tag,latest_size_bytes,snapshot_count,last_tracked_utc,trend_description`
+    /// rows, then a `_total` row; grouping and label columns are table-only
+    pub format: OutputFormat,
+    /// Field separator for `csv`, e.g. `;` where commas are decimal separators
+    pub csv_delimiter: char,
+    /// Latest value and trend shown in the table; totals, JSON and CSV are sizes
+    pub metric: Metric,
+    pub output: OutputStyle,
+}
+
+/// One image in `dit summary --format json`
+#[derive(Debug, Serialize)]
+pub struct SummaryEntry {
+    /// `name:tag`
+    pub image: String,
+    /// `os/arch`, when multi-platform builds are listed separately
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    pub latest_size: u64,
+    pub snapshot_count: usize,
+    /// Sizes of the last 10 snapshots, oldest first (what the sparkline draws)
+    pub trend: Vec<u64>,
+    pub last_tracked: DateTime<Utc>,
+}
 
-pub async fn show_summary() -> Result<()> {
-    let history = load_history()?;
+pub async fn show_summary(
+    out: &mut impl Write,
+    store: &Store,
+    options: SummaryOptions,
+) -> Result<()> {
+    let group_by = options.group_by.as_ref();
+    let columns = &options.columns;
 
+    let history = aggregate(store.load_history()?, options.platforms);
+    let grouped = has_groups(&history);
+
+    if matches!(options.format, OutputFormat::Csv) && options.csv_delimiter != ',' {
+        eprintln!("{}", format!("CSV ({})", delimiter_name(options.csv_delimiter)).dimmed());
+    }
+
+    if history.is_empty() && matches!(options.format, OutputFormat::Json) {
+        writeln!(out, "[]")?;
+        return Ok(());
+    }
+    if history.is_empty() && matches!(options.format, OutputFormat::Csv) {
+        let entries = summary_entries(&HashMap::new());
+        write!(out, "{}", render_csv(&entries, options.csv_delimiter))?;
+        return Ok(());
+    }
     if history.is_empty() {
-        println!("No tracked images found. Use 'dit track' or 'dit track-all' to start tracking.");
+        writeln!(
+            out,
+            "No tracked images found. Use 'dit track' or 'dit track-all' to start tracking."
+        )?;
         return Ok(());
     }
 
-    // Group snapshots by image
-    let mut by_image: HashMap<String, Vec<ImageSnapshot>> = HashMap::new();
+    // Group snapshots by image, and by platform when multi-platform builds are shown separately
+    let mut by_image: HashMap<(String, String), Vec<ImageSnapshot>> = HashMap::new();
 
     for snapshot in history {
         let key = format!("{}:{}", 
             snapshot.image, 
             snapshot.tag.as_deref().unwrap_or("latest")
         );
-        by_image.entry(key).or_insert_with(Vec::new).push(snapshot);
+        let platform = match options.platforms {
+            PlatformMode::Separate if snapshot.group_id.is_some() => snapshot.platform(),
+            _ => String::new(),
+        };
+        by_image.entry((key, platform)).or_default().push(snapshot);
     }
 
     // Sort each image's snapshots by timestamp
     for snapshots in by_image.values_mut() {
-        snapshots.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        snapshots.sort_by_key(|s| s.timestamp);
     }
 
-    println!("\n{}", "Docker Image Tracker Summary".bold().underline());
-    println!("Total tracked images: {}\n", by_image.len());
+    match options.format {
+        OutputFormat::Json => {
+            writeln!(out, "{}", serde_json::to_string_pretty(&summary_entries(&by_image))?)?;
+            return Ok(());
+        }
+        OutputFormat::Csv => {
+            let entries = summary_entries(&by_image);
+            write!(out, "{}", render_csv(&entries, options.csv_delimiter))?;
+            return Ok(());
+        }
+        OutputFormat::Table => {}
+    }
+
+    writeln!(out, "\n{}", "Docker Image Tracker Summary".bold().underline())?;
+    writeln!(out, "Total tracked images: {}\n", by_image.len())?;
+
+    let latest_heading = format!("Latest {}", options.metric.label());
+    let mut header: Vec<String> = ["Image", &latest_heading, "Trend", "Snapshots", "Last Tracked"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+    if grouped {
+        header.push("Platform".to_string());
+    }
+    header.extend(label_headers(columns));
+    let width = header.len();
 
     let mut builder = Builder::default();
-    builder.push_record([
-        "Image",
-        "Latest Size",
-        "Trend",
-        "Snapshots",
-        "Last Tracked",
-    ]);
+    builder.push_record(header);
 
     let mut total_size = 0u64;
 
     // Convert to sorted vector for consistent output
-    let mut images: Vec<_> = by_image.iter().collect();
+    let mut images: Vec<_> = by_image.iter().filter(|(_, s)| !s.is_empty()).collect();
     images.sort_by(|a, b| a.0.cmp(b.0));
 
-    for (image_name, snapshots) in images {
+    // Group on each image's latest snapshot
+    let groups = if let Some(key) = group_by {
+        group_by_label(images, key, |(_, s)| s.last().unwrap())
+    } else if options.group_by_image {
+        let mut by_base: BTreeMap<String, Vec<_>> = BTreeMap::new();
+        for entry in images {
+            by_base
+                .entry(entry.1.last().unwrap().image.clone())
+                .or_default()
+                .push(entry);
+        }
+        by_base.into_iter().collect()
+    } else {
+        vec![(String::new(), images)]
+    };
+
+    for (group, images) in groups {
+        let group_size: u64 = images.iter().map(|(_, s)| s.last().unwrap().size()).sum();
+        let group_count = images.len();
+
+        // Tag variants of one base image become indented sub-rows under a total row
+        let nest_tags = options.group_by_image && group_count > 1;
+        if nest_tags {
+            let name = if options.wide {
+                group.clone()
+            } else {
+                shorten_reference(&group, options.output.name_width())
+            };
+            let mut row = vec![
+                format!("{} ({} tags)", name, group_count).bold().to_string(),
+                format_size(group_size).bold().to_string(),
+            ];
+            row.resize(width, String::new());
+            builder.push_record(row);
+        }
+
+        for ((image_name, _), snapshots) in images {
+            let latest = snapshots.last().unwrap();
+
+            // Calculate trend with sparkline (last 10 snapshots)
+            let trend = calculate_trend_with_sparkline(snapshots, 10, options.metric, options.style);
+
+            let last_tracked = format_timestamp(latest.timestamp, "%Y-%m-%d %H:%M", options.output.time_zone);
+
+            let name = if nest_tags {
+                format!("  :{}", latest.tag.as_deref().unwrap_or("latest"))
+            } else if options.wide {
+                image_name.clone()
+            } else {
+                shorten_reference(image_name, options.output.name_width())
+            };
+
+            let latest_value = match snapshots.iter().rev().find_map(|s| options.metric.value(s)) {
+                Some(value) => options.metric.format(value),
+                None => options.style.dash().to_string(),
+            };
+
+            let mut row = vec![
+                name,
+                latest_value,
+                trend,
+                snapshots.len().to_string(),
+                last_tracked,
+            ];
+            if grouped {
+                row.push(match latest.group_id {
+                    Some(_) => latest.platform(),
+                    None => options.style.dash().to_string(),
+                });
+            }
+            row.extend(label_cells(latest, columns));
+            builder.push_record(row);
+        }
+
+        if let Some(key) = group_by {
+            builder.push_record(subtotal_row(&key.0, &group, group_count, group_size, width));
+        }
+
+        total_size += group_size;
+    }
+
+    let table = render_table_with(builder, options.output.plain, |table| {
+        if options.style == SparklineStyle::Ascii {
+            table.with(Style::ascii());
+        }
+    });
+    writeln!(out, "{}\n", table)?;
+
+    writeln!(
+        out,
+        "{}",
+        format!("Total combined size: {}", format_size(total_size)).bold()
+    )?;
+
+    Ok(())
+}
+
+/// JSON rows sorted by image, then platform
+fn summary_entries(by_image: &HashMap<(String, String), Vec<ImageSnapshot>>) -> Vec<SummaryEntry> {
+    let mut entries: Vec<SummaryEntry> = by_image
+        .iter()
+        .filter_map(|((image, platform), snapshots)| {
+            let latest = snapshots.last()?;
+            Some(SummaryEntry {
+                image: image.clone(),
+                platform: (!platform.is_empty()).then(|| platform.clone()),
+                latest_size: latest.size(),
+                snapshot_count: snapshots.len(),
+                trend: snapshots
+                    .iter()
+                    .skip(snapshots.len().saturating_sub(10))
+                    .map(|s| s.size())
+                    .collect(),
+                last_tracked: latest.timestamp,
+            })
+        })
+        .collect();
+
+    entries.sort_by(|a, b| (&a.image, &a.platform).cmp(&(&b.image, &b.platform)));
+    entries
+}
+
+/// `dit summary --format csv`: one row per entry, then the `_total` row, with fields
+/// separated by `delimiter`
+pub fn render_csv(entries: &[SummaryEntry], delimiter: char) -> String {
+    let row = |fields: &[String]| {
+        format!("{}\n", fields.join(delimiter.encode_utf8(&mut [0; 4])))
+    };
+    let header = [
+        "image",
+        "tag",
+        "latest_size_bytes",
+        "snapshot_count",
+        "last_tracked_utc",
+        "trend_description",
+    ];
+    let mut out = row(&header.map(String::from));
+
+    for entry in entries {
+        let (image, tag) = parse_image_reference(&entry.image);
+        let image = match &entry.platform {
+            Some(platform) => format!("{} ({})", image, platform),
+            None => image,
+        };
+        out.push_str(&row(&[
+            csv_field(&image, delimiter),
+            csv_field(tag.as_deref().unwrap_or("latest"), delimiter),
+            entry.latest_size.to_string(),
+            entry.snapshot_count.to_string(),
+            entry.last_tracked.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            trend_description(&entry.trend).to_string(),
+        ]));
+    }
+
+    let total: u64 = entries.iter().map(|e| e.latest_size).sum();
+    let mut total_row = vec![String::new(); header.len()];
+    total_row[0] = "_total".to_string();
+    total_row[2] = total.to_string();
+    out.push_str(&row(&total_row));
+    out
+}
+
+/// Parse a `--csv-delimiter` argument: one ASCII character other than a quote or line break
+pub fn parse_csv_delimiter(s: &str) -> Result<char, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii() && !matches!(c, '"' | '\n' | '\r') => Ok(c),
+        _ => Err(format!(
+            "invalid CSV delimiter '{}': expected a single ASCII character other than a quote",
+            s
+        )),
+    }
+}
+
+/// How the progress line names a delimiter, e.g. `semicolon-separated`
+fn delimiter_name(delimiter: char) -> String {
+    match delimiter {
+        ',' => "comma-separated".to_string(),
+        ';' => "semicolon-separated".to_string(),
+        '\t' => "tab-separated".to_string(),
+        '|' => "pipe-separated".to_string(),
+        ' ' => "space-separated".to_string(),
+        other => format!("'{}'-separated", other),
+    }
+}
+
+/// `stable`, `growing` or `shrinking`: the oldest to newest of `sizes`, within 10 KiB is stable
+pub fn trend_description(sizes: &[u64]) -> &'static str {
+    let (Some(first), Some(last)) = (sizes.first(), sizes.last()) else {
+        return "stable";
+    };
+    let delta = *last as i64 - *first as i64;
+    if delta.abs() < STABLE_BYTES {
+        "stable"
+    } else if delta > 0 {
+        "growing"
+    } else {
+        "shrinking"
+    }
+}
+
+/// Quote a CSV field when it contains the delimiter, a quote or a line break
+fn csv_field(value: &str, delimiter: char) -> String {
+    if value.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Compact summary of just-tracked images: latest size and change from the previous snapshot
+pub fn print_mini_summary(
+    out: &mut impl Write,
+    images: &[String],
+    history: &[ImageSnapshot],
+    output: OutputStyle,
+    style: SparklineStyle,
+) -> Result<()> {
+    let mut builder = Builder::default();
+    builder.push_record(["Image", "Latest Size", "Change", "Trend", "Snapshots"]);
+
+    for image in images {
+        let mut snapshots: Vec<ImageSnapshot> = history
+            .iter()
+            .filter(|s| {
+                format!("{}:{}", s.image, s.tag.as_deref().unwrap_or("latest")) == *image
+            })
+            .cloned()
+            .collect();
+
         if snapshots.is_empty() {
             continue;
         }
 
-        let latest = snapshots.last().unwrap();
-        total_size += latest.total_size;
+        snapshots.sort_by_key(|s| s.timestamp);
 
-        // Calculate trend with sparkline (last 10 snapshots)
-        let trend = calculate_trend_with_sparkline(snapshots, 10);
-
-        let last_tracked = latest.timestamp.format("%Y-%m-%d %H:%M").to_string();
+        let latest = snapshots.last().unwrap();
+        let change = if snapshots.len() > 1 {
+            let previous = &snapshots[snapshots.len() - 2];
+            format_size_delta(latest.size() as i64 - previous.size() as i64, output.plain)
+        } else {
+            "new".to_string()
+        };
 
         builder.push_record([
-            image_name,
-            &format_size(latest.total_size),
-            &trend,
+            &shorten_reference(image, output.name_width()),
+            &format_size(latest.size()),
+            &change,
+            &calculate_trend_with_sparkline(&snapshots, 10, Metric::Size, style),
             &snapshots.len().to_string(),
-            &last_tracked,
         ]);
     }
 
-    let mut table = builder.build();
-    table
-        .with(Style::rounded())
-        .with(Modify::new(Rows::first()).with(Alignment::center()));
+    let table = render_table_with(builder, output.plain, |table| {
+        if style == SparklineStyle::Ascii {
+            table.with(Style::ascii());
+        }
+    });
+    writeln!(out, "\n{}", "Summary".bold().underline())?;
+    writeln!(out, "{}", table)?;
 
-    println!("{}\n", table);
+    Ok(())
+}
 
-    println!(
-        "{}",
-        format!("Total combined size: {}", format_size(total_size)).bold()
-    );
+/// Bold `KEY=value subtotal` row for label-grouped tables, padded to `width` columns
+pub fn subtotal_row(key: &str, group: &str, count: usize, size: u64, width: usize) -> Vec<String> {
+    let mut row = vec![
+        format!("{}={} ({} image(s))", key, group, count).bold().to_string(),
+        format_size(size).bold().to_string(),
+    ];
+    row.resize(width, String::new());
+    row
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{layer, tracked};
+
+    /// `app:v1` tracked at `sizes`, one minute apart
+    fn tracked_sizes(image: &str, sizes: &[u64]) -> Vec<ImageSnapshot> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| tracked(image, "v1", "abc1234", "main", i as i64, vec![layer("sha256:a", size, "RUN x")]))
+            .collect()
+    }
+
+    fn by_image(images: &[(&str, &[u64])]) -> HashMap<(String, String), Vec<ImageSnapshot>> {
+        images
+            .iter()
+            .map(|(image, sizes)| ((format!("{}:v1", image), String::new()), tracked_sizes(image, sizes)))
+            .collect()
+    }
+
+    #[test]
+    fn json_entries_have_the_documented_shape() {
+        let entries = summary_entries(&by_image(&[("web", &[100, 200, 300]), ("api", &[50])]));
+        let json = serde_json::to_value(&entries).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "image": "api:v1",
+                    "latest_size": 50,
+                    "snapshot_count": 1,
+                    "trend": [50],
+                    "last_tracked": "2024-01-01T00:00:00Z",
+                },
+                {
+                    "image": "web:v1",
+                    "latest_size": 300,
+                    "snapshot_count": 3,
+                    "trend": [100, 200, 300],
+                    "last_tracked": "2024-01-01T00:02:00Z",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn trends_keep_the_last_ten_sizes() {
+        let sizes: Vec<u64> = (1..=12).collect();
+        let entries = summary_entries(&by_image(&[("web", &sizes)]));
+
+        assert_eq!(entries[0].snapshot_count, 12);
+        assert_eq!(entries[0].trend, (3..=12).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn csv_rows_end_with_a_total() {
+        let mib = 1024 * 1024;
+        let entries = summary_entries(&by_image(&[("web", &[mib, 2 * mib]), ("api", &[mib, mib / 2])]));
+
+        assert_eq!(
+            render_csv(&entries, ','),
+            "image,tag,latest_size_bytes,snapshot_count,last_tracked_utc,trend_description\n\
+             api,v1,524288,2,2024-01-01T00:01:00Z,shrinking\n\
+             web,v1,2097152,2,2024-01-01T00:01:00Z,growing\n\
+             _total,,2621440,,,\n"
+        );
+        assert_eq!(render_csv(&[], ','), "image,tag,latest_size_bytes,snapshot_count,last_tracked_utc,trend_description\n_total,,0,,,\n");
+    }
+
+    #[test]
+    fn csv_delimiter_applies_to_header_rows_and_quoting() {
+        let entries = summary_entries(&by_image(&[("web", &[1024, 2048])]));
+
+        assert_eq!(
+            render_csv(&entries, ';'),
+            "image;tag;latest_size_bytes;snapshot_count;last_tracked_utc;trend_description\n\
+             web;v1;2048;2;2024-01-01T00:01:00Z;stable\n\
+             _total;;2048;;;\n"
+        );
+        assert_eq!(csv_field("a;b", ';'), "\"a;b\"");
+        assert_eq!(csv_field("a,b", ';'), "a,b");
+    }
+
+    #[test]
+    fn csv_delimiters_are_single_ascii_characters() {
+        assert_eq!(parse_csv_delimiter(";"), Ok(';'));
+        assert_eq!(parse_csv_delimiter("\t"), Ok('\t'));
+        assert!(parse_csv_delimiter("").is_err());
+        assert!(parse_csv_delimiter(";;").is_err());
+        assert!(parse_csv_delimiter("\"").is_err());
+        assert!(parse_csv_delimiter("§").is_err());
+        assert_eq!(delimiter_name(';'), "semicolon-separated");
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("web", ','), "web");
+        assert_eq!(csv_field("web (linux/arm64)", ','), "web (linux/arm64)");
+        assert_eq!(csv_field("a,b", ','), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\"", ','), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn small_changes_are_stable() {
+        assert_eq!(trend_description(&[]), "stable");
+        assert_eq!(trend_description(&[100_000, 100_000 + 10 * 1024 - 1]), "stable");
+        assert_eq!(trend_description(&[100_000, 100_000 + 10 * 1024]), "growing");
+        assert_eq!(trend_description(&[100_000, 50_000, 100_000]), "stable");
+    }
 }
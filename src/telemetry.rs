@@ -0,0 +1,300 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Subscriber;
+use tracing_opentelemetry::MetricsLayer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Standard OTLP variable, used when `--otel-endpoint` isn't given
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Exports dit's spans and counters over OTLP/HTTP until dropped, which flushes them.
+/// Without an endpoint nothing is installed and the `tracing` spans cost next to nothing.
+///
+/// Spans are named `dit.<operation>` (`dit.ci`, `dit.inspect`, `dit.registry`, ...).
+/// Counters are `tracing` events with a `monotonic_counter.` field: `dit.budget_failures`
+/// (every failed budget or policy check) and `dit.comment_posts`.
+pub struct Telemetry {
+    tracer_provider: TracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+/// CI system dit runs under, exported as the `ci.provider` resource attribute
+pub fn ci_provider() -> &'static str {
+    let set = |var: &str| std::env::var_os(var).is_some();
+    if set("GITHUB_ACTIONS") {
+        "github-actions"
+    } else if set("GITLAB_CI") {
+        "gitlab"
+    } else if set("CIRCLECI") {
+        "circleci"
+    } else if set("BUILDKITE") {
+        "buildkite"
+    } else if set("JENKINS_URL") {
+        "jenkins"
+    } else if set("CI") {
+        "other"
+    } else {
+        "local"
+    }
+}
+
+/// Start exporting to `endpoint` (base URL; `/v1/traces` and `/v1/metrics` are appended)
+pub fn init(endpoint: Option<String>) -> Result<Option<Telemetry>> {
+    let Some(endpoint) = endpoint.or_else(|| std::env::var(ENDPOINT_ENV).ok()) else {
+        return Ok(None);
+    };
+
+    let resource = Resource::new([
+        KeyValue::new("service.name", "dit"),
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        KeyValue::new("ci.provider", ci_provider()),
+    ]);
+
+    let base = endpoint.trim_end_matches('/');
+
+    let span_exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", base))
+        .build()
+        .context(format!("Failed to set up OTLP trace export to {}", endpoint))?;
+    let tracer_provider = TracerProvider::builder()
+        .with_batch_exporter(span_exporter, runtime::Tokio)
+        .with_resource(resource.clone())
+        .build();
+
+    let metric_exporter = MetricExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/metrics", base))
+        .build()
+        .context(format!("Failed to set up OTLP metric export to {}", endpoint))?;
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(metric_exporter, runtime::Tokio).build())
+        .with_resource(resource)
+        .build();
+
+    subscriber(&tracer_provider, &meter_provider)
+        .try_init()
+        .context("Failed to install the telemetry subscriber")?;
+
+    Ok(Some(Telemetry {
+        tracer_provider,
+        meter_provider,
+    }))
+}
+
+/// Bridge `tracing` spans and counter events to the OpenTelemetry providers
+fn subscriber(
+    tracer_provider: &TracerProvider,
+    meter_provider: &SdkMeterProvider,
+) -> impl Subscriber + Send + Sync {
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("dit")))
+        .with(MetricsLayer::new(meter_provider.clone()))
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            eprintln!("⚠️  Failed to export spans: {}", e);
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            eprintln!("⚠️  Failed to export metrics: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::{run_ci, BaselineOrigin, CiConfig, CiOutputFormat, CommentMode};
+    use crate::diff::BasisChoice;
+    use crate::extract::export_layer_files;
+    use crate::docker::MockDockerClient;
+    use crate::platforms::PlatformMode;
+    use crate::store::Store;
+    use opentelemetry::Value;
+    use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+    use opentelemetry_sdk::metrics::data::{ResourceMetrics, Sum};
+    use opentelemetry_sdk::metrics::reader::MetricReader;
+    use opentelemetry_sdk::metrics::{InstrumentKind, ManualReader, MetricResult, Pipeline, Temporality};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex, Weak};
+
+    /// Keeps every exported span in memory
+    #[derive(Debug, Clone, Default)]
+    struct InMemorySpans(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for InMemorySpans {
+        fn export(&mut self, batch: Vec<SpanData>) -> Pin<Box<dyn Future<Output = ExportResult> + Send>> {
+            self.0.lock().unwrap().extend(batch);
+            Box::pin(std::future::ready(Ok(())))
+        }
+    }
+
+    /// A [`ManualReader`] the test can still collect from after the provider takes it
+    #[derive(Debug, Clone)]
+    struct SharedReader(Arc<ManualReader>);
+
+    impl MetricReader for SharedReader {
+        fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+            self.0.register_pipeline(pipeline)
+        }
+        fn collect(&self, rm: &mut ResourceMetrics) -> MetricResult<()> {
+            self.0.collect(rm)
+        }
+        fn force_flush(&self) -> MetricResult<()> {
+            self.0.force_flush()
+        }
+        fn shutdown(&self) -> MetricResult<()> {
+            self.0.shutdown()
+        }
+        fn temporality(&self, kind: InstrumentKind) -> Temporality {
+            self.0.temporality(kind)
+        }
+    }
+
+    struct Recording {
+        spans: InMemorySpans,
+        reader: SharedReader,
+        _guard: tracing::subscriber::DefaultGuard,
+        _providers: (TracerProvider, SdkMeterProvider),
+    }
+
+    /// Route this thread's spans and counters through the same subscriber `init` installs
+    fn record() -> Recording {
+        let spans = InMemorySpans::default();
+        let reader = SharedReader(Arc::new(ManualReader::builder().build()));
+        let tracer_provider = TracerProvider::builder()
+            .with_simple_exporter(spans.clone())
+            .build();
+        let meter_provider = SdkMeterProvider::builder().with_reader(reader.clone()).build();
+        let guard = tracing::subscriber::set_default(subscriber(&tracer_provider, &meter_provider));
+        Recording {
+            spans,
+            reader,
+            _guard: guard,
+            _providers: (tracer_provider, meter_provider),
+        }
+    }
+
+    impl Recording {
+        fn span(&self, name: &str) -> SpanData {
+            let spans = self.spans.0.lock().unwrap();
+            let found = spans.iter().find(|s| s.name == name).cloned();
+            found.unwrap_or_else(|| {
+                let names: Vec<_> = spans.iter().map(|s| s.name.to_string()).collect();
+                panic!("no {} span in {:?}", name, names)
+            })
+        }
+
+        fn counter(&self, name: &str) -> u64 {
+            let mut metrics = ResourceMetrics {
+                resource: Resource::empty(),
+                scope_metrics: Vec::new(),
+            };
+            self.reader.collect(&mut metrics).unwrap();
+            metrics
+                .scope_metrics
+                .iter()
+                .flat_map(|scope| &scope.metrics)
+                .filter(|metric| metric.name == name)
+                .filter_map(|metric| metric.data.as_any().downcast_ref::<Sum<u64>>())
+                .flat_map(|sum| &sum.data_points)
+                .map(|point| point.value)
+                .sum()
+        }
+    }
+
+    fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    }
+
+    fn ci_config() -> CiConfig {
+        CiConfig {
+            images: Vec::new(),
+            budget_bytes: Some(0),
+            budget_increase_percent: None,
+            github_comment: false,
+            base_branch: None,
+            fail_on_increase: false,
+            fail_if_base_image_changed: false,
+            warn_large_layer_bytes: None,
+            fail_on_large_layer: false,
+            explain_baseline: false,
+            warn_at_percent: 90.0,
+            // An empty run sits exactly at a zero budget: a warning, failed by this
+            strict_warnings: true,
+            show_env_values: false,
+            paths: Vec::new(),
+            trigger_paths: Vec::new(),
+            format: CiOutputFormat::Markdown,
+            report_template: None,
+            print_template_context: false,
+            cost: None,
+            output_path: None,
+            output_append: false,
+            output_header: true,
+            comment_mode: CommentMode::Always,
+            multi_platform: false,
+            platforms: PlatformMode::default(),
+            image_budgets: Vec::new(),
+            github_actions: false,
+            path_budgets: Vec::new(),
+            basis: BasisChoice::default(),
+            max_changes: 0,
+            baseline_origin: BaselineOrigin::default(),
+            dry_run: false,
+            build_metadata: None,
+            report_link: None,
+            output: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn ci_phases_and_budget_failures_are_exported() {
+        let recording = record();
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+
+        let docker = MockDockerClient::new();
+        assert!(run_ci(&mut Vec::new(), &docker, &store, ci_config()).await.unwrap());
+
+        let ci = recording.span("dit.ci");
+        assert_eq!(attribute(&ci, "images"), Some(Value::I64(0)));
+        assert_eq!(attribute(&ci, "bytes_analyzed"), Some(Value::I64(0)));
+        assert_eq!(attribute(&ci, "provider"), Some(Value::from(ci_provider())));
+
+        let report = recording.span("dit.report");
+        assert_eq!(report.parent_span_id, ci.span_context.span_id());
+
+        assert_eq!(recording.counter("dit.budget_failures"), 1);
+    }
+
+    #[tokio::test]
+    async fn image_exports_are_spans_with_their_reference() {
+        let recording = record();
+        let docker = MockDockerClient::new();
+
+        let _ = export_layer_files(&docker, "app:v1").await;
+
+        let export = recording.span("dit.export");
+        assert_eq!(attribute(&export, "reference"), Some(Value::from("app:v1")));
+    }
+
+    #[test]
+    fn without_an_endpoint_nothing_is_installed() {
+        if std::env::var_os(ENDPOINT_ENV).is_none() {
+            assert!(init(None).unwrap().is_none());
+        }
+    }
+}
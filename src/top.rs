@@ -0,0 +1,305 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use tabled::builder::Builder;
+
+use crate::analyze::OutputFormat;
+use crate::docker::{ImageFilter, ImageInspector};
+use crate::format::{
+    format_size, format_timestamp, middle_ellipsis, render_table, shorten_reference, OutputStyle,
+};
+use crate::models::ImageSnapshot;
+
+/// Images inspected at once
+const CONCURRENCY: usize = 8;
+
+#[derive(Debug)]
+pub struct TopOptions {
+    /// Layers to show; 0 shows all
+    pub limit: usize,
+    pub format: OutputFormat,
+    pub output: OutputStyle,
+}
+
+/// One layer of `dit top`, with every image that contains it
+#[derive(Debug, Serialize)]
+pub struct TopLayer {
+    pub size: u64,
+    pub command: String,
+    pub created: DateTime<Utc>,
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopReport {
+    pub images_scanned: usize,
+    pub layers: Vec<TopLayer>,
+    /// Size of `layers`, each shared layer counted once
+    pub unique_bytes: u64,
+    /// Size of `layers` counted once per image that contains them
+    pub per_image_bytes: u64,
+}
+
+/// `dit top`: the largest layers across local images. Tags of one image ID are inspected once.
+pub async fn show_top(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    filter: &ImageFilter,
+    options: &TopOptions,
+) -> Result<()> {
+    options.format.ensure_not_csv("top")?;
+    let selected = docker.select_images(filter).await?;
+    let ids = docker.image_ids().await?;
+
+    // Tags sharing an image ID have identical layers
+    let mut by_id: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for tag in selected {
+        let id = ids.get(&tag).cloned().unwrap_or_else(|| tag.clone());
+        by_id.entry(id).or_default().push(tag);
+    }
+
+    let inspected: Vec<(Vec<String>, Result<ImageSnapshot>)> = stream::iter(by_id.into_values())
+        .map(|tags| {
+            async move {
+                let snapshot = docker.inspect_image(&tags[0]).await;
+                (tags, snapshot)
+            }
+        })
+        .buffer_unordered(CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut images = Vec::new();
+    for (tags, snapshot) in inspected {
+        match snapshot {
+            Ok(snapshot) => images.push((tags, snapshot)),
+            Err(e) => eprintln!("⚠️  {}: {:#}", tags.join(", "), e),
+        }
+    }
+
+    let report = top_layers(&images, options.limit);
+    match options.format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?,
+        OutputFormat::Table => print_top_table(out, &report, options.output)?,
+        OutputFormat::Csv => unreachable!("rejected by ensure_not_csv"),
+    }
+    Ok(())
+}
+
+/// Group layers across `images` (tags, snapshot) and keep the `limit` largest.
+///
+/// `docker history` has no content digests for pulled layers, so a layer is identified
+/// by its command and exact size; empty (metadata-only) layers are skipped.
+pub fn top_layers(images: &[(Vec<String>, ImageSnapshot)], limit: usize) -> TopReport {
+    let mut layers: HashMap<(&str, u64), TopLayer> = HashMap::new();
+
+    for (tags, snapshot) in images {
+        for layer in snapshot.layers.iter().filter(|l| l.size > 0) {
+            let entry = layers
+                .entry((layer.command.as_str(), layer.size))
+                .or_insert_with(|| TopLayer {
+                    size: layer.size,
+                    command: layer.command.clone(),
+                    created: layer.created,
+                    images: Vec::new(),
+                });
+            entry.created = entry.created.min(layer.created);
+            entry.images.extend(tags.iter().cloned());
+        }
+    }
+
+    let mut layers: Vec<TopLayer> = layers.into_values().collect();
+    for layer in &mut layers {
+        layer.images.sort();
+        layer.images.dedup();
+    }
+    layers.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.command.cmp(&b.command)));
+    if limit > 0 {
+        layers.truncate(limit);
+    }
+
+    TopReport {
+        images_scanned: images.len(),
+        unique_bytes: layers.iter().map(|l| l.size).sum(),
+        per_image_bytes: layers.iter().map(|l| l.size * l.images.len() as u64).sum(),
+        layers,
+    }
+}
+
+fn print_top_table(out: &mut impl Write, report: &TopReport, output: OutputStyle) -> Result<()> {
+    if report.layers.is_empty() {
+        writeln!(out, "No layers found")?;
+        return Ok(());
+    }
+
+    let mut builder = Builder::default();
+    builder.push_record(["Size", "Images", "Command", "Created"]);
+
+    for layer in &report.layers {
+        let mut owners: Vec<String> = layer
+            .images
+            .iter()
+            .take(3)
+            .map(|i| shorten_reference(i, output.name_width()))
+            .collect();
+        if layer.images.len() > 3 {
+            owners.push(format!("+{} more", layer.images.len() - 3));
+        }
+
+        builder.push_record([
+            format_size(layer.size),
+            owners.join("\n"),
+            middle_ellipsis(&layer.command, 60),
+            format_timestamp(layer.created, "%Y-%m-%d", output.time_zone),
+        ]);
+    }
+
+    writeln!(out, "\n{}", "Largest Layers".bold().underline())?;
+    writeln!(out, "{}", render_table(builder, output.plain))?;
+    writeln!(
+        out,
+        "Top {} across {} image(s): {} unique ({} if shared layers counted per image)",
+        report.layers.len(),
+        report.images_scanned,
+        format_size(report.unique_bytes).bold(),
+        format_size(report.per_image_bytes)
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{at, layer, snapshot};
+    use crate::models::LayerInfo;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+    const CUDA: &str = "RUN apt-get install -y cuda-toolkit-12-4";
+
+    fn image(tags: &[&str], layers: Vec<LayerInfo>) -> (Vec<String>, ImageSnapshot) {
+        let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+        let (name, tag) = tags[0].split_once(':').unwrap();
+        (tags.clone(), snapshot(name, tag, layers))
+    }
+
+    fn created(mut layer: LayerInfo, minutes: i64) -> LayerInfo {
+        layer.created = at(minutes);
+        layer
+    }
+
+    /// Six ML images on one CUDA layer, each with its own code on top
+    fn fleet() -> Vec<(Vec<String>, ImageSnapshot)> {
+        (1..=6)
+            .map(|i| {
+                let tag = format!("ml-{}:latest", i);
+                image(&[&tag], vec![
+                    created(layer("sha256:cuda", 2 * GIB, CUDA), i),
+                    layer(&format!("sha256:app{}", i), i as u64 * 1024, "COPY . /app"),
+                ])
+            })
+            .collect()
+    }
+
+    fn owners(layer: &TopLayer) -> Vec<&str> {
+        layer.images.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn shared_layers_are_listed_once_with_every_owner() {
+        let report = top_layers(&fleet(), 20);
+
+        assert_eq!(report.images_scanned, 6);
+        assert_eq!(report.layers.len(), 7);
+        let cuda = &report.layers[0];
+        assert_eq!((cuda.size, cuda.command.as_str()), (2 * GIB, CUDA));
+        assert_eq!(owners(cuda), vec![
+            "ml-1:latest", "ml-2:latest", "ml-3:latest", "ml-4:latest", "ml-5:latest", "ml-6:latest",
+        ]);
+        // The earliest time any image created it
+        assert_eq!(cuda.created, at(1));
+    }
+
+    #[test]
+    fn shared_layers_count_once_in_the_unique_total() {
+        let report = top_layers(&fleet(), 2);
+
+        assert_eq!(report.layers.len(), 2);
+        assert_eq!(report.layers[1].size, 6 * 1024);
+        assert_eq!(report.unique_bytes, 2 * GIB + 6 * 1024);
+        assert_eq!(report.per_image_bytes, 6 * 2 * GIB + 6 * 1024);
+    }
+
+    #[test]
+    fn limit_zero_keeps_every_layer() {
+        assert_eq!(top_layers(&fleet(), 0).layers.len(), 7);
+        assert_eq!(top_layers(&fleet(), 1).layers.len(), 1);
+    }
+
+    #[test]
+    fn tags_of_one_image_are_owners_of_each_layer() {
+        let images = vec![image(&["app:2.1", "app:latest"], vec![layer("sha256:a", 500, "COPY . /app")])];
+        let report = top_layers(&images, 10);
+
+        assert_eq!(owners(&report.layers[0]), vec!["app:2.1", "app:latest"]);
+        assert_eq!(report.per_image_bytes, 1000);
+    }
+
+    #[test]
+    fn layers_match_by_command_and_exact_size() {
+        let images = vec![
+            image(&["a:1"], vec![layer("sha256:a", 500, "COPY . /app")]),
+            // Same command, different content
+            image(&["b:1"], vec![layer("sha256:b", 501, "COPY . /app")]),
+            // Same size, different command
+            image(&["c:1"], vec![layer("sha256:c", 500, "RUN make")]),
+            // Pulled layers have no digest in `docker history`, but match all the same
+            image(&["d:1"], vec![layer("<missing>", 500, "COPY . /app")]),
+        ];
+        let report = top_layers(&images, 10);
+
+        let layers: Vec<(u64, &str, Vec<&str>)> = report
+            .layers
+            .iter()
+            .map(|l| (l.size, l.command.as_str(), owners(l)))
+            .collect();
+        assert_eq!(layers, vec![
+            (501, "COPY . /app", vec!["b:1"]),
+            (500, "COPY . /app", vec!["a:1", "d:1"]),
+            (500, "RUN make", vec!["c:1"]),
+        ]);
+    }
+
+    #[test]
+    fn a_layer_repeated_within_an_image_lists_it_once() {
+        let images = vec![image(&["a:1"], vec![
+            layer("sha256:x", 10, "RUN touch /x"),
+            layer("sha256:y", 10, "RUN touch /x"),
+        ])];
+        let report = top_layers(&images, 10);
+
+        assert_eq!(report.layers.len(), 1);
+        assert_eq!(owners(&report.layers[0]), vec!["a:1"]);
+    }
+
+    #[test]
+    fn empty_layers_and_images_are_skipped() {
+        let images = vec![
+            image(&["a:1"], vec![layer("sha256:a", 0, "ENV PATH=/usr/bin"), layer("sha256:b", 5, "RUN true")]),
+            image(&["b:1"], Vec::new()),
+        ];
+        let report = top_layers(&images, 10);
+
+        assert_eq!(report.images_scanned, 2);
+        assert_eq!(report.layers.len(), 1);
+        assert_eq!(report.layers[0].command, "RUN true");
+
+        let empty = top_layers(&[], 10);
+        assert!(empty.layers.is_empty());
+        assert_eq!((empty.unique_bytes, empty.per_image_bytes), (0, 0));
+    }
+}
@@ -1,50 +1,156 @@
 use anyhow::{Context, Result};
 use chrono::Utc;
-use std::fs;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::Write;
 use std::process::Command;
 
-use crate::docker::DockerClient;
+use crate::build_metadata::BuildMetadata;
+use crate::docker::{ImageInspector, ImageSpec};
+use crate::extract::measure_extract;
+use crate::metric::format_duration;
 use crate::models::ImageSnapshot;
+use crate::platforms::inspect_index;
+use crate::store::Store;
 
-const HISTORY_DIR: &str = ".dit";
-const HISTORY_FILE: &str = "history.json";
+pub async fn track_image(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    store: &Store,
+    image: &ImageSpec,
+    measure: bool,
+    annotations: BTreeMap<String, String>,
+    build_metadata: Option<&BuildMetadata>,
+) -> Result<()> {
+    let git_context = get_git_context()?;
+    let snapshot = record_snapshot(
+        docker,
+        store,
+        image,
+        &git_context,
+        measure,
+        annotations,
+        build_metadata,
+    )
+    .await?;
 
-pub async fn track_image(image: &str) -> Result<()> {
-    // Get Docker snapshot
-    let docker = DockerClient::new()?;
-    let mut snapshot = docker.inspect_image(image).await?;
+    writeln!(out, "✅ Tracked snapshot for {} at commit {}", 
+        snapshot.image, 
+        snapshot.commit_sha.chars().take(7).collect::<String>()
+    )?;
+    writeln!(out, "Branch: {}", snapshot.branch)?;
+    writeln!(out, "Size: {} bytes", snapshot.total_size)?;
+    if let Some(ms) = snapshot.build_duration_ms {
+        writeln!(out, "Build time: {}", format_duration(ms))?;
+    }
 
-    // Get git context
-    let git_context = get_git_context()?;
-    snapshot.commit_sha = git_context.commit_sha;
-    snapshot.branch = git_context.branch;
-    snapshot.commit_message = git_context.commit_message;
-    snapshot.author = git_context.author;
+    Ok(())
+}
+
+/// Inspect `image`, stamp it with `git_context` and save it to `store`; returns the saved snapshot
+pub async fn record_snapshot(
+    docker: &impl ImageInspector,
+    store: &Store,
+    image: &ImageSpec,
+    git_context: &GitContext,
+    measure: bool,
+    annotations: BTreeMap<String, String>,
+    build_metadata: Option<&BuildMetadata>,
+) -> Result<ImageSnapshot> {
+    let mut snapshot = docker.inspect_spec(image).await?;
+
+    if measure {
+        measure_extract(docker, &image.reference, &mut snapshot).await?;
+    }
+
+    git_context.apply(&mut snapshot);
+    warn_revision_mismatch(&snapshot);
     snapshot.timestamp = Utc::now();
+    snapshot.annotations = annotations;
+    if let Some(metadata) = build_metadata {
+        metadata.apply(&mut snapshot);
+    }
 
-    // Save to history
-    save_snapshot(&snapshot)?;
+    store.save_snapshot(&snapshot)?;
+    Ok(snapshot)
+}
 
-    println!("✅ Tracked snapshot for {} at commit {}", 
-        snapshot.image, 
-        snapshot.commit_sha.chars().take(7).collect::<String>()
-    );
-    println!("Branch: {}", snapshot.branch);
-    println!("Size: {} bytes", snapshot.total_size);
+/// Track every platform of a multi-platform image index as one snapshot group
+pub async fn track_index(
+    out: &mut impl Write,
+    store: &Store,
+    reference: &str,
+    annotations: BTreeMap<String, String>,
+) -> Result<()> {
+    let snapshots = inspect_index(reference)?;
+    let git_context = get_git_context()?;
+    let timestamp = Utc::now();
+
+    for mut snapshot in snapshots {
+        snapshot.commit_sha = git_context.commit_sha.clone();
+        snapshot.branch = git_context.branch.clone();
+        snapshot.commit_message = git_context.commit_message.clone();
+        snapshot.author = git_context.author.clone();
+        snapshot.timestamp = timestamp;
+        snapshot.annotations = annotations.clone();
+        warn_revision_mismatch(&snapshot);
+
+        store.save_snapshot(&snapshot)?;
+        writeln!(
+            out,
+            "✅ Tracked {} ({}): {} bytes compressed",
+            snapshot.image,
+            snapshot.platform(),
+            snapshot.compressed_size.unwrap_or_default()
+        )?;
+    }
+
+    writeln!(
+        out,
+        "Commit: {} | Branch: {}",
+        git_context.commit_sha.chars().take(7).collect::<String>(),
+        git_context.branch
+    )?;
 
     Ok(())
 }
 
 #[derive(Debug)]
-struct GitContext {
-    commit_sha: String,
-    branch: String,
-    commit_message: String,
-    author: String,
+pub struct GitContext {
+    pub commit_sha: String,
+    pub branch: String,
+    pub commit_message: String,
+    pub author: String,
 }
 
-fn get_git_context() -> Result<GitContext> {
+impl GitContext {
+    /// Record this commit, branch, message and author on `snapshot`
+    pub fn apply(&self, snapshot: &mut ImageSnapshot) {
+        snapshot.commit_sha = self.commit_sha.clone();
+        snapshot.branch = self.branch.clone();
+        snapshot.commit_message = self.commit_message.clone();
+        snapshot.author = self.author.clone();
+    }
+}
+
+/// Record the current commit, branch, message and author on `snapshot`
+pub fn attach_git_context(snapshot: &mut ImageSnapshot) -> Result<()> {
+    get_git_context()?.apply(snapshot);
+    Ok(())
+}
+
+/// Warn on stderr when the image's `org.opencontainers.image.revision` isn't the tracked commit
+pub fn warn_revision_mismatch(snapshot: &ImageSnapshot) {
+    if let Some(warning) = snapshot.revision_mismatch() {
+        eprintln!(
+            "⚠️  {}:{}: {}",
+            snapshot.image,
+            snapshot.tag.as_deref().unwrap_or("latest"),
+            warning
+        );
+    }
+}
+
+pub fn get_git_context() -> Result<GitContext> {
     let commit_sha = run_git(&["rev-parse", "HEAD"])?;
     let branch = run_git(&["rev-parse", "--abbrev-ref", "HEAD"])?;
     let commit_message = run_git(&["log", "-1", "--pretty=%s"])?;
@@ -58,7 +164,13 @@ fn get_git_context() -> Result<GitContext> {
     })
 }
 
-fn run_git(args: &[&str]) -> Result<String> {
+/// Full SHA for a git revision such as `HEAD~1`
+pub fn resolve_commit(rev: &str) -> Result<String> {
+    run_git(&["rev-parse", "--verify", &format!("{}^{{commit}}", rev)])
+        .context(format!("Could not resolve git revision '{}'", rev))
+}
+
+pub fn run_git(args: &[&str]) -> Result<String> {
     let output = Command::new("git")
         .args(args)
         .output()
@@ -72,44 +184,114 @@ fn run_git(args: &[&str]) -> Result<String> {
     Ok(String::from_utf8(output.stdout)?.trim().to_string())
 }
 
-pub fn save_snapshot(snapshot: &ImageSnapshot) -> Result<()> {
-    // Create .dit directory if it doesn't exist
-    let dit_dir = PathBuf::from(HISTORY_DIR);
-    if !dit_dir.exists() {
-        fs::create_dir(&dit_dir).context("Failed to create .dit directory")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::MockDockerClient;
+    use crate::fixtures::{layer, snapshot};
+
+    fn git_context() -> GitContext {
+        GitContext {
+            commit_sha: "0123456789abcdef".to_string(),
+            branch: "main".to_string(),
+            commit_message: "Shrink the image".to_string(),
+            author: "Dev <dev@example.com>".to_string(),
+        }
     }
 
-    // Load existing history
-    let history_path = dit_dir.join(HISTORY_FILE);
-    let mut snapshots: Vec<ImageSnapshot> = if history_path.exists() {
-        let content = fs::read_to_string(&history_path)
-            .context("Failed to read history.json")?;
-        serde_json::from_str(&content).context("Failed to parse history.json")?
-    } else {
-        Vec::new()
-    };
+    #[tokio::test]
+    async fn records_the_snapshot_with_git_context_and_annotations() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+        let docker = MockDockerClient::new().with_image(
+            "myapp:1.0",
+            Ok(snapshot("myapp", "1.0", vec![layer("sha256:a", 100, "COPY . .")])),
+        );
+        let annotations = BTreeMap::from([("ticket".to_string(), "OPS-1".to_string())]);
 
-    // Append new snapshot
-    snapshots.push(snapshot.clone());
+        let saved = record_snapshot(
+            &docker,
+            &store,
+            &ImageSpec::new("myapp:1.0"),
+            &git_context(),
+            false,
+            annotations,
+            None,
+        )
+        .await
+        .unwrap();
 
-    // Save back to file
-    let json = serde_json::to_string_pretty(&snapshots)?;
-    fs::write(&history_path, json).context("Failed to write history.json")?;
+        let history = store.load_history().unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].commit_sha, "0123456789abcdef");
+        assert_eq!(history[0].branch, "main");
+        assert_eq!(history[0].annotations["ticket"], "OPS-1");
+        assert_eq!(history[0].timestamp, saved.timestamp);
+    }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn logical_names_replace_the_inspected_reference() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+        let docker = MockDockerClient::new().with_image("sha256:abc123", Ok(snapshot("sha256", "abc123", vec![])));
+        let spec = ImageSpec {
+            reference: "sha256:abc123".to_string(),
+            logical_name: Some("myapp:pr-7".to_string()),
+        };
+
+        let saved = record_snapshot(&docker, &store, &spec, &git_context(), false, BTreeMap::new(), None)
+            .await
+            .unwrap();
 
-pub fn load_history() -> Result<Vec<ImageSnapshot>> {
-    let history_path = PathBuf::from(HISTORY_DIR).join(HISTORY_FILE);
+        assert_eq!(saved.image, "myapp");
+        assert_eq!(saved.tag.as_deref(), Some("pr-7"));
+        assert_eq!(saved.digest.as_deref(), Some("sha256:abc123"));
+    }
+
+    #[tokio::test]
+    async fn build_metadata_sets_the_build_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+        let docker = MockDockerClient::new().with_image("myapp:1.0", Ok(snapshot("myapp", "1.0", vec![])));
+        let metadata = BuildMetadata {
+            config_digest: None,
+            digest: None,
+            duration_ms: Some(42_000),
+        };
+
+        let saved = record_snapshot(
+            &docker,
+            &store,
+            &ImageSpec::new("myapp:1.0"),
+            &git_context(),
+            false,
+            BTreeMap::new(),
+            Some(&metadata),
+        )
+        .await
+        .unwrap();
 
-    if !history_path.exists() {
-        return Ok(Vec::new());
+        assert_eq!(saved.build_duration_ms, Some(42_000));
     }
 
-    let content = fs::read_to_string(&history_path)
-        .context("Failed to read history.json")?;
-    let snapshots: Vec<ImageSnapshot> = serde_json::from_str(&content)
-        .context("Failed to parse history.json")?;
+    #[tokio::test]
+    async fn failed_inspections_save_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path());
+        let docker = MockDockerClient::new();
 
-    Ok(snapshots)
+        let result = record_snapshot(
+            &docker,
+            &store,
+            &ImageSpec::new("missing:1"),
+            &git_context(),
+            false,
+            BTreeMap::new(),
+            None,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(store.load_history().unwrap().is_empty());
+    }
 }
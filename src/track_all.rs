@@ -1,39 +1,55 @@
 use anyhow::{Context, Result};
 use chrono::Utc;
-use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
 use std::process::Command;
 
-use crate::docker::DockerClient;
-use crate::format::format_size;
-use crate::models::ImageSnapshot;
-
-const HISTORY_DIR: &str = ".dit";
-const HISTORY_FILE: &str = "history.json";
+use crate::docker::{ImageFilter, ImageInspector};
+use crate::format::{format_size, short_image_id, OutputStyle};
+use crate::progress::ProgressReporter;
+use crate::chart::SparklineStyle;
+use crate::summary::print_mini_summary;
+use crate::store::Store;
+
+#[derive(Debug, Default)]
+pub struct TrackAllOptions {
+    /// Print a mini summary of the tracked images afterwards
+    pub summary_after: bool,
+    /// Show each image's short ID next to its size
+    pub include_image_id: bool,
+    pub output: OutputStyle,
+}
 
-pub async fn track_all_images(filter: Option<&str>) -> Result<()> {
-    let docker = DockerClient::new()?;
-    let images = docker.list_all_images(filter).await?;
+pub async fn track_all_images(
+    out: &mut impl Write,
+    docker: &impl ImageInspector,
+    store: &Store,
+    filter: &ImageFilter,
+    mut progress: Box<dyn ProgressReporter>,
+    options: &TrackAllOptions,
+) -> Result<()> {
+    let images = docker.select_images(filter).await?;
 
     if images.is_empty() {
-        println!("No images found");
+        writeln!(out, "No images found")?;
         return Ok(());
     }
 
     // Get git context once for all images
     let git_context = get_git_context()?;
 
-    println!("Tracking {} images at commit {}...\n", 
+    writeln!(out, "Tracking {} images at commit {}...\n", 
         images.len(),
         git_context.commit_sha.chars().take(7).collect::<String>()
-    );
+    )?;
 
     let mut total_size = 0u64;
     let mut success_count = 0;
 
+    progress.start(images.len());
+
     for image in &images {
-        print!("  {} ... ", image);
-        
+        progress.item_started(image);
+
         match docker.inspect_image(image).await {
             Ok(mut snapshot) => {
                 // Apply git context
@@ -44,25 +60,41 @@ pub async fn track_all_images(filter: Option<&str>) -> Result<()> {
                 snapshot.timestamp = Utc::now();
 
                 // Save snapshot
-                if let Err(e) = save_snapshot(&snapshot) {
-                    println!("❌ Failed to save: {}", e);
+                if let Err(e) = store.save_snapshot(&snapshot) {
+                    progress.item_failed(image, &format!("Failed to save: {}", e));
                 } else {
                     total_size += snapshot.total_size;
                     success_count += 1;
-                    println!("✅ {} tracked", format_size(snapshot.total_size));
+                    let id = match (&snapshot.image_id, options.include_image_id) {
+                        (Some(id), true) => format!(", image ID {}", short_image_id(id)),
+                        _ => String::new(),
+                    };
+                    progress.item_succeeded(
+                        image,
+                        &format!("{} tracked{}", format_size(snapshot.total_size), id),
+                    );
                 }
             }
             Err(e) => {
-                println!("❌ Failed: {}", e);
+                progress.item_failed(image, &format!("Failed: {}", e));
             }
         }
     }
 
-    println!(
+    progress.finish();
+
+    writeln!(
+        out,
         "\n✅ Tracked {} images, total size: {}",
         success_count,
         format_size(total_size)
-    );
+    )?;
+
+    if options.summary_after {
+        let history = store.load_history()?;
+        let style = SparklineStyle::select(false, options.output.plain);
+        print_mini_summary(out, &images, &history, options.output, style)?;
+    }
 
     Ok(())
 }
@@ -102,30 +134,3 @@ fn run_git(args: &[&str]) -> Result<String> {
 
     Ok(String::from_utf8(output.stdout)?.trim().to_string())
 }
-
-fn save_snapshot(snapshot: &ImageSnapshot) -> Result<()> {
-    // Create .dit directory if it doesn't exist
-    let dit_dir = PathBuf::from(HISTORY_DIR);
-    if !dit_dir.exists() {
-        fs::create_dir(&dit_dir).context("Failed to create .dit directory")?;
-    }
-
-    // Load existing history
-    let history_path = dit_dir.join(HISTORY_FILE);
-    let mut snapshots: Vec<ImageSnapshot> = if history_path.exists() {
-        let content = fs::read_to_string(&history_path)
-            .context("Failed to read history.json")?;
-        serde_json::from_str(&content).context("Failed to parse history.json")?
-    } else {
-        Vec::new()
-    };
-
-    // Append new snapshot
-    snapshots.push(snapshot.clone());
-
-    // Save back to file
-    let json = serde_json::to_string_pretty(&snapshots)?;
-    fs::write(&history_path, json).context("Failed to write history.json")?;
-
-    Ok(())
-}
@@ -0,0 +1,250 @@
+use anyhow::Result;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::io::Write;
+
+use crate::config::DitConfig;
+use crate::schema::SCHEMA_VERSION;
+use crate::store::Store;
+
+/// This binary's version
+pub const DIT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Oldest dit that reads history written with [`SCHEMA_VERSION`]; raise it with the schema
+pub const MIN_READER_VERSION: &str = "0.1.0";
+
+/// `.dit/store.json`: which history schema the store holds and who last wrote it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoreMeta {
+    pub schema_version: u32,
+    /// dit version that last wrote the history
+    pub written_by: String,
+    /// Oldest dit that can read `schema_version`
+    pub min_reader_version: String,
+}
+
+impl StoreMeta {
+    /// What this binary records when it writes history
+    pub fn current() -> Self {
+        StoreMeta {
+            schema_version: SCHEMA_VERSION,
+            written_by: DIT_VERSION.to_string(),
+            min_reader_version: MIN_READER_VERSION.to_string(),
+        }
+    }
+}
+
+/// How a store's schema relates to what this binary reads
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compatibility {
+    Same,
+    /// Readable; the next write records the newer schema
+    OlderStore,
+    /// Written by a newer dit; this binary must not read or write it
+    NewerStore { min_reader_version: String },
+}
+
+/// Compare a store's schema with the one this binary (`binary_schema`) reads
+pub fn compatibility(binary_schema: u32, store: &StoreMeta) -> Compatibility {
+    match store.schema_version.cmp(&binary_schema) {
+        Ordering::Equal => Compatibility::Same,
+        Ordering::Less => Compatibility::OlderStore,
+        Ordering::Greater => Compatibility::NewerStore {
+            min_reader_version: store.min_reader_version.clone(),
+        },
+    }
+}
+
+/// Actionable error for a store this binary can't read, naming the version to install
+pub fn newer_store_message(store_dir: &str, store: &StoreMeta, binary_version: &str) -> String {
+    format!(
+        "{} holds history schema v{} (last written by dit {}), but this dit {} reads up to v{}. \
+         Install dit {} or newer to use it; 'dit self-check' shows the details.",
+        store_dir,
+        store.schema_version,
+        store.written_by,
+        binary_version,
+        SCHEMA_VERSION,
+        store.min_reader_version
+    )
+}
+
+/// Compare dotted versions numerically (`0.10.0` > `0.9.1`); pre-release suffixes are ignored
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// `dit self-check`: binary version, store schema and config keys this binary doesn't know.
+/// Returns false when something needs a newer dit.
+pub fn self_check(out: &mut impl Write, store: &Store) -> Result<bool> {
+    let mut ok = true;
+
+    writeln!(out, "dit {} (history schema v{})", DIT_VERSION, SCHEMA_VERSION)?;
+
+    let dir = store.dir().display().to_string();
+    match store.load_meta()? {
+        None if store.existing_history_path().is_some() => writeln!(
+            out,
+            "Store {}: no version record yet (written before dit recorded one); \
+             the next write adds it",
+            dir
+        )?,
+        None => writeln!(out, "Store {}: empty", dir)?,
+        Some(meta) => {
+            let status = match compatibility(SCHEMA_VERSION, &meta) {
+                Compatibility::Same => "✅ compatible".green().to_string(),
+                Compatibility::OlderStore => {
+                    "✅ readable; the next write upgrades it".green().to_string()
+                }
+                Compatibility::NewerStore { .. } => {
+                    ok = false;
+                    "❌ needs a newer dit".red().to_string()
+                }
+            };
+            writeln!(
+                out,
+                "Store {}: schema v{}, last written by dit {} — {}",
+                dir, meta.schema_version, meta.written_by, status
+            )?;
+            if !ok {
+                writeln!(out, "  {}", newer_store_message(&dir, &meta, DIT_VERSION))?;
+            } else if compare_versions(&meta.written_by, DIT_VERSION) == Ordering::Greater {
+                writeln!(
+                    out,
+                    "  {}",
+                    "A newer dit wrote this store; consider upgrading so everyone runs the same version"
+                        .yellow()
+                )?;
+            }
+        }
+    }
+
+    let unknown = DitConfig::unknown_keys()?;
+    if unknown.is_empty() {
+        writeln!(out, "Config: ✅ every .dit.toml setting is understood")?;
+    } else {
+        ok = false;
+        writeln!(
+            out,
+            "Config: {} .dit.toml has settings this dit doesn't know ({}); they may need a newer dit",
+            "❌".red(),
+            unknown.join(", ")
+        )?;
+    }
+
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::TextOutput;
+    use tempfile::TempDir;
+
+    fn meta(schema_version: u32, written_by: &str) -> StoreMeta {
+        StoreMeta {
+            schema_version,
+            written_by: written_by.to_string(),
+            min_reader_version: "0.4.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn stores_are_compared_by_schema_version() {
+        assert_eq!(compatibility(2, &meta(2, "0.3.0")), Compatibility::Same);
+        assert_eq!(compatibility(2, &meta(1, "0.1.0")), Compatibility::OlderStore);
+        assert_eq!(
+            compatibility(2, &meta(3, "0.4.2")),
+            Compatibility::NewerStore { min_reader_version: "0.4.0".to_string() }
+        );
+    }
+
+    #[test]
+    fn newer_stores_name_the_version_to_install() {
+        let message = newer_store_message(".dit", &meta(SCHEMA_VERSION + 1, "0.4.2"), "0.3.1");
+        assert_eq!(
+            message,
+            format!(
+                ".dit holds history schema v{} (last written by dit 0.4.2), but this dit 0.3.1 \
+                 reads up to v{}. Install dit 0.4.0 or newer to use it; 'dit self-check' shows \
+                 the details.",
+                SCHEMA_VERSION + 1,
+                SCHEMA_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        for (a, b, expected) in [
+            ("0.10.0", "0.9.1", Ordering::Greater),
+            ("0.1.0", "0.1.0", Ordering::Equal),
+            ("v0.2.0", "0.2", Ordering::Equal),
+            ("0.2.0-rc.1", "0.2.0", Ordering::Equal),
+            ("1.0.0+build.5", "1.0.1", Ordering::Less),
+        ] {
+            assert_eq!(compare_versions(a, b), expected, "{} vs {}", a, b);
+        }
+    }
+
+    /// What `dit self-check` prints for a store holding `meta`, and whether it passed
+    fn self_check_with(meta: Option<StoreMeta>) -> (String, bool) {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        if let Some(meta) = meta {
+            std::fs::create_dir(store.dir()).unwrap();
+            std::fs::write(store.dir().join("store.json"), serde_json::to_string(&meta).unwrap())
+                .unwrap();
+        }
+
+        let mut out = TextOutput::new(Vec::new());
+        out.strip_color();
+        let ok = self_check(&mut out, &store).unwrap();
+        let printed = String::from_utf8(out.into_inner()).unwrap();
+        (printed.replace(&dir.path().display().to_string(), "<dir>"), ok)
+    }
+
+    #[test]
+    fn self_check_reports_the_binary_and_store_versions() {
+        let (printed, ok) = self_check_with(Some(StoreMeta::current()));
+        assert!(ok, "{}", printed);
+        let first = printed.lines().next().unwrap();
+        assert_eq!(first, format!("dit {} (history schema v{})", DIT_VERSION, SCHEMA_VERSION));
+        assert!(
+            printed.contains(&format!(
+                "Store <dir>/.dit: schema v{}, last written by dit {} — ✅ compatible",
+                SCHEMA_VERSION, DIT_VERSION
+            )),
+            "{}",
+            printed
+        );
+
+        let (printed, ok) = self_check_with(None);
+        assert!(ok, "{}", printed);
+        assert!(printed.contains("Store <dir>/.dit: empty"), "{}", printed);
+    }
+
+    #[test]
+    fn self_check_fails_for_a_store_from_a_newer_dit() {
+        let (printed, ok) = self_check_with(Some(meta(SCHEMA_VERSION + 1, "99.0.0")));
+        assert!(!ok);
+        let store_line = "last written by dit 99.0.0 — ❌ needs a newer dit";
+        assert!(printed.contains(store_line), "{}", printed);
+        assert!(printed.contains("Install dit 0.4.0 or newer"), "{}", printed);
+    }
+}
@@ -0,0 +1,559 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use tabled::builder::Builder;
+
+use crate::docker::{parse_image_reference, DockerClient, ImageInspector};
+use crate::format::{format_size, format_size_delta, render_table, OutputStyle};
+use crate::models::{ImageOrigin, ImageSnapshot};
+use crate::platforms::{manifest_digest, manifest_download_size};
+use crate::reference::ImageRef;
+use crate::store::Store;
+
+/// Third-party image we consume but don't build, from `dit watch-list add`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedImage {
+    /// Reference as given, e.g. `postgres:16`
+    pub reference: String,
+    /// Maximum size in bytes
+    #[serde(default)]
+    pub budget: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum WatchFormat {
+    Table,
+    Json,
+    Markdown,
+}
+
+#[derive(Debug)]
+pub struct WatchCheckOptions {
+    /// Read the registry manifest instead of the locally pulled image
+    pub remote: bool,
+    /// Platform to size with `remote` (default: the Docker host's)
+    pub platform: Option<String>,
+    /// Largest allowed growth since the last observation, in percent
+    pub tolerance: f64,
+    /// Fail instead of warn when a reference now points at a different digest
+    pub fail_on_digest_change: bool,
+    pub format: WatchFormat,
+    pub output: OutputStyle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchStatus {
+    Ok,
+    /// First observation; later checks compare against it
+    Baseline,
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WatchCheck {
+    pub reference: String,
+    /// Compressed registry size with `--remote`, uncompressed local size otherwise
+    pub remote: bool,
+    pub digest: Option<String>,
+    pub previous_digest: Option<String>,
+    pub size: Option<u64>,
+    pub previous_size: Option<u64>,
+    pub budget: Option<u64>,
+    pub status: WatchStatus,
+    /// Why the status isn't `ok`
+    pub notes: Vec<String>,
+}
+
+/// `dit watch-list add`: start watching `reference`, or change its budget
+pub fn add_watched(
+    out: &mut impl Write,
+    store: &Store,
+    reference: &str,
+    budget: Option<u64>,
+) -> Result<()> {
+    let mut watched = store.load_watch_list()?;
+
+    match watched.iter_mut().find(|w| w.reference == reference) {
+        Some(existing) => {
+            existing.budget = budget;
+            writeln!(out, "Updated {}", reference)?;
+        }
+        None => {
+            watched.push(WatchedImage {
+                reference: reference.to_string(),
+                budget,
+            });
+            writeln!(out, "Watching {}", reference)?;
+        }
+    }
+
+    store.save_watch_list(&watched)
+}
+
+/// `dit watch-list remove`
+pub fn remove_watched(out: &mut impl Write, store: &Store, reference: &str) -> Result<()> {
+    let mut watched = store.load_watch_list()?;
+    let before = watched.len();
+    watched.retain(|w| w.reference != reference);
+
+    if watched.len() == before {
+        bail!("'{}' is not on the watch list", reference);
+    }
+
+    store.save_watch_list(&watched)?;
+    writeln!(out, "Stopped watching {}", reference)?;
+    Ok(())
+}
+
+/// `dit watch-list list`
+pub fn list_watched(out: &mut impl Write, store: &Store) -> Result<()> {
+    let watched = store.load_watch_list()?;
+    if watched.is_empty() {
+        writeln!(out, "Nothing is watched yet; add an image with 'dit watch-list add <image>'")?;
+        return Ok(());
+    }
+
+    for entry in watched {
+        match entry.budget {
+            Some(budget) => writeln!(out, "{} (budget {})", entry.reference, format_size(budget))?,
+            None => writeln!(out, "{}", entry.reference)?,
+        }
+    }
+    Ok(())
+}
+
+/// `dit watch-list check`: observe every watched image, compare it with its last
+/// observation and record the new one. Returns whether any check failed.
+pub async fn check_watched(
+    out: &mut impl Write,
+    store: &Store,
+    options: &WatchCheckOptions,
+) -> Result<bool> {
+    let watched = store.load_watch_list()?;
+    if watched.is_empty() {
+        bail!("Nothing is watched yet; add an image with 'dit watch-list add <image>'");
+    }
+
+    let checks = if options.remote {
+        let platform = match &options.platform {
+            Some(platform) => platform.clone(),
+            None => DockerClient::new()?.host_platform().await?,
+        };
+        let source: Source<DockerClient> = Source::Registry(platform);
+        observe_watched(&source, store, &watched, options).await?
+    } else {
+        let docker = DockerClient::new()?;
+        observe_watched(&Source::Local(&docker), store, &watched, options).await?
+    };
+
+    match options.format {
+        WatchFormat::Table => print_checks_table(out, &checks, options.output)?,
+        WatchFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&checks)?)?,
+        WatchFormat::Markdown => write!(out, "{}", render_markdown(&checks, options.output.plain))?,
+    }
+
+    Ok(checks.iter().any(|c| c.status == WatchStatus::Failed))
+}
+
+/// Where observations come from
+pub enum Source<'a, I> {
+    /// Registry manifest for this `os/arch[/variant]`
+    Registry(String),
+    /// The locally pulled image
+    Local(&'a I),
+}
+
+/// Observe each of `watched`, evaluate it against its last observation and record it.
+/// An image that can't be observed fails its check and records nothing.
+pub async fn observe_watched(
+    source: &Source<'_, impl ImageInspector>,
+    store: &Store,
+    watched: &[WatchedImage],
+    options: &WatchCheckOptions,
+) -> Result<Vec<WatchCheck>> {
+    let history = store.load_history()?;
+
+    let mut checks = Vec::new();
+    for entry in watched {
+        let observation = match source {
+            Source::Registry(platform) => observe_remote(&entry.reference, platform),
+            Source::Local(docker) => docker.inspect_image(&entry.reference).await.map(|mut s| {
+                s.watched = true;
+                s
+            }),
+        };
+
+        let observation = match observation {
+            Ok(observation) => observation,
+            Err(e) => {
+                checks.push(WatchCheck {
+                    reference: entry.reference.clone(),
+                    remote: options.remote,
+                    digest: None,
+                    previous_digest: None,
+                    size: None,
+                    previous_size: None,
+                    budget: entry.budget,
+                    status: WatchStatus::Failed,
+                    notes: vec![format!("{:#}", e)],
+                });
+                continue;
+            }
+        };
+
+        let previous = last_observation(&history, &entry.reference, options.remote);
+        checks.push(evaluate(entry, &observation, previous, options));
+        store.save_snapshot(&observation)?;
+    }
+
+    Ok(checks)
+}
+
+/// The latest watched snapshot of `reference` observed the same way. Local and registry
+/// sizes aren't comparable, so each mode has its own baseline, and builds tracked under
+/// the same name never count.
+pub fn last_observation<'a>(
+    history: &'a [ImageSnapshot],
+    reference: &str,
+    remote: bool,
+) -> Option<&'a ImageSnapshot> {
+    let reference = ImageRef::parse_lenient(reference);
+    history
+        .iter()
+        .filter(|s| s.watched && reference.matches(s))
+        .filter(|s| s.compressed_size.is_some() == remote)
+        .max_by_key(|s| s.timestamp)
+}
+
+/// Registry digest and compressed size of `reference` for `platform`, as a watched snapshot
+fn observe_remote(reference: &str, platform: &str) -> Result<ImageSnapshot> {
+    let digest = manifest_digest(reference)?;
+    let size = manifest_download_size(reference, platform)?;
+
+    let (image, tag) = parse_image_reference(reference);
+    let mut parts = platform.split('/');
+    let os = parts.next().unwrap_or("linux").to_string();
+    let arch = parts.next().unwrap_or("amd64").to_string();
+    let variant = parts.next().map(str::to_string);
+
+    Ok(ImageSnapshot {
+        image,
+        tag,
+        digest: Some(digest),
+        image_id: None,
+        commit_sha: String::new(),
+        branch: String::new(),
+        commit_message: String::new(),
+        author: String::new(),
+        timestamp: Utc::now(),
+        total_size: 0,
+        compressed_size: Some(size),
+        registry_only: true,
+        layer_count: 0,
+        layers: Vec::new(),
+        os,
+        arch,
+        variant,
+        group_id: None,
+        host_platform: None,
+        env: None,
+        labels: None,
+        user: None,
+        annotations: Default::default(),
+        oci_annotations: Default::default(),
+        extract_ms: None,
+        file_count: None,
+        watched: true,
+        origin: ImageOrigin::Pulled,
+        build_duration_ms: None,
+    })
+}
+
+pub fn evaluate(
+    entry: &WatchedImage,
+    observation: &ImageSnapshot,
+    previous: Option<&ImageSnapshot>,
+    options: &WatchCheckOptions,
+) -> WatchCheck {
+    let size = observation.size();
+    let digest = observation.digest.as_deref().map(bare_digest);
+    let mut status = WatchStatus::Ok;
+    let mut notes = Vec::new();
+
+    match previous {
+        None => status = WatchStatus::Baseline,
+        Some(previous) => {
+            let previous_digest = previous.digest.as_deref().map(bare_digest);
+            if let (Some(before), Some(after)) = (previous_digest, digest) {
+                if before != after {
+                    notes.push(format!(
+                        "digest changed ({} → {})",
+                        short_digest(before),
+                        short_digest(after)
+                    ));
+                    status = status.max(if options.fail_on_digest_change {
+                        WatchStatus::Failed
+                    } else {
+                        WatchStatus::Warning
+                    });
+                }
+            }
+
+            if previous.size() > 0 && size > previous.size() {
+                let growth = (size - previous.size()) as f64 / previous.size() as f64 * 100.0;
+                if growth > options.tolerance {
+                    notes.push(format!(
+                        "grew {:.1}%, over the {}% tolerance",
+                        growth, options.tolerance
+                    ));
+                    status = WatchStatus::Failed;
+                }
+            }
+        }
+    }
+
+    if let Some(budget) = entry.budget {
+        if size > budget {
+            notes.push(format!("over the {} budget", format_size(budget)));
+            status = WatchStatus::Failed;
+        }
+    }
+
+    WatchCheck {
+        reference: entry.reference.clone(),
+        remote: options.remote,
+        digest: digest.map(str::to_string),
+        previous_digest: previous
+            .and_then(|p| p.digest.as_deref())
+            .map(|d| bare_digest(d).to_string()),
+        size: Some(size),
+        previous_size: previous.map(|p| p.size()),
+        budget: entry.budget,
+        status,
+        notes,
+    }
+}
+
+/// Local repo digests are recorded as `postgres@sha256:…`; registry ones without the name
+fn bare_digest(digest: &str) -> &str {
+    digest.rsplit_once('@').map_or(digest, |(_, digest)| digest)
+}
+
+fn short_digest(digest: &str) -> String {
+    digest.trim_start_matches("sha256:").chars().take(12).collect()
+}
+
+fn change(check: &WatchCheck, plain: bool) -> String {
+    match (check.size, check.previous_size) {
+        (Some(size), Some(previous)) => format_size_delta(size as i64 - previous as i64, plain),
+        _ => "-".to_string(),
+    }
+}
+
+fn print_checks_table(
+    out: &mut impl Write,
+    checks: &[WatchCheck],
+    output: OutputStyle,
+) -> Result<()> {
+    let mut builder = Builder::default();
+    builder.push_record(["Image", "Size", "Change", "Budget", "Digest", "Status"]);
+
+    for check in checks {
+        let status = match check.status {
+            WatchStatus::Ok => "ok".green().to_string(),
+            WatchStatus::Baseline => "new baseline".cyan().to_string(),
+            WatchStatus::Warning => "warning".yellow().to_string(),
+            WatchStatus::Failed => "FAILED".red().bold().to_string(),
+        };
+        builder.push_record([
+            check.reference.clone(),
+            check.size.map_or("-".to_string(), format_size),
+            change(check, output.plain),
+            check.budget.map_or("-".to_string(), format_size),
+            check.digest.as_deref().map_or("-".to_string(), short_digest),
+            status,
+        ]);
+    }
+
+    writeln!(out, "{}", render_table(builder, output.plain))?;
+
+    for check in checks.iter().filter(|c| !c.notes.is_empty()) {
+        let icon = if check.status == WatchStatus::Failed { "❌" } else { "⚠️ " };
+        writeln!(out, "{} {}: {}", icon, check.reference, check.notes.join("; "))?;
+    }
+
+    Ok(())
+}
+
+pub fn render_markdown(checks: &[WatchCheck], plain: bool) -> String {
+    let mut out = String::from(
+        "## 👀 Watched images\n\n| Image | Size | Change | Budget | Digest | Status |\n\
+         |-------|------|--------|--------|--------|--------|\n",
+    );
+
+    for check in checks {
+        let status = match check.status {
+            WatchStatus::Ok => "✅".to_string(),
+            WatchStatus::Baseline => "🆕 baseline".to_string(),
+            WatchStatus::Warning => format!("⚠️ {}", check.notes.join("; ")),
+            WatchStatus::Failed => format!("❌ {}", check.notes.join("; ")),
+        };
+        out.push_str(&format!(
+            "| `{}` | {} | {} | {} | `{}` | {} |\n",
+            check.reference,
+            check.size.map_or("-".to_string(), format_size),
+            change(check, plain),
+            check.budget.map_or("-".to_string(), format_size),
+            check.digest.as_deref().map_or("-".to_string(), short_digest),
+            status.replace('|', "\\|")
+        ));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::MockDockerClient;
+    use crate::fixtures::{at, layer, snapshot};
+    use tempfile::TempDir;
+
+    const OLD: &str = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+    const NEW: &str = "sha256:2222222222222222222222222222222222222222222222222222222222222222";
+
+    fn options() -> WatchCheckOptions {
+        WatchCheckOptions {
+            remote: false,
+            platform: None,
+            tolerance: 10.0,
+            fail_on_digest_change: false,
+            format: WatchFormat::Table,
+            output: OutputStyle::default(),
+        }
+    }
+
+    fn postgres(budget: Option<u64>) -> WatchedImage {
+        WatchedImage {
+            reference: "postgres:16".to_string(),
+            budget,
+        }
+    }
+
+    /// postgres:16 observed locally at `size` bytes with repo digest `digest`
+    fn observed(size: u64, digest: &str, minutes: i64) -> ImageSnapshot {
+        let mut snapshot = snapshot("postgres", "16", vec![layer("sha256:pg", size, "ADD rootfs")]);
+        snapshot.digest = Some(format!("postgres@{}", digest));
+        snapshot.timestamp = at(minutes);
+        snapshot.watched = true;
+        snapshot
+    }
+
+    #[test]
+    fn the_last_observation_is_the_latest_watched_one_of_the_same_kind() {
+        let mut built = observed(999, OLD, 5);
+        built.watched = false;
+        let mut registry = observed(0, OLD, 6);
+        registry.compressed_size = Some(50);
+        registry.registry_only = true;
+        let history = vec![
+            observed(100, OLD, 1),
+            observed(120, OLD, 3),
+            observed(110, OLD, 2),
+            built,
+            registry,
+        ];
+
+        assert_eq!(last_observation(&history, "postgres:16", false).unwrap().size(), 120);
+        assert_eq!(last_observation(&history, "postgres:16", true).unwrap().size(), 50);
+        assert!(last_observation(&history, "postgres:15", false).is_none());
+    }
+
+    #[test]
+    fn the_first_observation_is_the_baseline() {
+        let check = evaluate(&postgres(None), &observed(100, OLD, 0), None, &options());
+        assert_eq!(check.status, WatchStatus::Baseline);
+        assert_eq!(check.digest.as_deref(), Some(OLD));
+        assert!(check.notes.is_empty());
+    }
+
+    #[test]
+    fn growth_fails_only_beyond_the_tolerance() {
+        let before = observed(1000, OLD, 0);
+
+        let within = evaluate(&postgres(None), &observed(1100, OLD, 1), Some(&before), &options());
+        assert_eq!(within.status, WatchStatus::Ok);
+
+        let beyond = evaluate(&postgres(None), &observed(1101, OLD, 1), Some(&before), &options());
+        assert_eq!(beyond.status, WatchStatus::Failed);
+        assert_eq!(beyond.notes, vec!["grew 10.1%, over the 10% tolerance"]);
+
+        let shrunk = evaluate(&postgres(None), &observed(500, OLD, 1), Some(&before), &options());
+        assert_eq!(shrunk.status, WatchStatus::Ok);
+    }
+
+    #[test]
+    fn digest_changes_warn_unless_strict() {
+        let before = observed(1000, OLD, 0);
+        let after = observed(1000, NEW, 1);
+
+        let check = evaluate(&postgres(None), &after, Some(&before), &options());
+        assert_eq!(check.status, WatchStatus::Warning);
+        assert_eq!(check.notes, vec!["digest changed (111111111111 → 222222222222)"]);
+
+        let strict = WatchCheckOptions { fail_on_digest_change: true, ..options() };
+        let check = evaluate(&postgres(None), &after, Some(&before), &strict);
+        assert_eq!(check.status, WatchStatus::Failed);
+    }
+
+    #[test]
+    fn budgets_fail_even_on_the_first_observation() {
+        let check = evaluate(&postgres(Some(99)), &observed(100, OLD, 0), None, &options());
+        assert_eq!(check.status, WatchStatus::Failed);
+        assert_eq!(check.notes, vec!["over the 99 B budget"]);
+    }
+
+    #[tokio::test]
+    async fn checks_compare_with_and_record_each_observation() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path().join(".dit"));
+        let redis = WatchedImage {
+            reference: "redis:7".to_string(),
+            budget: None,
+        };
+        let watched = vec![postgres(None), redis];
+        // What `docker image inspect` returns, before it's marked as an observation
+        let pulled = |size, digest, minutes| {
+            let snapshot = ImageSnapshot { watched: false, ..observed(size, digest, minutes) };
+            MockDockerClient::new().with_image("postgres:16", Ok(snapshot))
+        };
+
+        let first = pulled(1000, OLD, 0);
+        let checks = observe_watched(&Source::Local(&first), &store, &watched, &options())
+            .await
+            .unwrap();
+        let statuses: Vec<WatchStatus> = checks.iter().map(|c| c.status).collect();
+        assert_eq!(statuses, vec![WatchStatus::Baseline, WatchStatus::Failed]);
+        assert!(checks[1].notes[0].contains("redis:7"), "{:?}", checks[1].notes);
+
+        // Upstream re-pushed the tag 30% bigger
+        let second = pulled(1300, NEW, 1);
+        let checks = observe_watched(&Source::Local(&second), &store, &watched[..1], &options())
+            .await
+            .unwrap();
+        assert_eq!(checks[0].status, WatchStatus::Failed);
+        assert_eq!(checks[0].previous_size, Some(1000));
+        assert_eq!(checks[0].previous_digest.as_deref(), Some(OLD));
+        assert_eq!(checks[0].notes.len(), 2, "{:?}", checks[0].notes);
+
+        // Only successful observations are recorded, all marked as watched
+        let history = store.load_history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|s| s.watched && s.image == "postgres"));
+    }
+}
@@ -0,0 +1,27 @@
+use std::io::Read;
+use std::process::{Command, Stdio};
+
+use assert_cmd::cargo::CommandCargoExt;
+
+/// A reader that stops early (`dit ... | head -c 10`) ends dit with 141, like SIGPIPE
+/// would, and no panic message
+#[test]
+fn closed_stdout_exits_141_quietly() {
+    let mut child = Command::cargo_bin("dit")
+        .unwrap()
+        // Well over a pipe buffer, so writing can't finish before the reader is gone
+        .args(["completions", "bash"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = child.stdout.take().unwrap();
+    let mut head = [0; 10];
+    stdout.read_exact(&mut head).unwrap();
+    drop(stdout);
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(141));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
@@ -0,0 +1,266 @@
+//! Two stores driven at once from a multi-threaded runtime must never see each other's
+//! snapshots, diffs, reports or output settings: nothing about a run may live in
+//! process-wide state, and everything printed goes to the writer each run passes in.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use regex::Regex;
+use tempfile::TempDir;
+use tokio::sync::Barrier;
+
+use dit::analyze::{run_analysis, AnalyzeOptions, OutputFormat};
+use dit::diff::{diff_snapshots, select_snapshots, BasisChoice, DiffAlgorithm, DiffOptions};
+use dit::docker::{ImageInspector, ImageSpec};
+use dit::format::{print_diff_table, LayerOrder, LayerSizeUnit, OutputStyle, UnchangedLayers};
+use dit::models::{ImageOrigin, ImageSnapshot, LayerInfo};
+use dit::report::{ReportContext, ReportTemplate};
+use dit::store::Store;
+
+/// Serves one fixed snapshot per image, whatever the tag
+struct FakeDocker {
+    image: String,
+    layers: Vec<(String, u64)>,
+}
+
+impl ImageInspector for FakeDocker {
+    async fn inspect_image(&self, image: &str) -> Result<ImageSnapshot> {
+        let (name, tag) = image.split_once(':').unwrap_or((image, "latest"));
+        if name != self.image {
+            bail!("Failed to inspect image '{}'", image);
+        }
+        Ok(snapshot(name, tag, &self.layers))
+    }
+
+    async fn list_all_images(&self, _: Option<&str>, _: Option<&Regex>) -> Result<Vec<String>> {
+        Ok(vec![format!("{}:latest", self.image)])
+    }
+
+    async fn list_images_by_label(&self, _: &str, _: Option<&str>) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn export_image_to_file(&self, reference: &str, _: &Path) -> Result<()> {
+        bail!("FakeDocker can't export '{}'", reference)
+    }
+}
+
+fn snapshot(image: &str, tag: &str, layers: &[(String, u64)]) -> ImageSnapshot {
+    let layers: Vec<LayerInfo> = layers
+        .iter()
+        .map(|(digest, size)| LayerInfo {
+            digest: digest.clone(),
+            size: *size,
+            command: format!("RUN build {}", digest),
+            created: Utc::now(),
+            extract_ms: None,
+            file_count: None,
+        })
+        .collect();
+    ImageSnapshot {
+        image: image.to_string(),
+        tag: Some(tag.to_string()),
+        digest: None,
+        image_id: None,
+        commit_sha: String::new(),
+        branch: String::new(),
+        commit_message: String::new(),
+        author: String::new(),
+        timestamp: Utc::now(),
+        total_size: layers.iter().map(|l| l.size).sum(),
+        compressed_size: None,
+        registry_only: false,
+        layer_count: layers.len(),
+        layers,
+        os: "linux".to_string(),
+        arch: "amd64".to_string(),
+        variant: None,
+        group_id: None,
+        host_platform: None,
+        env: None,
+        labels: None,
+        user: None,
+        annotations: Default::default(),
+        oci_annotations: Default::default(),
+        extract_ms: None,
+        file_count: None,
+        watched: false,
+        origin: ImageOrigin::Unknown,
+        build_duration_ms: None,
+    }
+}
+
+fn analyze_options() -> AnalyzeOptions {
+    AnalyzeOptions {
+        format: OutputFormat::Json,
+        warn_large_layer: None,
+        show_as_dockerfile: false,
+        measure_extract: false,
+        total_only: false,
+        compare_to_previous: true,
+        platform: Some("linux/amd64".to_string()),
+        auto_track: true,
+        no_git: true,
+        deep: None,
+        layer_size_unit: LayerSizeUnit::default(),
+        layer_order: LayerOrder::default(),
+        layer_labels: None,
+        output: OutputStyle::default(),
+    }
+}
+
+fn diff_options(output: OutputStyle) -> DiffOptions {
+    DiffOptions {
+        base_branch: None,
+        explain_baseline: false,
+        show_env_values: false,
+        layer_order_matters: false,
+        algorithm: DiffAlgorithm::Digest,
+        match_by_command: false,
+        max_changes: 0,
+        total_only: false,
+        compare_with_parent_commit: false,
+        against_k8s: None,
+        ignore_below: None,
+        ignore_below_pct: None,
+        verbose_if_below_threshold: false,
+        word_diff: false,
+        save_to_history: true,
+        layer_size_unit: LayerSizeUnit::default(),
+        basis: BasisChoice::Auto,
+        unchanged_layers: UnchangedLayers::default(),
+        left_file: None,
+        right_file: None,
+        output,
+    }
+}
+
+/// What one store's run produced
+struct Run {
+    history: Vec<ImageSnapshot>,
+    delta: i64,
+    report: String,
+    /// The diff table as printed to this run's own writer
+    printed: String,
+}
+
+/// Track two builds of `image` (`base` then `base + growth` bytes) and diff, print and
+/// report them, meeting the other run at `barrier` before every step
+async fn run(
+    store: Store,
+    image: &str,
+    (base, growth): (u64, u64),
+    output: OutputStyle,
+    barrier: Arc<Barrier>,
+) -> Result<Run> {
+    let before = FakeDocker {
+        image: image.to_string(),
+        layers: vec![(format!("sha256:{}-base", image), base)],
+    };
+    let after = FakeDocker {
+        image: image.to_string(),
+        layers: vec![
+            (format!("sha256:{}-base", image), base),
+            (format!("sha256:{}-app", image), growth),
+        ],
+    };
+    let spec = ImageSpec::new(&format!("{}:v1", image));
+
+    barrier.wait().await;
+    let first = run_analysis(&before, &store, &spec, &analyze_options()).await?;
+    assert!(first.tracked);
+    assert!(first.previous_diff.is_none());
+
+    barrier.wait().await;
+    let second = run_analysis(&after, &store, &spec, &analyze_options()).await?;
+    let previous = second.previous_diff.expect("the first build is tracked");
+    assert_eq!(previous.total_delta, growth as i64);
+
+    barrier.wait().await;
+    let options = diff_options(output);
+    let mut printed = Vec::new();
+    let (older, newer) = select_snapshots(&mut printed, &store, image, None, None, &options)?;
+    let outcome = diff_snapshots(&store, older, newer.clone(), &options)?;
+    assert!(outcome.saved_to.is_some());
+
+    barrier.wait().await;
+    print_diff_table(&mut printed, &outcome.diff, &options)?;
+
+    barrier.wait().await;
+    let template = ReportTemplate::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/examples/report-templates/minimal.md.j2"
+    ))?;
+    let context = ReportContext::new(&[(newer, Some(outcome.diff.clone()))], &[], None, 80.0, None, None);
+    let report = template.render(&context)?;
+
+    Ok(Run {
+        history: store.load_history()?,
+        delta: outcome.diff.total_delta,
+        report,
+        printed: String::from_utf8(printed)?,
+    })
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_stores_stay_isolated() {
+    let dir_a = TempDir::new().unwrap();
+    let dir_b = TempDir::new().unwrap();
+    let barrier = Arc::new(Barrier::new(2));
+
+    let a = tokio::spawn(run(
+        Store::new(dir_a.path().join(".dit")),
+        "alpha",
+        (1_000_000, 250_000),
+        OutputStyle {
+            plain: true,
+            ..Default::default()
+        },
+        barrier.clone(),
+    ));
+    let b = tokio::spawn(run(
+        Store::new(dir_b.path().join(".dit")),
+        "beta",
+        (5_000_000, 3_000_000),
+        OutputStyle::default(),
+        barrier,
+    ));
+    let (a, b) = (a.await.unwrap().unwrap(), b.await.unwrap().unwrap());
+
+    assert_eq!(a.delta, 250_000);
+    assert_eq!(b.delta, 3_000_000);
+
+    for (run, image, sizes) in [
+        (&a, "alpha", [1_000_000, 1_250_000]),
+        (&b, "beta", [5_000_000, 8_000_000]),
+    ] {
+        assert_eq!(run.history.len(), 2);
+        assert!(run.history.iter().all(|s| s.image == image));
+        let recorded: Vec<u64> = run.history.iter().map(|s| s.total_size).collect();
+        assert_eq!(recorded, sizes);
+        assert!(run.report.contains(image));
+    }
+    assert!(!a.report.contains("beta"));
+    assert!(!b.report.contains("alpha"));
+
+    // Each run printed its own image in its own style: alpha plain, beta as tables
+    assert!(a.printed.contains("alpha") && !a.printed.contains("beta"), "{}", a.printed);
+    assert!(b.printed.contains("beta") && !b.printed.contains("alpha"), "{}", b.printed);
+    assert!(a.printed.contains("increase of 244.1 kibibytes"), "{}", a.printed);
+    assert!(!a.printed.contains('╭'), "{}", a.printed);
+    assert!(b.printed.contains("+2.9 MiB (+60.0%)"), "{}", b.printed);
+    assert!(b.printed.contains('╭'), "{}", b.printed);
+
+    // Saved diffs land in their own store only
+    for (dir, image, other) in [(&dir_a, "alpha", "beta"), (&dir_b, "beta", "alpha")] {
+        let diffs = dir.path().join(".dit").join("diffs");
+        let saved: Vec<String> = std::fs::read_dir(&diffs)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(saved.iter().any(|name| name.starts_with(image)), "{:?}", saved);
+        assert!(!saved.iter().any(|name| name.starts_with(other)), "{:?}", saved);
+    }
+}
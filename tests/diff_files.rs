@@ -0,0 +1,163 @@
+//! `dit diff --left / --right` compares exported snapshot files with each other or with
+//! history, labels where each side came from, and honors thresholds and exit codes.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use assert_cmd::cargo::CommandCargoExt;
+use serde_json::Value;
+use tempfile::TempDir;
+
+const IMAGE: &str = "registry.example.com/team/myapp:2.1";
+
+/// myapp:2.1 tracked at c1a0001 … c5e0005 (and worker:1.0), with the c4d0004 and c5e0005
+/// snapshots exported as before.json and after.json
+fn workspace() -> TempDir {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/plain");
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join(".dit")).unwrap();
+    fs::copy(fixtures.join("history.json"), dir.path().join(".dit/history.json")).unwrap();
+    for file in ["before.json", "after.json"] {
+        fs::copy(fixtures.join(file), dir.path().join(file)).unwrap();
+    }
+    dir
+}
+
+fn dit(dir: &Path, args: &[&str]) -> Output {
+    Command::cargo_bin("dit")
+        .unwrap()
+        .current_dir(dir)
+        .env("NO_COLOR", "1")
+        .env_remove("GITHUB_ACTIONS")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(dir: &Path, args: &[&str]) -> String {
+    let output = dit(dir, args);
+    assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn stderr(dir: &Path, args: &[&str]) -> String {
+    let output = dit(dir, args);
+    assert!(!output.status.success(), "{:?} succeeded", args);
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// `name` in the workspace: after.json with `edit` applied
+fn write_variant(dir: &Path, name: &str, edit: impl FnOnce(&mut Value)) {
+    let mut value: Value = serde_json::from_str(&fs::read_to_string(dir.join("after.json")).unwrap()).unwrap();
+    edit(&mut value);
+    fs::write(dir.join(name), value.to_string()).unwrap();
+}
+
+#[test]
+fn file_against_file() {
+    let dir = workspace();
+    let output = stdout(dir.path(), &["diff", "--left", "before.json", "--right", "after.json"]);
+
+    assert!(output.starts_with(&format!(
+        "Before: file before.json ({}, commit c4d0004)\nAfter: file after.json ({}, commit c5e0005)\n",
+        IMAGE, IMAGE
+    )), "{}", output);
+    assert!(output.contains("Change: +13.5 MiB (+25.6%)"), "{}", output);
+
+    // Nothing needs to be tracked
+    fs::remove_dir_all(dir.path().join(".dit")).unwrap();
+    let total = stdout(dir.path(), &["diff", "--left-file", "before.json", "--right-file", "after.json", "--total-only"]);
+    assert_eq!(total, "+13.5 MiB\n");
+}
+
+#[test]
+fn file_against_the_latest_history_snapshot() {
+    let dir = workspace();
+    let output = stdout(dir.path(), &["diff", "--left", "before.json"]);
+
+    assert!(output.contains(&format!("After: dit history on main ({}, commit c5e0005)", IMAGE)), "{}", output);
+    assert!(output.contains("Change: +13.5 MiB"), "{}", output);
+}
+
+#[test]
+fn history_commit_against_a_file() {
+    let dir = workspace();
+    let output = stdout(dir.path(), &["diff", IMAGE, "c2b0002", "--right", "after.json"]);
+
+    assert!(output.contains(&format!("Before: dit history on main ({}, commit c2b0002)", IMAGE)), "{}", output);
+    assert!(output.contains(&format!("After: file after.json ({}, commit c5e0005)", IMAGE)), "{}", output);
+    assert!(output.contains("Change: +15.0 MiB"), "{}", output);
+}
+
+#[test]
+fn history_baseline_against_a_file() {
+    let dir = workspace();
+    let output = stdout(dir.path(), &["diff", "--base", "main", "--right", "before.json", "--total-only"]);
+
+    // The latest main snapshot (c5e0005) is 13.5 MiB bigger than before.json
+    assert_eq!(output, "-13.5 MiB\n");
+}
+
+#[test]
+fn a_file_for_an_untracked_image_needs_both_sides() {
+    let dir = workspace();
+    write_variant(dir.path(), "other.json", |v| v["image"] = "ghcr.io/acme/other".into());
+    let error = stderr(dir.path(), &["diff", "--right", "other.json"]);
+
+    assert!(error.contains("No history found for image 'ghcr.io/acme/other:2.1'"), "{}", error);
+}
+
+#[test]
+fn commits_need_a_history_side() {
+    let dir = workspace();
+    let error = stderr(dir.path(), &["diff", IMAGE, "c2b0002", "--left", "before.json", "--right", "after.json"]);
+
+    assert!(error.contains("with both --left and --right there is none"), "{}", error);
+}
+
+#[test]
+fn exit_codes_and_thresholds_apply() {
+    let dir = workspace();
+    let files = ["diff", "--left", "before.json", "--right", "after.json", "--total-only"];
+    let code = |extra: &[&str]| dit(dir.path(), &[&files[..], extra].concat()).status.code();
+
+    assert_eq!(code(&["--emit-exit-code-for-increase"]), Some(1));
+    assert_eq!(code(&["--emit-exit-code-for-increase", "--fail-threshold", "20MB"]), Some(0));
+    let shrank = dit(dir.path(), &["diff", "--left", "after.json", "--right", "before.json", "--emit-exit-code-for-increase"]);
+    assert_eq!(shrank.status.code(), Some(2));
+
+    // Below the threshold nothing is printed, source labels included
+    let quiet = stdout(dir.path(), &["diff", "--left", "before.json", "--right", "after.json", "--ignore-below-threshold", "20MB"]);
+    assert_eq!(quiet, "");
+}
+
+#[test]
+fn schema_versions_are_checked() {
+    let dir = workspace();
+    write_variant(dir.path(), "future.json", |v| v["schema_version"] = 99.into());
+    write_variant(dir.path(), "current.json", |v| v["schema_version"] = 2.into());
+    write_variant(dir.path(), "broken.json", |v| v["total_size"] = "big".into());
+
+    let error = stderr(dir.path(), &["diff", "--left", "future.json", "--right", "after.json"]);
+    assert!(error.contains("future.json uses snapshot schema v99"), "{}", error);
+    assert!(error.contains("Upgrade dit"), "{}", error);
+
+    let error = stderr(dir.path(), &["diff", "--left", "before.json", "--right", "broken.json"]);
+    assert!(error.contains("broken.json is not a dit snapshot"), "{}", error);
+    assert!(error.contains("/total_size"), "{}", error);
+
+    let same = stdout(dir.path(), &["diff", "--left", "after.json", "--right", "current.json", "--total-only"]);
+    assert_eq!(same, "unchanged\n");
+}
+
+#[test]
+fn compare_to_previous_exports_are_accepted() {
+    let dir = workspace();
+    let after: Value = serde_json::from_str(&fs::read_to_string(dir.path().join("after.json")).unwrap()).unwrap();
+    let wrapped = serde_json::json!({ "snapshot": after, "changes_from_previous": null, "platform_mismatch": false });
+    fs::write(dir.path().join("wrapped.json"), wrapped.to_string()).unwrap();
+
+    let output = stdout(dir.path(), &["diff", "--left", "before.json", "--right", "wrapped.json", "--total-only"]);
+    assert_eq!(output, "+13.5 MiB\n");
+}
@@ -0,0 +1,105 @@
+//! `--plain` output is pinned for the commands a screen reader user runs most: records
+//! instead of tables, spelled-out deltas, trend sentences and no color.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use assert_cmd::cargo::CommandCargoExt;
+use dit::format::{snapshot_table, LayerOrder, LayerSizeUnit, OutputStyle};
+use dit::output::TextOutput;
+use dit::schema::read_snapshot_file;
+use tempfile::TempDir;
+
+const IMAGE: &str = "registry.example.com/team/myapp:2.1";
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/plain").join(name)
+}
+
+/// A temp directory whose `.dit` holds five snapshots of myapp:2.1 and one of worker:1.0,
+/// with the two newest myapp snapshots also as before.json and after.json
+fn tracked_dir() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join(".dit")).unwrap();
+    fs::copy(fixture("history.json"), dir.path().join(".dit/history.json")).unwrap();
+    for file in ["before.json", "after.json"] {
+        fs::copy(fixture(file), dir.path().join(file)).unwrap();
+    }
+    dir
+}
+
+fn plain(dir: &Path, args: &[&str]) -> String {
+    let output = Command::cargo_bin("dit")
+        .unwrap()
+        .current_dir(dir)
+        .env("DIT_TZ", "UTC")
+        .env_remove("GITHUB_ACTIONS")
+        .arg("--plain")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn assert_golden(name: &str, actual: &str) {
+    let path = fixture(name);
+    let expected = fs::read_to_string(&path).unwrap();
+    assert_eq!(actual, expected, "{} is out of date", path.display());
+}
+
+fn assert_accessible(output: &str) {
+    assert!(!output.contains('\x1b'), "color in plain output:\n{}", output);
+    for symbol in ['│', '─', '╭', '▁', '█', '📈', '📉', '✅'] {
+        assert!(!output.contains(symbol), "{:?} in plain output:\n{}", symbol, output);
+    }
+    assert!(output.lines().all(|line| line == line.trim_end()), "trailing spaces:\n{}", output);
+}
+
+#[test]
+fn analyze() {
+    let snapshot = read_snapshot_file(fixture("after.json").to_str().unwrap()).unwrap();
+    let style = OutputStyle { plain: true, ..Default::default() };
+    let table =
+        snapshot_table(&snapshot, LayerSizeUnit::Auto, LayerOrder::Chronological, None, style);
+
+    // Written the way `main` writes it, with color stripped
+    let mut out = TextOutput::new(Vec::new());
+    out.strip_color();
+    out.write_all(table.as_bytes()).unwrap();
+    let output = String::from_utf8(out.into_inner()).unwrap();
+
+    assert_accessible(&output);
+    assert_golden("analyze.txt", &output);
+}
+
+#[test]
+fn diff() {
+    let dir = tracked_dir();
+    let output = plain(dir.path(), &["diff", "--left-file", "before.json", "--right-file", "after.json"]);
+
+    assert_accessible(&output);
+    assert!(output.contains("Change: increase of 13.5 mebibytes, 25.6 percent"), "{}", output);
+    assert_golden("diff.txt", &output);
+}
+
+#[test]
+fn history() {
+    let dir = tracked_dir();
+    let output = plain(dir.path(), &["history", IMAGE]);
+
+    assert_accessible(&output);
+    assert_golden("history.txt", &output);
+}
+
+#[test]
+fn summary() {
+    let dir = tracked_dir();
+    let output = plain(dir.path(), &["summary"]);
+
+    assert_accessible(&output);
+    assert!(output.contains("Trend: grew in 3 and shrank in 1 of the last 5 snapshots"), "{}", output);
+    assert_golden("summary.txt", &output);
+}
@@ -0,0 +1,109 @@
+//! `--read-only` gates commands before they do any work: commands that must record
+//! history exit 2, optional saves are skipped after one notice, and reads work normally.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use assert_cmd::cargo::CommandCargoExt;
+use tempfile::TempDir;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/ingest/docker-24.0");
+const IMAGE: &str = "registry.example.com/team/myapp:2.1";
+
+fn dit(dir: &Path, args: &[&str]) -> Output {
+    Command::cargo_bin("dit")
+        .unwrap()
+        .current_dir(dir)
+        .env_remove("GITHUB_ACTIONS")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn ingest_args(read_only: bool) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    if read_only {
+        args.push("--read-only".to_string());
+    }
+    args.extend(
+        [
+            "ingest",
+            "--inspect",
+            &format!("{}/inspect.json", FIXTURE),
+            "--history",
+            &format!("{}/history.json", FIXTURE),
+            "--commit",
+            "abc1234",
+            "--branch",
+            "main",
+        ]
+        .map(str::to_string),
+    );
+    args
+}
+
+/// A temp directory with one ingested snapshot in `.dit`
+fn tracked_dir() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let args = ingest_args(false);
+    let output = dit(dir.path(), &args.iter().map(String::as_str).collect::<Vec<_>>());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    dir
+}
+
+fn history_file(dir: &Path) -> Vec<u8> {
+    fs::read(dir.join(".dit/history.json")).unwrap()
+}
+
+#[test]
+fn commands_that_record_history_exit_2_before_any_work() {
+    let dir = tracked_dir();
+    let before = history_file(dir.path());
+
+    let ingest = ingest_args(true);
+    for args in [
+        ingest.iter().map(String::as_str).collect::<Vec<_>>(),
+        vec!["--read-only", "prune", "--keep-last", "0"],
+        vec!["--read-only", "migrate-refs"],
+        vec!["--read-only", "track", IMAGE],
+    ] {
+        let output = dit(dir.path(), &args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr);
+        assert!(stderr.contains("records history, but"), "{:?}: {}", args, stderr);
+        assert!(stderr.contains("is read-only"), "{:?}: {}", args, stderr);
+    }
+
+    assert_eq!(history_file(dir.path()), before);
+}
+
+#[test]
+fn dry_runs_of_write_commands_still_work() {
+    let dir = tracked_dir();
+    let output = dit(dir.path(), &["--read-only", "migrate-refs", "--dry-run"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn read_commands_work_without_a_notice() {
+    let dir = tracked_dir();
+    let output = dit(dir.path(), &["--read-only", "history", IMAGE]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("abc1234"));
+    assert!(!stderr.contains("read-only"), "{}", stderr);
+}
+
+#[test]
+fn optional_saves_are_skipped_with_one_notice() {
+    let dir = tracked_dir();
+    let before = history_file(dir.path());
+
+    // Fails later without a Docker daemon; the notice comes first either way
+    let output = dit(dir.path(), &["--read-only", "analyze", "--auto-track", IMAGE]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("nothing will be saved to history").count(), 1, "{}", stderr);
+
+    assert_eq!(history_file(dir.path()), before);
+}
@@ -0,0 +1,95 @@
+//! The example report templates under examples/report-templates render a fixed report
+//! the same way every time: a changed image, a first run and a skipped one, with git,
+//! budget and storage cost details.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{TimeZone, Utc};
+use dit::cost::StorageCostImpact;
+use dit::diff::compute_diff;
+use dit::models::ImageSnapshot;
+use dit::report::{ReportContext, ReportGit, ReportTemplate};
+use dit::schema::read_snapshot_file;
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn fixture(name: &str) -> PathBuf {
+    root().join("tests/fixtures").join(name)
+}
+
+fn snapshot(name: &str) -> ImageSnapshot {
+    read_snapshot_file(fixture(&format!("plain/{}", name)).to_str().unwrap()).unwrap()
+}
+
+/// myapp:2.1 grown by 13.5 MiB, worker:1.0 tracked for the first time and api:3.0 skipped
+fn context() -> ReportContext {
+    let before = snapshot("before.json");
+    let after = snapshot("after.json");
+    let diff = compute_diff(before, after.clone());
+
+    let mut worker = snapshot("after.json");
+    worker.image = "registry.example.com/team/worker".to_string();
+    worker.tag = Some("1.0".to_string());
+    let mut api = snapshot("before.json");
+    api.image = "registry.example.com/team/api".to_string();
+    api.tag = Some("3.0".to_string());
+
+    let git = ReportGit {
+        commit_sha: "c5e0005f3a1b2c3d4e5f60718293a4b5c6d7e8f9".to_string(),
+        commit_short: "c5e0005".to_string(),
+        branch: "main".to_string(),
+        commit_message: "Add image processing dependencies".to_string(),
+        author: "Sam Doe".to_string(),
+    };
+    let storage_cost = StorageCostImpact {
+        currency: "USD".to_string(),
+        retained_tags: 10,
+        monthly_before: 1.5,
+        monthly_after: 1.65,
+        monthly_delta: 0.15,
+        summary: "Storage: +$0.15/month across 10 retained tags".to_string(),
+    };
+
+    let mut context = ReportContext::new(
+        &[(after, Some(diff)), (worker, None)],
+        &[api],
+        Some(200 * 1024 * 1024),
+        80.0,
+        Some(git),
+        Some(storage_cost),
+    );
+    context.meta.dit_version = "0.0.0".to_string();
+    context.meta.generated_at = Utc.with_ymd_and_hms(2024, 3, 5, 12, 0, 0).unwrap();
+    context
+}
+
+fn render(template: &str) -> String {
+    let path = root().join("examples/report-templates").join(template);
+    ReportTemplate::load(path.to_str().unwrap())
+        .unwrap()
+        .render(&context())
+        .unwrap()
+}
+
+fn assert_golden(name: &str, actual: &str) {
+    let path = fixture(&format!("report-templates/{}", name));
+    let expected = fs::read_to_string(&path).unwrap();
+    assert_eq!(actual, expected, "{} is out of date", path.display());
+}
+
+#[test]
+fn minimal() {
+    assert_golden("minimal.md", &render("minimal.md.j2"));
+}
+
+#[test]
+fn verbose() {
+    let output = render("verbose.md.j2");
+
+    assert!(output.contains("*First run* 🆕"), "{}", output);
+    assert!(output.contains("*skipped* ⏭️"), "{}", output);
+    assert_golden("verbose.md", &output);
+}
@@ -0,0 +1,35 @@
+//! The schemas checked in under schemas/ are what `dit schema <kind>` prints, so a change
+//! to a serialized type fails here until they are regenerated.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use assert_cmd::cargo::CommandCargoExt;
+
+fn schema(kind: &str) -> String {
+    let output = Command::cargo_bin("dit")
+        .unwrap()
+        .env_remove("GITHUB_ACTIONS")
+        .args(["schema", kind])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}: {}", kind, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn committed_schemas_are_up_to_date() {
+    for kind in ["snapshot", "diff", "report", "history"] {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("schemas/{}.schema.json", kind));
+        let committed = fs::read_to_string(&path).unwrap();
+
+        assert!(
+            schema(kind) == committed,
+            "{} is out of date; regenerate it with `dit schema {} > schemas/{}.schema.json`",
+            path.display(),
+            kind,
+            kind
+        );
+    }
+}